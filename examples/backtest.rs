@@ -0,0 +1,71 @@
+// Replays a random walk of liquidity and compares buying 1000 OSMO in one market order against working it
+// through a TWAP over the same period.
+//
+// Run with `cargo run --example backtest`.
+use orderbook::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const STEPS: u32 = 20;
+const PARENT_QUANTITY: u64 = 1000;
+
+fn account(account_id: u64, balances: &[(Currency, u64)]) -> Rc<RefCell<Account>> {
+    let account = Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual)));
+    for (currency, amount) in balances {
+        account.borrow_mut().deposit(*currency, *amount);
+    }
+    account
+}
+
+// Builds the same sequence of books for every strategy: each step a liquidity provider adds a few asks
+// around a drifting price
+fn replay(seed: u64, mut strategy: impl FnMut(&mut Orderbook, u32)) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut book = Orderbook::new(0);
+    let provider = account(1, &[(Currency::OSMO, u64::MAX / 2)]);
+    let mut price_tick: i64 = 100;
+
+    for step in 0..STEPS {
+        price_tick = (price_tick + rng.gen_range(-3..=3)).max(10);
+        for level in 0..5 {
+            let tick_id = price_tick as u64 + level;
+            let mut ask = Order::new(0, tick_id, 0, provider.clone(), OrderType::Limit, OrderDirection::Ask, rng.gen_range(20..=80));
+            book.handle_order(&mut ask).unwrap();
+        }
+        strategy(&mut book, step);
+    }
+}
+
+fn usd_spent(trader: &Rc<RefCell<Account>>, starting_usd: u64) -> (u64, u64) {
+    let trader = trader.borrow();
+    (starting_usd - trader.balance(Currency::USD), trader.balance(Currency::OSMO))
+}
+
+fn main() {
+    let starting_usd = 1000000;
+
+    // All at once, on the first step
+    let trader = account(2, &[(Currency::USD, starting_usd)]);
+    replay(42, |book, step| {
+        if step == 0 {
+            let mut order = Order::new(0, 0, 0, trader.clone(), OrderType::Market, OrderDirection::Bid, PARENT_QUANTITY);
+            book.handle_order(&mut order).unwrap();
+        }
+    });
+    let (cost, filled) = usd_spent(&trader, starting_usd);
+    println!("Single market order: {} of {} OSMO for {} USD, average tick {:.2}", filled, PARENT_QUANTITY, cost, cost as f64 / filled as f64);
+
+    // One slice per step, driven by a simulated clock instead of wall time
+    let trader = account(2, &[(Currency::USD, starting_usd)]);
+    let start = Instant::now();
+    let interval = Duration::from_secs(60);
+    let mut twap = TwapOrder::new(0, trader.clone(), OrderDirection::Bid, PARENT_QUANTITY, STEPS as u64, interval, start).unwrap();
+    replay(42, |book, step| {
+        twap.poll(book, start + interval * step);
+    });
+    let (cost, filled) = usd_spent(&trader, starting_usd);
+    println!("TWAP over {} slices: {} of {} OSMO for {} USD, average tick {:.2}", STEPS, filled, PARENT_QUANTITY, cost, cost as f64 / filled as f64);
+}
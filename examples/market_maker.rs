@@ -0,0 +1,67 @@
+// A market maker keeps one bid and one ask around a fixed mid while random takers trade against it,
+// then reports its inventory and P&L.
+//
+// Run with `cargo run --example market_maker`.
+use orderbook::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const MID_TICK: u64 = 100;
+const HALF_SPREAD: u64 = 2;
+const QUOTE_SIZE: u64 = 50;
+const ROUNDS: usize = 200;
+
+fn account(account_id: u64, balances: &[(Currency, u64)]) -> Rc<RefCell<Account>> {
+    let account = Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual)));
+    for (currency, amount) in balances {
+        account.borrow_mut().deposit(*currency, *amount);
+    }
+    account
+}
+
+// Quantity the maker has resting on a tick, read from the level's queue
+fn resting(book: &Orderbook, tick_id: u64, maker_id: u64) -> u64 {
+    book.tick_detail(tick_id)
+        .iter()
+        .filter(|queued| *queued.owner_id() == maker_id)
+        .map(|queued| *queued.quantity())
+        .sum()
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut book = Orderbook::new(0);
+    let maker = account(1, &[(Currency::OSMO, 10000), (Currency::USD, 1000000)]);
+    let taker = account(2, &[(Currency::OSMO, 10000), (Currency::USD, 1000000)]);
+    let (bid_tick, ask_tick) = (MID_TICK - HALF_SPREAD, MID_TICK + HALF_SPREAD);
+    let mut next_order_id = 0;
+
+    for _ in 0..ROUNDS {
+        // Top each quote back up to full size once takers have eaten into it
+        for (direction, tick_id) in [(OrderDirection::Bid, bid_tick), (OrderDirection::Ask, ask_tick)] {
+            let missing = QUOTE_SIZE - resting(&book, tick_id, 1).min(QUOTE_SIZE);
+            if missing > 0 {
+                next_order_id += 1;
+                let mut quote = Order::new(next_order_id, tick_id, 0, maker.clone(), OrderType::Limit, direction, missing);
+                if let Err(e) = book.handle_order(&mut quote) {
+                    println!("maker stopped quoting: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let direction = if rng.gen_bool(0.5) { OrderDirection::Bid } else { OrderDirection::Ask };
+        next_order_id += 1;
+        let mut take = Order::new(next_order_id, 0, 0, taker.clone(), OrderType::Market, direction, rng.gen_range(1..=40));
+        book.handle_order(&mut take).unwrap();
+    }
+
+    // Mark the maker's holdings, including what is escrowed in its quotes, at the mid
+    let maker = maker.borrow();
+    let osmo = maker.balance(Currency::OSMO) + resting(&book, ask_tick, 1);
+    let usd = maker.balance(Currency::USD) + resting(&book, bid_tick, 1) * bid_tick;
+    let pnl = (usd + osmo * MID_TICK) as i64 - (1000000 + 10000 * MID_TICK) as i64;
+    println!("After {} rounds the maker holds {} OSMO and {} USD, P&L at mid {} USD (tick units)", ROUNDS, osmo, usd, pnl);
+}
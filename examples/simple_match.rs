@@ -0,0 +1,37 @@
+// Matches a market buy against two resting asks and prints where everyone ends up.
+//
+// Run with `cargo run --example simple_match`.
+use orderbook::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn account(account_id: u64, balances: &[(Currency, u64)]) -> Rc<RefCell<Account>> {
+    let account = Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual)));
+    for (currency, amount) in balances {
+        account.borrow_mut().deposit(*currency, *amount);
+    }
+    account
+}
+
+fn main() {
+    let mut book = Orderbook::new(0);
+    let maker = account(1, &[(Currency::OSMO, 200)]);
+    let taker = account(2, &[(Currency::USD, 5000)]);
+
+    // Tick ids are the price times 10, so these asks offer OSMO at 1.2 and 1.5 USD
+    book.handle_order(&mut Order::new(1, 12, 0, maker.clone(), OrderType::Limit, OrderDirection::Ask, 100)).unwrap();
+    book.handle_order(&mut Order::new(2, 15, 0, maker.clone(), OrderType::Limit, OrderDirection::Ask, 100)).unwrap();
+
+    let impact = book.impact(150, OrderDirection::Bid);
+    println!("Buying 150 OSMO should cost {} USD (tick units), average tick {:.2}", impact.total_cost(), impact.average_price().unwrap());
+
+    book.handle_order(&mut Order::new(3, 0, 0, taker.clone(), OrderType::Market, OrderDirection::Bid, 150)).unwrap();
+
+    for (name, owner) in [("maker", &maker), ("taker", &taker)] {
+        let owner = owner.borrow();
+        println!("{}: {} OSMO, {} USD", name, owner.balance(Currency::OSMO), owner.balance(Currency::USD));
+    }
+    for queued in book.tick_detail(15) {
+        println!("still resting at 1.5: order {} for {} OSMO", queued.order_id(), queued.quantity());
+    }
+}
@@ -0,0 +1,109 @@
+// Stress scenario for wide, sparse tick ranges like those of concentrated liquidity pools: a few hundred levels
+// scattered over tick ids up to 10^9, where a dense 1-40 book would have them next to each other. Times the
+// operations whose cost should follow the number of populated levels rather than the span of tick ids they cover,
+// and checks their results along the way.
+//
+// Run with `cargo run --release --example sparse_ticks`.
+use orderbook::prelude::*;
+use orderbook::ui::chart::{visible_bars, visible_ticks};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const MAX_TICK: u64 = 1_000_000_000;
+const LEVELS_PER_SIDE: usize = 200;
+const ROUNDS: u32 = 1000;
+// Bars the depth chart fits in a 160 column terminal
+const TERMINAL_WIDTH: u16 = 160;
+
+fn account(account_id: u64, balances: &[(Currency, u64)]) -> Rc<RefCell<Account>> {
+    let account = Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual)));
+    for (currency, amount) in balances {
+        account.borrow_mut().deposit(*currency, *amount);
+    }
+    account
+}
+
+// Runs `operation` ROUNDS times and prints the mean time per call
+fn time<T>(name: &str, mut operation: impl FnMut(u32) -> T) -> T {
+    let started = Instant::now();
+    let mut result = None;
+    for round in 0..ROUNDS {
+        result = Some(operation(round));
+    }
+    let per_call = started.elapsed() / ROUNDS;
+    println!("{:<36} {:>10.2?} per call", name, per_call);
+    assert!(per_call < Duration::from_millis(50), "{} slowed down with sparse ticks", name);
+    result.unwrap()
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut book = Orderbook::new(0);
+    let maker = account(1, &[(Currency::USD, u64::MAX / 4), (Currency::OSMO, u64::MAX / 4)]);
+    let taker = account(2, &[(Currency::USD, u64::MAX / 4), (Currency::OSMO, u64::MAX / 4)]);
+
+    // Bids below the middle of the range and asks above it, so the spread alone spans millions of ticks
+    let mut order_id = 0;
+    let started = Instant::now();
+    for (direction, ticks) in [(OrderDirection::Bid, 1..MAX_TICK / 2), (OrderDirection::Ask, MAX_TICK / 2 + 1..MAX_TICK)] {
+        for _ in 0..LEVELS_PER_SIDE {
+            order_id += 1;
+            let tick_id = rng.gen_range(ticks.clone());
+            let mut order = Order::new(order_id, tick_id, 0, Rc::clone(&maker), OrderType::Limit, direction, rng.gen_range(1..1000));
+            book.handle_order(&mut order).unwrap();
+        }
+    }
+    println!("placed {} sparse levels in {:.2?}", book.ticks().len(), started.elapsed());
+    let populated = book.ticks().len();
+
+    // Every round moves the book on by one order so nothing is served from the cached snapshot
+    let snapshot = time("snapshot after a change", |round| {
+        order_id += 1;
+        let mut order = Order::new(order_id, MAX_TICK / 4 + u64::from(round), 0, Rc::clone(&maker), OrderType::Limit, OrderDirection::Bid, 1);
+        book.handle_order(&mut order).unwrap();
+        book.snapshot()
+    });
+    assert_eq!(snapshot.bids().len() + snapshot.asks().len(), book.ticks().len());
+
+    let depth = time("depth of the 10 best levels", |_| book.depth(10));
+    assert_eq!(depth.len(), 20);
+
+    // What the depth chart does every frame: pick the window of ticks that fits and read each bar's quantity
+    let bars = visible_bars(TERMINAL_WIDTH);
+    let window = time("chart window and bar quantities", |_| {
+        let window = visible_ticks(&snapshot, bars);
+        let quantities: u64 = window.clone().map(|tick_id| snapshot.quantity_at(tick_id)).sum();
+        (window, quantities)
+    });
+    assert_eq!(window.0.end - window.0.start, bars);
+
+    let impact = time("impact of a 50,000 OSMO market buy", |_| book.impact(50 * 1000, OrderDirection::Bid));
+    assert!(*impact.filled_quantity() > 0);
+
+    // Takers sweep whole levels at a time and the maker tops the book back up at a far away tick
+    time("market sweep and refill", |_| {
+        book.handle_order(&mut Order::new(0, 0, 0, Rc::clone(&taker), OrderType::Market, OrderDirection::Bid, 2000)).unwrap();
+        order_id += 1;
+        let tick_id = rng.gen_range(MAX_TICK / 2 + 1..MAX_TICK);
+        book.handle_order(&mut Order::new(order_id, tick_id, 0, Rc::clone(&maker), OrderType::Limit, OrderDirection::Ask, 2000)).unwrap();
+    });
+
+    time("cancel and replace a far bid", |round| {
+        let tick_id = MAX_TICK / 4 + u64::from(round);
+        let resting = book.tick_detail(tick_id);
+        let order_id = *resting[0].order_id();
+        book.cancel_order(order_id, 1).unwrap();
+        book.handle_order(&mut Order::new(order_id, tick_id, 0, Rc::clone(&maker), OrderType::Limit, OrderDirection::Bid, 1)).unwrap();
+    });
+
+    book.verify_escrow().unwrap();
+    println!("{} levels populated at the start, {} at the end, spanning ticks {} to {}",
+        populated,
+        book.ticks().len(),
+        book.ticks().keys().next().unwrap(),
+        book.ticks().keys().next_back().unwrap(),
+    );
+}
@@ -0,0 +1,9 @@
+#!/usr/bin/env sh
+# Builds, lints and tests every supported feature combination, from the bare matching engine up to the full build.
+set -eu
+
+for features in "" "sim" "persistence" "sim,persistence" "persistence,sqlite" "serde" "tui" "tui,sqlite"; do
+    echo "== features: ${features:-none}"
+    cargo clippy --all-targets --no-default-features --features "$features" -- -D warnings
+    cargo test --no-default-features --features "$features"
+done
@@ -0,0 +1,246 @@
+use getset::Getters;
+use crate::bank::account::Account;
+use crate::bank::currency::Currency;
+use crate::book::error::OrderbookError;
+use crate::book::order::{Order, OrderDirection, OrderType};
+use crate::book::orderbook::Orderbook;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum AlgoState {
+    // Slices are released as they come due.
+    Running,
+    // No slices are released until the algo is resumed.
+    Paused,
+    // Stopped by the user before every slice was released.
+    Cancelled,
+    // Every slice has been released.
+    Completed,
+}
+
+// Outcome of one child order released by a parent algo.
+#[derive(Debug)]
+pub struct SliceReport {
+    pub slice: u64,
+    pub quantity: u64,
+    pub result: Result<u64, OrderbookError>,
+}
+
+// Time-weighted average price algo: a parent order of `total_quantity` split into `slices` equally sized
+// child market orders, released one every `interval`. Any remainder from the split goes into the last slice.
+#[derive(Getters, Debug)]
+pub struct TwapOrder {
+    #[get = "pub"]
+    parent_id: u64,
+    #[get = "pub"]
+    owner: Rc<RefCell<Account>>,
+    #[get = "pub"]
+    direction: OrderDirection,
+    #[get = "pub"]
+    total_quantity: u64,
+    #[get = "pub"]
+    slices: u64,
+    #[get = "pub"]
+    interval: Duration,
+    #[get = "pub"]
+    state: AlgoState,
+    // Number of child orders released so far.
+    #[get = "pub"]
+    slices_sent: u64,
+    // Quantity of OSMO actually traded by the child orders, which can be less than what was sent if the book ran dry.
+    #[get = "pub"]
+    filled_quantity: u64,
+    next_slice_at: Instant,
+}
+
+impl TwapOrder {
+    // The first slice is released immediately, the rest follow every `interval`.
+    pub fn new(
+        parent_id: u64,
+        owner: Rc<RefCell<Account>>,
+        direction: OrderDirection,
+        total_quantity: u64,
+        slices: u64,
+        interval: Duration,
+        start: Instant,
+    ) -> Result<TwapOrder, &'static str> {
+        if slices == 0 || total_quantity < slices {
+            return Err("TWAP needs at least one unit of quantity per slice");
+        }
+
+        Ok(TwapOrder {
+            parent_id,
+            owner,
+            direction,
+            total_quantity,
+            slices,
+            interval,
+            state: AlgoState::Running,
+            slices_sent: 0,
+            filled_quantity: 0,
+            next_slice_at: start,
+        })
+    }
+
+    // Quantity of the given zero-indexed slice.
+    pub fn slice_quantity(&self, slice: u64) -> u64 {
+        let base_quantity = self.total_quantity / self.slices;
+        if slice + 1 == self.slices {
+            base_quantity + self.total_quantity % self.slices
+        } else {
+            base_quantity
+        }
+    }
+
+    // Quantity handed to the book so far.
+    pub fn sent_quantity(&self) -> u64 {
+        (0..self.slices_sent).map(|slice| self.slice_quantity(slice)).sum()
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, AlgoState::Running | AlgoState::Paused)
+    }
+
+    pub fn pause(&mut self) -> Result<(), &'static str> {
+        match self.state {
+            AlgoState::Running => {
+                self.state = AlgoState::Paused;
+                Ok(())
+            }
+            _ => Err("Only running algos can be paused"),
+        }
+    }
+
+    // Picks the schedule back up from `now`, so slices missed while paused are not released in a burst.
+    pub fn resume(&mut self, now: Instant) -> Result<(), &'static str> {
+        match self.state {
+            AlgoState::Paused => {
+                self.state = AlgoState::Running;
+                self.next_slice_at = self.next_slice_at.max(now);
+                Ok(())
+            }
+            _ => Err("Only paused algos can be resumed"),
+        }
+    }
+
+    // Stops releasing slices. Child orders already sent are not affected.
+    pub fn cancel(&mut self) -> Result<(), &'static str> {
+        if !self.is_active() {
+            return Err("Algo has already finished");
+        }
+        self.state = AlgoState::Cancelled;
+        Ok(())
+    }
+
+    // Releases every slice that has come due by `now` as a child market order on the book.
+    pub fn poll(&mut self, book: &mut Orderbook, now: Instant) -> Vec<SliceReport> {
+        let mut reports = Vec::new();
+
+        while self.state == AlgoState::Running && self.next_slice_at <= now {
+            let slice = self.slices_sent;
+            let quantity = self.slice_quantity(slice);
+            let mut child = Order::new(
+                0,
+                0,
+                *book.book_id(),
+                Rc::clone(&self.owner),
+                OrderType::Market,
+                self.direction,
+                quantity,
+            );
+            child.set_parent_order_id(Some(self.parent_id));
+
+            // Market orders don't report their fill, but both sides move the owner's OSMO balance by exactly the traded quantity.
+            let osmo_before = self.owner.borrow().balance(Currency::OSMO);
+            let result = book.handle_order(&mut child).map(|_| {
+                let osmo_after = self.owner.borrow().balance(Currency::OSMO);
+                osmo_after.abs_diff(osmo_before)
+            });
+            if let Ok(filled_quantity) = result {
+                self.filled_quantity += filled_quantity;
+            }
+            reports.push(SliceReport { slice, quantity, result });
+
+            self.slices_sent += 1;
+            self.next_slice_at += self.interval;
+            if self.slices_sent == self.slices {
+                self.state = AlgoState::Completed;
+            }
+        }
+
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{AccountBuilder, BookBuilder};
+
+    fn twap_bid(quantity: u64, slices: u64, start: Instant) -> TwapOrder {
+        let owner = AccountBuilder::funded(1, &[(Currency::USD, 100000)]).build();
+        TwapOrder::new(0, owner, OrderDirection::Bid, quantity, slices, Duration::from_secs(10), start).unwrap()
+    }
+
+    #[test]
+    fn test_slice_quantities() {
+        let twap = twap_bid(100, 3, Instant::now());
+
+        // The remainder of the split goes into the last slice
+        assert_eq!(twap.slice_quantity(0), 33);
+        assert_eq!(twap.slice_quantity(1), 33);
+        assert_eq!(twap.slice_quantity(2), 34);
+
+        let owner = AccountBuilder::new(1).build();
+        assert!(TwapOrder::new(0, owner, OrderDirection::Bid, 2, 3, Duration::from_secs(1), Instant::now()).is_err());
+    }
+
+    #[test]
+    fn test_poll_releases_due_slices() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 50), (12, 100)]).build();
+        let start = Instant::now();
+        let mut twap = twap_bid(90, 3, start);
+
+        // The first slice goes out immediately
+        let reports = twap.poll(&mut book, start);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].result.as_ref().unwrap(), &30);
+        assert_eq!(*twap.slices_sent(), 1);
+
+        // Nothing is due until the interval has passed
+        assert!(twap.poll(&mut book, start + Duration::from_secs(5)).is_empty());
+
+        // Missing a tick releases every slice that came due in the meantime
+        let reports = twap.poll(&mut book, start + Duration::from_secs(25));
+        assert_eq!(reports.len(), 2);
+        assert_eq!(*twap.filled_quantity(), 90);
+        assert_eq!(twap.sent_quantity(), 90);
+        assert_eq!(*twap.state(), AlgoState::Completed);
+        assert_eq!(twap.owner().borrow().balance(Currency::OSMO), 90);
+        assert_eq!(*book.ticks().get(&12).unwrap().total_liquidity(), 60);
+    }
+
+    #[test]
+    fn test_pause_resume_and_cancel() {
+        let mut book = BookBuilder::new().with_ask(10, 1000).build();
+        let start = Instant::now();
+        let mut twap = twap_bid(100, 4, start);
+        twap.poll(&mut book, start);
+
+        // Slices that come due while paused are not released, and resuming doesn't catch up in a burst
+        twap.pause().unwrap();
+        assert!(twap.poll(&mut book, start + Duration::from_secs(30)).is_empty());
+        twap.resume(start + Duration::from_secs(30)).unwrap();
+        assert_eq!(twap.poll(&mut book, start + Duration::from_secs(30)).len(), 1);
+        assert_eq!(*twap.slices_sent(), 2);
+
+        // A cancelled parent stops for good, keeping what was already filled
+        twap.cancel().unwrap();
+        assert!(twap.poll(&mut book, start + Duration::from_secs(100)).is_empty());
+        assert!(twap.resume(start + Duration::from_secs(100)).is_err());
+        assert_eq!(*twap.filled_quantity(), 50);
+        assert_eq!(*twap.state(), AlgoState::Cancelled);
+    }
+}
@@ -1,17 +1,41 @@
+use getset::Getters;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use super::currency::Currency;
+use crate::book::error::OrderbookError;
 
 // enum for AccountType between individual and orderbook
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AccountType {
     Individual,
     Orderbook,
 }
 
-#[derive(Clone, Debug)]
+/// Per-currency balances of a trader.
+///
+/// ```
+/// use orderbook::bank::account::{Account, AccountType};
+/// use orderbook::bank::currency::Currency;
+/// use orderbook::book::error::OrderbookError;
+///
+/// let mut account = Account::new(0, AccountType::Individual);
+/// account.deposit(Currency::USD, 500);
+/// assert!(account.withdraw(Currency::USD, 200).is_ok());
+/// assert_eq!(
+///     account.withdraw(Currency::USD, 400),
+///     Err(OrderbookError::InsufficientFunds { currency: Currency::USD, required: 400, available: 300 }),
+/// );
+/// assert_eq!(account.balance(Currency::USD), 300);
+/// ```
+#[derive(Getters, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Account {
+    #[get = "pub"]
     account_id: u64,
     balances: HashMap<Currency, u64>,
+    #[get = "pub"]
     account_type: AccountType,
 }
 
@@ -30,10 +54,10 @@ impl Account {
     }
 
     // withdraw but return Result error if insufficient funds
-    pub fn withdraw(&mut self, currency: Currency, amount: u64) -> Result<(), &'static str> {
+    pub fn withdraw(&mut self, currency: Currency, amount: u64) -> Result<(), OrderbookError> {
         let balance = self.balances.entry(currency).or_insert(0);
         if *balance < amount {
-            return Err("Insufficient funds");
+            return Err(OrderbookError::InsufficientFunds { currency, required: amount, available: *balance });
         }
         *balance -= amount;
         Ok(())
@@ -43,4 +67,10 @@ impl Account {
     pub fn balance(&self, currency: Currency) -> u64 {
         *self.balances.get(&currency).unwrap_or(&0)
     }
+
+    // Sum of one currency across accounts, e.g. every trader plus a book's escrow, for checking that trading only
+    // moved funds around.
+    pub fn total_balance<'a>(accounts: impl IntoIterator<Item = &'a Rc<RefCell<Account>>>, currency: Currency) -> u64 {
+        accounts.into_iter().map(|account| account.borrow().balance(currency)).sum()
+    }
 }
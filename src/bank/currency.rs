@@ -1,6 +1,7 @@
-use strum_macros::ToString;
+use strum_macros::{Display, EnumString};
 
-#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug, ToString)]
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Currency {
     USD,
     OSMO,
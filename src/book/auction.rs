@@ -0,0 +1,90 @@
+use getset::Getters;
+use super::trade::Trade;
+
+// Outcome of a call auction (see Orderbook::run_auction).
+#[derive(Getters, Clone, Debug, Default, PartialEq)]
+pub struct AuctionResult {
+    // Single price every fill of the auction went through at, None if nothing crossed.
+    #[get = "pub"]
+    clearing_tick: Option<u64>,
+    #[get = "pub"]
+    matched_quantity: u64,
+    // One trade per pair of bid and ask matched, best priced and oldest orders first.
+    #[get = "pub"]
+    trades: Vec<Trade>,
+    // Orders collected during the window that weren't filled in full and now rest on the book.
+    #[get = "pub"]
+    rested: usize,
+}
+
+impl AuctionResult {
+    pub(crate) fn new(clearing_tick: Option<u64>, matched_quantity: u64, trades: Vec<Trade>, rested: usize) -> AuctionResult {
+        AuctionResult { clearing_tick, matched_quantity, trades, rested }
+    }
+}
+
+// The (tick, quantity) a call auction of these bids and asks, as (tick, quantity) pairs in any order, crosses at.
+// The clearing tick is the participant price that matches the most volume: bids at or above it buy and asks at or
+// below it sell. Ties go to the tick leaving the smallest unmatched imbalance, then to the lowest tick. None if no
+// bid reaches any ask. Both sides are sorted once and the candidates walked upwards, so demand and supply are
+// running totals rather than summed again at every tick.
+pub fn clearing_tick(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> Option<(u64, u64)> {
+    let (mut bids, mut asks) = (bids.to_vec(), asks.to_vec());
+    bids.sort_unstable();
+    asks.sort_unstable();
+    let mut candidates: Vec<u64> = bids.iter().chain(&asks).map(|(tick_id, _)| *tick_id).collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    // Every bid is still in the demand at the lowest candidate, and no ask is in the supply yet
+    let mut demand: u64 = bids.iter().map(|(_, quantity)| quantity).sum();
+    let mut supply = 0;
+    let (mut bids_below, mut asks_at_or_below) = (bids.iter().peekable(), asks.iter().peekable());
+    let mut best: Option<(u64, u64, u64)> = None;
+    for tick_id in candidates {
+        while let Some((_, quantity)) = bids_below.next_if(|(bid_tick, _)| *bid_tick < tick_id) {
+            demand -= quantity;
+        }
+        while let Some((_, quantity)) = asks_at_or_below.next_if(|(ask_tick, _)| *ask_tick <= tick_id) {
+            supply += quantity;
+        }
+        let (matched, imbalance) = (demand.min(supply), demand.abs_diff(supply));
+        // Candidates come lowest first, so only a strictly better tick replaces the current one
+        let better = match best {
+            None => true,
+            Some((_, best_matched, best_imbalance)) => matched > best_matched || matched == best_matched && imbalance < best_imbalance,
+        };
+        if matched > 0 && better {
+            best = Some((tick_id, matched, imbalance));
+        }
+    }
+    best.map(|(tick_id, matched, _)| (tick_id, matched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clearing_tick_maximizes_matched_volume() {
+        // At 11 the bids at 12 and 11 (50) meet the asks at 10 and 11 (45); at 12 only 20 of demand is left
+        let bids = [(12, 20), (11, 30), (9, 40)];
+        let asks = [(10, 25), (11, 20), (13, 50)];
+        assert_eq!(clearing_tick(&bids, &asks), Some((11, 45)));
+    }
+
+    #[test]
+    fn test_clearing_tick_breaks_ties_on_imbalance_then_price() {
+        // 10 matches at both 11 and 12, but at 12 the 10 of supply meets exactly 10 of demand
+        assert_eq!(clearing_tick(&[(12, 10), (11, 5)], &[(11, 10)]), Some((12, 10)));
+        // Same volume and imbalance at every tick from 10 to 12: the lowest wins
+        assert_eq!(clearing_tick(&[(12, 10)], &[(10, 10)]), Some((10, 10)));
+    }
+
+    #[test]
+    fn test_no_clearing_tick_when_nothing_crosses() {
+        assert_eq!(clearing_tick(&[(10, 10)], &[(11, 10)]), None);
+        assert_eq!(clearing_tick(&[(10, 10)], &[]), None);
+        assert_eq!(clearing_tick(&[], &[]), None);
+    }
+}
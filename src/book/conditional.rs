@@ -0,0 +1,99 @@
+use getset::Getters;
+use super::query::DepthSnapshot;
+use strum_macros::{Display, EnumString};
+
+// Book state a conditional order can wait on.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum BookMetric {
+    // Best bid tick.
+    Bid,
+    // Best ask tick.
+    Ask,
+    // Best ask minus best bid, in ticks.
+    Spread,
+    // Total quantity resting on the bid side.
+    BidDepth,
+    // Total quantity resting on the ask side.
+    AskDepth,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+// Predicate over the book, e.g. "spread above 3". Conditions on prices or the spread never hold while a side
+// they need is empty, depth conditions treat an empty side as zero.
+#[derive(Getters, PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookCondition {
+    #[get = "pub"]
+    metric: BookMetric,
+    #[get = "pub"]
+    comparison: Comparison,
+    #[get = "pub"]
+    threshold: u64,
+}
+
+impl BookCondition {
+    pub fn new(metric: BookMetric, comparison: Comparison, threshold: u64) -> BookCondition {
+        BookCondition { metric, comparison, threshold }
+    }
+
+    pub fn holds(&self, snapshot: &DepthSnapshot) -> bool {
+        let best_bid = snapshot.bids().first().map(|(tick_id, _)| *tick_id);
+        let best_ask = snapshot.asks().first().map(|(tick_id, _)| *tick_id);
+        let side_depth = |levels: &Vec<(u64, u64)>| levels.iter().map(|(_, quantity)| quantity).sum();
+
+        let value = match self.metric {
+            BookMetric::Bid => best_bid,
+            BookMetric::Ask => best_ask,
+            BookMetric::Spread => best_bid.zip(best_ask).map(|(bid, ask)| ask.saturating_sub(bid)),
+            BookMetric::BidDepth => Some(side_depth(snapshot.bids())),
+            BookMetric::AskDepth => Some(side_depth(snapshot.asks())),
+        };
+
+        match (value, self.comparison) {
+            (Some(value), Comparison::Above) => value > self.threshold,
+            (Some(value), Comparison::Below) => value < self.threshold,
+            (None, _) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::BookBuilder;
+
+    #[test]
+    fn test_conditions_against_book() {
+        let book = BookBuilder::new().with_bids(&[(8, 100), (9, 50)]).with_ask(14, 70).build();
+        let snapshot = book.snapshot();
+
+        assert!(BookCondition::new(BookMetric::Spread, Comparison::Above, 3).holds(&snapshot));
+        assert!(!BookCondition::new(BookMetric::Spread, Comparison::Above, 5).holds(&snapshot));
+        assert!(BookCondition::new(BookMetric::Bid, Comparison::Below, 10).holds(&snapshot));
+        assert!(BookCondition::new(BookMetric::BidDepth, Comparison::Above, 120).holds(&snapshot));
+        assert!(!BookCondition::new(BookMetric::AskDepth, Comparison::Above, 70).holds(&snapshot));
+
+        // Price conditions can't hold on an empty side, depth conditions see it as zero
+        let empty = BookBuilder::new().build().snapshot();
+        assert!(!BookCondition::new(BookMetric::Ask, Comparison::Below, 100).holds(&empty));
+        assert!(!BookCondition::new(BookMetric::Spread, Comparison::Below, 100).holds(&empty));
+        assert!(BookCondition::new(BookMetric::AskDepth, Comparison::Below, 1).holds(&empty));
+    }
+
+    #[test]
+    fn test_parse_metric_and_comparison() {
+        assert_eq!("spread".parse::<BookMetric>(), Ok(BookMetric::Spread));
+        assert_eq!("askdepth".parse::<BookMetric>(), Ok(BookMetric::AskDepth));
+        assert_eq!("below".parse::<Comparison>(), Ok(Comparison::Below));
+        assert!("sideways".parse::<Comparison>().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use super::rejection::RejectCode;
+use crate::bank::currency::Currency;
+use std::error::Error;
+use std::fmt;
+
+// Every way the book can refuse an order, a cancel or a cross. Callers branch on the variant (or its stable
+// `code()`), while the Display form is the short human readable reason.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderbookError {
+    // The order's fields don't fit the operation, e.g. a post-only market order.
+    InvalidOrder(&'static str),
+    // A price the book can't hold: a sentinel tick, or one off the tick size grid of its band, in which case the
+    // nearest valid ticks below and above are given.
+    InvalidTick { tick_id: u64, nearest_valid: Option<(u64, u64)> },
+    // No resting order with this id belongs to the account.
+    UnknownOrder(u64),
+    // No market is listed under the book id, or its book is checked out of the exchange.
+    UnknownMarket { book_id: u64, checked_out: bool },
+    // A fill-or-kill or strict market order would only fill `filled` of `requested`.
+    InsufficientLiquidity { filled: u64, requested: u64 },
+    // A market order would sweep past its slippage limit.
+    SlippageExceeded,
+    // A limit order is priced further through the opposite best than the book allows.
+    PriceProtection { ticks_through: u64, max_ticks: u64 },
+    // A cross is priced outside the best bid and ask.
+    CrossOutsideSpread,
+    DuplicateOrder,
+    // A post-only order would have crossed the spread.
+    PostOnly,
+    // The account holds `available` of the asset but `required` is needed.
+    InsufficientFunds { currency: Currency, required: u64, available: u64 },
+}
+
+impl OrderbookError {
+    pub fn code(&self) -> RejectCode {
+        match self {
+            OrderbookError::InvalidOrder(_) | OrderbookError::InvalidTick { .. } => RejectCode::InvalidOrder,
+            OrderbookError::UnknownOrder(_) => RejectCode::UnknownOrder,
+            OrderbookError::UnknownMarket { .. } => RejectCode::UnknownMarket,
+            OrderbookError::InsufficientLiquidity { .. } => RejectCode::InsufficientLiquidity,
+            OrderbookError::SlippageExceeded => RejectCode::RiskLimitExceeded,
+            OrderbookError::PriceProtection { .. } | OrderbookError::CrossOutsideSpread => RejectCode::PriceProtection,
+            OrderbookError::DuplicateOrder => RejectCode::DuplicateOrder,
+            OrderbookError::PostOnly => RejectCode::PostOnly,
+            OrderbookError::InsufficientFunds { .. } => RejectCode::InsufficientFunds,
+        }
+    }
+}
+
+impl fmt::Display for OrderbookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderbookError::InvalidOrder(reason) => write!(f, "{}", reason),
+            OrderbookError::InvalidTick { nearest_valid: Some((below, above)), .. } => {
+                write!(f, "Limit price is off the tick size grid, nearest valid ticks are {} and {}", below, above)
+            }
+            OrderbookError::InvalidTick { tick_id, nearest_valid: None } => write!(f, "Tick {} is outside the book's range", tick_id),
+            OrderbookError::UnknownOrder(order_id) => write!(f, "No resting order {}", order_id),
+            OrderbookError::UnknownMarket { book_id, checked_out: true } => write!(f, "Book {} is checked out", book_id),
+            OrderbookError::UnknownMarket { book_id, checked_out: false } => write!(f, "No market with book id {}", book_id),
+            OrderbookError::InsufficientLiquidity { filled, requested } => {
+                write!(f, "All-or-none order would only fill {} of {}", filled, requested)
+            }
+            OrderbookError::SlippageExceeded => write!(f, "Market order exceeds maximum slippage"),
+            OrderbookError::PriceProtection { ticks_through, max_ticks } => {
+                write!(f, "Limit order is priced {} ticks through the opposite best (max {})", ticks_through, max_ticks)
+            }
+            OrderbookError::CrossOutsideSpread => write!(f, "Cross price is outside the best bid and ask"),
+            OrderbookError::DuplicateOrder => write!(f, "Identical order was sent moments ago"),
+            OrderbookError::PostOnly => write!(f, "Post-only order would cross the spread and take liquidity"),
+            OrderbookError::InsufficientFunds { .. } => write!(f, "Insufficient funds"),
+        }
+    }
+}
+
+impl Error for OrderbookError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_carry_codes_and_reasons() {
+        let error = OrderbookError::InsufficientFunds { currency: Currency::USD, required: 50, available: 20 };
+        assert_eq!(error.code(), RejectCode::InsufficientFunds);
+        assert_eq!(error.to_string(), "Insufficient funds");
+
+        let error = OrderbookError::InvalidTick { tick_id: 1015, nearest_valid: Some((1010, 1020)) };
+        assert_eq!(error.code(), RejectCode::InvalidOrder);
+        assert!(error.to_string().contains("1010 and 1020"));
+        assert_eq!(OrderbookError::CrossOutsideSpread.code(), RejectCode::PriceProtection);
+
+        // Cancels and amends both look orders up, so the reason doesn't name either
+        assert_eq!(OrderbookError::UnknownOrder(7).to_string(), "No resting order 7");
+    }
+}
@@ -0,0 +1,182 @@
+use super::order::Order;
+use super::orderbook::Orderbook;
+use super::error::OrderbookError;
+use crate::bank::currency::Currency;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Instant;
+
+// A book's pair as (base asset, quote asset), e.g. OSMO/USD is (USD, OSMO).
+pub type Pair = (Currency, Currency);
+
+// Owns every market's book, keyed by book id, and routes orders to them by `Order::book_id`. Order ids are handed
+// out here rather than per book so they stay unique across markets.
+//
+// A book can be checked out to be driven directly (the app does this with the market it shows) and checked back in
+// later. It stays listed while it is out, but orders can't be routed to it until it is back.
+#[derive(Debug)]
+pub struct Exchange {
+    books: BTreeMap<u64, Orderbook>,
+    // every listed market, including books currently checked out
+    pairs: BTreeMap<u64, Pair>,
+    next_book_id: u64,
+    // starts at 1 so no allocated id is confused with the 0 orders are built with before routing
+    next_order_id: u64,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Exchange {
+            books: BTreeMap::new(),
+            pairs: BTreeMap::new(),
+            next_book_id: 0,
+            next_order_id: 1,
+        }
+    }
+}
+
+impl Exchange {
+    pub fn new() -> Exchange {
+        Exchange::default()
+    }
+
+    // Lists a new market trading `quote_asset` priced in `base_asset`, returning its book id. Each pair is listed once.
+    pub fn create_market(&mut self, base_asset: Currency, quote_asset: Currency) -> Result<u64, Box<dyn Error>> {
+        if let Some(book_id) = self.find_market((base_asset, quote_asset)) {
+            return Err(format!("{} is already listed as book {}", pair_name((base_asset, quote_asset)), book_id).into());
+        }
+
+        let book = Orderbook::new_pair(self.next_book_id, base_asset, quote_asset)?;
+        self.next_book_id += 1;
+        self.pairs.insert(*book.book_id(), (base_asset, quote_asset));
+        self.books.insert(*book.book_id(), book);
+        Ok(self.next_book_id - 1)
+    }
+
+    // (book id, pair) of every listed market, by book id.
+    pub fn markets(&self) -> Vec<(u64, Pair)> {
+        self.pairs.iter().map(|(book_id, pair)| (*book_id, *pair)).collect()
+    }
+
+    pub fn find_market(&self, pair: Pair) -> Option<u64> {
+        self.pairs.iter().find(|(_, listed)| **listed == pair).map(|(book_id, _)| *book_id)
+    }
+
+    // None if the market isn't listed or its book is checked out.
+    pub fn book(&self, book_id: u64) -> Option<&Orderbook> {
+        self.books.get(&book_id)
+    }
+
+    pub fn book_mut(&mut self, book_id: u64) -> Option<&mut Orderbook> {
+        self.books.get_mut(&book_id)
+    }
+
+    pub fn allocate_order_id(&mut self) -> u64 {
+        self.next_order_id += 1;
+        self.next_order_id - 1
+    }
+
+    // Gives the order a fresh id and places it on the book its book_id names.
+    pub fn handle_order(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
+        let book_id = *order.book_id();
+        let book = match self.books.get_mut(&book_id) {
+            Some(book) => book,
+            None => return Err(OrderbookError::UnknownMarket { book_id, checked_out: self.pairs.contains_key(&book_id) }),
+        };
+
+        order.set_order_id(self.next_order_id);
+        self.next_order_id += 1;
+        book.handle_order(order)
+    }
+
+    // Runs `Orderbook::expire_orders` on every book that is checked in, returning the expired orders by book id.
+    pub fn expire_orders(&mut self, now: Instant) -> Vec<Order> {
+        self.books.values_mut().flat_map(|book| book.expire_orders(now)).collect()
+    }
+
+    // Takes a listed book out of the exchange to be driven directly.
+    pub fn checkout(&mut self, book_id: u64) -> Option<Orderbook> {
+        self.books.remove(&book_id)
+    }
+
+    // Returns a book taken out with checkout.
+    pub fn checkin(&mut self, book: Orderbook) -> Result<(), Box<dyn Error>> {
+        match self.pairs.get(book.book_id()) {
+            Some(_) if self.books.contains_key(book.book_id()) => Err(format!("Book {} is already checked in", book.book_id()).into()),
+            Some(_) => {
+                self.books.insert(*book.book_id(), book);
+                Ok(())
+            }
+            None => Err(format!("Book {} was never listed here", book.book_id()).into()),
+        }
+    }
+}
+
+// "OSMO/USD": the traded asset, then the asset it is priced in.
+pub fn pair_name((base_asset, quote_asset): Pair) -> String {
+    format!("{}/{}", quote_asset, base_asset)
+}
+
+// "osmo/usd" to (USD, OSMO), case insensitive.
+pub fn parse_pair(name: &str) -> Option<Pair> {
+    let (quote_asset, base_asset) = name.split_once('/')?;
+    Some((Currency::from_str(&base_asset.to_uppercase()).ok()?, Currency::from_str(&quote_asset.to_uppercase()).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::order::{OrderDirection, OrderType};
+    use crate::book::rejection::RejectCode;
+    use crate::testkit::AccountBuilder;
+
+    #[test]
+    fn test_routes_orders_by_book_id() {
+        let mut exchange = Exchange::new();
+        let osmo_usd = exchange.create_market(Currency::USD, Currency::OSMO).unwrap();
+        let usd_osmo = exchange.create_market(Currency::OSMO, Currency::USD).unwrap();
+        assert_eq!(exchange.markets(), vec![(0, (Currency::USD, Currency::OSMO)), (1, (Currency::OSMO, Currency::USD))]);
+        assert!(exchange.create_market(Currency::USD, Currency::OSMO).is_err());
+        assert!(exchange.create_market(Currency::USD, Currency::USD).is_err());
+
+        let maker = AccountBuilder::funded(1, &[(Currency::USD, 100), (Currency::OSMO, 100)]).build();
+        let mut ask = Order::new(0, 12, osmo_usd, maker.clone(), OrderType::Limit, OrderDirection::Ask, 10);
+        let mut bid = Order::new(0, 8, usd_osmo, maker.clone(), OrderType::Limit, OrderDirection::Bid, 10);
+        exchange.handle_order(&mut ask).unwrap();
+        exchange.handle_order(&mut bid).unwrap();
+
+        // Ids are unique across the two books, and each order escrowed its own book's assets
+        assert_eq!((*ask.order_id(), *bid.order_id()), (1, 2));
+        assert_eq!(exchange.book(osmo_usd).unwrap().snapshot().asks(), &vec![(12, 10)]);
+        assert_eq!(exchange.book(usd_osmo).unwrap().snapshot().bids(), &vec![(8, 10)]);
+        assert_eq!((maker.borrow().balance(Currency::OSMO), maker.borrow().balance(Currency::USD)), (100 - 10 - 8, 100));
+
+        let mut stray = Order::new(0, 12, 7, maker, OrderType::Limit, OrderDirection::Ask, 10);
+        assert_eq!(exchange.handle_order(&mut stray).unwrap_err(), OrderbookError::UnknownMarket { book_id: 7, checked_out: false });
+        assert_eq!(OrderbookError::UnknownMarket { book_id: 7, checked_out: false }.code(), RejectCode::UnknownMarket);
+    }
+
+    #[test]
+    fn test_checked_out_books_stay_listed() {
+        let mut exchange = Exchange::new();
+        let book_id = exchange.create_market(Currency::USD, Currency::OSMO).unwrap();
+        let book = exchange.checkout(book_id).unwrap();
+
+        assert!(exchange.book(book_id).is_none());
+        assert_eq!(exchange.find_market((Currency::USD, Currency::OSMO)), Some(book_id));
+        assert!(exchange.create_market(Currency::USD, Currency::OSMO).is_err());
+        assert!(exchange.checkin(Orderbook::new(5)).is_err());
+
+        exchange.checkin(book).unwrap();
+        assert!(exchange.book(book_id).is_some());
+    }
+
+    #[test]
+    fn test_pair_names() {
+        assert_eq!(parse_pair("osmo/USD"), Some((Currency::USD, Currency::OSMO)));
+        assert_eq!(pair_name((Currency::OSMO, Currency::USD)), "USD/OSMO");
+        assert_eq!(parse_pair("OSMO-USD"), None);
+        assert_eq!(parse_pair("BTC/USD"), None);
+    }
+}
@@ -0,0 +1,174 @@
+use super::order::OrderDirection;
+use super::tick_converter::TickConverter;
+use super::trade::Trade;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// Trade blotter in a FIX-like tag=value format, so tools that read exchange execution reports can consume it.
+// Every trade becomes two ExecutionReport messages (35=8, ExecType 150=F), one per side, written one message per
+// line with fields separated by '|' instead of SOH. Header trailers (BodyLength, CheckSum) are left out.
+//
+//   8   BeginString          always FIX.4.4
+//   35  MsgType              always 8 (ExecutionReport)
+//   17  ExecID               "<trade id>-T" for the taker's report, "<trade id>-M" for the maker's
+//   37  OrderID              id of the order on this side
+//   1   Account              account id on this side, or its pseudonym ("P1", "P2", ...) in anonymized blotters
+//   55  Symbol               e.g. OSMO/USD
+//   54  Side                 1 = buy, 2 = sell
+//   150 ExecType             always F (trade)
+//   32  LastQty              quantity filled, in the quote asset
+//   31  LastPx               fill price, as the book's tick converter writes it
+//   851 LastLiquidityInd     1 = added liquidity (maker), 2 = removed liquidity (taker)
+const FIELD_SEPARATOR: char = '|';
+
+// Stand-ins for account ids in a blotter that is shared outside the session, e.g. a simulation's trades handed out for
+// teaching, so the participants can be told apart without revealing which account ran which strategy. Accounts are
+// numbered P1, P2, ... in the order they first appear, and an account keeps its pseudonym for as long as the same
+// AccountPseudonyms is used.
+#[derive(Clone, Debug, Default)]
+pub struct AccountPseudonyms {
+    assigned: HashMap<u64, usize>,
+}
+
+impl AccountPseudonyms {
+    pub fn new() -> Self {
+        AccountPseudonyms::default()
+    }
+
+    // The account's pseudonym, assigning it the next free one if it has none yet.
+    pub fn pseudonym(&mut self, account_id: u64) -> String {
+        let next = self.assigned.len() + 1;
+        format!("P{}", self.assigned.entry(account_id).or_insert(next))
+    }
+
+    // The account's pseudonym if it was assigned one.
+    pub fn get(&self, account_id: u64) -> Option<String> {
+        self.assigned.get(&account_id).map(|number| format!("P{}", number))
+    }
+
+    pub fn len(&self) -> usize {
+        self.assigned.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assigned.is_empty()
+    }
+}
+
+// The taker's and the maker's report for a trade, in that order.
+pub fn execution_reports(trade: &Trade, symbol: &str, converter: &TickConverter) -> [String; 2] {
+    let [taker, maker] = [*trade.taker_account_id(), *trade.maker_account_id()].map(|account_id| account_id.to_string());
+    reports(trade, symbol, converter, taker, maker)
+}
+
+// Same as `execution_reports`, with both accounts replaced by their pseudonyms.
+pub fn anonymized_execution_reports(trade: &Trade, symbol: &str, converter: &TickConverter, pseudonyms: &mut AccountPseudonyms) -> [String; 2] {
+    let taker = pseudonyms.pseudonym(*trade.taker_account_id());
+    let maker = pseudonyms.pseudonym(*trade.maker_account_id());
+    reports(trade, symbol, converter, taker, maker)
+}
+
+// Writes the reports of every trade, in the order given, one message per line. Account ids are replaced by their
+// pseudonyms when `pseudonyms` is given.
+pub fn write_execution_reports<'a>(
+    trades: impl IntoIterator<Item = &'a Trade>,
+    symbol: &str,
+    converter: &TickConverter,
+    mut pseudonyms: Option<&mut AccountPseudonyms>,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    for trade in trades {
+        let reports = match pseudonyms.as_deref_mut() {
+            Some(pseudonyms) => anonymized_execution_reports(trade, symbol, converter, pseudonyms),
+            None => execution_reports(trade, symbol, converter),
+        };
+        for report in reports {
+            writeln!(writer, "{}", report)?;
+        }
+    }
+    Ok(())
+}
+
+fn reports(trade: &Trade, symbol: &str, converter: &TickConverter, taker_account: String, maker_account: String) -> [String; 2] {
+    let maker_direction = match trade.aggressor() {
+        OrderDirection::Bid => OrderDirection::Ask,
+        OrderDirection::Ask => OrderDirection::Bid,
+    };
+    let price = converter.format_tick(*trade.tick_id(), None);
+    [
+        report(trade, symbol, &price, 'T', *trade.taker_order_id(), taker_account, *trade.aggressor(), 2),
+        report(trade, symbol, &price, 'M', *trade.maker_order_id(), maker_account, maker_direction, 1),
+    ]
+}
+
+#[allow(clippy::too_many_arguments)]
+fn report(trade: &Trade, symbol: &str, price: &str, role: char, order_id: u64, account: String, direction: OrderDirection, liquidity: u8) -> String {
+    let side = match direction {
+        OrderDirection::Bid => 1,
+        OrderDirection::Ask => 2,
+    };
+    let fields = [
+        (8, "FIX.4.4".to_string()),
+        (35, "8".to_string()),
+        (17, format!("{}-{}", trade.trade_id(), role)),
+        (37, order_id.to_string()),
+        (1, account),
+        (55, symbol.to_string()),
+        (54, side.to_string()),
+        (150, "F".to_string()),
+        (32, trade.quantity().to_string()),
+        (31, price.to_string()),
+        (851, liquidity.to_string()),
+    ];
+    fields.iter()
+        .map(|(tag, value)| format!("{}={}", tag, value))
+        .collect::<Vec<_>>()
+        .join(&FIELD_SEPARATOR.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_reports_for_both_sides() {
+        let trade = Trade::new(7, 12, 40, OrderDirection::Bid, 1, 21, 2, 5, Instant::now());
+        let [taker, maker] = execution_reports(&trade, "OSMO/USD", &TickConverter::default());
+
+        assert_eq!(taker, "8=FIX.4.4|35=8|17=7-T|37=21|1=1|55=OSMO/USD|54=1|150=F|32=40|31=1.2|851=2");
+        assert_eq!(maker, "8=FIX.4.4|35=8|17=7-M|37=5|1=2|55=OSMO/USD|54=2|150=F|32=40|31=1.2|851=1");
+
+        let mut blotter = Vec::new();
+        write_execution_reports(&[trade, trade], "OSMO/USD", &TickConverter::default(), None, &mut blotter).unwrap();
+        assert_eq!(String::from_utf8(blotter).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn test_reports_price_with_the_book_tick_size() {
+        // Tick 13 is 0.65 in steps of 0.05, not the 1.3 it would be in tenths
+        let trade = Trade::new(7, 13, 40, OrderDirection::Bid, 1, 21, 2, 5, Instant::now());
+        let [taker, maker] = execution_reports(&trade, "OSMO/USD", &TickConverter::from_tick_size("0.05").unwrap());
+        assert!(taker.contains("|32=40|31=0.65|") && maker.contains("|31=0.65|"));
+    }
+
+    #[test]
+    fn test_pseudonyms_are_consistent_within_a_blotter() {
+        let trades = [
+            Trade::new(0, 12, 40, OrderDirection::Bid, 7, 21, 3, 5, Instant::now()),
+            Trade::new(1, 11, 10, OrderDirection::Ask, 3, 22, 9, 6, Instant::now()),
+            Trade::new(2, 12, 5, OrderDirection::Bid, 9, 23, 7, 7, Instant::now()),
+        ];
+        let mut pseudonyms = AccountPseudonyms::new();
+        let mut blotter = Vec::new();
+        write_execution_reports(&trades, "OSMO/USD", &TickConverter::default(), Some(&mut pseudonyms), &mut blotter).unwrap();
+        let blotter = String::from_utf8(blotter).unwrap();
+
+        let accounts: Vec<&str> = blotter.lines()
+            .map(|report| report.split('|').find_map(|field| field.strip_prefix("1=")).unwrap())
+            .collect();
+        assert_eq!(accounts, ["P1", "P2", "P2", "P3", "P3", "P1"]);
+        assert_eq!((pseudonyms.len(), pseudonyms.get(9), pseudonyms.get(4)), (3, Some("P3".to_string()), None));
+        assert!(blotter.lines().next().unwrap().contains("|17=0-T|37=21|1=P1|"));
+    }
+}
@@ -0,0 +1,149 @@
+use super::error::OrderbookError;
+use super::order::Order;
+use super::orderbook::Orderbook;
+
+// Where an order stands, as seen through its handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderStatus {
+    // Waiting on a stop, if-touched trigger or condition before it is placed.
+    Pending,
+    // On the book with `remaining()` left to fill.
+    Resting,
+    // Off the book: filled, cancelled, or done trading straight away without resting (market, IOC and FOK orders).
+    Closed,
+}
+
+/// One order on a book, for library code that would rather call methods on the order than pass its id and owner
+/// back to the book each time. The handle borrows the book mutably, so it is meant to be used and dropped; a new
+/// one for the same order can be taken at any time with `Orderbook::order_handle`. Order ids are assumed unique
+/// per owner, as the `Exchange` and the terminal UI allocate them.
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use orderbook::prelude::*;
+///
+/// let mut book = Orderbook::new(0);
+/// let maker = Rc::new(RefCell::new(Account::new(1, AccountType::Individual)));
+/// maker.borrow_mut().deposit(Currency::USD, 1000);
+///
+/// let mut handle = book.place(&mut Order::new(7, 10, 0, maker.clone(), OrderType::Limit, OrderDirection::Bid, 50)).unwrap();
+/// assert_eq!((handle.status(), handle.remaining()), (OrderStatus::Resting, 50));
+///
+/// handle.amend(20, 9).unwrap();
+/// // 20 at 0.9 USD
+/// assert_eq!(maker.borrow().balance(Currency::USD), 1000 - 18);
+/// handle.cancel().unwrap();
+/// assert_eq!(handle.status(), OrderStatus::Closed);
+/// assert_eq!(maker.borrow().balance(Currency::USD), 1000);
+/// ```
+pub struct OrderHandle<'a> {
+    book: &'a mut Orderbook,
+    order_id: u64,
+    account_id: u64,
+}
+
+impl<'a> OrderHandle<'a> {
+    pub fn new(book: &'a mut Orderbook, order_id: u64, account_id: u64) -> OrderHandle<'a> {
+        OrderHandle { book, order_id, account_id }
+    }
+
+    pub fn order_id(&self) -> u64 {
+        self.order_id
+    }
+
+    pub fn account_id(&self) -> u64 {
+        self.account_id
+    }
+
+    pub fn status(&self) -> OrderStatus {
+        if self.book.resting_order(self.order_id, self.account_id).is_some() {
+            OrderStatus::Resting
+        } else if self.book.pending_order(self.order_id, self.account_id).is_some() {
+            OrderStatus::Pending
+        } else {
+            OrderStatus::Closed
+        }
+    }
+
+    // Quantity still to fill: what rests on the book, all of it while pending, and nothing once closed.
+    pub fn remaining(&self) -> u64 {
+        self.book.resting_order(self.order_id, self.account_id)
+            .or_else(|| self.book.pending_order(self.order_id, self.account_id))
+            .map_or(0, |order| *order.quantity())
+    }
+
+    // Takes the order off the book and refunds its escrow, see `Orderbook::cancel_order`.
+    pub fn cancel(&mut self) -> Result<Order, OrderbookError> {
+        self.book.cancel_order(self.order_id, self.account_id)
+    }
+
+    // Resizes or reprices the resting order, see `Orderbook::amend_order`.
+    pub fn amend(&mut self, new_quantity: u64, new_tick: u64) -> Result<Order, OrderbookError> {
+        self.book.amend_order(self.order_id, self.account_id, new_quantity, new_tick)
+    }
+}
+
+impl Orderbook {
+    // Handles the order like `handle_order` and returns a handle to it, whether it rests, waits on a trigger or
+    // already traded in full.
+    pub fn place(&mut self, order: &mut Order) -> Result<OrderHandle<'_>, OrderbookError> {
+        self.handle_order(order)?;
+        let account_id = *order.owner().borrow().account_id();
+        Ok(OrderHandle::new(self, *order.order_id(), account_id))
+    }
+
+    // Handle to an order placed earlier by the account.
+    pub fn order_handle(&mut self, order_id: u64, account_id: u64) -> OrderHandle<'_> {
+        OrderHandle::new(self, order_id, account_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+    use crate::book::order::OrderDirection;
+    use crate::book::rejection::RejectCode;
+    use crate::testkit::{limit_order, market_order, AccountBuilder, BookBuilder};
+
+    #[test]
+    fn test_handle_follows_order_through_its_life() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let mut bid = limit_order(&trader, OrderDirection::Bid, 10, 50);
+        bid.set_order_id(7);
+        assert_eq!(book.place(&mut bid).unwrap().status(), OrderStatus::Resting);
+
+        // Another account takes 30 of the 50, and the rest can be resized and cancelled through a fresh handle
+        let seller = AccountBuilder::funded(2, &[(Currency::OSMO, 100)]).build();
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 30)).unwrap();
+        let mut handle = book.order_handle(7, 1);
+        assert_eq!(handle.remaining(), 20);
+        assert_eq!(*handle.amend(15, 10).unwrap().quantity(), 20);
+        assert_eq!(handle.remaining(), 15);
+        assert_eq!(*handle.cancel().unwrap().quantity(), 15);
+        assert_eq!((handle.status(), handle.remaining()), (OrderStatus::Closed, 0));
+        assert_eq!(handle.cancel().unwrap_err().code(), RejectCode::UnknownOrder);
+
+        // Another account's handle to the same id sees nothing
+        assert_eq!(book.order_handle(7, 2).status(), OrderStatus::Closed);
+        // 30 bought at 1.0
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - 30);
+    }
+
+    #[test]
+    fn test_handle_to_pending_and_filled_orders() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+
+        let mut stop = market_order(&trader, OrderDirection::Bid, 5);
+        stop.set_order_id(8);
+        stop.set_trigger_tick(Some(15));
+        let handle = book.place(&mut stop).unwrap();
+        assert_eq!((handle.status(), handle.remaining()), (OrderStatus::Pending, 5));
+
+        let mut buy = market_order(&trader, OrderDirection::Bid, 10);
+        buy.set_order_id(9);
+        assert_eq!(book.place(&mut buy).unwrap().status(), OrderStatus::Closed);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::bank::currency::Currency;
+use super::order::OrderDirection;
+use super::orderbook::Orderbook;
+
+// Read-only view of a book with the pair turned around, pricing USD in OSMO instead of OSMO in USD.
+// A bid for OSMO is an ask for USD and the other way round, prices become 1 / price, and sizes are the USD the
+// level settles for (its notional, the same amount the book escrows), so either orientation can be shown
+// without redoing the conversion at every call site. Both come from the book's tick converter, so a level's size
+// times its price gives back its OSMO quantity.
+#[derive(Clone, Copy, Debug)]
+pub struct InvertedView<'a> {
+    book: &'a Orderbook,
+}
+
+impl<'a> InvertedView<'a> {
+    pub fn new(book: &'a Orderbook) -> InvertedView<'a> {
+        InvertedView { book }
+    }
+
+    pub fn quote_asset(&self) -> Currency {
+        *self.book.base_asset()
+    }
+
+    pub fn base_asset(&self) -> Currency {
+        *self.book.quote_asset()
+    }
+
+    // Side an order on the book is on in the inverted pair, and the other way round.
+    pub fn invert_direction(direction: OrderDirection) -> OrderDirection {
+        match direction {
+            OrderDirection::Bid => OrderDirection::Ask,
+            OrderDirection::Ask => OrderDirection::Bid,
+        }
+    }
+
+    // Price of a tick in the inverted pair, in OSMO per USD at the book's tick size. None for tick 0, which has no
+    // inverse.
+    pub fn price(&self, tick_id: u64) -> Option<f64> {
+        (tick_id > 0).then(|| 1.0 / self.book.tick_converter().tick_to_price(tick_id))
+    }
+
+    // (price, USD quantity) levels bidding for USD, best first. These are the book's asks.
+    pub fn bids(&self) -> Vec<(f64, u64)> {
+        self.invert_levels(self.book.snapshot().asks())
+    }
+
+    // (price, USD quantity) levels offering USD, best first. These are the book's bids.
+    pub fn asks(&self) -> Vec<(f64, u64)> {
+        self.invert_levels(self.book.snapshot().bids())
+    }
+
+    pub fn best_bid(&self) -> Option<f64> {
+        self.bids().first().map(|(price, _)| *price)
+    }
+
+    pub fn best_ask(&self) -> Option<f64> {
+        self.asks().first().map(|(price, _)| *price)
+    }
+
+    fn invert_levels(&self, levels: &[(u64, u64)]) -> Vec<(f64, u64)> {
+        let converter = self.book.tick_converter();
+        levels.iter()
+            .filter_map(|(tick_id, quantity)| Some((self.price(*tick_id)?, converter.notional(*quantity, *tick_id))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tick_converter::TickConverter;
+    use crate::testkit::BookBuilder;
+
+    #[test]
+    fn test_inverted_levels() {
+        let book = BookBuilder::new().with_bids(&[(4, 100), (5, 10)]).with_asks(&[(8, 20), (10, 30)]).build();
+        let view = book.inverted_view();
+        assert_eq!(view.quote_asset(), Currency::USD);
+        assert_eq!(view.base_asset(), Currency::OSMO);
+
+        // The lowest OSMO ask is the highest USD bid: 1 / 0.8 OSMO per USD, for the 20 * 0.8 USD the level settles for
+        assert_eq!(view.bids(), vec![(1.25, 16), (1.0, 30)]);
+        assert_eq!(view.asks(), vec![(2.0, 5), (2.5, 40)]);
+        assert_eq!(view.best_bid(), Some(1.25));
+        assert_eq!(view.best_ask(), Some(2.0));
+        assert!(view.best_bid() < view.best_ask());
+
+        // USD size at the OSMO-per-USD price is the OSMO quantity the book holds there, at any tick size
+        let osmo = |levels: Vec<(f64, u64)>| levels.iter().map(|(price, size)| (*size as f64 * price).round() as u64).collect::<Vec<_>>();
+        assert_eq!((osmo(view.bids()), osmo(view.asks())), (vec![20, 30], vec![10, 100]));
+        let mut book = BookBuilder::new().with_bid(13, 100).with_ask(26, 40).build();
+        book.set_tick_converter(TickConverter::from_tick_size("0.05").unwrap());
+        let view = book.inverted_view();
+        assert_eq!((view.bids(), view.asks()), (vec![(1.0 / 1.3, 52)], vec![(1.0 / 0.65, 65)]));
+        assert_eq!((osmo(view.bids()), osmo(view.asks())), (vec![40], vec![100]));
+    }
+
+    #[test]
+    fn test_invert_direction_and_price() {
+        assert_eq!(InvertedView::invert_direction(OrderDirection::Bid), OrderDirection::Ask);
+        assert_eq!(InvertedView::invert_direction(OrderDirection::Ask), OrderDirection::Bid);
+        let mut book = BookBuilder::new().build();
+        assert_eq!(book.inverted_view().price(25), Some(0.4));
+        assert_eq!(book.inverted_view().price(0), None);
+
+        // Tick 25 is 1.25 in steps of 0.05, so it inverts to 0.8 OSMO per USD
+        book.set_tick_converter(TickConverter::from_tick_size("0.05").unwrap());
+        assert_eq!(book.inverted_view().price(25), Some(0.8));
+    }
+}
@@ -0,0 +1,200 @@
+use super::order::{Order, OrderDirection, OrderType};
+use super::orderbook::Orderbook;
+use super::tick_converter::TickConverter;
+use crate::bank::account::Account;
+use getset::Getters;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::error::Error;
+use std::io::Read;
+use std::rc::Rc;
+
+// Depth levels read from a level 2 snapshot in the shape most exchanges publish:
+//
+//   {"bids": [["1.20", "350"], ["1.10", "1200.5"]], "asks": [["1.30", "80"]]}
+//
+// Prices and quantities may be strings or numbers, and anything after them in a level (e.g. an order count) is
+// ignored. Prices are converted to ticks with the book's TickConverter and must fall on a tick. Quantities are whole
+// units of the quote asset here, so fractions are dropped and levels smaller than one unit are skipped.
+#[derive(Getters, Clone, Debug, Default, PartialEq, Eq)]
+pub struct L2Snapshot {
+    // (tick, quantity) of every level, in the order they were listed
+    #[get = "pub"]
+    bids: Vec<(u64, u64)>,
+    #[get = "pub"]
+    asks: Vec<(u64, u64)>,
+    // Levels dropped for being smaller than one unit.
+    #[get = "pub"]
+    skipped: usize,
+}
+
+impl L2Snapshot {
+    pub fn from_reader(reader: impl Read, converter: &TickConverter) -> Result<L2Snapshot, Box<dyn Error>> {
+        let snapshot: Value = serde_json::from_reader(reader)?;
+        let mut skipped = 0;
+        let mut side = |name: &str| -> Result<Vec<(u64, u64)>, Box<dyn Error>> {
+            let levels = match snapshot.get(name) {
+                Some(Value::Array(levels)) => levels,
+                Some(_) => return Err(format!("\"{}\" isn't a list of [price, quantity] levels", name).into()),
+                None => return Ok(Vec::new()),
+            };
+            let mut parsed = Vec::with_capacity(levels.len());
+            for (index, level) in levels.iter().enumerate() {
+                let invalid = || format!("{} level {} isn't a [price, quantity] pair", name, index + 1);
+                let (price, quantity) = match level.as_array().map(Vec::as_slice) {
+                    Some([price, quantity, ..]) => (number(price).ok_or_else(invalid)?, number(quantity).ok_or_else(invalid)?),
+                    _ => return Err(invalid().into()),
+                };
+                let tick_id = converter.price_to_tick(&price)
+                    .map_err(|_| format!("{} level {} is priced at {}, which isn't a multiple of the tick size {}", name, index + 1, price, converter.format_tick(1, None)))?;
+                match whole_units(&quantity) {
+                    Some(0) => skipped += 1,
+                    Some(quantity) => parsed.push((tick_id, quantity)),
+                    None => return Err(format!("{} level {} has an invalid quantity {}", name, index + 1, quantity).into()),
+                }
+            }
+            Ok(parsed)
+        };
+        let (bids, asks) = (side("bids")?, side("asks")?);
+        Ok(L2Snapshot { bids, asks, skipped })
+    }
+
+    pub fn best_bid(&self) -> Option<u64> {
+        self.bids.iter().map(|(tick_id, _)| *tick_id).max()
+    }
+
+    pub fn best_ask(&self) -> Option<u64> {
+        self.asks.iter().map(|(tick_id, _)| *tick_id).min()
+    }
+}
+
+// A price or quantity as written, whether it was sent as a string or a number.
+fn number(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.trim().to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// The whole part of a decimal quantity.
+fn whole_units(quantity: &str) -> Option<u64> {
+    let (whole, fraction) = quantity.split_once('.').unwrap_or((quantity, ""));
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) || whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    match whole {
+        "" => Some(0),
+        whole => whole.parse().ok(),
+    }
+}
+
+impl Orderbook {
+    // Rests every level of the snapshot on the book as a limit order owned by `account` (usually the one funding the
+    // liquidity bot), with ids from `next_order_id`. Returns the number of orders placed.
+    //
+    // Nothing is placed unless all of it can rest: the levels have to be in the book's price range, the snapshot
+    // can't be crossed or cross what already rests on the book, and the account has to hold the USD and OSMO the
+    // orders lock.
+    pub fn seed_from_l2(
+        &mut self,
+        snapshot: &L2Snapshot,
+        account: &Rc<RefCell<Account>>,
+        mut next_order_id: impl FnMut() -> u64,
+    ) -> Result<usize, Box<dyn Error>> {
+        let levels = snapshot.bids.iter().map(|level| (OrderDirection::Bid, level))
+            .chain(snapshot.asks.iter().map(|level| (OrderDirection::Ask, level)));
+        if let Some((_, (tick_id, _))) = levels.clone().find(|(_, (tick_id, _))| !self.tick_converter().contains(*tick_id)) {
+            return Err(format!("{} is outside the prices orders can use on this book", self.tick_converter().format_tick(*tick_id, None)).into());
+        }
+
+        let best_bid = snapshot.best_bid().max(self.best_bid());
+        let best_ask = [snapshot.best_ask(), self.best_ask()].into_iter().flatten().min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                let converter = self.tick_converter();
+                return Err(format!(
+                    "a bid at {} would cross an ask at {}",
+                    converter.format_tick(best_bid, None),
+                    converter.format_tick(best_ask, None),
+                ).into());
+            }
+        }
+
+        let bid_funds: u64 = snapshot.bids.iter().map(|(tick_id, quantity)| self.tick_converter().notional(*quantity, *tick_id)).sum();
+        let ask_funds: u64 = snapshot.asks.iter().map(|(_, quantity)| quantity).sum();
+        for (currency, needed) in [(*self.base_asset(), bid_funds), (*self.quote_asset(), ask_funds)] {
+            let held = account.borrow().balance(currency);
+            if held < needed {
+                return Err(format!("the orders lock {} {} but account {} holds {}", needed, currency, account.borrow().account_id(), held).into());
+            }
+        }
+
+        let mut placed = 0;
+        for (direction, (tick_id, quantity)) in levels {
+            let mut order = Order::new(next_order_id(), *tick_id, *self.book_id(), Rc::clone(account), OrderType::Limit, direction, *quantity);
+            self.handle_order(&mut order)?;
+            placed += 1;
+        }
+        Ok(placed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+    use crate::testkit::{limit_order, AccountBuilder};
+
+    const SNAPSHOT: &str = r#"{"bids": [["1.20", "350"], [1.1, 1200.5], ["1.00", "0.4"]], "asks": [["1.30", "80", 3], ["1.5", "20"]]}"#;
+
+    #[test]
+    fn test_parses_exchange_levels() {
+        let snapshot = L2Snapshot::from_reader(SNAPSHOT.as_bytes(), &TickConverter::default()).unwrap();
+        assert_eq!(*snapshot.bids(), vec![(12, 350), (11, 1200)]);
+        assert_eq!(*snapshot.asks(), vec![(13, 80), (15, 20)]);
+        assert_eq!((*snapshot.skipped(), snapshot.best_bid(), snapshot.best_ask()), (1, Some(12), Some(13)));
+
+        let error = L2Snapshot::from_reader(SNAPSHOT.as_bytes(), &TickConverter::from_tick_size("0.2").unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "bids level 2 is priced at 1.1, which isn't a multiple of the tick size 0.2");
+        assert!(L2Snapshot::from_reader(r#"{"bids": [["1.2"]]}"#.as_bytes(), &TickConverter::default()).is_err());
+        assert!(L2Snapshot::from_reader(r#"{"asks": [["1.2", "-3"]]}"#.as_bytes(), &TickConverter::default()).is_err());
+        assert_eq!(L2Snapshot::from_reader("{}".as_bytes(), &TickConverter::default()).unwrap(), L2Snapshot::default());
+    }
+
+    #[test]
+    fn test_seeds_resting_orders_for_the_account() {
+        let snapshot = L2Snapshot::from_reader(SNAPSHOT.as_bytes(), &TickConverter::default()).unwrap();
+        let mut book = Orderbook::new(0);
+        let liquidity = AccountBuilder::funded(1, &[(Currency::USD, 100_000), (Currency::OSMO, 100)]).build();
+        let mut next_order_id = 10..;
+        assert_eq!(book.seed_from_l2(&snapshot, &liquidity, || next_order_id.next().unwrap()).unwrap(), 4);
+
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(12), Some(13)));
+        assert_eq!(book.open_orders(1).len(), 4);
+        // 350 bid at 1.2 and 1200 at 1.1
+        assert_eq!(liquidity.borrow().balance(Currency::USD), 100_000 - 420 - 1320);
+        assert_eq!(liquidity.borrow().balance(Currency::OSMO), 0);
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_refuses_snapshots_that_cannot_all_rest() {
+        let snapshot = L2Snapshot::from_reader(SNAPSHOT.as_bytes(), &TickConverter::default()).unwrap();
+        let liquidity = AccountBuilder::funded(1, &[(Currency::USD, 100_000), (Currency::OSMO, 50)]).build();
+        let mut book = Orderbook::new(0);
+        let error = book.seed_from_l2(&snapshot, &liquidity, || 0).unwrap_err();
+        assert_eq!(error.to_string(), "the orders lock 100 OSMO but account 1 holds 50");
+
+        let trader = AccountBuilder::funded(2, &[(Currency::OSMO, 100)]).build();
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 12, 10)).unwrap();
+        let liquidity = AccountBuilder::funded(1, &[(Currency::USD, 100_000), (Currency::OSMO, 100)]).build();
+        let error = book.seed_from_l2(&snapshot, &liquidity, || 0).unwrap_err();
+        assert_eq!(error.to_string(), "a bid at 1.2 would cross an ask at 1.2");
+        assert!(book.open_orders(1).is_empty());
+
+        let mut book = Orderbook::new(0);
+        book.set_tick_converter(TickConverter::default().with_tick_range(1, 12));
+        assert!(book.seed_from_l2(&snapshot, &liquidity, || 0).unwrap_err().to_string().contains("1.3 is outside"));
+    }
+}
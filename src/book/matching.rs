@@ -0,0 +1,14 @@
+// How an incoming quantity is split between the resting orders of a single tick.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchingPolicy {
+    // Orders are filled one after the other in the order they were placed.
+    #[default]
+    Fifo,
+    // Every order receives a share proportional to its size. Shares smaller than `min_allocation` are
+    // dropped, and whatever is left over after rounding goes to the oldest orders first.
+    ProRata { min_allocation: u64 },
+    // Orders take turns in queue order, each giving up at most `slice` per turn, so one very large order can't
+    // soak up a level's whole flow. An order left with quantity after its turn moves to the back of the queue.
+    RoundRobin { slice: u64 },
+}
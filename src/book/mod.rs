@@ -1,4 +1,23 @@
+pub mod auction;
+pub mod conditional;
+pub mod error;
+pub mod exchange;
+pub mod execution_report;
+pub mod handle;
+pub mod inverted;
+#[cfg(feature = "serde")]
+pub mod l2_snapshot;
+pub mod matching;
 pub mod order;
+#[cfg(feature = "serde")]
+pub mod persist;
 pub mod orderbook;
 pub mod query;
+pub mod rejection;
+pub mod risk;
 pub mod tick;
+pub mod tick_converter;
+pub mod tick_size;
+pub mod toxicity;
+pub mod trade;
+pub mod volatility;
@@ -1,24 +1,72 @@
 use getset::Getters;
-use strum_macros::ToString;
-use crate::bank::account::Account;
+use strum_macros::Display;
+use crate::bank::account::{Account, AccountType};
 use crate::bank::currency::Currency;
+use super::conditional::BookCondition;
+use super::error::OrderbookError;
+use super::risk::SlippageGuard;
+use super::tick_converter::TickConverter;
 use std::cell::RefCell;
-use std::error::Error;
 use std::rc::Rc;
+use std::time::Instant;
 
-#[derive(PartialEq, Copy, Clone, Debug, ToString)]
+#[derive(PartialEq, Copy, Clone, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OrderType {
     Market,
     Limit,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug, ToString)]
+#[derive(PartialEq, Copy, Clone, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OrderDirection {
     Bid,
     Ask,
 }
 
+// How a trigger order reacts to the price: stops fire when it moves against the order (a buy stop once trades print
+// at or above the trigger), if-touched orders when it moves in the order's favor (a buy once trades print at or below).
+#[derive(PartialEq, Copy, Clone, Debug, Default, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TriggerType {
+    #[default]
+    Stop,
+    #[strum(serialize = "If-touched")]
+    IfTouched,
+}
+
+// How long a limit order's unfilled part stays on the book: good-til-cancelled rests it, immediate-or-cancel drops it,
+// and fill-or-kill only trades if the whole order fills straight away. Market orders never rest either way.
+#[derive(PartialEq, Copy, Clone, Debug, Default, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeInForce {
+    #[default]
+    GTC,
+    IOC,
+    FOK,
+}
+
+/// An instruction to buy (bid) or sell (ask) OSMO for USD, owned by an account.
+///
+/// Limit orders rest on `tick_id` (price * 10 at the default tick size, see `TickConverter`), market orders ignore it and take the best available prices.
+/// Quantities are always in OSMO:
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use orderbook::bank::account::{Account, AccountType};
+/// use orderbook::bank::currency::Currency;
+/// use orderbook::book::order::{Order, OrderDirection, OrderType};
+///
+/// let owner = Rc::new(RefCell::new(Account::new(0, AccountType::Individual)));
+/// let mut bid = Order::new(0, 25, 0, owner.clone(), OrderType::Limit, OrderDirection::Bid, 100);
+///
+/// // Filling 40 of the bid pays its owner 40 OSMO and leaves 60 on the order
+/// assert_eq!(bid.fill_order(40), 0);
+/// assert_eq!(*bid.quantity(), 60);
+/// assert_eq!(owner.borrow().balance(Currency::OSMO), 40);
+/// ```
 #[derive(Getters, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Order {
     #[get = "pub"]
     order_id: u64,
@@ -27,6 +75,7 @@ pub struct Order {
     #[get = "pub"]
     book_id: u64,
     #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "super::persist::owner_id"))]
     owner: Rc<RefCell<Account>>,
     #[get = "pub"]
     order_type: OrderType,
@@ -34,6 +83,63 @@ pub struct Order {
     order_direction: OrderDirection,
     #[get = "pub"]
     quantity: u64,
+    #[get = "pub"]
+    max_slippage: Option<SlippageGuard>,
+    // Instruction this order was generated from (e.g. an execution algo's parent order), if any.
+    #[get = "pub"]
+    parent_order_id: Option<u64>,
+    // Marks a limit order as intentionally aggressive so the book's price protection doesn't apply to it.
+    #[get = "pub"]
+    skip_price_protection: bool,
+    // Marks a limit order as maker-only: it is rejected rather than take liquidity if it would cross on arrival.
+    #[get = "pub"]
+    post_only: bool,
+    // Marks a market order as all-or-none: it is rejected untouched rather than fill partially when the book is too
+    // thin for its whole quantity.
+    #[get = "pub"]
+    strict: bool,
+    // Trigger: the order is held off the book until a trade prints at or through this tick, then runs as a normal
+    // market or limit order. Which way "through" is depends on the trigger type and the order's side.
+    #[get = "pub"]
+    trigger_tick: Option<u64>,
+    #[get = "pub"]
+    trigger_type: TriggerType,
+    #[get = "pub"]
+    time_in_force: TimeInForce,
+    // Book state the order waits for before it is placed, checked whenever the book changes.
+    #[get = "pub"]
+    condition: Option<BookCondition>,
+    // When the order was created. Resting orders keep it, so it also tells how long they have been queued.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "super::persist::instant_age"))]
+    placed_at: Instant,
+    // Pair of the book the order trades on, which the book sets when it handles the order: the quote asset is
+    // what the order buys or sells, the base asset what it pays or receives for it. OSMO/USD until then.
+    #[get = "pub"]
+    quote_asset: Currency,
+    #[get = "pub"]
+    base_asset: Currency,
+    // Price steps of the book the order is on, which it is worth at its tick by (see `worth`). The book sets it
+    // along with the pair; the default tenths until then.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(default))]
+    tick_converter: TickConverter,
+    // Account of the book the order is on, which the order's locked funds and fill proceeds pass through. The book
+    // sets it when it handles the order; until then fills are paid straight to the owner.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    escrow: Option<Rc<RefCell<Account>>>,
+    // Iceberg limit orders only show this much of their quantity at a time; the rest is a hidden reserve that
+    // refills the displayed slice whenever it is used up. None shows the whole order.
+    #[get = "pub"]
+    display_quantity: Option<u64>,
+    // What is left of the displayed slice, always the whole quantity for orders without a display quantity.
+    visible_quantity: u64,
+    // Good-till-date: once this passes, the book's `expire_orders` sweep cancels the order if it rests. None keeps
+    // it until it fills or is cancelled.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(default, with = "super::persist::instant_remaining"))]
+    expires_at: Option<Instant>,
 }
 
 impl Order {
@@ -47,73 +153,246 @@ impl Order {
         quantity: u64,
     ) -> Order {
         Order {
-            order_id: order_id,
-            tick_id: tick_id,
-            book_id: book_id,
-            owner: owner,
-            order_type: order_type,
-            order_direction: order_direction,
-            quantity: quantity,
+            order_id,
+            tick_id,
+            book_id,
+            owner,
+            order_type,
+            order_direction,
+            quantity,
+            max_slippage: None,
+            parent_order_id: None,
+            skip_price_protection: false,
+            post_only: false,
+            strict: false,
+            trigger_tick: None,
+            trigger_type: TriggerType::Stop,
+            time_in_force: TimeInForce::GTC,
+            condition: None,
+            placed_at: Instant::now(),
+            quote_asset: Currency::OSMO,
+            base_asset: Currency::USD,
+            tick_converter: TickConverter::default(),
+            escrow: None,
+            display_quantity: None,
+            visible_quantity: quantity,
+            expires_at: None,
         }
     }
 
     // implement public function for filling an order. Should return the remaining amount of the input quantity.
     pub fn fill_order(&mut self, fill_quantity: u64) -> u64 {
-        let mut remaining_quantity = fill_quantity;
-        if self.quantity > fill_quantity {
+        let worth_before = self.worth(self.quantity);
+        self.visible_quantity = self.visible_quantity.saturating_sub(fill_quantity);
+        let remaining_quantity = if self.quantity > fill_quantity {
             self.quantity -= fill_quantity;
-            remaining_quantity = 0;
+            0
         } else {
-            remaining_quantity = fill_quantity - self.quantity;
+            let remaining_quantity = fill_quantity - self.quantity;
             self.quantity = 0;
-        }
+            remaining_quantity
+        };
 
         // If order was a bid, this means osmo was bought, so we need to update owner's balance with just quantity osmo.
-        // If it was an ask, this means osmo was sold, so we need to update owner's balance with just quantity * price (tick).
+        // If it was an ask, this means osmo was sold, so the owner gets what the fill took off the order's worth.
         let amount_filled = fill_quantity - remaining_quantity;
-        let price_per_sold_unit = self.tick_id;
-        self.distribute_filled_assets(amount_filled, price_per_sold_unit);
+        let proceeds = worth_before - self.worth(self.quantity);
+        self.distribute_filled_assets(amount_filled, proceeds);
 
-        return remaining_quantity;
+        remaining_quantity
+    }
+
+    pub fn set_order_id(&mut self, order_id: u64) {
+        self.order_id = order_id;
     }
 
     pub fn set_quantity(&mut self, quantity: u64) {
         self.quantity = quantity;
+        self.visible_quantity = match self.display_quantity {
+            Some(_) => self.visible_quantity.min(quantity),
+            None => quantity,
+        };
+    }
+
+    // Makes the order an iceberg showing `display_quantity` at a time, or shows all of it again with None.
+    pub fn set_display_quantity(&mut self, display_quantity: Option<u64>) {
+        self.display_quantity = display_quantity;
+        self.show_next_slice();
+    }
+
+    // Quantity the book shows for the order: the current slice of an iceberg, otherwise all of it.
+    pub fn visible_quantity(&self) -> u64 {
+        self.visible_quantity
+    }
+
+    // Starts a new displayed slice from what is left of the order, e.g. when it joins a queue or its slice has
+    // been filled.
+    pub fn show_next_slice(&mut self) {
+        self.visible_quantity = self.display_quantity.map_or(self.quantity, |display| display.min(self.quantity));
+    }
+
+    pub fn set_tick_id(&mut self, tick_id: u64) {
+        self.tick_id = tick_id;
+    }
+
+    // Overrides the owner's account-level slippage guard for this order.
+    pub fn set_max_slippage(&mut self, max_slippage: Option<SlippageGuard>) {
+        self.max_slippage = max_slippage;
+    }
+
+    // Links this order to the instruction that generated it. The link is kept on the resting copy in the book.
+    pub fn set_parent_order_id(&mut self, parent_order_id: Option<u64>) {
+        self.parent_order_id = parent_order_id;
+    }
+
+    pub fn set_skip_price_protection(&mut self, skip_price_protection: bool) {
+        self.skip_price_protection = skip_price_protection;
+    }
+
+    pub fn set_post_only(&mut self, post_only: bool) {
+        self.post_only = post_only;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_trigger_tick(&mut self, trigger_tick: Option<u64>) {
+        self.trigger_tick = trigger_tick;
     }
 
-    // Send order owner the appropriate amount of filled assets depending on their original order.
-    pub fn distribute_filled_assets(&mut self, amount_filled: u64, price_per_filled_unit: u64) {
+    pub fn set_trigger_type(&mut self, trigger_type: TriggerType) {
+        self.trigger_type = trigger_type;
+    }
+
+    pub fn set_time_in_force(&mut self, time_in_force: TimeInForce) {
+        self.time_in_force = time_in_force;
+    }
+
+    pub fn set_expires_at(&mut self, expires_at: Option<Instant>) {
+        self.expires_at = expires_at;
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn set_assets(&mut self, base_asset: Currency, quote_asset: Currency) {
+        self.base_asset = base_asset;
+        self.quote_asset = quote_asset;
+    }
+
+    pub fn set_tick_converter(&mut self, tick_converter: TickConverter) {
+        self.tick_converter = tick_converter;
+    }
+
+    // What `quantity` of the order is worth at its own tick, i.e. what a bid locks for it. Rounding makes worth
+    // not quite proportional to quantity, so fills and partial refunds move the change in the order's worth rather
+    // than the worth of the part taken off: what a bid has locked is then always the worth of what is left.
+    pub fn worth(&self, quantity: u64) -> u64 {
+        self.tick_converter.notional(quantity, self.tick_id)
+    }
+
+    // Copy of the order whose fills pay nobody, for playing fills out ahead of time.
+    pub(crate) fn detached(&self) -> Order {
+        let account_id = *self.owner.borrow().account_id();
+        Order { owner: Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual))), escrow: None, ..self.clone() }
+    }
+
+    pub fn set_condition(&mut self, condition: Option<BookCondition>) {
+        self.condition = condition;
+    }
+
+    // Points a restored order back at its owner's shared account, see `Orderbook::restore_from_reader`.
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_owner(&mut self, owner: Rc<RefCell<Account>>) {
+        self.owner = owner;
+    }
+
+    pub fn set_escrow(&mut self, escrow: Rc<RefCell<Account>>) {
+        self.escrow = Some(escrow);
+    }
+
+    // Moves funds from the owner into the book's escrow.
+    fn lock(&self, currency: Currency, amount: u64) -> Result<(), OrderbookError> {
+        self.owner.borrow_mut().withdraw(currency, amount)?;
+        if let Some(escrow) = &self.escrow {
+            escrow.borrow_mut().deposit(currency, amount);
+        }
+        Ok(())
+    }
+
+    // Pays funds out of the book's escrow to the owner. The escrow always holds them: resting orders locked them
+    // when they were placed, and a taker locks its side of a sweep before any maker is paid.
+    pub(crate) fn release(&self, currency: Currency, amount: u64) {
+        if let Some(escrow) = &self.escrow {
+            escrow.borrow_mut().withdraw(currency, amount).expect("escrow holds every locked fund and fill proceed");
+        }
+        self.owner.borrow_mut().deposit(currency, amount);
+    }
+
+    // Send order owner the appropriate amount of filled assets depending on their original order: bids get the
+    // quantity bought, asks the proceeds of the quantity sold.
+    pub fn distribute_filled_assets(&mut self, amount_filled: u64, proceeds: u64) {
+        match self.order_direction {
+            OrderDirection::Bid => self.release(self.quote_asset, amount_filled),
+            OrderDirection::Ask => self.release(self.base_asset, proceeds),
+        }
+    }
+
+    // Settles a taker fill spanning several ticks in one go, where total_cost is what the fill took off the worth
+    // of the resting orders it filled. The taker's side goes into the escrow, where the makers are paid from, and
+    // nothing is paid out unless it can be locked.
+    pub fn settle_sweep(&mut self, filled_quantity: u64, total_cost: u64) -> Result<(), OrderbookError> {
         match self.order_direction {
             OrderDirection::Bid => {
-                self.owner.borrow_mut().deposit(Currency::OSMO, amount_filled);
+                self.lock(self.base_asset, total_cost)?;
+                self.release(self.quote_asset, filled_quantity);
             },
             OrderDirection::Ask => {
-                self.owner.borrow_mut().deposit(Currency::USD, amount_filled * price_per_filled_unit);
+                self.lock(self.quote_asset, filled_quantity)?;
+                self.release(self.base_asset, total_cost);
             },
         }
+        Ok(())
     }
 
     // withdraw_deposited assets is simple a mirrored version of distribute_filled_assets since it's for providing
     // the other side of the order.
-    pub fn withdraw_deposited_assets(&mut self, amount_filled: u64, price_per_filled_unit: u64) -> Result<(), Box<dyn Error>> {
+    pub fn withdraw_deposited_assets(&mut self, amount_filled: u64, price_per_filled_unit: u64) -> Result<(), OrderbookError> {
         match self.order_direction {
             OrderDirection::Bid => {
-                self.owner.borrow_mut().withdraw(Currency::USD, amount_filled * price_per_filled_unit)?;
+                self.lock(self.base_asset, self.tick_converter.notional(amount_filled, price_per_filled_unit))?;
             },
             OrderDirection::Ask => {
-                self.owner.borrow_mut().withdraw(Currency::OSMO, amount_filled)?;
+                self.lock(self.quote_asset, amount_filled)?;
             },
         }
         Ok(())
     }
-    
+
+    // Hands back what withdraw_deposited_assets took for the part of the order that never filled, e.g. on cancel.
+    pub fn refund_deposited_assets(&mut self, amount_unfilled: u64, price_per_unfilled_unit: u64) {
+        match self.order_direction {
+            OrderDirection::Bid => self.release(self.base_asset, self.tick_converter.notional(amount_unfilled, price_per_unfilled_unit)),
+            OrderDirection::Ask => self.release(self.quote_asset, amount_unfilled),
+        }
+    }
+
+    // Hands back what the order no longer locks once it shrinks from `from_quantity` to `to_quantity` at its tick.
+    pub fn refund_reduction(&mut self, from_quantity: u64, to_quantity: u64) {
+        match self.order_direction {
+            OrderDirection::Bid => self.release(self.base_asset, self.worth(from_quantity) - self.worth(to_quantity)),
+            OrderDirection::Ask => self.release(self.quote_asset, from_quantity - to_quantity),
+        }
+    }
 }
 
 // write unit tests for fill_order
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bank::account::AccountType;
+    use crate::testkit::AccountBuilder;
 
     #[test]
     fn test_fill_order_bid() {
@@ -121,7 +400,7 @@ mod tests {
             0,
             5,
             0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
+            AccountBuilder::new(0).build(),
             OrderType::Limit,
             OrderDirection::Bid,
             100,
@@ -156,7 +435,7 @@ mod tests {
             0,
             5,
             0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
+            AccountBuilder::new(0).build(),
             OrderType::Limit,
             OrderDirection::Ask,
             100,
@@ -180,7 +459,7 @@ mod tests {
         // Sanity check that the order quantity is still 0.
         assert_eq!(*order.quantity(), 0);
 
-        // Sanity check that the owner's balance is 500 USD since 100 OSMO was sold at tick 5 (5 USD per OSMO).
-        assert_eq!(order.owner.borrow_mut().balance(Currency::USD), 500);
+        // Sanity check that the owner's balance is 50 USD since 100 OSMO was sold at tick 5 (0.5 USD per OSMO).
+        assert_eq!(order.owner.borrow_mut().balance(Currency::USD), 50);
     }
 }
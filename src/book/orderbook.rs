@@ -1,61 +1,1044 @@
 use getset::Getters;
 
+use super::auction::{clearing_tick, AuctionResult};
+use super::matching::MatchingPolicy;
 use super::order::*;
+use super::risk::{DuplicatePolicy, RiskConfig, SlippageGuard, SlippageLimit, SlippagePolicy};
+use super::query::{DepthSnapshot, MemoryStats};
+use super::error::OrderbookError;
 use super::tick::Tick;
-use crate::bank::account::*;
+use super::tick_converter::TickConverter;
+use super::tick_size::TickSizeTable;
+use super::trade::Trade;
+use crate::bank::account::{Account, AccountType};
 use crate::bank::currency::*;
+use crate::storage::Storage;
+use std::cell::RefCell;
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::error::Error;
-use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+// Storage stream archived trades are appended to. Books other than book 0 suffix it with their id ("trades-1").
+pub const TRADES_STREAM: &str = "trades";
+
+// Fields that make two orders from the same account identical for duplicate detection: side, type, tick and quantity.
+type OrderFingerprint = (OrderDirection, OrderType, u64, u64);
+
+// Levels a sweep will consume, worked out before anything is mutated.
+#[derive(Default, Debug)]
+pub(crate) struct SweepPlan {
+    // (tick_id, quantity) taken from each level, best price first.
+    pub(crate) levels: Vec<(u64, u64)>,
+    pub(crate) filled_quantity: u64,
+    // What the consumed levels are worth (see Tick::fill_worth), i.e. the base asset exchanged.
+    pub(crate) total_cost: u64,
+    // Best non-empty tick left on the swept side afterwards (None if the side would be emptied).
+    pub(crate) next_tick: Option<u64>,
+}
+
+impl SweepPlan {
+    // How much of the planned fill can go through before the average price of an order of `quantity` passes
+    // notional / quantity per unit: buys may not pay more than that on average, sells may not get less. Levels come
+    // best price first, so the average only worsens along the sweep and the bound cuts it at one point. Prices are
+    // compared exactly, in units of the converter's last decimal, so the bound ignores the rounding of worth.
+    pub(crate) fn quantity_within_notional(&self, converter: &TickConverter, direction: OrderDirection, quantity: u64, notional: u64) -> u64 {
+        let scale = 10u128.pow(*converter.precision());
+        let (quantity, notional) = (u128::from(quantity), u128::from(notional) * scale);
+        let (mut filled, mut cost) = (0u128, 0u128);
+        for (tick_id, taken) in &self.levels {
+            let (price, taken) = (u128::from(*tick_id) * u128::from(*converter.tick_size()), u128::from(*taken));
+            // Filling q units in total keeps the average within the bound while (cost + (q - filled) * price) *
+            // quantity stays at or below notional * q for buys (at or above for sells)
+            let within_level = match direction {
+                OrderDirection::Bid if price * quantity > notional => {
+                    Some((filled * price - cost) * quantity / (price * quantity - notional))
+                }
+                OrderDirection::Ask if price * quantity < notional => {
+                    Some((cost - filled * price) * quantity / (notional - price * quantity))
+                }
+                _ => None,
+            };
+            match within_level {
+                Some(limit) => return limit.min(filled + taken) as u64,
+                None => {
+                    filled += taken;
+                    cost += taken * price;
+                }
+            }
+        }
+        filled as u64
+    }
+}
 
+/// Limit orderbook for the OSMO/USD pair, with prices expressed as tick ids (price * 10).
+///
+/// Resting limit orders escrow their owner's funds, and orders that cross the book fill immediately
+/// against the best prices on the other side:
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use orderbook::bank::account::{Account, AccountType};
+/// use orderbook::bank::currency::Currency;
+/// use orderbook::book::order::{Order, OrderDirection, OrderType};
+/// use orderbook::book::orderbook::Orderbook;
+///
+/// let mut book = Orderbook::new(0);
+///
+/// // A maker offers 100 OSMO at 1.2 USD and another 100 at 1.5 USD
+/// let maker = Rc::new(RefCell::new(Account::new(1, AccountType::Individual)));
+/// maker.borrow_mut().deposit(Currency::OSMO, 200);
+/// book.handle_order(&mut Order::new(0, 12, 0, maker.clone(), OrderType::Limit, OrderDirection::Ask, 100)).unwrap();
+/// book.handle_order(&mut Order::new(1, 15, 0, maker.clone(), OrderType::Limit, OrderDirection::Ask, 100)).unwrap();
+/// assert_eq!(maker.borrow().balance(Currency::OSMO), 0);
+///
+/// // A taker buys 150 OSMO at market, taking all of the first level and half of the second: 120 + 75 USD
+/// let taker = Rc::new(RefCell::new(Account::new(2, AccountType::Individual)));
+/// taker.borrow_mut().deposit(Currency::USD, 500);
+/// assert_eq!(*book.impact(150, OrderDirection::Bid).total_cost(), 120 + 75);
+/// book.handle_order(&mut Order::new(2, 0, 0, taker.clone(), OrderType::Market, OrderDirection::Bid, 150)).unwrap();
+///
+/// assert_eq!(taker.borrow().balance(Currency::OSMO), 150);
+/// assert_eq!(taker.borrow().balance(Currency::USD), 500 - 195);
+/// assert_eq!(maker.borrow().balance(Currency::USD), 195);
+/// assert_eq!(*book.ticks().get(&15).unwrap().total_liquidity(), 50);
+/// ```
 #[derive(Getters, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Orderbook {
+    #[get = "pub"]
     book_id: u64,
     #[get = "pub"]
     quote_asset: Currency,
     #[get = "pub"]
     base_asset: Currency,
+    #[get = "pub(crate)"]
     next_bid_tick: u64,
+    #[get = "pub(crate)"]
     next_ask_tick: u64,
     #[get = "pub"]
     ticks: BTreeMap<u64, Tick>,
+    // Tick each resting order id was placed on, for cancellation, so ids should be unique among resting orders.
+    // Entries go away when the order is filled or cancelled.
     cancellation_map: HashMap<u64, u64>,
+    // Ids of each account's resting orders, with how much of each has filled so far (including what it took on
+    // arrival, before it rested). Kept in step with cancellation_map.
+    open_orders: HashMap<u64, BTreeMap<u64, u64>>,
+    risk_configs: HashMap<u64, RiskConfig>,
+    // How fills are split between the orders resting on each tick.
+    #[get = "pub"]
+    matching_policy: MatchingPolicy,
+    // Furthest a limit order may be priced through the opposite best before it is rejected as a likely fat finger.
+    #[get = "pub"]
+    price_protection_ticks: Option<u64>,
+    // Rejects every market order the book can't fill in full, as if each was sent strict.
+    #[get = "pub"]
+    strict_market_orders: bool,
+    // Price increments limit orders must respect in each price band.
+    #[get = "pub"]
+    tick_sizes: TickSizeTable,
+    // How tick ids map to decimal prices, and the range of ticks orders can be priced at.
+    #[get = "pub"]
+    tick_converter: TickConverter,
+    // Orders seen within each account's duplicate window, oldest first.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    recent_orders: HashMap<u64, VecDeque<(Instant, OrderFingerprint)>>,
+    // Number of orders accepted despite matching a recent one, under the Flag duplicate policy.
+    #[get = "pub"]
+    flagged_duplicates: u64,
+    // Stop orders waiting for their trigger, keyed by trigger tick with FIFO order per level. Buy stops fire when
+    // trades print at or above the trigger, so they are activated from the lowest key up, sell stops from the highest down.
+    stop_bids: BTreeMap<u64, VecDeque<Order>>,
+    stop_asks: BTreeMap<u64, VecDeque<Order>>,
+    // If-touched orders, keyed the same way. They fire the other way round: buys when trades print at or below the
+    // trigger (activated from the highest key down), sells at or above it (from the lowest key up).
+    touch_bids: BTreeMap<u64, VecDeque<Order>>,
+    touch_asks: BTreeMap<u64, VecDeque<Order>>,
+    // Conditional orders waiting for their condition, oldest first.
+    conditional_orders: Vec<Order>,
+    // Set while a call auction collects orders (see start_auction). Limit orders are then held aside with their
+    // funds locked instead of matching, until run_auction crosses them all at once.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(default))]
+    collecting_auction: bool,
+    // Limit orders collected for the auction, oldest first.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(default))]
+    auction_orders: Vec<Order>,
+    // Number of conditional orders whose condition was met so far, and how many of those were rejected when they ran.
+    #[get = "pub"]
+    conditional_activations: u64,
+    #[get = "pub"]
+    failed_conditional_activations: u64,
+    // Fills kept in memory, oldest first. Older fills may have been moved to storage by archive_trades, so trade
+    // ids are positions in the full history: archived trades first, then this log.
+    #[get = "pub"]
+    trades: Vec<Trade>,
+    // Number of trades moved to storage so far.
+    #[get = "pub"]
+    archived_trades: u64,
+    // Reference point for the execution times of archived trades.
+    #[cfg_attr(feature = "serde", serde(with = "super::persist::instant_age"))]
+    created_at: Instant,
+    // Tick of the most recent fill, which stop triggers are compared against.
+    #[get = "pub"]
+    last_trade_tick: Option<u64>,
+    // Number of stop orders triggered so far, and how many of those were rejected when they ran (e.g. for insufficient funds).
+    #[get = "pub"]
+    stop_activations: u64,
+    #[get = "pub"]
+    failed_stop_activations: u64,
+    // Same for if-touched orders.
+    #[get = "pub"]
+    touch_activations: u64,
+    #[get = "pub"]
+    failed_touch_activations: u64,
+    // Monotonically increasing counter bumped on every mutation of the book's resting orders.
+    // Readers holding an older version know they have missed updates and should fetch fresh state.
+    #[get = "pub"]
+    version: u64,
+    // Most recently built depth snapshot, reused until the version moves on.
+    #[get = "pub(crate)"]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    latest_snapshot: RefCell<Option<Arc<DepthSnapshot>>>,
+    // Holds what resting orders have locked, and passes every fill's proceeds from one side to the other, so funds
+    // only ever move between accounts. Its account id is the book's id; the Orderbook type tells it apart from traders.
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "super::persist::shared_account"))]
+    escrow: Rc<RefCell<Account>>,
 }
 
 impl Orderbook {
+    // An OSMO/USD book: OSMO is traded and priced in USD.
     pub fn new(book_id: u64) -> Orderbook {
-        // Initialize min and max ticks in cancellation map.
-        // This is to track whether the book has run out of ticks when doing market orders.
-        let mut cancellation_map = HashMap::new();
-        cancellation_map.insert(u64::MIN, u64::MIN);
+        Orderbook::with_assets(book_id, Currency::USD, Currency::OSMO)
+    }
+
+    // A book trading `quote_asset` priced in `base_asset`, with the same tick ids (a tenth of the base asset per
+    // unit) as OSMO/USD. Matching and settlement only ever touch the book's own two assets.
+    pub fn new_pair(book_id: u64, base_asset: Currency, quote_asset: Currency) -> Result<Orderbook, Box<dyn Error>> {
+        if base_asset == quote_asset {
+            return Err(format!("A book can't trade {} against itself", base_asset).into());
+        }
+        Ok(Orderbook::with_assets(book_id, base_asset, quote_asset))
+    }
 
-        // We default to an OSMO/USD pair for now. This can be generalized to more assets later.
+    fn with_assets(book_id: u64, base_asset: Currency, quote_asset: Currency) -> Orderbook {
         Orderbook {
-            book_id: book_id,
-            quote_asset: Currency::OSMO,
-            base_asset: Currency::USD,
+            book_id,
+            quote_asset,
+            base_asset,
             next_bid_tick: u64::MIN,
             next_ask_tick: u64::MAX,
             ticks: BTreeMap::new(),
-            cancellation_map: cancellation_map,
+            cancellation_map: HashMap::new(),
+            open_orders: HashMap::new(),
+            risk_configs: HashMap::new(),
+            matching_policy: MatchingPolicy::default(),
+            price_protection_ticks: None,
+            strict_market_orders: false,
+            tick_sizes: TickSizeTable::new(),
+            tick_converter: TickConverter::default(),
+            recent_orders: HashMap::new(),
+            flagged_duplicates: 0,
+            stop_bids: BTreeMap::new(),
+            stop_asks: BTreeMap::new(),
+            touch_bids: BTreeMap::new(),
+            touch_asks: BTreeMap::new(),
+            conditional_orders: Vec::new(),
+            collecting_auction: false,
+            auction_orders: Vec::new(),
+            conditional_activations: 0,
+            failed_conditional_activations: 0,
+            trades: Vec::new(),
+            archived_trades: 0,
+            created_at: Instant::now(),
+            last_trade_tick: None,
+            stop_activations: 0,
+            failed_stop_activations: 0,
+            touch_activations: 0,
+            failed_touch_activations: 0,
+            version: 0,
+            latest_snapshot: RefCell::new(None),
+            escrow: Rc::new(RefCell::new(Account::new(book_id, AccountType::Orderbook))),
         }
     }
 
-    pub fn handle_order(&mut self, order: &mut Order) -> Result<(), Box<dyn Error>> {
-        match order.order_type() {
-            OrderType::Market => {
-                self.run_market_order(order)?
-            }
-            OrderType::Limit => {
-                self.run_partial_or_full_limit(order)?
+    pub fn handle_order(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
+        order.set_assets(self.base_asset, self.quote_asset);
+        order.set_tick_converter(self.tick_converter);
+        order.set_escrow(Rc::clone(&self.escrow));
+        self.validate_order(order)?;
+        self.check_duplicate(order, Instant::now())?;
+
+        self.dispatch_order(order)?;
+        self.activate_pending_orders();
+        Ok(())
+    }
+
+    // Parks conditional and trigger orders until they activate, and runs everything else straight away.
+    fn dispatch_order(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
+        if order.condition().is_some() {
+            self.conditional_orders.push(order.clone());
+        } else if let Some(trigger_tick) = *order.trigger_tick() {
+            let triggers = match (order.trigger_type(), order.order_direction()) {
+                (TriggerType::Stop, OrderDirection::Bid) => &mut self.stop_bids,
+                (TriggerType::Stop, OrderDirection::Ask) => &mut self.stop_asks,
+                (TriggerType::IfTouched, OrderDirection::Bid) => &mut self.touch_bids,
+                (TriggerType::IfTouched, OrderDirection::Ask) => &mut self.touch_asks,
+            };
+            triggers.entry(trigger_tick).or_default().push_back(order.clone());
+        } else if self.collecting_auction {
+            self.collect_for_auction(order)?
+        } else {
+            match order.order_type() {
+                OrderType::Market => {
+                    self.run_market_order(order)?
+                }
+                OrderType::Limit => {
+                    self.run_partial_or_full_limit(order)?
+                }
             }
         }
         Ok(())
     }
+
+    // Holds a limit order aside for the auction being collected, locking what it would lock resting on the book.
+    // Orders that can't rest have nothing to wait for, so market, IOC and FOK orders are refused, and post-only
+    // ones too since every collected order may end up taking.
+    fn collect_for_auction(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
+        if *order.order_type() == OrderType::Market || *order.time_in_force() != TimeInForce::GTC || *order.post_only() {
+            return Err(OrderbookError::InvalidOrder("Only limit orders that can rest join an auction, not market, IOC, FOK or post-only ones"));
+        }
+        order.set_escrow(Rc::clone(&self.escrow));
+        order.withdraw_deposited_assets(*order.quantity(), *order.tick_id())?;
+        self.auction_orders.push(order.clone());
+        self.version += 1;
+        Ok(())
+    }
+
+    // Opens a call auction's collection window: from now on limit orders are collected rather than matched, until
+    // run_auction. Orders already resting stay on the book and take part in the auction too.
+    pub fn start_auction(&mut self) {
+        self.collecting_auction = true;
+    }
+
+    // Closes the collection window and crosses the collected and resting orders at the single clearing tick that
+    // matches the most volume (see auction::clearing_tick). Bids fill from the highest price down and asks from the
+    // lowest up, resting orders before collected ones and then oldest first on each price. Every fill goes through
+    // at the clearing tick, so bids priced above it get the difference back. Collected orders that didn't fill in
+    // full rest on the book afterwards as if placed then, and they can't cross each other or the book: any pair
+    // still crossing would have matched more volume at a higher or lower tick. A book that only ever matched
+    // continuously has nothing crossed, so running an auction without collecting first just ends with no trades.
+    pub fn run_auction(&mut self) -> AuctionResult {
+        self.collecting_auction = false;
+        // Resting orders, ascending tick and queue order, then the collected ones in the order they arrived
+        let mut participants: Vec<(Order, bool)> = self.ticks.values()
+            .flat_map(|tick| tick.orders().iter().cloned())
+            .map(|order| (order, true))
+            .chain(std::mem::take(&mut self.auction_orders).into_iter().map(|order| (order, false)))
+            .collect();
+        let side = |direction| -> Vec<usize> {
+            (0..participants.len()).filter(|index| *participants[*index].0.order_direction() == direction).collect()
+        };
+        let (mut bids, mut asks) = (side(OrderDirection::Bid), side(OrderDirection::Ask));
+        // Stable sorts, so orders on the same tick keep the priority they were listed in
+        bids.sort_by_key(|index| Reverse(*participants[*index].0.tick_id()));
+        asks.sort_by_key(|index| *participants[*index].0.tick_id());
+
+        let levels = |indices: &[usize]| -> Vec<(u64, u64)> {
+            indices.iter().map(|index| (*participants[*index].0.tick_id(), *participants[*index].0.quantity())).collect()
+        };
+        let (clearing, matched_quantity) = match clearing_tick(&levels(&bids), &levels(&asks)) {
+            Some((tick_id, quantity)) => (Some(tick_id), quantity),
+            None => (None, 0),
+        };
+        let allocate = |indices: &[usize]| -> Vec<(usize, u64)> {
+            let mut left = matched_quantity;
+            indices.iter()
+                .map_while(|index| {
+                    let quantity = left.min(*participants[*index].0.quantity());
+                    left -= quantity;
+                    (quantity > 0).then_some((*index, quantity))
+                })
+                .collect()
+        };
+        let (bid_fills, ask_fills) = (allocate(&bids), allocate(&asks));
+
+        // Both sides fill the matched quantity, so walking them side by side pairs every bid fill with asks. The
+        // collected order of each pair is its taker: only collected orders can cross the resting book.
+        let first_trade = self.trades.len();
+        let executed_at = Instant::now();
+        let mut pending_asks = ask_fills.iter().copied();
+        let mut ask = pending_asks.next();
+        for (bid_index, mut bid_left) in bid_fills.iter().copied() {
+            while bid_left > 0 {
+                let (ask_index, ask_left) = ask.as_mut().expect("both sides fill the matched quantity");
+                let quantity = bid_left.min(*ask_left);
+                let (bid, bid_rests) = &participants[bid_index];
+                let (ask_order, _) = &participants[*ask_index];
+                let (aggressor, taker, maker) = match bid_rests {
+                    false => (OrderDirection::Bid, bid, ask_order),
+                    true => (OrderDirection::Ask, ask_order, bid),
+                };
+                self.trades.push(Trade::new(
+                    self.archived_trades + self.trades.len() as u64,
+                    clearing.expect("there is a clearing tick when anything matched"),
+                    quantity,
+                    aggressor,
+                    *taker.owner().borrow().account_id(),
+                    *taker.order_id(),
+                    *maker.owner().borrow().account_id(),
+                    *maker.order_id(),
+                    executed_at,
+                ));
+                bid_left -= quantity;
+                *ask_left -= quantity;
+                if *ask_left == 0 {
+                    ask = pending_asks.next();
+                }
+            }
+        }
+
+        // Each bid pays what its fill is worth at the clearing price, out of what the fill frees of its lock, and
+        // gets the rest back as price improvement. The asks share what the bids paid by the quantity they sold.
+        let freed = |order: &Order, quantity: u64| order.worth(*order.quantity()) - order.worth(order.quantity() - quantity);
+        let payments: Vec<u64> = bid_fills.iter()
+            .map(|(index, quantity)| {
+                let order = &participants[*index].0;
+                freed(order, *quantity).min(self.tick_converter.notional(*quantity, clearing.unwrap_or_default()))
+            })
+            .collect();
+        let paid = u128::from(payments.iter().sum::<u64>());
+        let share_of = |sold: u64| (paid * u128::from(sold) / u128::from(matched_quantity)) as u64;
+        let mut sold = 0;
+        let shares: Vec<u64> = ask_fills.iter()
+            .map(|(_, quantity)| {
+                sold += quantity;
+                share_of(sold) - share_of(sold - quantity)
+            })
+            .collect();
+
+        let mut filled = vec![0; participants.len()];
+        for ((index, quantity), amount) in bid_fills.iter().zip(&payments).chain(ask_fills.iter().zip(&shares)) {
+            let (order, rests) = &mut participants[*index];
+            let (tick_id, order_id, account_id) = (*order.tick_id(), *order.order_id(), *order.owner().borrow().account_id());
+            match order.order_direction() {
+                OrderDirection::Bid => {
+                    order.release(*order.base_asset(), freed(order, *quantity) - amount);
+                    order.distribute_filled_assets(*quantity, 0);
+                }
+                OrderDirection::Ask => order.distribute_filled_assets(*quantity, *amount),
+            }
+            let left = order.quantity() - quantity;
+            order.set_quantity(left);
+            filled[*index] = *quantity;
+
+            if *rests {
+                let tick = self.ticks.get_mut(&tick_id).expect("resting orders are on their tick");
+                if left > 0 {
+                    tick.reduce_order(order_id, account_id, left);
+                    self.record_fill(order_id, account_id, *quantity);
+                } else {
+                    tick.remove_order(order_id, account_id);
+                    if tick.orders().is_empty() {
+                        self.ticks.remove(&tick_id);
+                    }
+                    self.forget_resting_order(order_id, account_id);
+                }
+            }
+        }
+
+        let mut rested = 0;
+        for ((order, rests), filled) in participants.iter_mut().zip(filled) {
+            if *rests || *order.quantity() == 0 {
+                continue;
+            }
+            // The order locked its funds when it was collected, so they go back to the owner to be locked again
+            order.refund_deposited_assets(*order.quantity(), *order.tick_id());
+            self.run_place_limit(order).expect("the owner was just refunded what the order locks");
+            self.record_fill(*order.order_id(), *order.owner().borrow().account_id(), filled);
+            rested += 1;
+        }
+
+        // The book is uncrossed again, so each side's best is its nearest level to the other side
+        let best = |direction| self.ticks.values()
+            .filter(move |tick| tick.orders().front().is_some_and(|order| *order.order_direction() == direction))
+            .map(|tick| *tick.tick_id());
+        self.next_bid_tick = best(OrderDirection::Bid).max().unwrap_or(u64::MIN);
+        self.next_ask_tick = best(OrderDirection::Ask).min().unwrap_or(u64::MAX);
+        if matched_quantity > 0 {
+            self.last_trade_tick = clearing;
+            self.version += 1;
+        }
+
+        let trades = self.trades[first_trade..].to_vec();
+        self.activate_pending_orders();
+        AuctionResult::new(clearing, matched_quantity, trades, rested)
+    }
+
+    // Number of (buy, sell) stop orders still waiting for their trigger.
+    pub fn pending_stops(&self) -> (usize, usize) {
+        (Self::count_triggers(&self.stop_bids), Self::count_triggers(&self.stop_asks))
+    }
+
+    // Number of (buy, sell) if-touched orders still waiting for their trigger.
+    pub fn pending_touches(&self) -> (usize, usize) {
+        (Self::count_triggers(&self.touch_bids), Self::count_triggers(&self.touch_asks))
+    }
+
+    fn count_triggers(triggers: &BTreeMap<u64, VecDeque<Order>>) -> usize {
+        triggers.values().map(VecDeque::len).sum()
+    }
+
+    // Number of conditional orders still waiting for their condition.
+    pub fn pending_conditionals(&self) -> usize {
+        self.conditional_orders.len()
+    }
+
+    // Runs every trigger order whose trigger the last trade has reached and every conditional order whose condition
+    // holds. Activated orders can trade and change the book further, so this keeps going until nothing else activates.
+    fn activate_pending_orders(&mut self) {
+        loop {
+            if let Some(mut order) = self.next_triggered_order() {
+                order.set_trigger_tick(None);
+                let failed = self.dispatch_order(&mut order).is_err();
+                match order.trigger_type() {
+                    TriggerType::Stop => {
+                        self.stop_activations += 1;
+                        self.failed_stop_activations += failed as u64;
+                    }
+                    TriggerType::IfTouched => {
+                        self.touch_activations += 1;
+                        self.failed_touch_activations += failed as u64;
+                    }
+                }
+            } else if let Some(mut order) = self.next_met_condition() {
+                // A conditional order can also carry a stop trigger, in which case it is armed rather than run
+                order.set_condition(None);
+                self.conditional_activations += 1;
+                if self.dispatch_order(&mut order).is_err() {
+                    self.failed_conditional_activations += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Removes the oldest conditional order whose condition holds against the current depth.
+    fn next_met_condition(&mut self) -> Option<Order> {
+        if self.conditional_orders.is_empty() {
+            return None;
+        }
+
+        let snapshot = self.snapshot();
+        let index = self.conditional_orders.iter()
+            .position(|order| order.condition().is_some_and(|condition| condition.holds(&snapshot)))?;
+        Some(self.conditional_orders.remove(index))
+    }
+
+    // Pops the highest priority trigger order whose trigger has been reached: stops before if-touched orders, then
+    // the nearest trigger first, then FIFO.
+    fn next_triggered_order(&mut self) -> Option<Order> {
+        let last_trade_tick = self.last_trade_tick?;
+
+        Self::pop_triggered(&mut self.stop_bids, last_trade_tick, true)
+            .or_else(|| Self::pop_triggered(&mut self.stop_asks, last_trade_tick, false))
+            .or_else(|| Self::pop_triggered(&mut self.touch_bids, last_trade_tick, false))
+            .or_else(|| Self::pop_triggered(&mut self.touch_asks, last_trade_tick, true))
+    }
+
+    // Takes the first order off the nearest reached trigger level. `fires_at_or_above` triggers are reached once the
+    // last trade is at or above them, so the lowest one goes first, the others are reached at or below it.
+    fn pop_triggered(triggers: &mut BTreeMap<u64, VecDeque<Order>>, last_trade_tick: u64, fires_at_or_above: bool) -> Option<Order> {
+        let mut level = match fires_at_or_above {
+            true => triggers.first_entry().filter(|level| *level.key() <= last_trade_tick)?,
+            false => triggers.last_entry().filter(|level| *level.key() >= last_trade_tick)?,
+        };
+        let order = level.get_mut().pop_front();
+        if level.get().is_empty() {
+            level.remove();
+        }
+        order
+    }
     
 
-    fn cancel_order(&mut self, order_id: u64) {}
+    // Sets the risk configuration applied to all orders owned by the given account.
+    pub fn set_risk_config(&mut self, account_id: u64, config: RiskConfig) {
+        self.risk_configs.insert(account_id, config);
+    }
+
+    pub fn risk_config(&self, account_id: u64) -> Option<&RiskConfig> {
+        self.risk_configs.get(&account_id)
+    }
+
+    pub fn set_matching_policy(&mut self, matching_policy: MatchingPolicy) {
+        self.matching_policy = matching_policy;
+    }
+
+    pub fn set_price_protection_ticks(&mut self, price_protection_ticks: Option<u64>) {
+        self.price_protection_ticks = price_protection_ticks;
+    }
+
+    pub fn set_strict_market_orders(&mut self, strict_market_orders: bool) {
+        self.strict_market_orders = strict_market_orders;
+    }
+
+    pub fn set_tick_sizes(&mut self, tick_sizes: TickSizeTable) {
+        self.tick_sizes = tick_sizes;
+    }
+
+    pub fn set_tick_converter(&mut self, tick_converter: TickConverter) {
+        self.tick_converter = tick_converter;
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        let order_size = std::mem::size_of::<Order>();
+        let level_bytes: usize = self.ticks.values()
+            .map(|tick| std::mem::size_of::<(u64, Tick)>() + tick.heap_bytes())
+            .sum();
+        let stop_bytes: usize = self.stop_bids.values()
+            .chain(self.stop_asks.values())
+            .chain(self.touch_bids.values())
+            .chain(self.touch_asks.values())
+            .map(|stops| std::mem::size_of::<(u64, VecDeque<Order>)>() + stops.capacity() * order_size)
+            .sum();
+        let recent_order_bytes: usize = self.recent_orders.values()
+            .map(|recent| std::mem::size_of::<(u64, VecDeque<(Instant, OrderFingerprint)>)>() + recent.capacity() * std::mem::size_of::<(Instant, OrderFingerprint)>())
+            .sum();
+        let approx_bytes = std::mem::size_of::<Orderbook>()
+            + level_bytes
+            + stop_bytes
+            + recent_order_bytes
+            + self.conditional_orders.capacity() * order_size
+            + self.trades.capacity() * std::mem::size_of::<Trade>()
+            + self.cancellation_map.capacity() * std::mem::size_of::<(u64, u64)>()
+            + self.open_orders.values().map(|orders| std::mem::size_of::<(u64, BTreeMap<u64, u64>)>() + orders.len() * std::mem::size_of::<(u64, u64)>()).sum::<usize>();
+
+        let (stop_bids, stop_asks) = self.pending_stops();
+        let (touch_bids, touch_asks) = self.pending_touches();
+        MemoryStats::new(
+            self.ticks.len(),
+            self.ticks.values().map(|tick| tick.orders().len()).sum(),
+            stop_bids + stop_asks + touch_bids + touch_asks + self.conditional_orders.len(),
+            self.trades.len(),
+            approx_bytes,
+        )
+    }
+
+    // Moves all but the newest `keep` trades to the storage's trades stream, so long sessions don't hold their whole
+    // trade history in memory. Returns how many trades were archived. Nothing is dropped if the storage fails.
+    pub fn archive_trades(&mut self, storage: &mut dyn Storage, keep: usize) -> Result<usize, Box<dyn Error>> {
+        let archived = self.trades.len().saturating_sub(keep);
+        if archived == 0 {
+            return Ok(0);
+        }
+
+        let records: Vec<String> = self.trades[..archived].iter().map(|trade| trade.to_record(self.created_at)).collect();
+        storage.append(&self.trades_stream(), &records)?;
+        self.trades.drain(..archived);
+        self.archived_trades += archived as u64;
+        Ok(archived)
+    }
+
+    // Trades previously moved to the storage by archive_trades, oldest first.
+    pub fn archived_trade_history(&self, storage: &dyn Storage) -> Result<Vec<Trade>, Box<dyn Error>> {
+        storage.read(&self.trades_stream())?
+            .iter()
+            .map(|record| Trade::from_record(record, self.created_at).ok_or_else(|| format!("corrupt trade record `{}`", record).into()))
+            .collect()
+    }
+
+    fn trades_stream(&self) -> String {
+        match self.book_id {
+            0 => TRADES_STREAM.to_string(),
+            book_id => format!("{}-{}", TRADES_STREAM, book_id),
+        }
+    }
+
+    // Gives back spare capacity in every internal container and forgets duplicate-check history that has gone
+    // quiet. Nothing observable about the book changes.
+    pub fn compact(&mut self) {
+        for tick in self.ticks.values_mut() {
+            tick.shrink_to_fit();
+        }
+        let triggers = [&mut self.stop_bids, &mut self.stop_asks, &mut self.touch_bids, &mut self.touch_asks];
+        for stops in triggers.into_iter().flat_map(|triggers| triggers.values_mut()) {
+            stops.shrink_to_fit();
+        }
+        self.recent_orders.retain(|_, recent| !recent.is_empty());
+        for recent in self.recent_orders.values_mut() {
+            recent.shrink_to_fit();
+        }
+        self.recent_orders.shrink_to_fit();
+        self.conditional_orders.shrink_to_fit();
+        self.trades.shrink_to_fit();
+        self.risk_configs.shrink_to_fit();
+        self.cancellation_map.shrink_to_fit();
+        self.open_orders.shrink_to_fit();
+    }
+
+    // Limit prices and stop triggers must sit strictly between the best price sentinels, otherwise the order
+    // would be free (tick 0) or unreachable (u64::MAX). Limit prices must also be on the tick size grid of their band.
+    fn validate_order(&self, order: &Order) -> Result<(), OrderbookError> {
+        let tick_id = *order.tick_id();
+        if *order.order_type() == OrderType::Limit && !self.tick_converter.contains(tick_id) {
+            return Err(OrderbookError::InvalidTick { tick_id, nearest_valid: None });
+        }
+        if *order.order_type() == OrderType::Limit && !self.tick_sizes.is_valid(tick_id) {
+            return Err(OrderbookError::InvalidTick { tick_id, nearest_valid: Some(self.tick_sizes.nearest_valid(tick_id)) });
+        }
+        if *order.order_type() == OrderType::Market && *order.post_only() {
+            return Err(OrderbookError::InvalidOrder("Market orders always take liquidity and can't be post-only"));
+        }
+        if *order.order_type() == OrderType::Limit && *order.strict() {
+            return Err(OrderbookError::InvalidOrder("Only market orders can be strict, limit orders can use fill-or-kill"));
+        }
+        match *order.display_quantity() {
+            Some(_) if *order.order_type() == OrderType::Market => {
+                return Err(OrderbookError::InvalidOrder("Only limit orders can hide part of their quantity"));
+            }
+            Some(0) => return Err(OrderbookError::InvalidOrder("Display quantity must be positive")),
+            _ => {}
+        }
+        if let Some(trigger_tick) = order.trigger_tick().filter(|trigger_tick| !self.tick_converter.contains(*trigger_tick)) {
+            return Err(OrderbookError::InvalidTick { tick_id: trigger_tick, nearest_valid: None });
+        }
+        Ok(())
+    }
+
+    // Compares the order against the ones its owner sent within their duplicate window and remembers it if it goes through.
+    fn check_duplicate(&mut self, order: &Order, now: Instant) -> Result<(), OrderbookError> {
+        let account_id = *order.owner().borrow().account_id();
+        let guard = match self.risk_configs.get(&account_id).and_then(|config| *config.duplicate_guard()) {
+            Some(guard) => guard,
+            None => return Ok(()),
+        };
+
+        let recent_orders = self.recent_orders.entry(account_id).or_default();
+        while recent_orders.front().is_some_and(|(seen_at, _)| now.duration_since(*seen_at) > *guard.window()) {
+            recent_orders.pop_front();
+        }
+
+        let fingerprint = (
+            *order.order_direction(),
+            *order.order_type(),
+            *order.tick_id(),
+            *order.quantity(),
+        );
+        if recent_orders.iter().any(|(_, seen)| *seen == fingerprint) {
+            match guard.policy() {
+                DuplicatePolicy::Reject => {
+                    return Err(OrderbookError::DuplicateOrder);
+                }
+                DuplicatePolicy::Flag => self.flagged_duplicates += 1,
+            }
+        }
+
+        recent_orders.push_back((now, fingerprint));
+        Ok(())
+    }
+
+    // Prints an agreed trade of `quantity` at `tick_id` from the seller to the buyer without touching the queue, e.g.
+    // a block two accounts negotiated, or one account moving stock between its own books. The buyer and seller may
+    // be the same account. The price has to be inside the current best bid and ask (inclusive) so the cross can't
+    // trade through orders waiting on the book. Crosses are on the tape but, being off the queue, don't move the
+    // last trade price that stop and if-touched triggers follow.
+    pub fn cross(&mut self, buyer: &Rc<RefCell<Account>>, seller: &Rc<RefCell<Account>>, tick_id: u64, quantity: u64) -> Result<Trade, OrderbookError> {
+        if quantity == 0 {
+            return Err(OrderbookError::InvalidOrder("Cross quantity must be positive"));
+        }
+        if !self.tick_converter.contains(tick_id) || !self.tick_sizes.is_valid(tick_id) {
+            return Err(OrderbookError::InvalidTick { tick_id, nearest_valid: None });
+        }
+        let snapshot = self.snapshot();
+        let best_bid = snapshot.bids().first().map_or(u64::MIN, |(tick_id, _)| *tick_id);
+        let best_ask = snapshot.asks().first().map_or(u64::MAX, |(tick_id, _)| *tick_id);
+        if tick_id < best_bid || tick_id > best_ask {
+            return Err(OrderbookError::CrossOutsideSpread);
+        }
+
+        let cost = self.tick_converter.notional(quantity, tick_id);
+        // The seller is checked before the buyer pays, so a cross either side can't cover leaves both balances untouched
+        let seller_balance = seller.borrow().balance(self.quote_asset);
+        if seller_balance < quantity {
+            return Err(OrderbookError::InsufficientFunds { currency: self.quote_asset, required: quantity, available: seller_balance });
+        }
+        buyer.borrow_mut().withdraw(self.base_asset, cost)?;
+        seller.borrow_mut().withdraw(self.quote_asset, quantity)?;
+        buyer.borrow_mut().deposit(self.quote_asset, quantity);
+        seller.borrow_mut().deposit(self.base_asset, cost);
+
+        let trade = Trade::new(
+            self.archived_trades + self.trades.len() as u64,
+            tick_id,
+            quantity,
+            OrderDirection::Bid,
+            *buyer.borrow().account_id(),
+            0,
+            *seller.borrow().account_id(),
+            0,
+            Instant::now(),
+        ).into_cross();
+        self.trades.push(trade);
+        Ok(trade)
+    }
+
+    // Takes the account's resting order off the book and refunds what it escrowed for the unfilled quantity.
+    // Returns the order as it was when cancelled. Orders waiting on a trigger or condition aren't on the book yet,
+    // while those collected for an auction can be cancelled until it runs.
+    pub fn cancel_order(&mut self, order_id: u64, account_id: u64) -> Result<Order, OrderbookError> {
+        let unknown = || OrderbookError::UnknownOrder(order_id);
+        if let Some(index) = self.auction_orders.iter()
+            .position(|order| *order.order_id() == order_id && *order.owner().borrow().account_id() == account_id) {
+            // Collected for an auction: not on a tick yet, but its funds are locked all the same
+            let mut order = self.auction_orders.remove(index);
+            order.refund_deposited_assets(*order.quantity(), *order.tick_id());
+            self.version += 1;
+            return Ok(order);
+        }
+        let tick_id = *self.cancellation_map.get(&order_id).ok_or_else(unknown)?;
+        let tick = self.ticks.get_mut(&tick_id).ok_or_else(unknown)?;
+        let mut order = tick.remove_order(order_id, account_id).ok_or_else(unknown)?;
+        let tick_emptied = tick.orders().is_empty();
+
+        order.refund_deposited_assets(*order.quantity(), tick_id);
+        self.forget_resting_order(order_id, account_id);
+        self.version += 1;
+
+        // The best price moves on to the next level on the same side if this was the last order at the touch
+        if tick_emptied {
+            self.ticks.remove(&tick_id);
+            if tick_id == self.next_bid_tick {
+                self.next_bid_tick = self.ticks.range(..tick_id).next_back().map_or(u64::MIN, |(tick_id, _)| *tick_id);
+            }
+            if tick_id == self.next_ask_tick {
+                self.next_ask_tick = self.ticks.range(tick_id + 1..).next().map_or(u64::MAX, |(tick_id, _)| *tick_id);
+            }
+        }
+
+        Ok(order)
+    }
+
+    // Cancels every resting order, and every order collected for an auction, whose good-till-date expiry is at or
+    // before `now`, refunding what each escrowed for its unfilled quantity, and returns them lowest order id first.
+    // Orders still waiting on a trigger or condition aren't on the book yet; if they expire before they rest they
+    // are cancelled by the first sweep after.
+    pub fn expire_orders(&mut self, now: Instant) -> Vec<Order> {
+        let mut expired: Vec<(u64, u64)> = self.ticks.values()
+            .flat_map(|tick| tick.orders())
+            .chain(self.auction_orders.iter())
+            .filter(|order| order.is_expired(now))
+            .map(|order| (*order.order_id(), *order.owner().borrow().account_id()))
+            .collect();
+        expired.sort_unstable();
+        expired.into_iter()
+            .filter_map(|(order_id, account_id)| self.cancel_order(order_id, account_id).ok())
+            .collect()
+    }
+
+    // Drops a resting order that filled or was cancelled from the cancellation map and its owner's open orders.
+    fn forget_resting_order(&mut self, order_id: u64, account_id: u64) {
+        self.cancellation_map.remove(&order_id);
+        if let Some(orders) = self.open_orders.get_mut(&account_id) {
+            orders.remove(&order_id);
+            if orders.is_empty() {
+                self.open_orders.remove(&account_id);
+            }
+        }
+    }
+
+    fn record_fill(&mut self, order_id: u64, account_id: u64, quantity: u64) {
+        if let Some(filled) = self.open_orders.get_mut(&account_id).and_then(|orders| orders.get_mut(&order_id)) {
+            *filled += quantity;
+        }
+    }
+
+    // How much of the account's resting order has filled so far, 0 if it isn't resting.
+    pub(crate) fn filled_quantity(&self, order_id: u64, account_id: u64) -> u64 {
+        self.open_orders.get(&account_id).and_then(|orders| orders.get(&order_id)).copied().unwrap_or(0)
+    }
+
+    // Ids of the account's orders resting on the book, lowest first.
+    pub(crate) fn open_order_ids(&self, account_id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.open_orders.get(&account_id).into_iter().flat_map(|orders| orders.keys().copied())
+    }
+
+    // Tick the order rests on, None once it has filled or been cancelled or while it waits on a trigger.
+    pub fn resting_tick(&self, order_id: u64) -> Option<u64> {
+        self.cancellation_map.get(&order_id).copied()
+    }
+
+    // The account's order with this id as it rests on the book, None once it has filled or been cancelled or while
+    // it waits on a trigger or condition.
+    pub fn resting_order(&self, order_id: u64, account_id: u64) -> Option<&Order> {
+        self.ticks.get(&self.resting_tick(order_id)?)?
+            .orders()
+            .iter()
+            .find(|order| *order.order_id() == order_id && *order.owner().borrow().account_id() == account_id)
+    }
+
+    // The account's stop, if-touched, conditional or auction order with this id while it waits to be placed.
+    pub fn pending_order(&self, order_id: u64, account_id: u64) -> Option<&Order> {
+        [&self.stop_bids, &self.stop_asks, &self.touch_bids, &self.touch_asks]
+            .into_iter()
+            .flat_map(|triggers| triggers.values().flatten())
+            .chain(self.conditional_orders.iter())
+            .chain(self.auction_orders.iter())
+            .find(|order| *order.order_id() == order_id && *order.owner().borrow().account_id() == account_id)
+    }
+
+    // Changes the account's resting limit order to `new_quantity` at `new_tick`. Lowering the quantity at the same
+    // price shrinks the order where it stands and refunds the difference. A new price or a larger quantity takes the
+    // order off its level and queues it again at the back of the new one, so it loses its time priority. Amends
+    // never trade: a new price that would cross the spread is rejected, as is one the owner can't pay for, and a
+    // rejected amend leaves the order untouched. Returns the order as it was before the amend.
+    pub fn amend_order(&mut self, order_id: u64, account_id: u64, new_quantity: u64, new_tick: u64) -> Result<Order, OrderbookError> {
+        let unknown = || OrderbookError::UnknownOrder(order_id);
+        if new_quantity == 0 {
+            return Err(OrderbookError::InvalidOrder("Amended quantity must be positive, cancel the order instead"));
+        }
+        let order = self.resting_order(order_id, account_id).ok_or_else(unknown)?.clone();
+        let (tick_id, old_quantity) = (*order.tick_id(), *order.quantity());
+
+        if new_tick == tick_id && new_quantity <= old_quantity {
+            if new_quantity < old_quantity {
+                if let Some(tick) = self.ticks.get_mut(&tick_id) {
+                    tick.reduce_order(order_id, account_id, new_quantity);
+                }
+                order.clone().refund_reduction(old_quantity, new_quantity);
+                self.version += 1;
+            }
+            return Ok(order);
+        }
+
+        let mut amended = order.clone();
+        amended.set_tick_id(new_tick);
+        amended.set_quantity(new_quantity);
+        self.validate_order(&amended)?;
+        let crosses = match order.order_direction() {
+            OrderDirection::Bid => new_tick >= self.next_ask_tick,
+            OrderDirection::Ask => new_tick <= self.next_bid_tick,
+        };
+        if crosses {
+            return Err(OrderbookError::InvalidOrder("Amended price would cross the spread"));
+        }
+
+        // Only what the amended order locks beyond the old one has to come out of the owner's balance
+        let (currency, old_locked, new_locked) = match order.order_direction() {
+            OrderDirection::Bid => (self.base_asset, order.worth(old_quantity), self.tick_converter.notional(new_quantity, new_tick)),
+            OrderDirection::Ask => (self.quote_asset, old_quantity, new_quantity),
+        };
+        let available = order.owner().borrow().balance(currency);
+        let required = new_locked.saturating_sub(old_locked);
+        if available < required {
+            return Err(OrderbookError::InsufficientFunds { currency, required, available });
+        }
+
+        // The re-queued order keeps what it filled before the amend
+        let filled = self.filled_quantity(order_id, account_id);
+        self.cancel_order(order_id, account_id)?;
+        self.run_place_limit(&mut amended)?;
+        self.record_fill(order_id, account_id, filled);
+        Ok(order)
+    }
+
+    // Cancels every `direction` order the account has resting from from_tick to to_tick (inclusive) in one pass, e.g. a
+    // quoting bot pulling one side of its quotes. The refund is paid as one deposit and the book version moves once,
+    // however many orders and levels the band held. Returns the cancelled orders by ascending tick.
+    pub fn cancel_range(&mut self, account_id: u64, direction: OrderDirection, from_tick: u64, to_tick: u64) -> Vec<Order> {
+        let mut cancelled = Vec::new();
+        let mut emptied = Vec::new();
+        for (tick_id, tick) in self.ticks.range_mut(from_tick.min(to_tick)..=from_tick.max(to_tick)) {
+            let removed = tick.remove_account_orders(account_id, direction);
+            if !removed.is_empty() && tick.orders().is_empty() {
+                emptied.push(*tick_id);
+            }
+            cancelled.extend(removed);
+        }
+        let owner = match cancelled.first() {
+            Some(order) => Rc::clone(order.owner()),
+            None => return cancelled,
+        };
+
+        let (asset, refund) = match direction {
+            OrderDirection::Bid => (self.base_asset, cancelled.iter().map(|order| order.worth(*order.quantity())).sum()),
+            OrderDirection::Ask => (self.quote_asset, cancelled.iter().map(|order| *order.quantity()).sum()),
+        };
+        self.escrow.borrow_mut().withdraw(asset, refund).expect("escrow holds what resting orders locked");
+        owner.borrow_mut().deposit(asset, refund);
+        for order in &cancelled {
+            self.forget_resting_order(*order.order_id(), account_id);
+        }
+        for tick_id in emptied {
+            self.ticks.remove(&tick_id);
+        }
+        self.version += 1;
+
+        // Every level at or below the old best bid is a bid level and at or above the old best ask an ask level,
+        // so the best prices move on to the nearest level left on their side.
+        match direction {
+            OrderDirection::Bid => {
+                self.next_bid_tick = self.ticks.range(..=self.next_bid_tick).next_back().map_or(u64::MIN, |(tick_id, _)| *tick_id);
+            }
+            OrderDirection::Ask => {
+                self.next_ask_tick = self.ticks.range(self.next_ask_tick..).next().map_or(u64::MAX, |(tick_id, _)| *tick_id);
+            }
+        }
+        cancelled
+    }
+
+    // Funds the resting orders, and those collected for an auction, have locked, as (base asset, quote asset): the
+    // worth of their quantity for bids and the quantity for asks.
+    pub fn locked_funds(&self) -> (u64, u64) {
+        self.ticks.values()
+            .flat_map(|tick| tick.orders().iter())
+            .chain(self.auction_orders.iter())
+            .fold((0, 0), |(base, quote), order| match order.order_direction() {
+                OrderDirection::Bid => (base + order.worth(*order.quantity()), quote),
+                OrderDirection::Ask => (base, quote + order.quantity()),
+            })
+    }
+
+    // Checks the escrow holds exactly what the resting orders have locked. Every lock, fill and refund is a transfer
+    // between the escrow and a trader, so a mismatch means the book created or lost funds somewhere.
+    pub fn verify_escrow(&self) -> Result<(), Box<dyn Error>> {
+        let (locked_base, locked_quote) = self.locked_funds();
+        let escrow = self.escrow.borrow();
+        let (held_base, held_quote) = (escrow.balance(self.base_asset), escrow.balance(self.quote_asset));
+        if (held_base, held_quote) != (locked_base, locked_quote) {
+            return Err(format!(
+                "escrow holds {} {} and {} {} but resting orders locked {} and {}",
+                held_base, self.base_asset, held_quote, self.quote_asset, locked_base, locked_quote,
+            ).into());
+        }
+        Ok(())
+    }
+
+    // Every order the book holds: resting ones, then those waiting on a stop, if-touched trigger, condition or auction.
+    #[cfg(feature = "serde")]
+    pub(crate) fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.ticks.values().flat_map(|tick| tick.orders())
+            .chain(self.stop_bids.values().chain(self.stop_asks.values()).flatten())
+            .chain(self.touch_bids.values().chain(self.touch_asks.values()).flatten())
+            .chain(self.conditional_orders.iter())
+            .chain(self.auction_orders.iter())
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn orders_mut(&mut self) -> impl Iterator<Item = &mut Order> {
+        self.ticks.values_mut().flat_map(|tick| tick.orders_mut())
+            .chain(self.stop_bids.values_mut().chain(self.stop_asks.values_mut()).flatten())
+            .chain(self.touch_bids.values_mut().chain(self.touch_asks.values_mut()).flatten())
+            .chain(self.conditional_orders.iter_mut())
+            .chain(self.auction_orders.iter_mut())
+    }
 
     // For T existing initialized ticks, do a log(T) search/insert for the tick_id in our BTreeMap.
     fn get_or_init_tick_in_tree(&mut self, tick_id: u64) -> &mut Tick {
@@ -63,17 +1046,22 @@ impl Orderbook {
     }
 
     // Place limit on specified tick and properly handle error if there is an issue.
-    fn run_place_limit(&mut self, order: &mut Order) -> Result<(), Box<dyn Error>>{
+    fn run_place_limit(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
         let tick_id = *order.tick_id();
-        let tick = self.get_or_init_tick_in_tree(tick_id);
+        order.set_escrow(Rc::clone(&self.escrow));
 
-        // Withdraw the assets placed in the books from the trader's balances
+        // Withdraw the assets placed in the books from the trader's balances.
+        // This happens before the tick is created so a rejected order doesn't leave an empty tick behind.
         order.withdraw_deposited_assets(*order.quantity(), tick_id)?;
+        let tick = self.get_or_init_tick_in_tree(tick_id);
 
         // Clone order and pass in cloned version
         let order_clone = order.clone();
 
         tick.place_limit(order_clone)?;
+        self.cancellation_map.insert(*order.order_id(), tick_id);
+        self.open_orders.entry(*order.owner().borrow().account_id()).or_default().insert(*order.order_id(), 0);
+        self.version += 1;
 
         // If bid and tick_id is higher than next bid tick, update next bid tick
         // If ask and tick_id is lower than next ask tick, update next ask tick
@@ -93,222 +1081,323 @@ impl Orderbook {
         Ok(())
     }
 
-    // Implement market bid abstraction that takes in a start tick and fills ticks as asks
-    fn run_market_ask(&mut self, order: &mut Order, end_tick: u64, quantity: u64) -> Result<u64, Box<dyn Error>> {
+    // Market ask abstraction: sweeps the bids downwards from the best bid, stopping before end_tick.
+    fn run_market_ask(&mut self, order: &mut Order, end_tick: u64, quantity: u64) -> Result<u64, OrderbookError> {
+        self.run_sweep(order, OrderDirection::Ask, end_tick, quantity)
+    }
+
+    // Market bid abstraction: sweeps the asks upwards from the best ask, stopping before end_tick.
+    fn run_market_bid(&mut self, order: &mut Order, end_tick: u64, quantity: u64) -> Result<u64, OrderbookError> {
+        self.run_sweep(order, OrderDirection::Bid, end_tick, quantity)
+    }
+
+    // Sweeps the book in two passes: the levels and quantities to consume are planned without touching
+    // anything, the taker is settled for the whole sweep at once, and only then are the resting orders filled.
+    // A taker that can't pay therefore never leaves the book partially swept.
+    // Returns the part of the quantity that could not be filled.
+    fn run_sweep(&mut self, order: &mut Order, direction: OrderDirection, end_tick: u64, quantity: u64) -> Result<u64, OrderbookError> {
+        let plan = self.plan_sweep(direction, end_tick, quantity);
+        self.execute_sweep(order, direction, quantity, &plan)
+    }
+
+    // Settles the taker for a planned sweep and fills the resting orders, returning the unfilled part of `quantity`.
+    fn execute_sweep(&mut self, order: &mut Order, direction: OrderDirection, quantity: u64, plan: &SweepPlan) -> Result<u64, OrderbookError> {
+        order.set_escrow(Rc::clone(&self.escrow));
+        order.settle_sweep(plan.filled_quantity, plan.total_cost)?;
+        self.apply_sweep(order, direction, plan);
+
+        Ok(quantity - plan.filled_quantity)
+    }
+
+    // Walks the side of the book a `direction` order trades against, best price first, and works out how much
+    // of each level `quantity` would consume. Levels at or past end_tick (exclusive) are not consumed.
+    pub(crate) fn plan_sweep(&self, direction: OrderDirection, end_tick: u64, quantity: u64) -> SweepPlan {
+        let levels: Box<dyn Iterator<Item = (&u64, &Tick)>> = match direction {
+            OrderDirection::Bid if self.next_ask_tick != u64::MAX => Box::new(self.ticks.range(self.next_ask_tick..)),
+            OrderDirection::Ask if self.next_bid_tick != u64::MIN => Box::new(self.ticks.range(..=self.next_bid_tick).rev()),
+            _ => Box::new(std::iter::empty()),
+        };
+
+        let mut plan = SweepPlan::default();
         let mut remaining_quantity = quantity;
-        let mut to_remove = Vec::new();
-    
-        // Define scope to borrow self.ticks as mutable in scope.
-        // When this scope ends, the borrow is dropped, letting us go back through to remove empty ticks.
-        {
-            // Reverse iterate, inclusive of start tick
-            let mut tick_iter = self.ticks.range_mut(..=self.next_bid_tick);
-    
-            while remaining_quantity > 0 && self.next_bid_tick != u64::MIN {
-                if let Some((tick_id, tick)) = tick_iter.next_back() {
-                    // If the next tick is below our end tick tick, we cut off the market ask process.
-                    if *tick_id <= end_tick {
-                        self.next_bid_tick = end_tick;
-                        break;
-                    } else {
-                        self.next_bid_tick = *tick_id;
-                    }
-    
-                    // Fill the tick and update remaining quantity
-                    let pre_fill_remaining = remaining_quantity;
-                    remaining_quantity = tick.fill_tick(remaining_quantity);
-                    let filled_quantity = pre_fill_remaining - remaining_quantity;
+        for (tick_id, tick) in levels {
+            let available = *tick.total_liquidity();
+            if available == 0 {
+                continue;
+            }
+
+            // The sweep is done, so this level is the best price left on the side.
+            let past_end = match direction {
+                OrderDirection::Bid => *tick_id >= end_tick,
+                OrderDirection::Ask => *tick_id <= end_tick,
+            };
+            if remaining_quantity == 0 || past_end {
+                plan.next_tick = Some(*tick_id);
+                break;
+            }
 
-                    tick.total_orders -= filled_quantity;
+            let filled_quantity = remaining_quantity.min(available);
+            remaining_quantity -= filled_quantity;
+            plan.levels.push((*tick_id, filled_quantity));
+            plan.filled_quantity += filled_quantity;
+            plan.total_cost = plan.total_cost.saturating_add(tick.fill_worth(filled_quantity, self.matching_policy));
 
-                    // Apply the exchange to the trader's balances
-                    order.withdraw_deposited_assets(filled_quantity, *tick.tick_id())?;
-                    order.distribute_filled_assets(filled_quantity, *tick.tick_id());
-    
-                    // If tick was fully filled, set to remove it from the book
-                    if tick.orders().len() == 0 {
-                        tick.total_orders = 0;
-                        to_remove.push(*tick_id);
-                    }
-                } else { break }
+            // Level only partially consumed, so it stays on the book as the best price.
+            if filled_quantity < available {
+                plan.next_tick = Some(*tick_id);
+                break;
             }
         }
-    
-        // Remove empty ticks from the book
-        for tick_id in to_remove {
-            self.ticks.remove(&tick_id);
-        }
 
-        return Ok(remaining_quantity);
+        plan
     }
-    
 
-    // Implement market bid abstraction that takes in a start tick and fills ticks as bids
-    fn run_market_bid(&mut self, order: &mut Order, end_tick: u64, quantity: u64) -> Result<u64, Box<dyn Error>> {
-        let mut remaining_quantity = quantity;
-        let mut to_remove = Vec::new();
-    
-        // Define scope to borrow self.ticks as mutable in scope.
-        // When this scope ends, the borrow is dropped, letting us go back through to remove empty ticks.
-        {
-            let mut tick_iter = self.ticks.range_mut(self.next_ask_tick..);
-    
-            while remaining_quantity > 0 && self.next_ask_tick != u64::MAX {
-                if let Some((tick_id, tick)) = tick_iter.next() {
-                    // If next tick is at or past our end tick, we cut off the market ask process.
-                    if *tick_id >= end_tick {
-                        self.next_ask_tick = end_tick;
-                        break;
-                    } else {
-                        self.next_ask_tick = *tick_id;
-                    }
+    // Fills the resting orders on every planned level, records a trade per maker filled, removes emptied ticks
+    // and moves the best price pointer.
+    fn apply_sweep(&mut self, taker: &Order, direction: OrderDirection, plan: &SweepPlan) {
+        let taker_account_id = *taker.owner().borrow().account_id();
+        let executed_at = Instant::now();
+        let matching_policy = self.matching_policy;
+        for (tick_id, quantity) in plan.levels.iter() {
+            let tick = self.ticks.get_mut(tick_id).expect("planned tick is on the book");
+            tick.fill_tick_with_policy(*quantity, matching_policy);
+
+            let fills = tick.take_fills();
+            let resting_ids: HashSet<u64> = tick.orders().iter().map(|order| *order.order_id()).collect();
+            let tick_emptied = tick.orders().is_empty();
+            for fill in fills {
+                // Fully filled orders can no longer be cancelled
+                if !resting_ids.contains(fill.order_id()) && self.cancellation_map.get(fill.order_id()) == Some(tick_id) {
+                    self.forget_resting_order(*fill.order_id(), *fill.account_id());
+                } else {
+                    self.record_fill(*fill.order_id(), *fill.account_id(), *fill.quantity());
+                }
+                self.trades.push(Trade::new(
+                    self.archived_trades + self.trades.len() as u64,
+                    *tick_id,
+                    *fill.quantity(),
+                    direction,
+                    taker_account_id,
+                    *taker.order_id(),
+                    *fill.account_id(),
+                    *fill.order_id(),
+                    executed_at,
+                ));
+            }
 
-                    // Fill the tick and update remaining quantity
-                    let pre_fill_remaining = remaining_quantity;
-                    remaining_quantity = tick.fill_tick(remaining_quantity);
-                    let filled_quantity = pre_fill_remaining - remaining_quantity;
-
-                    tick.total_orders -= filled_quantity;
-
-                    // Apply the exchange to the trader's balances
-                    order.withdraw_deposited_assets(filled_quantity, *tick.tick_id())?;
-                    order.distribute_filled_assets(filled_quantity, *tick.tick_id());
-                    
-                    // If tick was fully filled, set to it from the book
-                    if tick.orders().len() == 0 {
-                        tick.total_orders = 0;
-                        to_remove.push(*tick_id);
-                    }
-                } else { break }
+            if tick_emptied {
+                self.ticks.remove(tick_id);
             }
         }
-    
-        // Remove empty ticks from book
-        for tick_id in to_remove {
-            self.ticks.remove(&tick_id);
+
+        if plan.filled_quantity > 0 {
+            self.version += 1;
+        }
+        if let Some((tick_id, _)) = plan.levels.last() {
+            self.last_trade_tick = Some(*tick_id);
         }
 
-        return Ok(remaining_quantity);
+        match direction {
+            OrderDirection::Bid => self.next_ask_tick = plan.next_tick.unwrap_or(u64::MAX),
+            OrderDirection::Ask => self.next_bid_tick = plan.next_tick.unwrap_or(u64::MIN),
+        }
     }
 
-    // handle partial limits
-    fn run_partial_or_full_limit(&mut self, order: &mut Order) -> Result<(), Box<dyn Error>> {
+    // Fills whatever part of the limit order crosses and rests the rest, or drops the rest for IOC orders. FOK orders
+    // are rejected untouched unless the sweep plan covers their whole quantity, and post-only orders unless they don't
+    // cross at all. If part of the order didn't fill straight away, its quantity is left at that part.
+    fn run_partial_or_full_limit(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
         let tick_id = *order.tick_id();
-        let mut remaining_quantity = *order.quantity();
-        match order.order_direction() {
-            OrderDirection::Bid => {
-                // If the bid is past the lowest ask, immediately fill the appropriate portion of the order.
-                if tick_id > self.next_ask_tick {
-                    remaining_quantity = self.run_market_bid(order, tick_id, remaining_quantity)?;
-                }
-                
-                if remaining_quantity > 0 {
-                    order.set_quantity(remaining_quantity);
-                    self.run_place_limit(order)?;
-                }
+        let placed_quantity = *order.quantity();
+        let mut remaining_quantity = placed_quantity;
+
+        if let Some(max_ticks) = self.price_protection_ticks {
+            // Only orders that cross can be priced through the opposite best, so an empty side never triggers this.
+            let ticks_through = match order.order_direction() {
+                OrderDirection::Bid if self.next_ask_tick != u64::MAX => tick_id.saturating_sub(self.next_ask_tick),
+                OrderDirection::Ask if self.next_bid_tick != u64::MIN => self.next_bid_tick.saturating_sub(tick_id),
+                _ => 0,
+            };
+            if ticks_through > max_ticks && !order.skip_price_protection() {
+                return Err(OrderbookError::PriceProtection { ticks_through, max_ticks });
             }
-            OrderDirection::Ask => {
-                // If the ask is past the highest bid, immediately fill the appropriate portion of the order.
-                if tick_id < self.next_bid_tick {
-                    remaining_quantity = self.run_market_ask(order, tick_id, remaining_quantity)?;
-                }
+        }
 
-                if remaining_quantity > 0 {
-                    order.set_quantity(remaining_quantity);
-                    self.run_place_limit(order)?;
-                }
+        // If the bid reaches the lowest ask (or the ask the highest bid), the appropriate portion of the order fills immediately.
+        // Sweep end ticks are exclusive, so the sweep stops one tick past the limit to include the limit price itself.
+        let direction = *order.order_direction();
+        let (crosses, end_tick) = match direction {
+            OrderDirection::Bid => (tick_id >= self.next_ask_tick, tick_id + 1),
+            OrderDirection::Ask => (tick_id <= self.next_bid_tick, tick_id - 1),
+        };
+        if crosses && *order.post_only() {
+            return Err(OrderbookError::PostOnly);
+        }
+        let plan = if crosses {
+            self.plan_sweep(direction, end_tick, remaining_quantity)
+        } else {
+            SweepPlan::default()
+        };
+
+        let time_in_force = *order.time_in_force();
+        if time_in_force == TimeInForce::FOK && plan.filled_quantity < remaining_quantity {
+            return Err(OrderbookError::InsufficientLiquidity { filled: plan.filled_quantity, requested: remaining_quantity });
+        }
+
+        // Check the trader can pay for both the immediate fill and the escrow of whatever rests before touching
+        // the book, so an order can't fill halfway and then fail to place its remainder.
+        let resting_quantity = match time_in_force {
+            TimeInForce::GTC => remaining_quantity - plan.filled_quantity,
+            TimeInForce::IOC | TimeInForce::FOK => 0,
+        };
+        let (currency, required) = match direction {
+            OrderDirection::Bid => (self.base_asset, plan.total_cost.saturating_add(self.tick_converter.notional(resting_quantity, tick_id))),
+            OrderDirection::Ask => (self.quote_asset, plan.filled_quantity + resting_quantity),
+        };
+        let available = order.owner().borrow().balance(currency);
+        if available < required {
+            return Err(OrderbookError::InsufficientFunds { currency, required, available });
+        }
+
+        if crosses {
+            remaining_quantity = self.execute_sweep(order, direction, remaining_quantity, &plan)?;
+        }
+
+        if remaining_quantity > 0 {
+            order.set_quantity(remaining_quantity);
+            if time_in_force == TimeInForce::GTC {
+                self.run_place_limit(order)?;
+                let account_id = *order.owner().borrow().account_id();
+                self.record_fill(*order.order_id(), account_id, placed_quantity - remaining_quantity);
             }
         }
 
         Ok(())
     }
 
-    fn run_market_order(&mut self, order: &mut Order) -> Result<(), Box<dyn Error>> {
+    fn run_market_order(&mut self, order: &mut Order) -> Result<(), OrderbookError> {
+        let remaining_quantity = *order.quantity();
+        let direction = *order.order_direction();
+
+        // Without a slippage guard the order sweeps until the book runs out of ticks.
+        let mut bid_end_tick = u64::MAX;
+        let mut ask_end_tick = u64::MIN;
+        // Most the order may fill, less than its quantity when a notional limit truncates it.
+        let mut fill_quantity = remaining_quantity;
+
+        if let (Some(guard), Some(reference_tick)) = (self.slippage_guard_for(order), self.slippage_reference_tick(direction)) {
+            let bound_tick = guard.bound_tick(reference_tick, direction);
+
+            // Estimate the sweep first so a rejected order never touches the book.
+            let exceeds_bound = match (direction, *self.impact(remaining_quantity, direction).worst_tick()) {
+                (OrderDirection::Bid, Some(worst_tick)) => worst_tick > bound_tick,
+                (OrderDirection::Ask, Some(worst_tick)) => worst_tick < bound_tick,
+                (_, None) => false,
+            };
+            if exceeds_bound && *guard.policy() == SlippagePolicy::Reject {
+                return Err(OrderbookError::SlippageExceeded);
+            }
+
+            // End ticks are exclusive, so step one tick past the bound to keep the bound itself fillable.
+            bid_end_tick = bound_tick.saturating_add(1);
+            ask_end_tick = bound_tick.saturating_sub(1);
+
+            // Notional limits leave every tick reachable, so the whole sweep is planned to find where its average
+            // price passes the limit
+            if let SlippageLimit::Notional(notional) = *guard.limit() {
+                let end_tick = match direction {
+                    OrderDirection::Bid => bid_end_tick,
+                    OrderDirection::Ask => ask_end_tick,
+                };
+                let plan = self.plan_sweep(direction, end_tick, remaining_quantity);
+                let within = plan.quantity_within_notional(&self.tick_converter, direction, remaining_quantity, notional);
+                if within < plan.filled_quantity {
+                    if *guard.policy() == SlippagePolicy::Reject {
+                        return Err(OrderbookError::SlippageExceeded);
+                    }
+                    fill_quantity = within;
+                }
+            }
+        }
+
+        // Strict orders are all-or-none: check the depth within reach covers the whole order before touching the book.
+        if *order.strict() || self.strict_market_orders {
+            let end_tick = match direction {
+                OrderDirection::Bid => bid_end_tick,
+                OrderDirection::Ask => ask_end_tick,
+            };
+            let filled = self.plan_sweep(direction, end_tick, fill_quantity).filled_quantity;
+            if filled < remaining_quantity {
+                return Err(OrderbookError::InsufficientLiquidity { filled, requested: remaining_quantity });
+            }
+        }
+
         // In both cases, we let the return value drop quietly. This is the equivalent of not erroring if the market runs out of ticks,
         // which is appropriate behavior for a market order that is large enough to clear the book.
-        let remaining_quantity = *order.quantity();
-        match order.order_direction() {
+        match direction {
             OrderDirection::Bid => {
-                self.run_market_bid(order, u64::MAX, remaining_quantity)?;
+                self.run_market_bid(order, bid_end_tick, fill_quantity)?;
             }
             OrderDirection::Ask => {
-                self.run_market_ask(order, u64::MIN, remaining_quantity)?;
+                self.run_market_ask(order, ask_end_tick, fill_quantity)?;
             }
         }
         Ok(())
     }
+
+    // The order's own slippage guard takes precedence over the owner's account-level one.
+    fn slippage_guard_for(&self, order: &Order) -> Option<SlippageGuard> {
+        order.max_slippage().or_else(|| {
+            let account_id = *order.owner().borrow().account_id();
+            self.risk_configs.get(&account_id).and_then(|config| *config.max_slippage())
+        })
+    }
+
+    // Slippage is measured from the mid price, falling back to the touch if the other side is empty.
+    fn slippage_reference_tick(&self, direction: OrderDirection) -> Option<u64> {
+        let best_bid = (self.next_bid_tick != u64::MIN).then_some(self.next_bid_tick);
+        let best_ask = (self.next_ask_tick != u64::MAX).then_some(self.next_ask_tick);
+        match (best_bid, best_ask, direction) {
+            (Some(bid), Some(ask), _) => Some((bid + ask) / 2),
+            (_, ask, OrderDirection::Bid) => ask,
+            (bid, _, OrderDirection::Ask) => bid,
+        }
+    }
         
     
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, rc::Rc};
     use super::*;
-    use crate::{bank::currency::Currency, book::order};
+    use crate::book::conditional::{BookCondition, BookMetric, Comparison};
+    use crate::book::rejection::RejectCode;
+    use crate::book::risk::{DuplicateGuard, SlippageLimit};
+    use crate::storage::memory::MemoryStorage;
+    use std::time::Duration;
+    use crate::testkit::{limit_order, market_order, AccountBuilder, BookBuilder};
 
     const BASE_OSMO_AMT: u64 = 10000;
     const BASE_USD_AMT: u64 = 100000;
 
-    // Test helper that creates a specified number of orders of equal quantity on the passed in tick
-    fn create_limit_orders(book: &mut Orderbook, tick_id: &mut u64, num_orders: u64, quantity: u64, order_direction: &OrderDirection) {
-        for i in 0..num_orders {
-            let order = Order::new(
-                i,
-                *tick_id,
-                0,
-                Rc::new(RefCell::new(Account::new(i, AccountType::Individual))),
-                OrderType::Limit,
-                *order_direction,
-                quantity,
-            );
-
-            let tick = book.get_or_init_tick_in_tree(*tick_id);
-
-            if let Err(e) = tick.place_limit(order) {
-                println!("Problem placing limit order: {}", e);
-                process::exit(1);
-            }
-
-        }
-    }
-
-    // Helper that funds account with 100000 USD and 10000 OSMO
-    fn fund_account_for_order(order: &mut Order) {
-        order.owner().borrow_mut().deposit(Currency::USD, BASE_USD_AMT);
-        order.owner().borrow_mut().deposit(Currency::OSMO, BASE_OSMO_AMT);
+    // Helper that builds a market order owned by a fresh account funded with 100000 USD and 10000 OSMO
+    fn funded_market_order(order_direction: OrderDirection, quantity: u64) -> Order {
+        let owner = AccountBuilder::funded(7, &[(Currency::USD, BASE_USD_AMT), (Currency::OSMO, BASE_OSMO_AMT)]).build();
+        market_order(&owner, order_direction, quantity)
     }
 
-    // implement test case where the order book's next ask tick is 10, and there are orders on ticks 10 to 15 (using helper above)
+    // implement test case where the order book's next ask tick is 10, and there are orders on ticks 10 to 21
     #[test]
     fn test_run_market_bid() {
-        let mut book = Orderbook::new(0);
+        // create 300 units of depth on tick 10 (next ask tick) and on ticks 13, 14 and 21
+        let mut book = BookBuilder::new()
+            .with_asks(&[(10, 300), (13, 300), (14, 300), (21, 300)])
+            .build();
 
-        // set next ask tick on book to tick 10
-        book.next_ask_tick = 10;
-
-        // create orders on tick 10 (next ask tick)
-        create_limit_orders(&mut book, &mut 10, 3, 100, &OrderDirection::Ask);
-
-        // create orders on ticks 13, 14, and 21 to add more depth on the ask side
-        create_limit_orders(&mut book, &mut 13, 3, 100, &OrderDirection::Ask);
-        create_limit_orders(&mut book, &mut 14, 3, 100, &OrderDirection::Ask);
-        create_limit_orders(&mut book, &mut 21, 3, 100, &OrderDirection::Ask);
-
-        // run market ask for 1000 quantity
+        // run market bid for 1000 quantity
         // since market depth is 1200 (four ticks with 300 each), this should fill up to tick 21
-        let mut order = Order::new(
-            13,
-            0,
-            0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
-            OrderType::Market,
-            OrderDirection::Ask,
-            1000,
-        );
+        let mut order = funded_market_order(OrderDirection::Bid, 1000);
 
-        fund_account_for_order(&mut order);
-        
         // System under test
         book.run_market_bid(&mut order, u64::MAX, 1000).unwrap();
 
@@ -320,47 +1409,29 @@ mod tests {
         // tick 21 should still be in the book and have 200 quantity left
         assert!(book.ticks.contains_key(&21));
 
-        // assert with total liq on tick once tracking is implemented
-        // assert_eq!(book.ticks.get(&21).unwrap().quantity(), 200);
+        assert_eq!(*book.ticks.get(&21).unwrap().total_liquidity(), 200);
 
         // next ask tick should be updated to 21
         assert_eq!(book.next_ask_tick, 21);
 
-        // We expect the USD balance to be equal to the quantity filled at each tick times the prices at each tick
-        assert_eq!(order.owner().borrow_mut().balance(Currency::USD), BASE_USD_AMT + 300 * (10 + 13 + 14) + 100 * 21);
+        // We expect the USD balance to drop by the quantity filled at each tick times the prices at each tick (ticks
+        // are tenths of a USD), and the OSMO balance to grow by the quantity filled
+        assert_eq!(order.owner().borrow_mut().balance(Currency::USD), BASE_USD_AMT - (300 * (10 + 13 + 14) + 100 * 21) / 10);
+        assert_eq!(order.owner().borrow_mut().balance(Currency::OSMO), BASE_OSMO_AMT + 1000);
+        assert!(book.verify_escrow().is_ok());
     }
 
-    // implement a similar run market ask test but with a specified end tick at 15
+    // implement a similar run market ask test but with a specified end tick at 21
     #[test]
     fn test_run_market_bid_with_end_tick() {
-        let mut book = Orderbook::new(0);
-
-        // set next ask tick on book to tick 10
-        book.next_ask_tick = 10;
-
-        // create orders on tick 10 (next ask tick)
-        create_limit_orders(&mut book, &mut 10, 3, 100, &OrderDirection::Ask);
+        // create 300 units of depth on tick 10 (next ask tick) and on ticks 13, 14 and 21
+        let mut book = BookBuilder::new()
+            .with_asks(&[(10, 300), (13, 300), (14, 300), (21, 300)])
+            .build();
 
-        // create orders on ticks 13, 14, and 21 to add more depth on the ask side
-        create_limit_orders(&mut book, &mut 13, 3, 100, &OrderDirection::Ask);
-        create_limit_orders(&mut book, &mut 14, 3, 100, &OrderDirection::Ask);
-        create_limit_orders(&mut book, &mut 21, 3, 100, &OrderDirection::Ask);
-
-        // run market ask for 1000 quantity
-        // since market depth is 1200 (four ticks with 300 each), this should fill up to tick 15
-        let acc = Rc::new(RefCell::new(Account::new(0, AccountType::Individual)));
-        let mut order = Order::new(
-            13,
-            0,
-            0,
-            Rc::clone(&acc),
-            OrderType::Market,
-            OrderDirection::Ask,
-            1000,
-        );
-
-        // Fund order account
-        fund_account_for_order(&mut order);
+        // run market bid for 1000 quantity
+        // since market depth is 1200 (four ticks with 300 each), this would fill into tick 21 without the end tick
+        let mut order = funded_market_order(OrderDirection::Bid, 1000);
 
         // System under test
         book.run_market_bid(&mut order, 21, 1000).unwrap();
@@ -373,48 +1444,32 @@ mod tests {
         // tick 21 should still be in the book and remain untouched, as we stopped filling before processing it
         assert!(book.ticks.contains_key(&21));
 
-        // assert with total liq on tick once tracking is implemented
-        // assert_eq!(book.ticks.get(&21).unwrap().quantity(), 300);
+        assert_eq!(*book.ticks.get(&21).unwrap().total_liquidity(), 300);
 
         // next ask tick should be updated to 21
         assert_eq!(book.next_ask_tick, 21);
 
-        // We expect the USD balance to be equal to the quantity filled at each tick times the prices at each tick
-        assert_eq!(order.owner().borrow_mut().balance(Currency::USD), BASE_USD_AMT + 300 * (10 + 13 + 14));
+        // We expect the USD balance to drop by the quantity filled at each tick times the prices at each tick
+        assert_eq!(order.owner().borrow_mut().balance(Currency::USD), BASE_USD_AMT - 300 * (10 + 13 + 14) / 10);
+        assert!(book.verify_escrow().is_ok());
     }
 
-    // implement test for run_market_bid, which is similar to ask but in the opposite tick direction
+    // implement test for run_market_ask, which is similar to bid but in the opposite tick direction
     #[test]
     fn test_run_market_ask() {
-        let mut book = Orderbook::new(0);
-
-        // set next bid tick on book to tick 10
-        book.next_bid_tick = 21;
-
-        // create orders on ticks 10, 13, 14, and 21 (latter is next bid tick)
-        create_limit_orders(&mut book, &mut 10, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 13, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 14, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 21, 3, 100, &OrderDirection::Bid);
-
-        // run market bid for 1000 quantity
-        // since market depth is 1200 (four ticks with 300 each), this should fill up to tick 21
-        let mut order = Order::new(
-            13,
-            0,
-            0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
-            OrderType::Market,
-            OrderDirection::Bid,
-            1000,
-        );
+        // create 300 units of depth on ticks 10, 13, 14, and 21 (latter is next bid tick)
+        let mut book = BookBuilder::new()
+            .with_bids(&[(10, 300), (13, 300), (14, 300), (21, 300)])
+            .build();
 
-        fund_account_for_order(&mut order);
+        // run market ask for 1000 quantity
+        // since market depth is 1200 (four ticks with 300 each), this should fill down to tick 10
+        let mut order = funded_market_order(OrderDirection::Ask, 1000);
 
         // System under test
         book.run_market_ask(&mut order, u64::MIN, 1000).unwrap();
 
-        // ticks 10, 13, and 14 should all be emptied and removed from the book
+        // ticks 13, 14, and 21 should all be emptied and removed from the book
         assert!(!book.ticks.contains_key(&13));
         assert!(!book.ticks.contains_key(&14));
         assert!(!book.ticks.contains_key(&21));
@@ -422,43 +1477,29 @@ mod tests {
         // tick 10 should still be in the book and have 200 quantity left
         assert!(book.ticks.contains_key(&10));
 
-        // assert with total liq on tick once tracking is implemented
-        // assert_eq!(book.ticks.get(&10).unwrap().quantity(), 200);
+        assert_eq!(*book.ticks.get(&10).unwrap().total_liquidity(), 200);
 
         // next bid tick should be updated to 10
         assert_eq!(book.next_bid_tick, 10);
 
-        // We expect the OSMO balance to be equal to the quantity filled on each tick
-        assert_eq!(order.owner().borrow_mut().balance(Currency::OSMO), BASE_OSMO_AMT + 300 * 3 + 100 * 1);
+        // We expect the OSMO balance to drop by the quantity filled on each tick, and the USD balance to grow by
+        // what the bids paid for it
+        assert_eq!(order.owner().borrow_mut().balance(Currency::OSMO), BASE_OSMO_AMT - 300 * 3 - 100);
+        assert_eq!(order.owner().borrow_mut().balance(Currency::USD), BASE_USD_AMT + (300 * (21 + 14 + 13) + 100 * 10) / 10);
+        assert!(book.verify_escrow().is_ok());
     }
 
     // now write test with cutoff on 13
     #[test]
     fn test_run_market_ask_with_end_tick() {
-        let mut book = Orderbook::new(0);
-
-        // set next bid tick on book to tick 10
-        book.next_bid_tick = 21;
+        // create 300 units of depth on ticks 10, 13, 14, and 21 (latter is next bid tick)
+        let mut book = BookBuilder::new()
+            .with_bids(&[(10, 300), (13, 300), (14, 300), (21, 300)])
+            .build();
 
-        // create orders on ticks 10, 13, 14, and 21 (latter is next bid tick)
-        create_limit_orders(&mut book, &mut 10, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 13, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 14, 3, 100, &OrderDirection::Bid);
-        create_limit_orders(&mut book, &mut 21, 3, 100, &OrderDirection::Bid);
-
-        // run market bid for 1000 quantity
-        // since market depth is 1200 (four ticks with 300 each), this should fill up to tick 13
-        let mut order = Order::new(
-            13,
-            0,
-            0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
-            OrderType::Market,
-            OrderDirection::Bid,
-            1000,
-        );
-
-        fund_account_for_order(&mut order);
+        // run market ask for 1000 quantity
+        // since market depth is 1200 (four ticks with 300 each), this would fill down to tick 10 without the end tick
+        let mut order = funded_market_order(OrderDirection::Ask, 1000);
 
         // System under test
         book.run_market_ask(&mut order, 13, 1000).unwrap();
@@ -471,14 +1512,1035 @@ mod tests {
         assert!(book.ticks.contains_key(&10));
         assert!(book.ticks.contains_key(&13));
 
-        // assert with total liq on tick once tracking is implemented
-        // assert_eq!(book.ticks.get(&10).unwrap().quantity(), 300);
-        // assert_eq!(book.ticks.get(&13).unwrap().quantity(), 300);
+        assert_eq!(*book.ticks.get(&10).unwrap().total_liquidity(), 300);
+        assert_eq!(*book.ticks.get(&13).unwrap().total_liquidity(), 300);
 
-        // next bid tick should be updated to 10
+        // next bid tick should be updated to 13
         assert_eq!(book.next_bid_tick, 13);
 
-        // We expect the OSMO balance to be equal to the quantity filled on each tick
-        assert_eq!(order.owner().borrow_mut().balance(Currency::OSMO), BASE_OSMO_AMT +  300 * 2);
+        // We expect the OSMO balance to drop by the quantity filled on each tick
+        assert_eq!(order.owner().borrow_mut().balance(Currency::OSMO), BASE_OSMO_AMT - 300 * 2);
+        assert!(book.verify_escrow().is_ok());
+    }
+
+    #[test]
+    fn test_market_order_slippage_reject() {
+        let mut book = BookBuilder::new()
+            .with_asks(&[(10, 300), (13, 300), (21, 300)])
+            .build();
+
+        // 700 units would have to reach tick 21, which is more than 5 ticks past the best ask of 10
+        let mut order = funded_market_order(OrderDirection::Bid, 700);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Ticks(5), SlippagePolicy::Reject)));
+
+        let error = book.handle_order(&mut order).unwrap_err();
+        assert_eq!(error.to_string(), "Market order exceeds maximum slippage");
+        assert_eq!(error.code(), RejectCode::RiskLimitExceeded);
+
+        // Nothing should have been filled
+        assert_eq!(*book.ticks.get(&10).unwrap().total_liquidity(), 300);
+        assert_eq!(order.owner().borrow().balance(Currency::OSMO), BASE_OSMO_AMT);
+    }
+
+    #[test]
+    fn test_market_order_slippage_truncate_from_account_config() {
+        let mut book = BookBuilder::new()
+            .with_bids(&[(10, 300), (18, 300), (21, 300)])
+            .build();
+
+        // The account allows selling at most 3 ticks below the best bid of 21
+        let mut config = RiskConfig::new();
+        config.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Ticks(3), SlippagePolicy::Truncate)));
+        book.set_risk_config(7, config);
+
+        let mut order = funded_market_order(OrderDirection::Ask, 1000);
+        book.handle_order(&mut order).unwrap();
+
+        // Ticks 21 and 18 are consumed while tick 10 is left untouched
+        assert!(!book.ticks.contains_key(&21));
+        assert!(!book.ticks.contains_key(&18));
+        assert_eq!(*book.ticks.get(&10).unwrap().total_liquidity(), 300);
+        assert_eq!(order.owner().borrow().balance(Currency::OSMO), BASE_OSMO_AMT - 600);
+    }
+
+    #[test]
+    fn test_market_order_worst_tick_bound() {
+        let book = || BookBuilder::new().with_asks(&[(10, 300), (13, 300), (21, 300)]).build();
+
+        // 700 units would have to reach tick 21, past the worst acceptable tick of 13
+        let mut book_rejecting = book();
+        let mut order = funded_market_order(OrderDirection::Bid, 700);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::WorstTick(13), SlippagePolicy::Reject)));
+        assert_eq!(book_rejecting.handle_order(&mut order).unwrap_err(), OrderbookError::SlippageExceeded);
+        assert!(book_rejecting.trades().is_empty());
+
+        let mut book = book();
+        let mut order = funded_market_order(OrderDirection::Bid, 700);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::WorstTick(13), SlippagePolicy::Truncate)));
+        book.handle_order(&mut order).unwrap();
+        assert_eq!(book.snapshot().asks(), &vec![(21, 300)]);
+        assert_eq!(order.owner().borrow().balance(Currency::OSMO), BASE_OSMO_AMT + 600);
+    }
+
+    #[test]
+    fn test_market_order_notional_bound() {
+        // Buying 400 in full costs 300 * 1.0 + 100 * 1.3 = 430
+        let mut book = BookBuilder::new().with_asks(&[(10, 300), (13, 300)]).build();
+        let mut order = funded_market_order(OrderDirection::Bid, 400);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Notional(420), SlippagePolicy::Reject)));
+        assert_eq!(book.handle_order(&mut order).unwrap_err(), OrderbookError::SlippageExceeded);
+
+        // Truncated, it fills as far as an average of 420 / 400 = 1.05 per unit: 300 at 1.0 and 60 at 1.3
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Notional(420), SlippagePolicy::Truncate)));
+        book.handle_order(&mut order).unwrap();
+        assert_eq!(book.snapshot().asks(), &vec![(13, 240)]);
+        assert_eq!(order.owner().borrow().balance(Currency::USD), BASE_USD_AMT - 378);
+
+        // A sell's minimum output: 400 into bids of 300 at 2.1 and 300 at 1.8 gets back 810
+        let bids = || BookBuilder::new().with_bids(&[(21, 300), (18, 300)]).build();
+        let mut book = bids();
+        let mut order = funded_market_order(OrderDirection::Ask, 400);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Notional(800), SlippagePolicy::Reject)));
+        book.handle_order(&mut order).unwrap();
+        assert_eq!(order.owner().borrow().balance(Currency::USD), BASE_USD_AMT + 810);
+
+        let mut book = bids();
+        let mut order = funded_market_order(OrderDirection::Ask, 400);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Notional(820), SlippagePolicy::Truncate)));
+        book.handle_order(&mut order).unwrap();
+        assert_eq!(book.snapshot().bids(), &vec![(18, 240)]);
+        assert_eq!(order.owner().borrow().balance(Currency::USD), BASE_USD_AMT + (300 * 21 + 60 * 18) / 10);
+
+        // Strict orders count only what the bound lets them fill
+        let mut book = bids();
+        let mut order = funded_market_order(OrderDirection::Ask, 400);
+        order.set_strict(true);
+        order.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Notional(820), SlippagePolicy::Truncate)));
+        assert_eq!(book.handle_order(&mut order).unwrap_err(), OrderbookError::InsufficientLiquidity { filled: 360, requested: 400 });
+    }
+
+    #[test]
+    fn test_version_bumped_on_mutation() {
+        let mut book = Orderbook::new(0);
+        assert_eq!(*book.version(), 0);
+
+        // Resting a limit order is a mutation
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, BASE_OSMO_AMT)]).build();
+        book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 100)).unwrap();
+        assert_eq!(*book.version(), 1);
+
+        // A market order against an empty bid side changes nothing
+        let mut market_ask = funded_market_order(OrderDirection::Ask, 100);
+        book.handle_order(&mut market_ask).unwrap();
+        assert_eq!(*book.version(), 1);
+
+        // Filling the resting ask is a mutation
+        let mut market_bid = funded_market_order(OrderDirection::Bid, 50);
+        book.handle_order(&mut market_bid).unwrap();
+        assert_eq!(*book.version(), 2);
+    }
+
+    #[test]
+    fn test_orders_priced_outside_the_tick_range_are_rejected() {
+        let mut book = Orderbook::new(0);
+        book.set_tick_converter(TickConverter::from_tick_size("0.05").unwrap().with_tick_range(10, 100));
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, BASE_OSMO_AMT), (Currency::USD, 10000)]).build();
+
+        let error = book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 101, 10)).unwrap_err();
+        assert_eq!(error, OrderbookError::InvalidTick { tick_id: 101, nearest_valid: None });
+        let mut stop = market_order(&maker, OrderDirection::Bid, 10);
+        stop.set_trigger_tick(Some(9));
+        assert!(book.handle_order(&mut stop).is_err());
+        assert!(book.cross(&maker, &maker, 5, 10).is_err());
+
+        // Inside the range settlement follows the tick size: 10 at 5.00 costs 50
+        book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 100, 10)).unwrap();
+        assert_eq!(maker.borrow().balance(Currency::USD), 10000 - 50);
+    }
+
+    #[test]
+    fn test_unfunded_limit_rejected_with_code() {
+        let mut book = Orderbook::new(0);
+        let mut order = limit_order(&AccountBuilder::new(0).build(), OrderDirection::Bid, 10, 100);
+
+        let error = book.handle_order(&mut order).unwrap_err();
+        assert_eq!(error.to_string(), "Insufficient funds");
+        assert_eq!(error.code(), RejectCode::InsufficientFunds);
+        assert_eq!(error, OrderbookError::InsufficientFunds { currency: Currency::USD, required: 100, available: 0 });
+    }
+
+    #[test]
+    fn test_orders_worth_more_than_any_balance_are_refused() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let whale = AccountBuilder::funded(1, &[(Currency::USD, u64::MAX - 1), (Currency::OSMO, 10)]).build();
+
+        // The value saturates rather than overflowing, so the bid and the cross ask for more than the account holds
+        let error = book.handle_order(&mut limit_order(&whale, OrderDirection::Bid, 11, u64::MAX)).unwrap_err();
+        assert_eq!(error, OrderbookError::InsufficientFunds { currency: Currency::USD, required: u64::MAX, available: u64::MAX - 1 });
+        assert_eq!(book.cross(&whale, &whale, 12, u64::MAX).unwrap_err().code(), RejectCode::InsufficientFunds);
+        assert_eq!(whale.borrow().balance(Currency::USD), u64::MAX - 1);
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_market_order_pro_rata_matching() {
+        let small_maker = AccountBuilder::funded(1, &[(Currency::OSMO, BASE_OSMO_AMT)]).build();
+        let large_maker = AccountBuilder::funded(2, &[(Currency::OSMO, BASE_OSMO_AMT)]).build();
+        let mut book = BookBuilder::new()
+            .with_matching_policy(MatchingPolicy::ProRata { min_allocation: 1 })
+            .build();
+        book.handle_order(&mut limit_order(&small_maker, OrderDirection::Ask, 10, 100)).unwrap();
+        book.handle_order(&mut limit_order(&large_maker, OrderDirection::Ask, 10, 300)).unwrap();
+
+        // Under FIFO the older 100 unit order would be filled first; pro-rata splits 200 units 50/150
+        let mut order = funded_market_order(OrderDirection::Bid, 200);
+        book.handle_order(&mut order).unwrap();
+
+        assert_eq!(small_maker.borrow().balance(Currency::USD), 50);
+        assert_eq!(large_maker.borrow().balance(Currency::USD), 150);
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 200);
+    }
+
+    #[test]
+    fn test_market_order_round_robin_matching() {
+        let whale = AccountBuilder::funded(1, &[(Currency::OSMO, BASE_OSMO_AMT)]).build();
+        let small_maker = AccountBuilder::funded(2, &[(Currency::OSMO, BASE_OSMO_AMT)]).build();
+        let mut book = BookBuilder::new()
+            .with_matching_policy(MatchingPolicy::RoundRobin { slice: 25 })
+            .build();
+        book.handle_order(&mut limit_order(&whale, OrderDirection::Ask, 10, 10000)).unwrap();
+        book.handle_order(&mut limit_order(&small_maker, OrderDirection::Ask, 10, 50)).unwrap();
+
+        // Under FIFO the whale would take all 100 units; in slices of 25 the smaller order fills completely
+        let mut order = funded_market_order(OrderDirection::Bid, 100);
+        book.handle_order(&mut order).unwrap();
+
+        assert_eq!(whale.borrow().balance(Currency::USD), 50);
+        assert_eq!(small_maker.borrow().balance(Currency::USD), 50);
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 9950);
+        assert_eq!(book.trades().len(), 4);
+    }
+
+    #[test]
+    fn test_limit_price_protection() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (30, 100)]).with_bid(8, 100).build();
+        book.set_price_protection_ticks(Some(5));
+
+        // A bid 20 ticks through the best ask is rejected without touching the book or the trader's balance
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, BASE_USD_AMT), (Currency::OSMO, BASE_OSMO_AMT)]).build();
+        let error = book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 30, 150)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::PriceProtection);
+        assert_eq!(trader.borrow().balance(Currency::USD), BASE_USD_AMT);
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 100);
+
+        // Within the limit, and on the other side of the book, orders go through as usual
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 15, 50)).unwrap();
+        assert!(book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 1, 10)).is_err());
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 3, 10)).unwrap();
+
+        // Flagging the order as intentionally aggressive skips the check
+        let mut aggressive = limit_order(&trader, OrderDirection::Bid, 30, 50);
+        aggressive.set_skip_price_protection(true);
+        book.handle_order(&mut aggressive).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_orders_within_window() {
+        let mut book = Orderbook::new(0);
+        let mut config = RiskConfig::new();
+        config.set_duplicate_guard(Some(DuplicateGuard::new(Duration::from_secs(60), DuplicatePolicy::Reject)));
+        book.set_risk_config(1, config.clone());
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, BASE_USD_AMT)]).build();
+
+        // The repeated order is rejected, while a different size or an account without the guard goes through
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 100)).unwrap();
+        let error = book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 100)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::DuplicateOrder);
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 101)).unwrap();
+        let other_trader = AccountBuilder::funded(2, &[(Currency::USD, BASE_USD_AMT)]).build();
+        book.handle_order(&mut limit_order(&other_trader, OrderDirection::Bid, 10, 100)).unwrap();
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 301);
+
+        // Under the flag policy the duplicate is placed and counted
+        config.set_duplicate_guard(Some(DuplicateGuard::new(Duration::from_secs(60), DuplicatePolicy::Flag)));
+        book.set_risk_config(1, config);
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 100)).unwrap();
+        assert_eq!(*book.flagged_duplicates(), 1);
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 401);
+    }
+
+    #[test]
+    fn test_duplicate_window_expires() {
+        let mut book = Orderbook::new(0);
+        let mut config = RiskConfig::new();
+        config.set_duplicate_guard(Some(DuplicateGuard::new(Duration::from_millis(10), DuplicatePolicy::Reject)));
+        book.set_risk_config(1, config);
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, BASE_USD_AMT)]).build();
+
+        // Once the window has passed the same order is no longer a duplicate
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 100)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 100)).unwrap();
+    }
+
+    #[test]
+    fn test_sweep_leaves_book_untouched_when_taker_cannot_pay() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (20, 100)]).build();
+
+        // Buying both levels costs 300 USD but the taker only has 200
+        let taker = AccountBuilder::funded(1, &[(Currency::USD, 200)]).build();
+        let error = book.handle_order(&mut market_order(&taker, OrderDirection::Bid, 200)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InsufficientFunds);
+
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 100);
+        assert_eq!(*book.ticks().get(&20).unwrap().total_liquidity(), 100);
+        assert_eq!(*book.version(), 2);
+        assert_eq!(taker.borrow().balance(Currency::USD), 200);
+        assert_eq!(taker.borrow().balance(Currency::OSMO), 0);
+    }
+
+    #[test]
+    fn test_sweep_emptying_side_resets_best_price() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (20, 100)]).build();
+
+        let mut order = funded_market_order(OrderDirection::Bid, 500);
+        book.handle_order(&mut order).unwrap();
+
+        // With no asks left the best ask goes back to its sentinel instead of pointing at a removed tick
+        assert!(book.ticks().is_empty());
+        assert_eq!(book.next_ask_tick, u64::MAX);
+        assert_eq!(order.owner().borrow().balance(Currency::OSMO), BASE_OSMO_AMT + 200);
+    }
+
+    #[test]
+    fn test_crossing_limit_rejected_up_front_when_remainder_unaffordable() {
+        let mut book = BookBuilder::new().with_ask(10, 100).build();
+
+        // Crossing 100 at 1.0 costs 100 USD and resting the other 100 at 1.2 needs another 120
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 200)]).build();
+        let error = book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 12, 200)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InsufficientFunds);
+
+        // Nothing was filled and no empty tick was left behind for the remainder
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 100);
+        assert!(!book.ticks().contains_key(&12));
+        assert_eq!(trader.borrow().balance(Currency::USD), 200);
+
+        // With enough funds the same order fills and rests as before
+        trader.borrow_mut().deposit(Currency::USD, 20);
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 12, 200)).unwrap();
+        assert_eq!(*book.ticks().get(&12).unwrap().total_liquidity(), 100);
+        assert_eq!(trader.borrow().balance(Currency::USD), 0);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 100);
+    }
+
+    #[test]
+    fn test_limit_at_opposite_best_crosses() {
+        let mut book = BookBuilder::new().with_bid(9, 100).with_ask(10, 100).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000), (Currency::OSMO, 1000)]).build();
+
+        // A bid priced exactly at the best ask takes it instead of resting next to it on the same tick
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, 150)).unwrap();
+        let tick = book.ticks().get(&10).unwrap();
+        assert_eq!(*tick.orders().front().unwrap().order_direction(), OrderDirection::Bid);
+        assert_eq!(*tick.total_liquidity(), 50);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 1100);
+
+        // Likewise an ask at the best bid fills against it
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 10, 50)).unwrap();
+        assert!(!book.ticks().contains_key(&10));
+        assert_eq!(*book.ticks().get(&9).unwrap().total_liquidity(), 100);
+    }
+
+    #[test]
+    fn test_limit_outside_price_range_rejected() {
+        let mut book = Orderbook::new(0);
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000), (Currency::OSMO, 1000)]).build();
+
+        // A bid at tick 0 would escrow nothing, an ask at the top sentinel could never be reached
+        for (direction, tick_id) in [(OrderDirection::Bid, 0), (OrderDirection::Ask, u64::MAX)] {
+            let error = book.handle_order(&mut limit_order(&trader, direction, tick_id, 10)).unwrap_err();
+            assert_eq!(error.code(), RejectCode::InvalidOrder);
+        }
+        assert!(book.ticks().is_empty());
+
+        // A bid whose value doesn't fit in a u64 is refused rather than overflowing
+        let error = book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 10, u64::MAX)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_stop_orders_wait_for_trigger() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (12, 100), (14, 100)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000)]).build();
+
+        // Buy stops at 12 (market) and 14 (limit at 14) sit off the book until trades reach their triggers
+        let mut stop_market = market_order(&trader, OrderDirection::Bid, 150);
+        stop_market.set_trigger_tick(Some(12));
+        book.handle_order(&mut stop_market).unwrap();
+        let mut stop_limit = limit_order(&trader, OrderDirection::Bid, 14, 50);
+        stop_limit.set_trigger_tick(Some(14));
+        book.handle_order(&mut stop_limit).unwrap();
+        assert_eq!(book.pending_stops(), (2, 0));
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 100);
+        assert_eq!(trader.borrow().balance(Currency::USD), 100000);
+
+        // Taking all of tick 10 doesn't reach 12
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 100)).unwrap();
+        assert_eq!(book.pending_stops(), (2, 0));
+        assert_eq!(*book.last_trade_tick(), Some(10));
+
+        // A trade at 12 fires the first stop, whose fill at 14 in turn fires the second
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 10)).unwrap();
+        assert_eq!(book.pending_stops(), (0, 0));
+        assert!(!book.ticks().contains_key(&12));
+        // The stop limit only found 40 left at 14, so the rest of it now rests there as a bid
+        let tick = book.ticks().get(&14).unwrap();
+        assert_eq!(*tick.orders().front().unwrap().order_direction(), OrderDirection::Bid);
+        assert_eq!(*tick.total_liquidity(), 10);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 100 + 10 + 150 + 40);
+    }
+
+    #[test]
+    fn test_sell_stop_activation_and_failure() {
+        let mut book = BookBuilder::new().with_bids(&[(8, 100), (10, 100)]).build();
+        let seller = AccountBuilder::funded(1, &[(Currency::OSMO, 1000)]).build();
+        let unfunded = AccountBuilder::new(2).build();
+
+        // Sell stops fire when trades print at or below their trigger, nearest trigger first
+        let mut stop = market_order(&seller, OrderDirection::Ask, 50);
+        stop.set_trigger_tick(Some(9));
+        book.handle_order(&mut stop).unwrap();
+        let mut unaffordable = market_order(&unfunded, OrderDirection::Ask, 50);
+        unaffordable.set_trigger_tick(Some(10));
+        book.handle_order(&mut unaffordable).unwrap();
+        assert_eq!(book.pending_stops(), (0, 2));
+
+        // Trading at 10 fires the unfunded stop, which is dropped, while the stop at 9 keeps waiting
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 20)).unwrap();
+        assert_eq!(book.pending_stops(), (0, 1));
+        assert_eq!(*book.failed_stop_activations(), 1);
+
+        // Clearing tick 10 trades down to 8 and fires the last stop
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 90)).unwrap();
+        assert_eq!(book.pending_stops(), (0, 0));
+        assert_eq!(*book.ticks().get(&8).unwrap().total_liquidity(), 100 - 10 - 50);
+    }
+
+    #[test]
+    fn test_if_touched_orders_fire_on_favorable_prints() {
+        let mut book = BookBuilder::new().with_bids(&[(8, 100), (10, 100)]).with_ask(14, 100).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000), (Currency::OSMO, 1000)]).build();
+
+        // Buy if-touched at 9 waits for trades at or below 9, sell if-touched at 12 for trades at or above 12
+        let mut buy_touch = market_order(&trader, OrderDirection::Bid, 30);
+        buy_touch.set_trigger_tick(Some(9));
+        buy_touch.set_trigger_type(TriggerType::IfTouched);
+        book.handle_order(&mut buy_touch).unwrap();
+        let mut sell_touch = market_order(&trader, OrderDirection::Ask, 20);
+        sell_touch.set_trigger_tick(Some(12));
+        sell_touch.set_trigger_type(TriggerType::IfTouched);
+        book.handle_order(&mut sell_touch).unwrap();
+        assert_eq!(book.pending_touches(), (1, 1));
+        assert_eq!(book.pending_stops(), (0, 0));
+
+        // Trading at 10 is above the buy's trigger, where a sell stop would have fired
+        book.handle_order(&mut market_order(&trader, OrderDirection::Ask, 100)).unwrap();
+        assert_eq!(book.pending_touches(), (1, 1));
+
+        // A print at 8 fires the buy, whose fill at 14 in turn fires the sell into the bids at 8
+        book.handle_order(&mut market_order(&trader, OrderDirection::Ask, 10)).unwrap();
+        assert_eq!(book.pending_touches(), (0, 0));
+        assert_eq!(*book.touch_activations(), 2);
+        assert_eq!(*book.stop_activations(), 0);
+        assert_eq!(*book.ticks().get(&14).unwrap().total_liquidity(), 70);
+        assert_eq!(*book.ticks().get(&8).unwrap().total_liquidity(), 70);
+    }
+
+    #[test]
+    fn test_conditional_order_waits_for_book_state() {
+        let mut book = BookBuilder::new().with_bid(9, 100).with_ask(11, 100).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000), (Currency::OSMO, 1000)]).build();
+
+        // Only join the bid once the spread has widened past 3 ticks
+        let mut bid = limit_order(&trader, OrderDirection::Bid, 10, 40);
+        bid.set_condition(Some(BookCondition::new(BookMetric::Spread, Comparison::Above, 3)));
+        book.handle_order(&mut bid).unwrap();
+        assert_eq!(book.pending_conditionals(), 1);
+        assert!(!book.ticks().contains_key(&10));
+        assert_eq!(trader.borrow().balance(Currency::USD), 100000);
+
+        // Lifting the ask at 11 leaves the book empty on that side, which doesn't count as a wide spread
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 100)).unwrap();
+        assert_eq!(book.pending_conditionals(), 1);
+
+        // A new ask at 14 makes the spread 5, so the bid is placed in the same call
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 14, 10)).unwrap();
+        assert_eq!(book.pending_conditionals(), 0);
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 40);
+    }
+
+    #[test]
+    fn test_conditional_order_rejected_on_activation() {
+        let mut book = BookBuilder::new().with_ask(11, 100).build();
+        let unfunded = AccountBuilder::new(2).build();
+
+        // The condition already holds, so the order runs at once and its rejection is only counted
+        let mut bid = market_order(&unfunded, OrderDirection::Bid, 10);
+        bid.set_condition(Some(BookCondition::new(BookMetric::AskDepth, Comparison::Above, 50)));
+        book.handle_order(&mut bid).unwrap();
+        assert_eq!(book.pending_conditionals(), 0);
+        assert_eq!(*book.failed_conditional_activations(), 1);
+        assert_eq!(*book.ticks().get(&11).unwrap().total_liquidity(), 100);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_limit_price_must_respect_tick_size_band() {
+        let mut book = Orderbook::new(0);
+        book.set_tick_sizes(TickSizeTable::new().with_band(1000, 10));
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000000)]).build();
+
+        // Below the band every tick is fine, above it only whole prices are
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 995, 10)).unwrap();
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 1010, 10)).unwrap();
+        let error = book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 1015, 10)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InvalidOrder);
+        assert!(error.to_string().contains("1010 and 1020"));
+        assert!(!book.ticks().contains_key(&1015));
+    }
+
+    #[test]
+    fn test_memory_stats_and_compact() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (11, 100)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000000)]).build();
+        for _ in 0..500 {
+            book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 5, 1)).unwrap();
+        }
+
+        let stats = book.memory_stats();
+        assert_eq!((*stats.levels(), *stats.resting_orders(), *stats.trades()), (3, 502, 0));
+
+        // Selling into the bids empties the queue at 5 but its capacity stays allocated until compaction
+        let seller = AccountBuilder::funded(2, &[(Currency::OSMO, 1000)]).build();
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 499)).unwrap();
+        let swept = book.memory_stats();
+        assert_eq!((*swept.resting_orders(), *swept.trades()), (3, 499));
+
+        book.compact();
+        let compacted = book.memory_stats();
+        assert!(compacted.approx_bytes() < swept.approx_bytes());
+        assert_eq!(*compacted.resting_orders(), 3);
+        assert_eq!(*book.ticks().get(&5).unwrap().total_liquidity(), 1);
+    }
+
+    #[test]
+    fn test_archive_trades_keeps_ids_running() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 1), (11, 1), (12, 1), (13, 1)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000)]).build();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 3)).unwrap();
+
+        let mut storage = MemoryStorage::new();
+        assert_eq!(book.archive_trades(&mut storage, 1).unwrap(), 2);
+        assert_eq!(book.archive_trades(&mut storage, 1).unwrap(), 0);
+        assert_eq!(*book.archived_trades(), 2);
+        assert_eq!(book.trades().len(), 1);
+        assert_eq!(*book.trades()[0].trade_id(), 2);
+
+        // Trades keep counting across the archive, and archived ones come back as they were
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 1)).unwrap();
+        assert_eq!(*book.trades()[1].trade_id(), 3);
+        let archived = book.archived_trade_history(&storage).unwrap();
+        assert_eq!(archived.iter().map(|trade| (*trade.trade_id(), *trade.tick_id())).collect::<Vec<_>>(), vec![(0, 10), (1, 11)]);
+        assert_eq!(archived[1].to_record(book.created_at), storage.read(TRADES_STREAM).unwrap()[1]);
+
+        // Other books archive to a stream of their own
+        let mut other = BookBuilder::new().with_book_id(1).with_ask(10, 1).build();
+        other.handle_order(&mut market_order(&trader, OrderDirection::Bid, 1)).unwrap();
+        assert_eq!(other.archive_trades(&mut storage, 0).unwrap(), 1);
+        assert_eq!((storage.read(TRADES_STREAM).unwrap().len(), storage.read("trades-1").unwrap().len()), (2, 1));
+    }
+
+    #[test]
+    fn test_cancel_order_refunds_escrow() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000), (Currency::OSMO, 100)]).build();
+        let mut bid = limit_order(&trader, OrderDirection::Bid, 9, 50);
+        bid.set_order_id(7);
+        book.handle_order(&mut bid).unwrap();
+        let mut ask = limit_order(&trader, OrderDirection::Ask, 15, 40);
+        ask.set_order_id(8);
+        book.handle_order(&mut ask).unwrap();
+        // 50 bid at 0.9
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - 45);
+        let version = *book.version();
+
+        // Only the owner can cancel, and the escrow comes back in full
+        assert_eq!(book.cancel_order(7, 2).unwrap_err().code(), RejectCode::UnknownOrder);
+        assert_eq!(*book.cancel_order(7, 1).unwrap().quantity(), 50);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000);
+        assert!(book.ticks().get(&9).is_none());
+        assert_eq!(*book.next_bid_tick(), u64::MIN);
+        assert!(*book.version() > version);
+        assert!(book.cancel_order(7, 1).is_err());
+
+        // A partly filled ask refunds only what is still resting: the bid takes all 10 at 12, then 15 of the 40 at 15
+        let buyer = AccountBuilder::funded(2, &[(Currency::USD, 1000)]).build();
+        book.handle_order(&mut limit_order(&buyer, OrderDirection::Bid, 15, 25)).unwrap();
+        assert_eq!(*book.next_ask_tick(), 15);
+        assert_eq!(*book.cancel_order(8, 1).unwrap().quantity(), 25);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 100 - 15);
+        assert_eq!(*book.next_ask_tick(), u64::MAX);
+    }
+
+    #[test]
+    fn test_amend_order_keeps_priority_only_when_shrinking() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let other = AccountBuilder::funded(2, &[(Currency::USD, 1000)]).build();
+        for (order_id, owner) in [(7, &trader), (8, &other)] {
+            let mut bid = limit_order(owner, OrderDirection::Bid, 9, 50);
+            bid.set_order_id(order_id);
+            book.handle_order(&mut bid).unwrap();
+        }
+        let queue = |book: &Orderbook, tick_id: u64| {
+            book.ticks().get(&tick_id).map_or(vec![], |tick| tick.orders().iter().map(|order| (*order.order_id(), *order.quantity())).collect::<Vec<_>>())
+        };
+
+        // Shrinking at the same price keeps the front of the queue and refunds the difference
+        assert_eq!(*book.amend_order(7, 1, 30, 9).unwrap().quantity(), 50);
+        assert_eq!(queue(&book, 9), vec![(7, 30), (8, 50)]);
+        assert_eq!(*book.ticks().get(&9).unwrap().total_liquidity(), 80);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - 27);
+
+        // Growing it sends it to the back, and a new price moves it to a new level
+        book.amend_order(7, 1, 40, 9).unwrap();
+        assert_eq!(queue(&book, 9), vec![(8, 50), (7, 40)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - 36);
+        book.amend_order(7, 1, 40, 11).unwrap();
+        assert_eq!(queue(&book, 9), vec![(8, 50)]);
+        assert_eq!(queue(&book, 11), vec![(7, 40)]);
+        assert_eq!(*book.next_bid_tick(), 11);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - 44);
+        book.amend_order(7, 1, 40, 8).unwrap();
+        assert_eq!(*book.next_bid_tick(), 9);
+        assert!(book.ticks().get(&11).is_none());
+        assert!(book.verify_escrow().is_ok());
+    }
+
+    #[test]
+    fn test_rejected_amend_leaves_order_untouched() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        book.set_tick_sizes(TickSizeTable::new().with_band(100, 10));
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100)]).build();
+        let mut bid = limit_order(&trader, OrderDirection::Bid, 9, 50);
+        bid.set_order_id(7);
+        book.handle_order(&mut bid).unwrap();
+        let version = *book.version();
+
+        assert_eq!(book.amend_order(7, 2, 40, 9).unwrap_err().code(), RejectCode::UnknownOrder);
+        assert_eq!(book.amend_order(8, 1, 40, 9).unwrap_err().code(), RejectCode::UnknownOrder);
+        assert_eq!(book.amend_order(7, 1, 0, 9).unwrap_err().code(), RejectCode::InvalidOrder);
+        assert_eq!(book.amend_order(7, 1, 50, 12).unwrap_err(), OrderbookError::InvalidOrder("Amended price would cross the spread"));
+        assert_eq!(book.amend_order(7, 1, 50, 105).unwrap_err().code(), RejectCode::InvalidOrder);
+        // 100 at 1.1 needs 110 USD, of which the 45 escrowed for 50 at 0.9 already counts
+        assert_eq!(
+            book.amend_order(7, 1, 100, 11).unwrap_err(),
+            OrderbookError::InsufficientFunds { currency: Currency::USD, required: 110 - 45, available: 100 - 45 },
+        );
+        assert_eq!(book.amend_order(7, 1, 50, 9).unwrap().quantity(), &50);
+
+        assert_eq!(*book.version(), version);
+        assert_eq!(book.snapshot().bids(), &vec![(9, 50)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), 100 - 45);
+    }
+
+    #[test]
+    fn test_iceberg_hides_reserve_from_depth() {
+        let mut book = BookBuilder::new().with_ask(13, 10).build();
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, 1000)]).build();
+        let mut iceberg = limit_order(&maker, OrderDirection::Ask, 12, 100);
+        iceberg.set_order_id(7);
+        iceberg.set_display_quantity(Some(20));
+        book.handle_order(&mut iceberg).unwrap();
+
+        // Depth and the queue only show the slice, while the whole order is escrowed and can be taken
+        assert_eq!(book.snapshot().asks(), &vec![(12, 20), (13, 10)]);
+        assert_eq!(book.tick_detail(12)[0].quantity(), &20);
+        assert_eq!(maker.borrow().balance(Currency::OSMO), 900);
+        assert_eq!(book.impact(110, OrderDirection::Bid).filled_quantity(), &110);
+
+        let taker = AccountBuilder::funded(2, &[(Currency::USD, 10000)]).build();
+        book.handle_order(&mut market_order(&taker, OrderDirection::Bid, 50)).unwrap();
+        assert_eq!(book.snapshot().asks(), &vec![(12, 10), (13, 10)]);
+        assert_eq!(book.trades().iter().map(|trade| *trade.quantity()).collect::<Vec<_>>(), vec![20, 20, 10]);
+        assert_eq!(*book.resting_order(7, 1).unwrap().quantity(), 50);
+        assert!(book.verify_escrow().is_ok());
+
+        let mut market_iceberg = market_order(&taker, OrderDirection::Bid, 50);
+        market_iceberg.set_display_quantity(Some(10));
+        assert_eq!(book.handle_order(&mut market_iceberg).unwrap_err().code(), RejectCode::InvalidOrder);
+        let mut hidden = limit_order(&maker, OrderDirection::Ask, 14, 50);
+        hidden.set_display_quantity(Some(0));
+        assert_eq!(book.handle_order(&mut hidden).unwrap_err().code(), RejectCode::InvalidOrder);
+    }
+
+    #[test]
+    fn test_filled_orders_cannot_be_cancelled() {
+        let mut book = BookBuilder::new().build();
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, 100)]).build();
+        let mut ask = limit_order(&maker, OrderDirection::Ask, 10, 20);
+        ask.set_order_id(3);
+        book.handle_order(&mut ask).unwrap();
+
+        let taker = AccountBuilder::funded(2, &[(Currency::USD, 1000)]).build();
+        book.handle_order(&mut market_order(&taker, OrderDirection::Bid, 20)).unwrap();
+        assert!(book.cancel_order(3, 1).is_err());
+        assert_eq!(maker.borrow().balance(Currency::OSMO), 80);
+    }
+
+    #[test]
+    fn test_new_pair_settles_in_its_own_assets() {
+        // USD traded and priced in OSMO: bids pay OSMO, asks deliver USD
+        let mut book = Orderbook::new_pair(1, Currency::OSMO, Currency::USD).unwrap();
+        assert_eq!((*book.quote_asset(), *book.base_asset()), (Currency::USD, Currency::OSMO));
+
+        let maker = AccountBuilder::funded(1, &[(Currency::USD, 100)]).build();
+        book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 5, 100)).unwrap();
+        assert_eq!(maker.borrow().balance(Currency::USD), 0);
+
+        let taker = AccountBuilder::funded(2, &[(Currency::OSMO, 1000)]).build();
+        book.handle_order(&mut market_order(&taker, OrderDirection::Bid, 40)).unwrap();
+        assert_eq!(taker.borrow().balance(Currency::USD), 40);
+        assert_eq!(taker.borrow().balance(Currency::OSMO), 1000 - 20);
+        assert_eq!(maker.borrow().balance(Currency::OSMO), 20);
+
+        // Resting bids escrow OSMO, and cancelling the ask refunds the unsold USD
+        let mut bid = limit_order(&taker, OrderDirection::Bid, 4, 10);
+        bid.set_order_id(1);
+        book.handle_order(&mut bid).unwrap();
+        assert_eq!(taker.borrow().balance(Currency::OSMO), 1000 - 20 - 4);
+        book.cancel_order(0, 1).unwrap();
+        assert_eq!(maker.borrow().balance(Currency::USD), 60);
+        assert_eq!(book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 6, 1000)).unwrap_err().code(), RejectCode::InsufficientFunds);
+        assert!(Orderbook::new_pair(2, Currency::USD, Currency::USD).is_err());
+    }
+
+    #[test]
+    fn test_cross_trades_off_the_queue() {
+        let mut book = BookBuilder::new().with_bid(9, 100).with_ask(12, 100).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let seller = AccountBuilder::funded(2, &[(Currency::OSMO, 50)]).build();
+        let version = *book.version();
+
+        let trade = book.cross(&buyer, &seller, 10, 50).unwrap();
+        assert!(*trade.cross());
+        assert_eq!((*trade.taker_account_id(), *trade.maker_account_id()), (1, 2));
+        assert_eq!(buyer.borrow().balance(Currency::OSMO), 50);
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000 - 50);
+        assert_eq!(seller.borrow().balance(Currency::USD), 50);
+
+        // Resting orders, the last trade price and the book version are untouched
+        assert_eq!(*book.version(), version);
+        assert_eq!(*book.last_trade_tick(), None);
+        assert_eq!(book.depth(1), vec![(9, 100, 0), (12, 0, 100)]);
+
+        // An account may cross with itself, but never through the touch or beyond its balances
+        book.cross(&buyer, &buyer, 12, 10).unwrap();
+        assert_eq!((buyer.borrow().balance(Currency::USD), buyer.borrow().balance(Currency::OSMO)), (950, 50));
+        assert_eq!(book.cross(&buyer, &seller, 13, 1).unwrap_err().code(), RejectCode::PriceProtection);
+        assert_eq!(book.cross(&buyer, &seller, 10, 1).unwrap_err().code(), RejectCode::InsufficientFunds);
+        assert_eq!(book.trades().len(), 2);
+    }
+
+    #[test]
+    fn test_ioc_drops_unfilled_remainder() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 20), (11, 20), (13, 20)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let mut bid = limit_order(&trader, OrderDirection::Bid, 11, 50);
+        bid.set_time_in_force(TimeInForce::IOC);
+        book.handle_order(&mut bid).unwrap();
+
+        // Both levels up to the limit fill and the other 10 are dropped instead of resting at 11
+        assert_eq!(*bid.quantity(), 10);
+        assert_eq!(book.snapshot().bids(), &vec![]);
+        assert_eq!(book.snapshot().asks(), &vec![(13, 20)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - (20 * 10 + 20 * 11) / 10);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 40);
+    }
+
+    #[test]
+    fn test_fok_fills_in_full_or_not_at_all() {
+        let mut book = BookBuilder::new().with_bids(&[(10, 20), (9, 20)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::OSMO, 100)]).build();
+        let version = *book.version();
+
+        let mut ask = limit_order(&trader, OrderDirection::Ask, 10, 30);
+        ask.set_time_in_force(TimeInForce::FOK);
+        let error = book.handle_order(&mut ask).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InsufficientLiquidity);
+        assert_eq!((*book.version(), book.trades().len()), (version, 0));
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 100);
+
+        let mut ask = limit_order(&trader, OrderDirection::Ask, 9, 30);
+        ask.set_time_in_force(TimeInForce::FOK);
+        book.handle_order(&mut ask).unwrap();
+        assert_eq!(book.snapshot().bids(), &vec![(9, 10)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), (20 * 10 + 10 * 9) / 10);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 70);
+    }
+
+    #[test]
+    fn test_strict_market_orders_fill_in_full_or_not_at_all() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 20), (11, 20)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let version = *book.version();
+
+        let mut bid = market_order(&trader, OrderDirection::Bid, 50);
+        bid.set_strict(true);
+        let error = book.handle_order(&mut bid).unwrap_err();
+        assert_eq!(error, OrderbookError::InsufficientLiquidity { filled: 40, requested: 50 });
+        assert_eq!((*book.version(), book.trades().len()), (version, 0));
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000);
+
+        // Only the depth within the slippage limit counts
+        let mut bid = market_order(&trader, OrderDirection::Bid, 30);
+        bid.set_strict(true);
+        bid.set_max_slippage(Some(SlippageGuard::new(SlippageLimit::Ticks(0), SlippagePolicy::Truncate)));
+        assert_eq!(book.handle_order(&mut bid).unwrap_err().code(), RejectCode::InsufficientLiquidity);
+
+        // The book-wide setting makes every market order strict, and one the book can cover still fills in full
+        book.set_strict_market_orders(true);
+        assert!(book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 41)).is_err());
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 30)).unwrap();
+        assert_eq!(book.snapshot().asks(), &vec![(11, 10)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - (20 * 10 + 10 * 11) / 10);
+
+        let mut limit = limit_order(&trader, OrderDirection::Bid, 9, 10);
+        limit.set_strict(true);
+        assert_eq!(book.handle_order(&mut limit).unwrap_err().code(), RejectCode::InvalidOrder);
+    }
+
+    #[test]
+    fn test_post_only_never_takes() {
+        let mut book = BookBuilder::new().with_bid(9, 20).with_ask(12, 20).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+
+        let mut crossing = limit_order(&trader, OrderDirection::Bid, 12, 10);
+        crossing.set_post_only(true);
+        let error = book.handle_order(&mut crossing).unwrap_err();
+        assert_eq!(error.code(), RejectCode::PostOnly);
+        assert_eq!((book.trades().len(), trader.borrow().balance(Currency::USD)), (0, 1000));
+
+        let mut passive = limit_order(&trader, OrderDirection::Bid, 11, 10);
+        passive.set_post_only(true);
+        book.handle_order(&mut passive).unwrap();
+        assert_eq!(book.snapshot().bids(), &vec![(11, 10), (9, 20)]);
+
+        let mut market = market_order(&trader, OrderDirection::Bid, 10);
+        market.set_post_only(true);
+        assert_eq!(book.handle_order(&mut market).unwrap_err().code(), RejectCode::InvalidOrder);
+    }
+
+    #[test]
+    fn test_cancel_range_pulls_one_side_of_quotes() {
+        let mut book = BookBuilder::new().with_bid(10, 5).with_ask(14, 5).build();
+        let quoter = AccountBuilder::funded(1, &[(Currency::USD, 1000), (Currency::OSMO, 100)]).build();
+        for (order_id, (tick_id, direction)) in [(8, OrderDirection::Bid), (9, OrderDirection::Bid), (11, OrderDirection::Bid), (13, OrderDirection::Ask)].into_iter().enumerate() {
+            let mut order = limit_order(&quoter, direction, tick_id, 10);
+            order.set_order_id(10 + order_id as u64);
+            book.handle_order(&mut order).unwrap();
+        }
+        let version = *book.version();
+
+        // The band covers the other account's bid at 10 and the quoter's own ask at 13, which both stay
+        let cancelled = book.cancel_range(1, OrderDirection::Bid, 13, 9);
+        assert_eq!(cancelled.iter().map(|order| *order.tick_id()).collect::<Vec<_>>(), vec![9, 11]);
+        assert_eq!(*book.version(), version + 1);
+        assert_eq!(book.snapshot().bids(), &vec![(10, 5), (8, 10)]);
+        assert_eq!(book.snapshot().asks(), &vec![(13, 10), (14, 5)]);
+        assert_eq!(quoter.borrow().balance(Currency::USD), 1000 - 10 * 8 / 10);
+        assert_eq!(*book.next_bid_tick(), 10);
+
+        assert!(book.cancel_range(1, OrderDirection::Bid, 9, 13).is_empty());
+        assert_eq!(*book.version(), version + 1);
+        assert_eq!(book.cancel_range(1, OrderDirection::Bid, 0, 100).len(), 1);
+        assert_eq!(book.cancel_range(u64::MAX, OrderDirection::Bid, 0, 100).len(), 1);
+        assert_eq!(*book.next_bid_tick(), u64::MIN);
+        assert_eq!(book.memory_stats().resting_orders(), &2);
+    }
+
+    #[test]
+    fn test_expired_orders_are_cancelled_and_refunded() {
+        let mut book = Orderbook::new(0);
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000), (Currency::OSMO, 100)]).build();
+        let now = Instant::now();
+        for (order_id, direction, tick_id, expires_in) in [(1, OrderDirection::Bid, 10, Some(5)), (2, OrderDirection::Ask, 14, Some(10)), (3, OrderDirection::Bid, 9, None)] {
+            let mut order = limit_order(&trader, direction, tick_id, 20);
+            order.set_order_id(order_id);
+            order.set_expires_at(expires_in.map(|seconds| now + Duration::from_secs(seconds)));
+            book.handle_order(&mut order).unwrap();
+        }
+        let taker = AccountBuilder::funded(2, &[(Currency::OSMO, 100)]).build();
+        book.handle_order(&mut market_order(&taker, OrderDirection::Ask, 5)).unwrap();
+
+        assert!(book.expire_orders(now + Duration::from_secs(4)).is_empty());
+        let expired = book.expire_orders(now + Duration::from_secs(5));
+        assert_eq!(expired.iter().map(|order| (*order.order_id(), *order.quantity())).collect::<Vec<_>>(), vec![(1, 15)]);
+        assert_eq!(trader.borrow().balance(Currency::USD), 1000 - (20 * 9 + 5 * 10) / 10);
+        assert_eq!(book.snapshot().bids(), &vec![(9, 20)]);
+
+        // The sweep catches up on everything that expired since the last one
+        let expired = book.expire_orders(now + Duration::from_secs(60));
+        assert_eq!(expired.iter().map(|order| *order.order_id()).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(trader.borrow().balance(Currency::OSMO), 100 + 5);
+        assert_eq!(book.open_orders(1).len(), 1);
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_escrow_conserves_balances() {
+        let maker = AccountBuilder::funded(1, &[(Currency::USD, 10000), (Currency::OSMO, 1000)]).build();
+        let taker = AccountBuilder::funded(2, &[(Currency::USD, 10000), (Currency::OSMO, 1000)]).build();
+        let mut book = BookBuilder::new().with_maker(Rc::clone(&maker)).with_bids(&[(9, 50), (8, 50)]).with_asks(&[(11, 50), (12, 50)]).build();
+        let accounts = [Rc::clone(&maker), Rc::clone(&taker), Rc::clone(book.escrow())];
+        let totals = |book: &Orderbook| {
+            assert!(book.verify_escrow().is_ok(), "{}", book.verify_escrow().unwrap_err());
+            (Account::total_balance(&accounts, Currency::USD), Account::total_balance(&accounts, Currency::OSMO))
+        };
+        assert_eq!(totals(&book), (20000, 2000));
+        assert_eq!(book.locked_funds(), ((9 * 50 + 8 * 50) / 10, 100));
+
+        // A market buy, a resting sell partly filled by an IOC bid, and both kinds of cancel all move funds between the
+        // traders and the escrow without creating or losing any
+        book.handle_order(&mut market_order(&taker, OrderDirection::Bid, 70)).unwrap();
+        assert_eq!(totals(&book), (20000, 2000));
+        let mut ask = limit_order(&taker, OrderDirection::Ask, 10, 80);
+        ask.set_order_id(100);
+        book.handle_order(&mut ask).unwrap();
+        assert_eq!(totals(&book), (20000, 2000));
+        let mut ioc = limit_order(&maker, OrderDirection::Bid, 12, 40);
+        ioc.set_time_in_force(TimeInForce::IOC);
+        book.handle_order(&mut ioc).unwrap();
+        assert_eq!(totals(&book), (20000, 2000));
+        book.cancel_order(100, 2).unwrap();
+        assert_eq!(totals(&book), (20000, 2000));
+        book.cancel_range(1, OrderDirection::Ask, 0, 100);
+        book.cancel_range(1, OrderDirection::Bid, 0, 100);
+        assert_eq!(totals(&book), (20000, 2000));
+
+        // Nothing rests any more, so the escrow is empty again
+        assert_eq!(book.locked_funds(), (0, 0));
+        assert_eq!(book.escrow().borrow().balance(Currency::USD), 0);
+        assert_eq!(book.escrow().borrow().balance(Currency::OSMO), 0);
+    }
+
+    #[test]
+    fn test_auction_crosses_collected_orders_at_one_price() {
+        let mut book = BookBuilder::new().with_bid(9, 50).with_ask(12, 50).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let seller = AccountBuilder::funded(2, &[(Currency::OSMO, 100)]).build();
+        book.start_auction();
+        for (order_id, owner, direction, tick_id, quantity) in [
+            (101, &buyer, OrderDirection::Bid, 13, 30),
+            (102, &buyer, OrderDirection::Bid, 11, 20),
+            (103, &seller, OrderDirection::Ask, 10, 40),
+        ] {
+            let mut order = limit_order(owner, direction, tick_id, quantity);
+            order.set_order_id(order_id);
+            book.handle_order(&mut order).unwrap();
+        }
+        let error = book.handle_order(&mut market_order(&buyer, OrderDirection::Bid, 5)).unwrap_err();
+        assert_eq!(error.code(), RejectCode::InvalidOrder);
+
+        // Collected orders lock their funds but don't trade or show on the book until the auction runs
+        assert!(book.trades().is_empty());
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(9), Some(12)));
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000 - (30 * 13 + 20 * 11) / 10);
+        assert!(book.pending_order(102, 1).is_some());
+        book.verify_escrow().unwrap();
+
+        // 40 matches at both 10 and 11 with the same imbalance, so the lower price wins. The bid at 13 fills first
+        // and the one at 11 takes the other 10, then rests with the 10 left.
+        let result = book.run_auction();
+        assert_eq!((*result.clearing_tick(), *result.matched_quantity(), *result.rested()), (Some(10), 40, 1));
+        let fills: Vec<(u64, u64, u64, u64)> = result.trades().iter()
+            .map(|trade| (*trade.tick_id(), *trade.quantity(), *trade.taker_order_id(), *trade.maker_order_id()))
+            .collect();
+        assert_eq!(fills, vec![(10, 30, 101, 103), (10, 10, 102, 103)]);
+        assert_eq!(book.trades().len(), 2);
+        assert!(!*book.collecting_auction());
+
+        assert_eq!(buyer.borrow().balance(Currency::OSMO), 40);
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000 - (40 * 10 + 10 * 11) / 10);
+        assert_eq!(seller.borrow().balance(Currency::USD), 40 * 10 / 10);
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(11), Some(12)));
+        assert_eq!(book.filled_quantity(102, 1), 10);
+        assert_eq!(*book.last_trade_tick(), Some(10));
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_auction_fills_resting_orders_too() {
+        let mut book = BookBuilder::new().with_ask(12, 50).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 1000), (Currency::OSMO, 10)]).build();
+        book.start_auction();
+        book.handle_order(&mut limit_order(&buyer, OrderDirection::Bid, 13, 20)).unwrap();
+        let mut ask = limit_order(&buyer, OrderDirection::Ask, 20, 10);
+        ask.set_order_id(7);
+        book.handle_order(&mut ask).unwrap();
+        assert_eq!(buyer.borrow().balance(Currency::OSMO), 0);
+
+        // A collected order can be cancelled until the auction runs
+        assert_eq!(*book.cancel_order(7, 1).unwrap().quantity(), 10);
+        assert_eq!(buyer.borrow().balance(Currency::OSMO), 10);
+
+        // The resting ask fills where it stands, and the bid pays the clearing price rather than its limit
+        let result = book.run_auction();
+        assert_eq!((*result.clearing_tick(), *result.matched_quantity(), *result.rested()), (Some(12), 20, 0));
+        assert_eq!(*result.trades()[0].aggressor(), OrderDirection::Bid);
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000 - 20 * 12 / 10);
+        assert_eq!(*book.ticks().get(&12).unwrap().total_liquidity(), 30);
+        assert_eq!(book.filled_quantity(0, u64::MAX), 20);
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_collected_orders_expire_before_the_auction_runs() {
+        let mut book = BookBuilder::new().with_ask(12, 50).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        let now = Instant::now();
+        book.start_auction();
+        let mut bid = limit_order(&buyer, OrderDirection::Bid, 13, 20);
+        bid.set_order_id(5);
+        bid.set_expires_at(Some(now + Duration::from_secs(5)));
+        book.handle_order(&mut bid).unwrap();
+
+        // The expired bid is refunded and drops out of the auction, so nothing is left to cross
+        let expired = book.expire_orders(now + Duration::from_secs(5));
+        assert_eq!(expired.iter().map(|order| *order.order_id()).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000);
+        assert!(book.auction_orders().is_empty());
+        assert_eq!(*book.run_auction().clearing_tick(), None);
+        assert!(book.trades().is_empty());
+        book.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_auction_without_a_cross_rests_collected_orders() {
+        let mut book = BookBuilder::new().with_ask(12, 50).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        book.start_auction();
+        book.handle_order(&mut limit_order(&buyer, OrderDirection::Bid, 11, 20)).unwrap();
+
+        let result = book.run_auction();
+        assert_eq!((*result.clearing_tick(), *result.matched_quantity(), *result.rested()), (None, 0, 1));
+        assert!(book.trades().is_empty());
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(11), Some(12)));
+        assert_eq!(buyer.borrow().balance(Currency::USD), 1000 - 20 * 11 / 10);
+        book.verify_escrow().unwrap();
+
+        // Orders match continuously again once the auction has run
+        book.handle_order(&mut market_order(&buyer, OrderDirection::Bid, 5)).unwrap();
+        assert_eq!(book.trades().len(), 1);
+    }
+}
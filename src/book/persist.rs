@@ -0,0 +1,198 @@
+use super::orderbook::Orderbook;
+use crate::bank::account::Account;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+// A restored book and the accounts saved with it, keyed by account id.
+pub type RestoredBook = (Orderbook, HashMap<u64, Rc<RefCell<Account>>>);
+
+// A book saved with the balances of everyone trading on it, as written by `Orderbook::snapshot_to_writer`.
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    book: &'a Orderbook,
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    book: Orderbook,
+    accounts: Vec<Account>,
+}
+
+impl Orderbook {
+    // Writes the book as JSON together with the accounts of every order owner on it, plus `accounts` for traders
+    // that should be saved without orders on the book (e.g. the user of a session). The escrow account is part of
+    // the book. Duplicate-check history and the cached depth snapshot are left out, the book rebuilds them.
+    pub fn snapshot_to_writer(&self, accounts: &[Rc<RefCell<Account>>], writer: impl Write) -> Result<(), Box<dyn Error>> {
+        let mut saved: BTreeMap<u64, Account> = BTreeMap::new();
+        for account in self.orders().map(|order| order.owner()).chain(accounts) {
+            let account = account.borrow();
+            saved.entry(*account.account_id()).or_insert_with(|| account.clone());
+        }
+
+        let snapshot = SnapshotRef { book: self, accounts: saved.into_values().collect() };
+        serde_json::to_writer(writer, &snapshot)?;
+        Ok(())
+    }
+
+    // Reads a book written by `snapshot_to_writer`, returning it with the saved accounts keyed by id. Every order
+    // is linked back to its owner among those accounts and to the book's escrow, so the restored book trades and
+    // settles exactly like the saved one. Fails if an order's owner wasn't saved or the escrow doesn't hold what the
+    // resting orders locked.
+    pub fn restore_from_reader(reader: impl Read) -> Result<RestoredBook, Box<dyn Error>> {
+        let Snapshot { mut book, accounts } = serde_json::from_reader(reader)?;
+        let accounts: HashMap<u64, Rc<RefCell<Account>>> = accounts.into_iter()
+            .map(|account| (*account.account_id(), Rc::new(RefCell::new(account))))
+            .collect();
+
+        let escrow = Rc::clone(book.escrow());
+        for order in book.orders_mut() {
+            let account_id = *order.owner().borrow().account_id();
+            let owner = accounts.get(&account_id)
+                .ok_or_else(|| format!("order {} belongs to account {}, which isn't in the snapshot", order.order_id(), account_id))?;
+            order.set_owner(Rc::clone(owner));
+            order.set_escrow(Rc::clone(&escrow));
+        }
+        book.verify_escrow()?;
+        Ok((book, accounts))
+    }
+}
+
+// Instants only mean something within the process that took them, so they are saved as their age in microseconds and
+// restored that far back from the time of loading.
+pub(crate) mod instant_age {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, Instant};
+
+    pub fn serialize<S: Serializer>(instant: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        let age = Instant::now().saturating_duration_since(*instant).as_micros() as u64;
+        age.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let age = Duration::from_micros(u64::deserialize(deserializer)?);
+        let now = Instant::now();
+        Ok(now.checked_sub(age).unwrap_or(now))
+    }
+}
+
+// Expiry times lie ahead, so they are saved as the microseconds left until them (0 once passed) and restored that far
+// ahead of the time of loading.
+pub(crate) mod instant_remaining {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, Instant};
+
+    pub fn serialize<S: Serializer>(instant: &Option<Instant>, serializer: S) -> Result<S::Ok, S::Error> {
+        let remaining = instant.map(|instant| instant.saturating_duration_since(Instant::now()).as_micros() as u64);
+        remaining.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Instant>, D::Error> {
+        let remaining = Option::<u64>::deserialize(deserializer)?;
+        Ok(remaining.map(|remaining| Instant::now() + Duration::from_micros(remaining)))
+    }
+}
+
+// Orders share their owner's account with every other order and balance check of that owner, so they only save its
+// id. Loading gives each order a placeholder account with that id, which `restore_from_reader` swaps for the shared
+// account saved alongside the book.
+pub(crate) mod owner_id {
+    use crate::bank::account::{Account, AccountType};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    pub fn serialize<S: Serializer>(owner: &Rc<RefCell<Account>>, serializer: S) -> Result<S::Ok, S::Error> {
+        owner.borrow().account_id().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<RefCell<Account>>, D::Error> {
+        let account_id = u64::deserialize(deserializer)?;
+        Ok(Rc::new(RefCell::new(Account::new(account_id, AccountType::Individual))))
+    }
+}
+
+// The book's escrow is saved in full, balances included, since only the book refers to it.
+pub(crate) mod shared_account {
+    use crate::bank::account::Account;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    pub fn serialize<S: Serializer>(account: &Rc<RefCell<Account>>, serializer: S) -> Result<S::Ok, S::Error> {
+        account.borrow().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<RefCell<Account>>, D::Error> {
+        Ok(Rc::new(RefCell::new(Account::deserialize(deserializer)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+    use crate::book::matching::MatchingPolicy;
+    use crate::book::order::OrderDirection;
+    use crate::testkit::{limit_order, market_order, AccountBuilder};
+
+    #[test]
+    fn test_restored_book_trades_like_the_saved_one() {
+        let mut book = Orderbook::new(0);
+        book.set_matching_policy(MatchingPolicy::RoundRobin { slice: 5 });
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, 100), (Currency::USD, 100)]).build();
+        let user = AccountBuilder::funded(2, &[(Currency::USD, 1000)]).build();
+        let mut iceberg = limit_order(&maker, OrderDirection::Ask, 12, 40);
+        iceberg.set_display_quantity(Some(10));
+        book.handle_order(&mut iceberg).unwrap();
+        book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 9, 10)).unwrap();
+        let mut stop = market_order(&maker, OrderDirection::Ask, 5);
+        stop.set_trigger_tick(Some(11));
+        book.handle_order(&mut stop).unwrap();
+        book.handle_order(&mut market_order(&user, OrderDirection::Bid, 15)).unwrap();
+
+        let mut saved = Vec::new();
+        book.snapshot_to_writer(&[Rc::clone(&user)], &mut saved).unwrap();
+        let (mut restored, accounts) = Orderbook::restore_from_reader(saved.as_slice()).unwrap();
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(restored.snapshot().asks(), book.snapshot().asks());
+        assert_eq!(restored.snapshot().bids(), book.snapshot().bids());
+        // Execution times come back relative to the time of loading, so only the rest of each trade is compared
+        let fills = |book: &Orderbook| book.trades().iter().map(|trade| (*trade.trade_id(), *trade.quantity(), *trade.maker_order_id())).collect::<Vec<_>>();
+        assert_eq!(fills(&restored), fills(&book));
+        assert_eq!(*restored.matching_policy(), MatchingPolicy::RoundRobin { slice: 5 });
+        assert_eq!(restored.pending_stops(), (0, 1));
+        assert_eq!(accounts[&2].borrow().balance(Currency::OSMO), 15);
+
+        // The restored orders settle with the restored accounts and escrow
+        let buyer = Rc::clone(&accounts[&2]);
+        restored.handle_order(&mut market_order(&buyer, OrderDirection::Bid, 25)).unwrap();
+        assert!(restored.snapshot().asks().is_empty());
+        assert_eq!(buyer.borrow().balance(Currency::OSMO), 40);
+        assert_eq!(accounts[&1].borrow().balance(Currency::USD), 100 - 10 * 9 / 10 + 40 * 12 / 10);
+        restored.verify_escrow().unwrap();
+    }
+
+    #[test]
+    fn test_restore_rejects_inconsistent_snapshots() {
+        let mut book = Orderbook::new(0);
+        let maker = AccountBuilder::funded(1, &[(Currency::OSMO, 100)]).build();
+        book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 40)).unwrap();
+        let mut saved = Vec::new();
+        book.snapshot_to_writer(&[], &mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+
+        let without_owner = saved.replace(r#""accounts":[{"account_id":1"#, r#""accounts":[{"account_id":3"#);
+        let error = Orderbook::restore_from_reader(without_owner.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("belongs to account 1"));
+
+        let emptied_escrow = saved.replace(r#""OSMO":40"#, r#""OSMO":0"#);
+        assert!(Orderbook::restore_from_reader(emptied_escrow.as_bytes()).unwrap_err().to_string().contains("escrow holds"));
+        assert!(Orderbook::restore_from_reader("{".as_bytes()).is_err());
+    }
+}
@@ -1 +1,788 @@
+use getset::Getters;
+use super::inverted::InvertedView;
+use super::order::{Order, OrderDirection, OrderType};
+use super::orderbook::Orderbook;
+use super::toxicity::FlowToxicity;
+use super::trade::{Trade, TradeFilter, TradePage};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Outcome of walking the book for a hypothetical market order, computed without mutating anything.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct MarketImpact {
+    // Quantity the order asked for.
+    #[get = "pub"]
+    requested_quantity: u64,
+    // Portion of the requested quantity the current depth can absorb.
+    #[get = "pub"]
+    filled_quantity: u64,
+    // What the levels consumed are worth, i.e. the base asset paid or received.
+    #[get = "pub"]
+    total_cost: u64,
+    // Least favorable tick the order would trade at (None if nothing fills).
+    #[get = "pub"]
+    worst_tick: Option<u64>,
+    // Best tick left on the consumed side once the order is done (None if the side would be emptied).
+    #[get = "pub"]
+    final_tick: Option<u64>,
+}
+
+impl MarketImpact {
+    // Average price the order would fill at, in the base asset per unit.
+    pub fn average_price(&self) -> Option<f64> {
+        if self.filled_quantity == 0 {
+            return None;
+        }
+        Some(self.total_cost as f64 / self.filled_quantity as f64)
+    }
+
+    // Quantity that would be left over because the book ran out of depth.
+    pub fn unfilled_quantity(&self) -> u64 {
+        self.requested_quantity - self.filled_quantity
+    }
+}
+
+// One order resting on a tick, as seen from the tick's queue.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct QueuedOrder {
+    // Place in the queue, starting at 1 for the order that fills first under FIFO.
+    #[get = "pub"]
+    position: usize,
+    #[get = "pub"]
+    order_id: u64,
+    #[get = "pub"]
+    owner_id: u64,
+    #[get = "pub"]
+    order_direction: OrderDirection,
+    // Quantity shown on the book, which for an iceberg is only its current slice.
+    #[get = "pub"]
+    quantity: u64,
+    // Time since the order was placed.
+    #[get = "pub"]
+    age: Duration,
+}
+
+// One of an account's orders resting on the book, with how much of it has traded.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct OpenOrder {
+    #[get = "pub"]
+    order_id: u64,
+    #[get = "pub"]
+    owner_id: u64,
+    #[get = "pub"]
+    order_direction: OrderDirection,
+    #[get = "pub"]
+    tick_id: u64,
+    // Quantity filled since the order was placed, including what it took on arrival before it rested.
+    #[get = "pub"]
+    filled_quantity: u64,
+    // Quantity still resting, hidden iceberg reserve included.
+    #[get = "pub"]
+    remaining_quantity: u64,
+}
+
+// Size of the book's internal containers, for spotting growth in long-running sessions.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct MemoryStats {
+    #[get = "pub"]
+    levels: usize,
+    #[get = "pub"]
+    resting_orders: usize,
+    // Stop and conditional orders waiting to activate.
+    #[get = "pub"]
+    pending_orders: usize,
+    #[get = "pub"]
+    trades: usize,
+    // Estimate from container capacities and element sizes. Allocator and tree node overheads are not included.
+    #[get = "pub"]
+    approx_bytes: usize,
+}
+
+impl MemoryStats {
+    pub(crate) fn new(levels: usize, resting_orders: usize, pending_orders: usize, trades: usize, approx_bytes: usize) -> MemoryStats {
+        MemoryStats { levels, resting_orders, pending_orders, trades, approx_bytes }
+    }
+}
+
+// Immutable aggregate view of every price level at a given book version.
+// Snapshots are shared behind an Arc so the render thread and analytics can hold on to one
+// (or send it across threads) while the matching side keeps mutating the book.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct DepthSnapshot {
+    #[get = "pub"]
+    version: u64,
+    // (tick_id, total quantity) pairs ordered from the best bid downwards.
+    #[get = "pub"]
+    bids: Vec<(u64, u64)>,
+    // (tick_id, total quantity) pairs ordered from the best ask upwards.
+    #[get = "pub"]
+    asks: Vec<(u64, u64)>,
+}
+
+impl DepthSnapshot {
+    // Aggregates the resting quantity of every non-empty tick, classifying each level by the side of its orders.
+    // Only the displayed slice of icebergs counts, so their hidden reserve never shows in depth.
+    pub fn capture(book: &Orderbook) -> DepthSnapshot {
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+
+        for (tick_id, tick) in book.ticks() {
+            let quantity = tick.visible_orders();
+            match tick.orders().front().map(|order| *order.order_direction()) {
+                Some(OrderDirection::Bid) if quantity > 0 => bids.push((*tick_id, quantity)),
+                Some(OrderDirection::Ask) if quantity > 0 => asks.push((*tick_id, quantity)),
+                _ => {}
+            }
+        }
+        bids.reverse();
+
+        DepthSnapshot {
+            version: *book.version(),
+            bids,
+            asks,
+        }
+    }
+
+    // Resting quantity at a tick on either side, 0 if the level is empty. Both sides are sorted away from the touch,
+    // so this is a binary search per side however far apart the populated ticks are.
+    pub fn quantity_at(&self, tick_id: u64) -> u64 {
+        let bid = self.bids.binary_search_by(|(level_tick, _)| tick_id.cmp(level_tick));
+        let ask = self.asks.binary_search_by(|(level_tick, _)| level_tick.cmp(&tick_id));
+        match (bid, ask) {
+            (Ok(index), _) => self.bids[index].1,
+            (_, Ok(index)) => self.asks[index].1,
+            _ => 0,
+        }
+    }
+
+    // Midpoint between the best bid and best ask, falling back to the touch if one side is empty.
+    pub fn mid_tick(&self) -> Option<u64> {
+        match (self.bids.first(), self.asks.first()) {
+            (Some((bid, _)), Some((ask, _))) => Some((bid + ask) / 2),
+            (Some((bid, _)), None) => Some(*bid),
+            (None, Some((ask, _))) => Some(*ask),
+            (None, None) => None,
+        }
+    }
+
+    pub fn total_quantity(&self) -> u64 {
+        self.bids.iter().chain(self.asks.iter()).map(|(_, quantity)| quantity).sum()
+    }
+
+    // Herfindahl index of resting quantity across all levels of both sides: the sum of each level's squared
+    // share of total depth. 1.0 means all liquidity sits on one level, 1/N means it is spread evenly over N levels.
+    pub fn herfindahl_index(&self) -> Option<f64> {
+        let total_quantity = self.total_quantity();
+        if total_quantity == 0 {
+            return None;
+        }
+
+        Some(self.bids.iter()
+            .chain(self.asks.iter())
+            .map(|(_, quantity)| {
+                let share = *quantity as f64 / total_quantity as f64;
+                share * share
+            })
+            .sum())
+    }
+
+    // Fraction of total depth resting within `ticks` of the mid, inclusive.
+    pub fn depth_share_near_mid(&self, ticks: u64) -> Option<f64> {
+        let mid_tick = self.mid_tick()?;
+        let near_quantity: u64 = self.bids.iter()
+            .chain(self.asks.iter())
+            .filter(|(tick_id, _)| tick_id.abs_diff(mid_tick) <= ticks)
+            .map(|(_, quantity)| quantity)
+            .sum();
+
+        Some(near_quantity as f64 / self.total_quantity() as f64)
+    }
+
+    // Net liquidity added (positive) or removed (negative) per tick since an earlier snapshot,
+    // ordered by tick and skipping levels that did not change.
+    pub fn diff(&self, earlier: &DepthSnapshot) -> Vec<(u64, i64)> {
+        let mut tick_ids: Vec<u64> = self.bids.iter()
+            .chain(self.asks.iter())
+            .chain(earlier.bids.iter())
+            .chain(earlier.asks.iter())
+            .map(|(tick_id, _)| *tick_id)
+            .collect();
+        tick_ids.sort_unstable();
+        tick_ids.dedup();
+
+        tick_ids.into_iter()
+            .map(|tick_id| (tick_id, self.quantity_at(tick_id) as i64 - earlier.quantity_at(tick_id) as i64))
+            .filter(|(_, change)| *change != 0)
+            .collect()
+    }
+}
+
+impl Orderbook {
+    // Highest bid tick, None while no bids rest (next_bid_tick then holds the u64::MIN sentinel).
+    pub fn best_bid(&self) -> Option<u64> {
+        Some(*self.next_bid_tick()).filter(|tick_id| *tick_id != u64::MIN)
+    }
+
+    // Lowest ask tick, None while no asks rest (next_ask_tick then holds the u64::MAX sentinel).
+    pub fn best_ask(&self) -> Option<u64> {
+        Some(*self.next_ask_tick()).filter(|tick_id| *tick_id != u64::MAX)
+    }
+
+    // Ticks between the best bid and ask, None unless both sides have orders.
+    pub fn spread(&self) -> Option<u64> {
+        Some(self.best_ask()?.saturating_sub(self.best_bid()?))
+    }
+
+    // Midpoint of the best bid and ask in ticks, which lands halfway between two ticks when the spread is odd.
+    // None unless both sides have orders.
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.best_bid()? as f64 + self.best_ask()? as f64) / 2.0)
+    }
+
+    // Returns a shared snapshot of current depth. The snapshot is only rebuilt when the book version
+    // has moved since the last call, so repeated reads between mutations are cheap.
+    pub fn snapshot(&self) -> Arc<DepthSnapshot> {
+        let mut latest_snapshot = self.latest_snapshot().borrow_mut();
+        match latest_snapshot.as_ref() {
+            Some(snapshot) if snapshot.version == *self.version() => Arc::clone(snapshot),
+            _ => {
+                let snapshot = Arc::new(DepthSnapshot::capture(self));
+                *latest_snapshot = Some(Arc::clone(&snapshot));
+                snapshot
+            }
+        }
+    }
+
+    // The `levels` best price levels on each side as (tick_id, bid quantity, ask quantity), in ascending tick order.
+    // A level only ever holds one side, so the other quantity is always 0.
+    pub fn depth(&self, levels: usize) -> Vec<(u64, u64, u64)> {
+        let snapshot = self.snapshot();
+        let mut depth: Vec<(u64, u64, u64)> = snapshot.bids().iter()
+            .take(levels)
+            .rev()
+            .map(|(tick_id, quantity)| (*tick_id, *quantity, 0))
+            .collect();
+        depth.extend(snapshot.asks().iter().take(levels).map(|(tick_id, quantity)| (*tick_id, 0, *quantity)));
+        depth
+    }
+
+    // Resting orders generated from the given parent instruction, in ascending tick order.
+    pub fn child_orders(&self, parent_order_id: u64) -> Vec<&Order> {
+        self.ticks().values()
+            .flat_map(|tick| tick.orders().iter())
+            .filter(|order| *order.parent_order_id() == Some(parent_order_id))
+            .collect()
+    }
+
+    // Orders resting on a tick in priority order, empty if nothing rests there.
+    pub fn tick_detail(&self, tick_id: u64) -> Vec<QueuedOrder> {
+        let now = Instant::now();
+        self.ticks().get(&tick_id)
+            .map(|tick| tick.orders().iter()
+                .enumerate()
+                .map(|(index, order)| QueuedOrder {
+                    position: index + 1,
+                    order_id: *order.order_id(),
+                    owner_id: *order.owner().borrow().account_id(),
+                    order_direction: *order.order_direction(),
+                    quantity: order.visible_quantity(),
+                    age: now.saturating_duration_since(*order.placed_at()),
+                })
+                .collect())
+            .unwrap_or_default()
+    }
+
+    // Every order the account has resting on the book, lowest order id first. Stops, if-touched and conditional
+    // orders waiting to be placed aren't on the book yet and aren't listed.
+    pub fn open_orders(&self, account_id: u64) -> Vec<OpenOrder> {
+        self.open_order_ids(account_id)
+            .filter_map(|order_id| self.resting_order(order_id, account_id))
+            .map(|order| self.open_order(order))
+            .collect()
+    }
+
+    // Where a resting order stands, None once it has filled or been cancelled or while it waits on a trigger.
+    pub fn order_status(&self, order_id: u64) -> Option<OpenOrder> {
+        let order = self.ticks().get(&self.resting_tick(order_id)?)?
+            .orders()
+            .iter()
+            .find(|order| *order.order_id() == order_id)?;
+        Some(self.open_order(order))
+    }
+
+    fn open_order(&self, order: &Order) -> OpenOrder {
+        let owner_id = *order.owner().borrow().account_id();
+        OpenOrder {
+            order_id: *order.order_id(),
+            owner_id,
+            order_direction: *order.order_direction(),
+            tick_id: *order.tick_id(),
+            filled_quantity: self.filled_quantity(*order.order_id(), owner_id),
+            remaining_quantity: *order.quantity(),
+        }
+    }
+
+    // Trades with an id of at least `trade_id` that are still held in memory, oldest first. A consumer keeps
+    // next_trade_id() from its last call and passes it back to pick up every execution since, in sequence.
+    pub fn trades_since(&self, trade_id: u64) -> &[Trade] {
+        let start = trade_id.saturating_sub(*self.archived_trades()) as usize;
+        &self.trades()[start.min(self.trades().len())..]
+    }
+
+    // Id the next trade will get.
+    pub fn next_trade_id(&self) -> u64 {
+        self.archived_trades() + self.trades().len() as u64
+    }
+
+    // Trades matching the filter, newest first, limited to the filter's page.
+    pub fn trades_query(&self, filter: &TradeFilter) -> TradePage {
+        let matches: Vec<_> = self.trades().iter().rev().filter(|trade| filter.matches(trade)).collect();
+        let trades = matches.iter()
+            .skip(*filter.offset())
+            .take(*filter.limit())
+            .map(|trade| **trade)
+            .collect();
+        TradePage::new(trades, matches.len())
+    }
+
+    // Fingerprint of everything matching has produced: every resting order in queue order, every trade and the
+    // number of orders waiting for a trigger or condition. Two books that went through the same orders end up with
+    // the same checksum on any platform, since it only hashes integers in a fixed order (FNV-1a, 64 bit).
+    pub fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        let side = |direction: &OrderDirection| match direction {
+            OrderDirection::Bid => 0,
+            OrderDirection::Ask => 1,
+        };
+
+        for (tick_id, tick) in self.ticks() {
+            feed(*tick_id);
+            for order in tick.orders() {
+                feed(*order.order_id());
+                feed(*order.owner().borrow().account_id());
+                feed(side(order.order_direction()));
+                feed(*order.quantity());
+            }
+        }
+        for trade in self.trades() {
+            feed(*trade.trade_id());
+            feed(*trade.tick_id());
+            feed(*trade.quantity());
+            // Crosses count as a side of their own, which leaves the checksum of books without any unchanged
+            feed(side(trade.aggressor()) + if *trade.cross() { 2 } else { 0 });
+            feed(*trade.taker_order_id());
+            feed(*trade.maker_order_id());
+        }
+        let (stop_bids, stop_asks) = self.pending_stops();
+        let (touch_bids, touch_asks) = self.pending_touches();
+        for pending in [stop_bids, stop_asks, touch_bids, touch_asks, self.pending_conditionals()] {
+            feed(pending as u64);
+        }
+        hash
+    }
+
+    // The book as the USD/OSMO pair, with prices and sides flipped.
+    pub fn inverted_view(&self) -> InvertedView<'_> {
+        InvertedView::new(self)
+    }
+
+    // Order flow toxicity (VPIN) of the trades so far, bucketed every `bucket_volume` OSMO over the last `window` buckets.
+    pub fn flow_toxicity(&self, bucket_volume: u64, window: usize) -> FlowToxicity {
+        FlowToxicity::from_trades(self.trades(), bucket_volume, window)
+    }
+
+    // Largest quantity of the order its owner can currently pay for, following the same rules the book applies:
+    // asks need the OSMO they sell, bids pay for each crossed level at its own price and escrow the rest at their
+    // limit price. Market orders can't get past the depth on the other side, so the answer is capped by it.
+    pub fn max_affordable_quantity(&self, order: &Order) -> u64 {
+        let owner = order.owner().borrow();
+        let (end_tick, rests) = match order.order_type() {
+            OrderType::Limit => (*order.tick_id(), true),
+            OrderType::Market => (u64::MAX, false),
+        };
+
+        match order.order_direction() {
+            OrderDirection::Ask if rests => owner.balance(*self.quote_asset()),
+            OrderDirection::Ask => owner.balance(*self.quote_asset()).min(self.impact(u64::MAX, OrderDirection::Ask).filled_quantity),
+            OrderDirection::Bid => {
+                let converter = self.tick_converter();
+                let mut budget = owner.balance(*self.base_asset());
+                let mut quantity = 0;
+                let asks = self.snapshot();
+                for (tick_id, available) in asks.asks().iter().take_while(|(tick_id, _)| *tick_id <= end_tick) {
+                    // Worth is rounded per resting order, so a level can cost a little more than its notional: search
+                    // below that for the most the budget covers
+                    let tick = &self.ticks()[tick_id];
+                    let (mut affordable, mut too_much) = (0, converter.affordable_quantity(budget, *tick_id).min(*available) + 1);
+                    while affordable + 1 < too_much {
+                        let middle = affordable + (too_much - affordable) / 2;
+                        match tick.fill_worth(middle, *self.matching_policy()) <= budget {
+                            true => affordable = middle,
+                            false => too_much = middle,
+                        }
+                    }
+                    quantity += affordable;
+                    budget -= tick.fill_worth(affordable, *self.matching_policy());
+                    if affordable < *available {
+                        break;
+                    }
+                }
+                if rests {
+                    quantity += converter.affordable_quantity(budget, end_tick);
+                }
+                quantity
+            }
+        }
+    }
+
+    // Estimates the effect of a market order of `quantity` in `direction` against the current depth.
+    // A bid walks the asks upwards from the best ask, an ask walks the bids downwards from the best bid.
+    pub fn impact(&self, quantity: u64, direction: OrderDirection) -> MarketImpact {
+        let end_tick = match direction {
+            OrderDirection::Bid => u64::MAX,
+            OrderDirection::Ask => u64::MIN,
+        };
+        let plan = self.plan_sweep(direction, end_tick, quantity);
+
+        MarketImpact {
+            requested_quantity: quantity,
+            filled_quantity: plan.filled_quantity,
+            total_cost: plan.total_cost,
+            worst_tick: plan.levels.last().map(|(tick_id, _)| *tick_id),
+            final_tick: plan.next_tick,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+    use crate::testkit::{limit_order, market_order, AccountBuilder, BookBuilder};
+
+    #[test]
+    fn test_impact_bid_walks_asks() {
+        let book = BookBuilder::new().with_asks(&[(10, 300), (13, 300), (21, 300)]).build();
+
+        // 400 units consumes all of tick 10 and a third of tick 13
+        let impact = book.impact(400, OrderDirection::Bid);
+        assert_eq!(*impact.filled_quantity(), 400);
+        assert_eq!(*impact.total_cost(), (300 * 10 + 100 * 13) / 10);
+        assert_eq!(*impact.worst_tick(), Some(13));
+        assert_eq!(*impact.final_tick(), Some(13));
+        assert_eq!(impact.average_price(), Some(430.0 / 400.0));
+
+        // Consuming exactly the first level leaves the next level as the best ask
+        let impact = book.impact(300, OrderDirection::Bid);
+        assert_eq!(*impact.worst_tick(), Some(10));
+        assert_eq!(*impact.final_tick(), Some(13));
+
+        // The book itself should be untouched by the estimate
+        assert_eq!(*book.ticks().get(&10).unwrap().total_liquidity(), 300);
+    }
+
+    #[test]
+    fn test_impact_ask_exhausts_bids() {
+        let book = BookBuilder::new().with_bids(&[(10, 300), (14, 300)]).build();
+
+        // Only 600 units of depth are available, so the rest is left unfilled
+        let impact = book.impact(1000, OrderDirection::Ask);
+        assert_eq!(*impact.filled_quantity(), 600);
+        assert_eq!(impact.unfilled_quantity(), 400);
+        assert_eq!(*impact.total_cost(), (300 * 14 + 300 * 10) / 10);
+        assert_eq!(*impact.worst_tick(), Some(10));
+        assert_eq!(*impact.final_tick(), None);
+    }
+
+    #[test]
+    fn test_impact_empty_side() {
+        let book = Orderbook::new(0);
+
+        let impact = book.impact(100, OrderDirection::Bid);
+        assert_eq!(*impact.filled_quantity(), 0);
+        assert_eq!(impact.average_price(), None);
+        assert_eq!(*impact.worst_tick(), None);
+    }
+
+    #[test]
+    fn test_depth_levels_follow_fills_and_cancels() {
+        let mut book = BookBuilder::new().with_bids(&[(8, 50), (9, 20), (7, 5)]).with_asks(&[(11, 30), (12, 40)]).build();
+        assert_eq!(book.depth(2), vec![(8, 50, 0), (9, 20, 0), (11, 0, 30), (12, 0, 40)]);
+
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000), (Currency::OSMO, 100)]).build();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 10)).unwrap();
+        let mut ask = limit_order(&trader, OrderDirection::Ask, 10, 15);
+        ask.set_order_id(4);
+        book.handle_order(&mut ask).unwrap();
+        assert_eq!(book.depth(1), vec![(9, 20, 0), (10, 0, 15)]);
+
+        book.cancel_order(4, 1).unwrap();
+        assert_eq!(book.depth(1), vec![(9, 20, 0), (11, 0, 20)]);
+        assert!(BookBuilder::new().build().depth(5).is_empty());
+    }
+
+    #[test]
+    fn test_child_orders() {
+        let mut book = BookBuilder::new().with_ask(12, 300).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000)]).build();
+
+        for (tick_id, parent_order_id) in [(8, Some(1)), (9, Some(2)), (10, Some(1)), (11, None)] {
+            let mut order = limit_order(&trader, OrderDirection::Bid, tick_id, 10);
+            order.set_parent_order_id(parent_order_id);
+            book.handle_order(&mut order).unwrap();
+        }
+
+        // Only the resting orders linked to parent 1 are returned
+        let children: Vec<u64> = book.child_orders(1).iter().map(|order| *order.tick_id()).collect();
+        assert_eq!(children, vec![8, 10]);
+        assert!(book.child_orders(3).is_empty());
+    }
+
+    #[test]
+    fn test_open_orders_track_fills_amends_and_cancels() {
+        let mut book = BookBuilder::new().with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000), (Currency::OSMO, 100)]).build();
+        let other = AccountBuilder::funded(2, &[(Currency::OSMO, 100)]).build();
+        let place = |book: &mut Orderbook, order_id, direction, tick_id, quantity| {
+            let mut order = limit_order(&trader, direction, tick_id, quantity);
+            order.set_order_id(order_id);
+            book.handle_order(&mut order).unwrap();
+        };
+
+        // 4 of the bid fill against the ask on arrival and the rest rests
+        place(&mut book, 5, OrderDirection::Bid, 12, 14);
+        place(&mut book, 6, OrderDirection::Ask, 20, 30);
+        place(&mut book, 3, OrderDirection::Bid, 9, 50);
+        let status = book.order_status(5).unwrap();
+        assert_eq!((*status.owner_id(), *status.tick_id(), *status.filled_quantity(), *status.remaining_quantity()), (1, 12, 10, 4));
+        let open: Vec<u64> = book.open_orders(1).iter().map(|order| *order.order_id()).collect();
+        assert_eq!(open, vec![3, 5, 6]);
+
+        // Maker fills add up, and an amend keeps them while a full fill closes the order
+        book.handle_order(&mut market_order(&other, OrderDirection::Ask, 10)).unwrap();
+        assert_eq!(book.order_status(5), None);
+        assert_eq!(*book.order_status(3).unwrap().filled_quantity(), 6);
+        book.amend_order(3, 1, 60, 8).unwrap();
+        let status = book.order_status(3).unwrap();
+        assert_eq!((*status.tick_id(), *status.filled_quantity(), *status.remaining_quantity()), (8, 6, 60));
+
+        book.cancel_range(1, OrderDirection::Ask, 1, 100);
+        book.cancel_order(3, 1).unwrap();
+        assert!(book.open_orders(1).is_empty());
+        assert!(book.open_orders(2).is_empty());
+    }
+
+    #[test]
+    fn test_tick_detail_lists_queue_in_priority_order() {
+        let mut book = Orderbook::new(0);
+        let first = AccountBuilder::funded(1, &[(Currency::USD, 100000)]).build();
+        let second = AccountBuilder::funded(2, &[(Currency::USD, 100000)]).build();
+
+        for (order_id, owner, quantity) in [(7, &first, 30), (8, &second, 20), (9, &first, 10)] {
+            let mut order = limit_order(owner, OrderDirection::Bid, 10, quantity);
+            order.set_order_id(order_id);
+            book.handle_order(&mut order).unwrap();
+        }
+
+        let queue = book.tick_detail(10);
+        let summary: Vec<(usize, u64, u64, u64)> = queue.iter()
+            .map(|queued| (*queued.position(), *queued.order_id(), *queued.owner_id(), *queued.quantity()))
+            .collect();
+        assert_eq!(summary, vec![(1, 7, 1, 30), (2, 8, 2, 20), (3, 9, 1, 10)]);
+        assert!(queue[0].age() >= queue[2].age());
+
+        // A partial fill only shrinks the head of the queue
+        let seller = AccountBuilder::funded(3, &[(Currency::OSMO, 100)]).build();
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 35)).unwrap();
+        let queue = book.tick_detail(10);
+        assert_eq!(queue.len(), 2);
+        assert_eq!((*queue[0].order_id(), *queue[0].quantity()), (8, 15));
+
+        assert!(book.tick_detail(11).is_empty());
+    }
+
+    #[test]
+    fn test_trades_query_filters_and_pages() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100), (12, 100)]).with_bid(8, 100).build();
+        let buyer = AccountBuilder::funded(1, &[(Currency::USD, 100000)]).build();
+        let seller = AccountBuilder::funded(2, &[(Currency::OSMO, 1000)]).build();
+
+        // The buyer sweeps both ask levels, then the seller hits the bid twice
+        book.handle_order(&mut market_order(&buyer, OrderDirection::Bid, 150)).unwrap();
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 20)).unwrap();
+        book.handle_order(&mut market_order(&seller, OrderDirection::Ask, 30)).unwrap();
+        assert_eq!(book.trades().len(), 4);
+
+        // Results come back newest first
+        let page = book.trades_query(&TradeFilter::new());
+        let summary: Vec<(u64, u64)> = page.trades().iter().map(|trade| (*trade.tick_id(), *trade.quantity())).collect();
+        assert_eq!(summary, vec![(8, 30), (8, 20), (12, 50), (10, 100)]);
+
+        let buys = book.trades_query(&TradeFilter::new().with_aggressor(OrderDirection::Bid));
+        assert_eq!(*buys.total_matches(), 2);
+        assert!(buys.trades().iter().all(|trade| *trade.taker_account_id() == 1));
+        assert_eq!(*book.trades_query(&TradeFilter::new().with_account(2)).total_matches(), 2);
+        assert_eq!(*book.trades_query(&TradeFilter::new().with_ticks(9, 12)).total_matches(), 2);
+        assert_eq!(*book.trades_query(&TradeFilter::new().with_time_range(Some(Instant::now()), None)).total_matches(), 0);
+
+        // Pages skip earlier matches but still report the total
+        let page = book.trades_query(&TradeFilter::new().with_page(1, 2));
+        assert_eq!(*page.total_matches(), 4);
+        assert_eq!(page.trades().iter().map(|trade| *trade.trade_id()).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_flow_toxicity_from_trades() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 100)]).with_bid(8, 100).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000), (Currency::OSMO, 1000)]).build();
+        assert_eq!(book.flow_toxicity(50, 4).vpin(), None);
+
+        // 100 bought then 50 sold: two all-buy buckets and one all-sell bucket
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 100)).unwrap();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Ask, 50)).unwrap();
+        let toxicity = book.flow_toxicity(50, 4);
+        assert_eq!(toxicity.buckets().len(), 3);
+        assert_eq!(toxicity.vpin(), Some(1.0));
+    }
+
+    #[test]
+    fn test_checksum_follows_book_contents() {
+        let build = || BookBuilder::new().with_asks(&[(10, 100), (12, 50)]).with_bid(8, 20).build();
+        let mut book = build();
+        assert_eq!(book.checksum(), build().checksum());
+        assert_ne!(book.checksum(), BookBuilder::new().build().checksum());
+
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 10000)]).build();
+        let before = book.checksum();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 10)).unwrap();
+        assert_ne!(book.checksum(), before);
+    }
+
+    #[test]
+    fn test_max_affordable_quantity() {
+        let book = BookBuilder::new().with_asks(&[(10, 100), (12, 100)]).with_bid(8, 50).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 200), (Currency::OSMO, 30)]).build();
+
+        // 200 USD buys all 100 at 1.0, then 83 of the level at 1.2
+        assert_eq!(book.max_affordable_quantity(&market_order(&trader, OrderDirection::Bid, 500)), 183);
+
+        // A limit bid at 1.1 crosses the level at 1.0 and escrows the 100 USD left over at 1.1 for the rest
+        assert_eq!(book.max_affordable_quantity(&limit_order(&trader, OrderDirection::Bid, 11, 500)), 100 + 1000 / 11);
+
+        // A resting bid below the asks escrows everything at its own price
+        assert_eq!(book.max_affordable_quantity(&limit_order(&trader, OrderDirection::Bid, 7, 500)), 2000 / 7);
+
+        // Asks are limited by the OSMO held, market asks also by the bids available
+        assert_eq!(book.max_affordable_quantity(&limit_order(&trader, OrderDirection::Ask, 15, 500)), 30);
+        let rich_seller = AccountBuilder::funded(2, &[(Currency::OSMO, 1000)]).build();
+        assert_eq!(book.max_affordable_quantity(&market_order(&rich_seller, OrderDirection::Ask, 500)), 50);
+    }
+
+    #[test]
+    fn test_top_of_book() {
+        let book = BookBuilder::new().with_bids(&[(8, 100), (9, 200)]).with_asks(&[(12, 300), (14, 10)]).build();
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(9), Some(12)));
+        assert_eq!(book.spread(), Some(3));
+        assert_eq!(book.mid_price(), Some(10.5));
+
+        // An empty side has no best price, so there is no spread or mid either
+        let one_sided = BookBuilder::new().with_ask(12, 300).build();
+        assert_eq!((one_sided.best_bid(), one_sided.best_ask()), (None, Some(12)));
+        assert_eq!((one_sided.spread(), one_sided.mid_price()), (None, None));
+        let empty = Orderbook::new(0);
+        assert_eq!((empty.best_bid(), empty.best_ask(), empty.spread(), empty.mid_price()), (None, None, None, None));
+    }
+
+    #[test]
+    fn test_snapshot_levels_and_caching() {
+        let mut book = BookBuilder::new().with_bids(&[(8, 100), (9, 200)]).with_ask(12, 300).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000), (Currency::OSMO, 100000)]).build();
+
+        // Levels are ordered best price first on each side
+        let snapshot = book.snapshot();
+        assert_eq!(snapshot.bids(), &vec![(9, 200), (8, 100)]);
+        assert_eq!(snapshot.asks(), &vec![(12, 300)]);
+        assert_eq!(snapshot.quantity_at(8), 100);
+        assert_eq!(snapshot.quantity_at(10), 0);
+
+        // Lookups find levels on either side however far apart they are
+        let sparse = BookBuilder::new().with_bids(&[(3, 10), (500_000_000, 20)]).with_asks(&[(999_999_999, 30)]).build().snapshot();
+        assert_eq!(sparse.quantity_at(3), 10);
+        assert_eq!(sparse.quantity_at(500_000_000), 20);
+        assert_eq!(sparse.quantity_at(999_999_999), 30);
+        assert_eq!(sparse.quantity_at(500_000_001), 0);
+
+        // Without a mutation in between the same snapshot is handed out again
+        assert!(Arc::ptr_eq(&snapshot, &book.snapshot()));
+
+        // After a mutation a new snapshot is built while the old one stays readable
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 13, 50)).unwrap();
+        let updated = book.snapshot();
+        assert!(*updated.version() > *snapshot.version());
+        assert_eq!(updated.asks(), &vec![(12, 300), (13, 50)]);
+        assert_eq!(snapshot.asks(), &vec![(12, 300)]);
+    }
+
+    #[test]
+    fn test_liquidity_concentration() {
+        let book = BookBuilder::new().with_bids(&[(8, 100), (9, 300)]).with_asks(&[(11, 300), (30, 300)]).build();
+        let snapshot = book.snapshot();
+
+        // Shares are 0.1, 0.3, 0.3 and 0.3
+        assert_eq!(snapshot.mid_tick(), Some(10));
+        assert!((snapshot.herfindahl_index().unwrap() - 0.28).abs() < 1e-9);
+
+        // Everything but the far ask at 30 is within 2 ticks of the mid
+        assert_eq!(snapshot.depth_share_near_mid(2), Some(0.7));
+        assert_eq!(snapshot.depth_share_near_mid(20), Some(1.0));
+
+        // An empty book has no concentration to speak of
+        let empty = Orderbook::new(0).snapshot();
+        assert_eq!(empty.herfindahl_index(), None);
+        assert_eq!(empty.depth_share_near_mid(5), None);
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let mut book = BookBuilder::new().with_bid(9, 200).with_ask(12, 300).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 100000), (Currency::OSMO, 100000)]).build();
+        let earlier = book.snapshot();
+
+        // Add liquidity on a new level and an existing one, then take some off the ask
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 8, 100)).unwrap();
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 9, 50)).unwrap();
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 13, 120)).unwrap();
+
+        let diff = book.snapshot().diff(&earlier);
+        assert_eq!(diff, vec![(8, 100), (9, 50), (12, -120)]);
+
+        // Diffing a snapshot with itself reports nothing
+        assert!(earlier.diff(&earlier).is_empty());
+    }
+
+    #[test]
+    fn test_trades_since_resumes_across_archiving() {
+        let mut book = BookBuilder::new().with_asks(&[(10, 1), (11, 1), (12, 1)]).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000)]).build();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 2)).unwrap();
+
+        let seen = book.next_trade_id();
+        assert_eq!((seen, book.trades_since(0).len()), (2, 2));
+        assert!(book.trades_since(seen).is_empty());
+
+        // Archived trades are gone from memory, but the sequence carries on where the consumer left off
+        book.archive_trades(&mut crate::storage::memory::MemoryStorage::new(), 0).unwrap();
+        book.handle_order(&mut market_order(&trader, OrderDirection::Bid, 1)).unwrap();
+        let new_trades = book.trades_since(seen);
+        assert_eq!(new_trades.iter().map(|trade| (*trade.trade_id(), *trade.tick_id())).collect::<Vec<_>>(), vec![(2, 12)]);
+        assert_eq!(book.trades_since(0).len(), 1);
+        assert_eq!(book.next_trade_id(), 3);
+    }
+}
 
@@ -0,0 +1,93 @@
+use super::error::OrderbookError;
+use std::error::Error;
+use std::fmt;
+
+// Stable reason for an order or command being rejected. Programmatic clients should branch on the
+// numeric `code()` or the `as_str()` name, both of which never change once published.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum RejectCode {
+    // The order's fields are not valid for the operation (e.g. a market order placed as a limit).
+    InvalidOrder,
+    // The command text could not be parsed into an order or query.
+    InvalidCommand,
+    // No resting order with that id belongs to the account (e.g. it was filled or cancelled already).
+    UnknownOrder,
+    // No market is listed under the order's book id, or its book isn't available to route to.
+    UnknownMarket,
+    // A fill-or-kill order can't be filled in full from the liquidity on the book right now.
+    InsufficientLiquidity,
+    // The order would breach one of the owner's risk limits.
+    RiskLimitExceeded,
+    // A limit order is priced further through the opposite side than the book allows (likely a fat finger).
+    PriceProtection,
+    // The same order was already sent by the same account moments ago.
+    DuplicateOrder,
+    // A post-only order would have crossed the spread and taken liquidity.
+    PostOnly,
+    // The owner doesn't hold enough of the asset the order needs.
+    InsufficientFunds,
+}
+
+impl RejectCode {
+    // Validation codes are 1xx, risk 2xx, funds 3xx.
+    pub fn code(&self) -> u16 {
+        match self {
+            RejectCode::InvalidOrder => 100,
+            RejectCode::InvalidCommand => 101,
+            RejectCode::UnknownOrder => 102,
+            RejectCode::UnknownMarket => 103,
+            RejectCode::InsufficientLiquidity => 104,
+            RejectCode::RiskLimitExceeded => 200,
+            RejectCode::PriceProtection => 201,
+            RejectCode::DuplicateOrder => 202,
+            RejectCode::PostOnly => 203,
+            RejectCode::InsufficientFunds => 300,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectCode::InvalidOrder => "invalid_order",
+            RejectCode::InvalidCommand => "invalid_command",
+            RejectCode::UnknownOrder => "unknown_order",
+            RejectCode::UnknownMarket => "unknown_market",
+            RejectCode::InsufficientLiquidity => "insufficient_liquidity",
+            RejectCode::RiskLimitExceeded => "risk_limit_exceeded",
+            RejectCode::PriceProtection => "price_protection",
+            RejectCode::DuplicateOrder => "duplicate_order",
+            RejectCode::PostOnly => "post_only",
+            RejectCode::InsufficientFunds => "insufficient_funds",
+        }
+    }
+}
+
+impl fmt::Display for RejectCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.as_str())
+    }
+}
+
+// Pulls the reject code out of a boxed error, if it came from the orderbook.
+pub fn reject_code(error: &(dyn Error + 'static)) -> Option<RejectCode> {
+    error.downcast_ref::<OrderbookError>().map(OrderbookError::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+
+    #[test]
+    fn test_reject_code_from_boxed_error() {
+        let error: Box<dyn Error> = Box::new(OrderbookError::InsufficientFunds { currency: Currency::USD, required: 2, available: 1 });
+
+        // The boxed error still displays its reason and exposes the code through a downcast
+        assert_eq!(error.to_string(), "Insufficient funds");
+        assert_eq!(reject_code(error.as_ref()), Some(RejectCode::InsufficientFunds));
+        assert_eq!(RejectCode::InsufficientFunds.to_string(), "300 insufficient_funds");
+
+        // Errors that didn't come from a rejection have no code
+        let error: Box<dyn Error> = "something else".into();
+        assert_eq!(reject_code(error.as_ref()), None);
+    }
+}
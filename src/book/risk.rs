@@ -0,0 +1,148 @@
+use getset::Getters;
+use super::order::OrderDirection;
+use std::time::Duration;
+
+// How far a market order is allowed to trade, either from the reference price or in absolute terms.
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SlippageLimit {
+    // Absolute distance in ticks.
+    Ticks(u64),
+    // Distance in basis points of the reference price.
+    Bps(u64),
+    // Least favorable tick the order may trade at, whatever the reference price.
+    WorstTick(u64),
+    // Bound on the order's notional for its whole quantity: the most a buy may spend, or the least a sell must get
+    // back (its minimum output). The order may only fill as far as its average price stays within notional /
+    // quantity per unit.
+    Notional(u64),
+}
+
+// What to do with a market order whose estimated impact goes past its slippage limit.
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SlippagePolicy {
+    // Reject the whole order without touching the book.
+    Reject,
+    // Fill only the levels within the limit and drop the rest of the order.
+    Truncate,
+}
+
+#[derive(Getters, PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlippageGuard {
+    #[get = "pub"]
+    limit: SlippageLimit,
+    #[get = "pub"]
+    policy: SlippagePolicy,
+}
+
+impl SlippageGuard {
+    pub fn new(limit: SlippageLimit, policy: SlippagePolicy) -> SlippageGuard {
+        SlippageGuard { limit, policy }
+    }
+
+    // Least favorable tick an order in the given direction may trade at, measured from the reference tick. Notional
+    // limits bound the average price rather than any one tick, so they leave every tick reachable.
+    pub fn bound_tick(&self, reference_tick: u64, direction: OrderDirection) -> u64 {
+        let distance = match self.limit {
+            SlippageLimit::Ticks(ticks) => ticks,
+            SlippageLimit::Bps(bps) => reference_tick.saturating_mul(bps) / 10000,
+            SlippageLimit::WorstTick(tick_id) => return tick_id,
+            SlippageLimit::Notional(_) => u64::MAX,
+        };
+
+        match direction {
+            OrderDirection::Bid => reference_tick.saturating_add(distance),
+            OrderDirection::Ask => reference_tick.saturating_sub(distance),
+        }
+    }
+}
+
+// What to do with an order identical to one the same account sent within the duplicate window.
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicatePolicy {
+    // Accept the order but count it in the book's flagged duplicates.
+    Flag,
+    // Reject the order without touching the book.
+    Reject,
+}
+
+// Treats orders with the same owner, side, type, price and size sent within `window` of each other as
+// duplicates, e.g. from key repeat or a bot stuck in a loop.
+#[derive(Getters, PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuplicateGuard {
+    #[get = "pub"]
+    window: Duration,
+    #[get = "pub"]
+    policy: DuplicatePolicy,
+}
+
+impl DuplicateGuard {
+    pub fn new(window: Duration, policy: DuplicatePolicy) -> DuplicateGuard {
+        DuplicateGuard { window, policy }
+    }
+}
+
+// Risk settings applied to every order from an account unless the order carries its own.
+#[derive(Getters, Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RiskConfig {
+    #[get = "pub"]
+    max_slippage: Option<SlippageGuard>,
+    #[get = "pub"]
+    duplicate_guard: Option<DuplicateGuard>,
+}
+
+impl RiskConfig {
+    pub fn new() -> RiskConfig {
+        RiskConfig::default()
+    }
+
+    pub fn set_max_slippage(&mut self, max_slippage: Option<SlippageGuard>) {
+        self.max_slippage = max_slippage;
+    }
+
+    pub fn set_duplicate_guard(&mut self, duplicate_guard: Option<DuplicateGuard>) {
+        self.duplicate_guard = duplicate_guard;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bound_tick_in_ticks() {
+        let guard = SlippageGuard::new(SlippageLimit::Ticks(5), SlippagePolicy::Reject);
+
+        // Bids may pay up to 5 ticks more, asks may receive up to 5 ticks less
+        assert_eq!(guard.bound_tick(20, OrderDirection::Bid), 25);
+        assert_eq!(guard.bound_tick(20, OrderDirection::Ask), 15);
+
+        // Asks can never be bounded below tick 0
+        assert_eq!(guard.bound_tick(3, OrderDirection::Ask), 0);
+    }
+
+    #[test]
+    fn test_bound_tick_in_bps() {
+        let guard = SlippageGuard::new(SlippageLimit::Bps(500), SlippagePolicy::Truncate);
+
+        // 500 bps of tick 200 is 10 ticks
+        assert_eq!(guard.bound_tick(200, OrderDirection::Bid), 210);
+        assert_eq!(guard.bound_tick(200, OrderDirection::Ask), 190);
+    }
+
+    #[test]
+    fn test_absolute_bounds_ignore_the_reference() {
+        let guard = SlippageGuard::new(SlippageLimit::WorstTick(13), SlippagePolicy::Reject);
+        assert_eq!(guard.bound_tick(200, OrderDirection::Bid), 13);
+        assert_eq!(guard.bound_tick(2, OrderDirection::Ask), 13);
+
+        let guard = SlippageGuard::new(SlippageLimit::Notional(500), SlippagePolicy::Reject);
+        assert_eq!(guard.bound_tick(20, OrderDirection::Bid), u64::MAX);
+        assert_eq!(guard.bound_tick(20, OrderDirection::Ask), 0);
+    }
+}
@@ -1,9 +1,22 @@
-use super::order::{Order, OrderType};
+use super::matching::MatchingPolicy;
+use super::error::OrderbookError;
+use super::order::{Order, OrderDirection, OrderType};
 use getset::Getters;
 use std::collections::VecDeque;
-use crate::bank::account::{Account, AccountType};
+
+// Quantity taken from one resting order by a fill on its tick.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct MakerFill {
+    #[get = "pub"]
+    order_id: u64,
+    #[get = "pub"]
+    account_id: u64,
+    #[get = "pub"]
+    quantity: u64,
+}
 
 #[derive(Getters, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tick {
     #[get = "pub"]
     tick_id: u64,
@@ -11,43 +24,227 @@ pub struct Tick {
     next_order: u64,
     #[get = "pub"]
     orders: VecDeque<Order>,
+    // Total liquidity on the tick: the remaining quantity summed over the queue, kept in step on place, fill and cancel.
     #[get = "pub"]
-    pub total_orders: u64,
+    #[cfg_attr(feature = "serde", serde(alias = "total_orders"))]
+    pub total_liquidity: u64,
+    // Fills made on the tick since they were last taken, oldest first.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    fills: Vec<MakerFill>,
 }
 
 // implement public constructor and getters for all fields
 impl Tick {
     pub fn new(tick_id: u64) -> Tick {
         Tick {
-            tick_id: tick_id,
+            tick_id,
             next_order: 0,
             orders: VecDeque::new(),
-            total_orders: 0,
+            total_liquidity: 0,
+            fills: Vec::new(),
+        }
+    }
+
+    // The queued orders, for relinking their owners when a saved book is restored.
+    #[cfg(feature = "serde")]
+    pub(crate) fn orders_mut(&mut self) -> impl Iterator<Item = &mut Order> {
+        self.orders.iter_mut()
+    }
+
+    // Hands over the fills recorded since the last call, so the book can turn them into trades.
+    pub fn take_fills(&mut self) -> Vec<MakerFill> {
+        std::mem::take(&mut self.fills)
+    }
+
+    // Releases queue capacity left over from orders that have since been filled.
+    pub fn shrink_to_fit(&mut self) {
+        self.orders.shrink_to_fit();
+        self.fills.shrink_to_fit();
+    }
+
+    // Approximate heap bytes held by the tick's containers, counting allocated rather than used capacity.
+    pub fn heap_bytes(&self) -> usize {
+        self.orders.capacity() * std::mem::size_of::<Order>() + self.fills.capacity() * std::mem::size_of::<MakerFill>()
+    }
+
+    // Every fill goes through here, so this is where the tick's liquidity comes down.
+    fn record_fill(&mut self, index: usize, quantity: u64) {
+        self.total_liquidity -= quantity;
+        let order = &self.orders[index];
+        self.fills.push(MakerFill {
+            order_id: *order.order_id(),
+            account_id: *order.owner().borrow().account_id(),
+            quantity,
+        });
+    }
+
+    // Quantity the tick shows: the sum of the visible quantities, which leaves out the hidden reserve of icebergs.
+    pub fn visible_orders(&self) -> u64 {
+        self.orders.iter().map(Order::visible_quantity).sum()
+    }
+
+    // An iceberg whose displayed slice was used up shows its next slice from the reserve and, like a new order,
+    // joins the back of the queue.
+    fn requeue_front_if_slice_used(&mut self) {
+        if self.orders.front().is_some_and(|order| *order.quantity() > 0 && order.visible_quantity() == 0) {
+            let mut order = self.orders.pop_front().unwrap();
+            order.show_next_slice();
+            self.orders.push_back(order);
         }
     }
 
     // fill_tick fills as much of the tick as possible with the given quantity.
     // It returns the remaining portion of the input quantity (0 if the whole input is consumed).
+    // Only the visible slice of the order at the front is taken at a time, so an iceberg's reserve trades after the
+    // orders queued behind it.
     pub fn fill_tick(&mut self, quantity: u64) -> u64 {
         let mut remaining_quantity = quantity;
 
-        while remaining_quantity > 0 && self.orders.len() > 0 {
+        while remaining_quantity > 0 && !self.orders.is_empty() {
             let order = self.orders.front_mut().unwrap();
-            remaining_quantity = order.fill_order(remaining_quantity);
-            
-            if order.quantity() == &0 {
+            let filled_quantity = remaining_quantity.min(order.visible_quantity());
+            order.fill_order(filled_quantity);
+            self.record_fill(0, filled_quantity);
+            remaining_quantity -= filled_quantity;
+
+            if self.orders[0].quantity() == &0 {
                 self.orders.pop_front();
+            } else {
+                self.requeue_front_if_slice_used();
+            }
+        }
+        remaining_quantity
+    }
+
+    // What filling `quantity` under `policy` takes off the worth of the orders queued here, which is what a taker
+    // pays for it or gets for it. Worth is rounded per order, so it depends on how the fill is split between them:
+    // the fill is played out on a copy of the queue whose orders pay nobody.
+    pub fn fill_worth(&self, quantity: u64, policy: MatchingPolicy) -> u64 {
+        let mut copy = Tick {
+            tick_id: self.tick_id,
+            next_order: self.next_order,
+            orders: self.orders.iter().map(Order::detached).collect(),
+            total_liquidity: self.total_liquidity,
+            fills: Vec::new(),
+        };
+        copy.fill_tick_with_policy(quantity, policy);
+        let worth = |tick: &Tick| tick.orders.iter().map(|order| u128::from(order.worth(*order.quantity()))).sum::<u128>();
+        u64::try_from(worth(self) - worth(&copy)).unwrap_or(u64::MAX)
+    }
+
+    // Fills the tick according to the book's matching policy, returning the unconsumed part of the input quantity.
+    pub fn fill_tick_with_policy(&mut self, quantity: u64, policy: MatchingPolicy) -> u64 {
+        match policy {
+            MatchingPolicy::Fifo => self.fill_tick(quantity),
+            MatchingPolicy::ProRata { min_allocation } => self.fill_tick_pro_rata(quantity, min_allocation),
+            MatchingPolicy::RoundRobin { slice } => self.fill_tick_round_robin(quantity, slice),
+        }
+    }
+
+    // fill_tick_round_robin takes at most `slice` (at least 1) from the order at the front of the queue before moving
+    // on, sending orders that still have quantity left to the back of the queue.
+    // Like fill_tick, it returns the remaining portion of the input quantity.
+    pub fn fill_tick_round_robin(&mut self, quantity: u64, slice: u64) -> u64 {
+        let slice = slice.max(1);
+        let mut remaining_quantity = quantity;
+
+        while remaining_quantity > 0 && !self.orders.is_empty() {
+            let turn = remaining_quantity.min(slice);
+            let order = self.orders.front_mut().unwrap();
+            let filled_quantity = turn.min(order.visible_quantity());
+            order.fill_order(filled_quantity);
+            self.record_fill(0, filled_quantity);
+            remaining_quantity -= filled_quantity;
+
+            let mut order = self.orders.pop_front().unwrap();
+            if *order.quantity() > 0 {
+                if order.visible_quantity() == 0 {
+                    order.show_next_slice();
+                }
+                self.orders.push_back(order);
             }
         }
         remaining_quantity
     }
 
+    // fill_tick_pro_rata splits the quantity across all orders in proportion to their visible size.
+    // Allocations below min_allocation are dropped and the rounding remainder is handed out in FIFO order,
+    // to orders that already received an allocation first. Icebergs whose slice is used up show their next slice
+    // at the back of the queue.
+    // Like fill_tick, it returns the remaining portion of the input quantity.
+    pub fn fill_tick_pro_rata(&mut self, quantity: u64, min_allocation: u64) -> u64 {
+        let visible_quantity = self.visible_orders();
+
+        // Taking everything shown leaves nothing to prorate
+        if quantity >= visible_quantity {
+            return self.fill_tick(quantity);
+        }
+
+        let mut allocations: Vec<u64> = self.orders.iter()
+            .map(|order| (quantity as u128 * order.visible_quantity() as u128 / visible_quantity as u128) as u64)
+            .map(|allocation| if allocation < min_allocation { 0 } else { allocation })
+            .collect();
+
+        let mut leftover = quantity - allocations.iter().sum::<u64>();
+        let mut remainder_queue: Vec<usize> = (0..self.orders.len()).collect();
+        remainder_queue.sort_by_key(|i| allocations[*i] == 0);
+        for i in remainder_queue {
+            let extra = leftover.min(self.orders[i].visible_quantity() - allocations[i]);
+            allocations[i] += extra;
+            leftover -= extra;
+        }
+
+        for (index, allocation) in allocations.into_iter().enumerate() {
+            if allocation > 0 {
+                self.orders[index].fill_order(allocation);
+                self.record_fill(index, allocation);
+            }
+        }
+        self.orders.retain(|order| order.quantity() > &0);
+        let (mut used_up, kept): (VecDeque<Order>, VecDeque<Order>) = self.orders.drain(..)
+            .partition(|order| order.visible_quantity() == 0);
+        used_up.iter_mut().for_each(Order::show_next_slice);
+        self.orders = kept;
+        self.orders.extend(used_up);
+
+        0
+    }
+
+    // Takes the account's order with the given id out of the queue, keeping the others in priority order.
+    pub fn remove_order(&mut self, order_id: u64, account_id: u64) -> Option<Order> {
+        let index = self.orders.iter()
+            .position(|order| *order.order_id() == order_id && *order.owner().borrow().account_id() == account_id)?;
+        let order = self.orders.remove(index)?;
+        self.total_liquidity -= order.quantity();
+        Some(order)
+    }
+
+    // Lowers the quantity of the account's order without moving it in the queue. Returns the order as it was before.
+    pub fn reduce_order(&mut self, order_id: u64, account_id: u64, quantity: u64) -> Option<Order> {
+        let order = self.orders.iter_mut()
+            .find(|order| *order.order_id() == order_id && *order.owner().borrow().account_id() == account_id)?;
+        let previous = order.clone();
+        self.total_liquidity -= previous.quantity().saturating_sub(quantity);
+        order.set_quantity(quantity.min(*previous.quantity()));
+        Some(previous)
+    }
+
+    // Takes every order of the account on the given side out of the queue in one pass, keeping the rest in priority order.
+    pub fn remove_account_orders(&mut self, account_id: u64, direction: OrderDirection) -> Vec<Order> {
+        let (removed, kept): (VecDeque<Order>, VecDeque<Order>) = self.orders.drain(..)
+            .partition(|order| *order.order_direction() == direction && *order.owner().borrow().account_id() == account_id);
+        self.orders = kept;
+        self.total_liquidity -= removed.iter().map(|order| *order.quantity()).sum::<u64>();
+        removed.into()
+    }
+
     // Places limit order on tick
-    pub fn place_limit(&mut self, order: Order) -> Result<(), &'static str> {
+    pub fn place_limit(&mut self, mut order: Order) -> Result<(), OrderbookError> {
         if order.order_type() != &OrderType::Limit {
-            return Err("Order is not a limit order");
+            return Err(OrderbookError::InvalidOrder("Order is not a limit order"));
         }
-        self.total_orders += order.clone().quantity();
+        order.show_next_slice();
+        self.total_liquidity += order.clone().quantity();
         self.orders.push_back(order);
         Ok(())
     }
@@ -55,9 +252,8 @@ impl Tick {
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, rc::Rc};
-
     use super::*;
+    use crate::testkit::AccountBuilder;
     use crate::book::order::{OrderDirection, OrderType};
 
     // Helper function for placing orders on a tick (manual placement to avoid testing co-dependency)
@@ -67,15 +263,21 @@ mod tests {
                 i,
                 *tick.tick_id(),
                 0,
-                Rc::new(RefCell::new(Account::new(i, AccountType::Individual))),
+                AccountBuilder::new(i).build(),
                 OrderType::Market,
                 OrderDirection::Bid,
                 quantity_per_order,
             );
-            tick.orders.push_back(order);
+            push_order(tick, order);
         }
     }
 
+    // Queues the order as is, without place_limit's checks, keeping the tick's liquidity in step
+    fn push_order(tick: &mut Tick, order: Order) {
+        tick.total_liquidity += order.quantity();
+        tick.orders.push_back(order);
+    }
+
     #[test]
     fn test_fill_tick() {
         // Place 10 orders of 10 quantity each on tick 0
@@ -88,6 +290,7 @@ mod tests {
         // Check that the five filled orders were removed from the tick.
         // The partially filled order should still be there.
         assert_eq!(tick.orders.len(), 5);
+        assert_eq!(tick.total_liquidity, 45);
 
         // Fill the remaining 50 quantity on the tick.
         // Since there is only 45 quantity left on the tick, this fills the whole tick and overflows 5 units.
@@ -95,6 +298,110 @@ mod tests {
 
         // The tick should have zero orders remaining.
         assert_eq!(tick.orders.len(), 0);
+        assert_eq!(tick.total_liquidity, 0);
+    }
+
+    #[test]
+    fn test_fills_are_recorded_per_order() {
+        let mut tick = Tick::new(0);
+        place_orders(&mut tick, 3, 10);
+
+        // Each resting order touched by the fill is reported once with the quantity taken from it
+        tick.fill_tick(15);
+        let fills: Vec<(u64, u64, u64)> = tick.take_fills().iter()
+            .map(|fill| (*fill.order_id(), *fill.account_id(), *fill.quantity()))
+            .collect();
+        assert_eq!(fills, vec![(0, 0, 10), (1, 1, 5)]);
+
+        // Taking the fills clears them
+        assert!(tick.take_fills().is_empty());
+        tick.fill_tick_pro_rata(10, 0);
+        assert_eq!(tick.take_fills().iter().map(|fill| *fill.quantity()).sum::<u64>(), 10);
+    }
+
+    #[test]
+    fn test_fill_tick_pro_rata() {
+        // Orders of 100, 300 and 600 on the same tick
+        let mut tick = Tick::new(0);
+        for (i, quantity) in [100, 300, 600].into_iter().enumerate() {
+            push_order(&mut tick, Order::new(i as u64, 0, 0, AccountBuilder::new(i as u64).build(), OrderType::Market, OrderDirection::Bid, quantity));
+        }
+
+        // 505 units are split 50/151/303 by size, with the single unit lost to rounding going to the oldest order
+        assert_eq!(tick.fill_tick_pro_rata(505, 0), 0);
+        let remaining: Vec<u64> = tick.orders.iter().map(|order| *order.quantity()).collect();
+        assert_eq!(remaining, vec![49, 149, 297]);
+    }
+
+    #[test]
+    fn test_fill_tick_pro_rata_min_allocation() {
+        let mut tick = Tick::new(0);
+        place_orders(&mut tick, 1, 10);
+        push_order(&mut tick, Order::new(1, 0, 0, AccountBuilder::new(1).build(), OrderType::Market, OrderDirection::Bid, 990));
+
+        // The first order's 5 unit share is below the minimum, so the second order receives the whole fill
+        // including the units freed up by dropping it
+        assert_eq!(tick.fill_tick_pro_rata(500, 10), 0);
+        let remaining: Vec<u64> = tick.orders.iter().map(|order| *order.quantity()).collect();
+        assert_eq!(remaining, vec![10, 490]);
+
+        // Fully consumed orders are removed no matter where they sit in the queue
+        assert_eq!(tick.fill_tick_pro_rata(499, 10), 0);
+        assert_eq!(tick.orders.len(), 1);
+        assert_eq!(*tick.orders[0].quantity(), 1);
+
+        // Asking for more than the tick holds fills everything and returns the overflow
+        assert_eq!(tick.fill_tick_pro_rata(5, 10), 4);
+        assert!(tick.orders.is_empty());
+    }
+
+    #[test]
+    fn test_fill_tick_round_robin() {
+        // A 1000 unit order ahead of two 10 unit orders
+        let mut tick = Tick::new(0);
+        for (i, quantity) in [1000, 10, 10].into_iter().enumerate() {
+            push_order(&mut tick, Order::new(i as u64, 0, 0, AccountBuilder::new(i as u64).build(), OrderType::Market, OrderDirection::Bid, quantity));
+        }
+
+        // Slices of 5 go 5/5/5 and then 5 more to the large order, which is back at the front after the small ones
+        assert_eq!(tick.fill_tick_round_robin(20, 5), 0);
+        let queue: Vec<(u64, u64)> = tick.orders.iter().map(|order| (*order.order_id(), *order.quantity())).collect();
+        assert_eq!(queue, vec![(1, 5), (2, 5), (0, 990)]);
+
+        // Filled orders leave the queue, and more than the tick holds returns the overflow
+        assert_eq!(tick.fill_tick_round_robin(20, 5), 0);
+        assert_eq!(tick.orders.len(), 1);
+        assert_eq!(tick.fill_tick_round_robin(1000, 100), 1000 - 980);
+        assert!(tick.orders.is_empty());
+    }
+
+    #[test]
+    fn test_iceberg_refreshes_slice_at_back_of_queue() {
+        // A 100 unit iceberg showing 10 at a time ahead of a plain 20 unit order
+        let mut tick = Tick::new(0);
+        let mut iceberg = Order::new(0, 0, 0, AccountBuilder::new(0).build(), OrderType::Limit, OrderDirection::Bid, 100);
+        iceberg.set_display_quantity(Some(10));
+        tick.place_limit(iceberg).unwrap();
+        tick.place_limit(Order::new(1, 0, 0, AccountBuilder::new(1).build(), OrderType::Limit, OrderDirection::Bid, 20)).unwrap();
+        assert_eq!((tick.visible_orders(), tick.total_liquidity), (30, 120));
+
+        // The slice of 10 fills, the iceberg shows its next 10 behind the plain order, which takes the other 5
+        assert_eq!(tick.fill_tick(15), 0);
+        let queue: Vec<(u64, u64, u64)> = tick.orders.iter().map(|order| (*order.order_id(), *order.quantity(), order.visible_quantity())).collect();
+        assert_eq!(queue, vec![(1, 15, 15), (0, 90, 10)]);
+        assert_eq!(tick.take_fills().iter().map(|fill| (*fill.order_id(), *fill.quantity())).collect::<Vec<_>>(), vec![(0, 10), (1, 5)]);
+
+        // A large fill works through the reserve one slice at a time once the plain order is gone: 15, then 10 and
+        // 5 of the iceberg's next two slices
+        assert_eq!(tick.fill_tick(40), 0);
+        assert_eq!(tick.orders.len(), 1);
+        assert_eq!((*tick.orders[0].quantity(), tick.orders[0].visible_quantity()), (65, 5));
+
+        // Round robin turns also stop at the slice, taking the last 5 and then 7 of a fresh 10
+        assert_eq!(tick.fill_tick_round_robin(12, 100), 0);
+        assert_eq!((*tick.orders[0].quantity(), tick.orders[0].visible_quantity()), (53, 3));
+        assert_eq!(tick.fill_tick_pro_rata(8, 0), 0);
+        assert_eq!((*tick.orders[0].quantity(), tick.orders[0].visible_quantity()), (45, 5));
     }
 
     #[test]
@@ -104,7 +411,7 @@ mod tests {
             0,
             *tick.tick_id(),
             0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
+            AccountBuilder::new(0).build(),
             OrderType::Limit,
             OrderDirection::Bid,
             100,
@@ -114,7 +421,7 @@ mod tests {
         let result = tick.place_limit(order);
 
         // Check that result returned was not an error
-        assert_eq!(result.is_err(), false);
+        assert!(result.is_ok());
 
         // Check that the tick's queue was correctly updated
         assert_eq!(tick.orders.len(), 1);
@@ -129,7 +436,7 @@ mod tests {
             0,
             *tick.tick_id(),
             0,
-            Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
+            AccountBuilder::new(0).build(),
             OrderType::Market,
             OrderDirection::Bid,
             100,
@@ -137,9 +444,23 @@ mod tests {
 
         // Assert that correct error is returned
         let result = tick.place_limit(order);
-        assert_eq!(result.unwrap_err(), "Order is not a limit order");
+        assert_eq!(result.unwrap_err(), OrderbookError::InvalidOrder("Order is not a limit order"));
 
         // Assert that tick's queue was not updated
         assert_eq!(tick.orders.len(), 0);
     }
+
+    #[test]
+    fn test_remove_order() {
+        let mut tick = Tick::new(10);
+        place_orders(&mut tick, 3, 10);
+
+        // Order 1 belongs to account 1, so another account can't take it out
+        assert!(tick.remove_order(1, 2).is_none());
+        let removed = tick.remove_order(1, 1).unwrap();
+        assert_eq!(*removed.quantity(), 10);
+        assert_eq!(tick.total_liquidity, 20);
+        assert_eq!(tick.orders.iter().map(|order| *order.order_id()).collect::<Vec<_>>(), vec![0, 2]);
+        assert!(tick.remove_order(1, 1).is_none());
+    }
 }
@@ -0,0 +1,227 @@
+use getset::Getters;
+use std::error::Error;
+use std::fmt;
+
+// Most decimal places a price can be quoted with, so a tick's price in units of the last decimal fits a u64.
+pub const MAX_PRECISION: u32 = 9;
+
+// How a book's tick ids map to decimal prices. Tick `n` is priced at `n * tick_size` units of the `precision`-th
+// decimal place, so the default (tick size 1 at precision 1) makes every tick a tenth: tick 12 is 1.2. With tick size
+// 5 at precision 2, ticks go in steps of 0.05 and tick 12 is 0.6. Limit orders and crosses can only be priced from
+// `min_tick` to `max_tick`.
+//
+// Balances are counted in whole units of each asset, so what an order is worth follows its displayed price at any
+// tick size: 10 OSMO at 1.2 is worth 12 USD (see `notional`).
+#[derive(Getters, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TickConverter {
+    #[get = "pub"]
+    tick_size: u64,
+    #[get = "pub"]
+    precision: u32,
+    #[get = "pub"]
+    min_tick: u64,
+    #[get = "pub"]
+    max_tick: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParsePriceError {
+    // Not a plain non-negative decimal, or too large for a tick id
+    Invalid,
+    // Falls between two ticks of the converter it was parsed with
+    SubTick,
+}
+
+impl fmt::Display for ParsePriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsePriceError::Invalid => write!(f, "not a valid price"),
+            ParsePriceError::SubTick => write!(f, "price falls between two ticks"),
+        }
+    }
+}
+
+impl Error for ParsePriceError {}
+
+impl Default for TickConverter {
+    fn default() -> Self {
+        TickConverter::new(1, 1)
+    }
+}
+
+impl TickConverter {
+    // Every tick from 1 to u64::MAX - 1 is tradable. A tick size of 0 is treated as 1, and the precision is capped
+    // at MAX_PRECISION.
+    pub fn new(tick_size: u64, precision: u32) -> TickConverter {
+        TickConverter { tick_size: tick_size.max(1), precision: precision.min(MAX_PRECISION), min_tick: 1, max_tick: u64::MAX - 1 }
+    }
+
+    // Parses a tick size written as a price, e.g. "0.05" for tick size 5 at precision 2.
+    pub fn from_tick_size(tick_size: &str) -> Result<TickConverter, ParsePriceError> {
+        let precision = tick_size.split_once('.').map_or(0, |(_, fraction)| fraction.len() as u32);
+        if precision > MAX_PRECISION {
+            return Err(ParsePriceError::SubTick);
+        }
+        match TickConverter::new(1, precision).price_to_tick(tick_size)? {
+            0 => Err(ParsePriceError::Invalid),
+            units => Ok(TickConverter::new(units, precision)),
+        }
+    }
+
+    // Limits the tradable ticks to min_tick..=max_tick, never including tick 0 or u64::MAX.
+    pub fn with_tick_range(mut self, min_tick: u64, max_tick: u64) -> TickConverter {
+        self.min_tick = min_tick.max(1);
+        self.max_tick = max_tick.min(u64::MAX - 1);
+        self
+    }
+
+    pub fn contains(&self, tick_id: u64) -> bool {
+        (self.min_tick..=self.max_tick).contains(&tick_id)
+    }
+
+    // Tick of a decimal price, parsed from its digits so "3.3" is exactly tick 33 at the default tick size. Prices
+    // between ticks are rejected instead of rounded. The tick range isn't checked here; the book rejects orders
+    // priced outside it.
+    pub fn price_to_tick(&self, price: &str) -> Result<u64, ParsePriceError> {
+        let (whole, fraction) = price.split_once('.').unwrap_or((price, ""));
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+            return Err(ParsePriceError::Invalid);
+        }
+
+        let precision = self.precision as usize;
+        let (kept, rest) = fraction.split_at(fraction.len().min(precision));
+        if rest.bytes().any(|digit| digit != b'0') {
+            return Err(ParsePriceError::SubTick);
+        }
+        let fraction = kept.bytes().chain(std::iter::repeat(b'0')).take(precision)
+            .fold(0, |units, digit| units * 10 + u64::from(digit - b'0'));
+
+        let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| ParsePriceError::Invalid)? };
+        let units = whole.checked_mul(10u64.pow(self.precision))
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or(ParsePriceError::Invalid)?;
+        match units % self.tick_size {
+            0 => Ok(units / self.tick_size),
+            _ => Err(ParsePriceError::SubTick),
+        }
+    }
+
+    pub fn tick_to_price(&self, tick_id: u64) -> f64 {
+        tick_id as f64 * self.price_per_tick()
+    }
+
+    pub fn price_per_tick(&self) -> f64 {
+        self.tick_size as f64 / 10f64.powi(self.precision as i32)
+    }
+
+    // Exact price of a tick with as few decimals as it needs ("1.2", "2"), or at least `decimals` of them when given
+    // ("1.20"). Decimals the price needs are never dropped.
+    pub fn format_tick(&self, tick_id: u64, decimals: Option<usize>) -> String {
+        let units = u128::from(tick_id) * u128::from(self.tick_size);
+        let scale = 10u128.pow(self.precision);
+        let mut fraction = match self.precision {
+            0 => String::new(),
+            precision => format!("{:0width$}", units % scale, width = precision as usize),
+        };
+        fraction.truncate(fraction.trim_end_matches('0').len());
+        let width = fraction.len().max(decimals.unwrap_or(0));
+        match width {
+            0 => (units / scale).to_string(),
+            width => format!("{}.{:0<width$}", units / scale, fraction, width = width),
+        }
+    }
+
+    // Ticks in one whole unit of price, if a whole price always falls on a tick (10 at the default tick size).
+    pub fn ticks_per_unit(&self) -> Option<u64> {
+        let scale = 10u64.pow(self.precision);
+        scale.is_multiple_of(self.tick_size).then(|| scale / self.tick_size)
+    }
+
+    // Whole units of the pricing asset `quantity` is worth at a tick's price: 10 OSMO at 1.2 is worth 12 USD. Worth
+    // that isn't a whole unit rounds up, so a bid never locks less than its price. Every amount orders lock, get back,
+    // pay or receive comes from here, so the book always agrees with itself and with the prices it shows. It
+    // saturates instead of overflowing: no balance can cover u64::MAX, so an order worth that much is refused when
+    // it tries to lock it, and every amount an accepted order settles fits.
+    pub fn notional(&self, quantity: u64, tick_id: u64) -> u64 {
+        u128::from(quantity).checked_mul(u128::from(tick_id))
+            .and_then(|units| units.checked_mul(u128::from(self.tick_size)))
+            .map_or(u64::MAX, |units| u64::try_from(units.div_ceil(10u128.pow(self.precision))).unwrap_or(u64::MAX))
+    }
+
+    // Largest quantity whose notional at a tick fits in `budget`.
+    pub fn affordable_quantity(&self, budget: u64, tick_id: u64) -> u64 {
+        let units_per_quantity = u128::from(tick_id) * u128::from(self.tick_size);
+        match units_per_quantity {
+            0 => u64::MAX,
+            units => u64::try_from(u128::from(budget) * 10u128.pow(self.precision) / units).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_ticks_are_tenths() {
+        let converter = TickConverter::default();
+        assert_eq!(converter.price_to_tick("3.3"), Ok(33));
+        assert_eq!(converter.price_to_tick("1.50"), Ok(15));
+        assert_eq!(converter.price_to_tick("0.95"), Err(ParsePriceError::SubTick));
+        assert_eq!(converter.price_to_tick(".5"), Ok(5));
+        assert_eq!(converter.price_to_tick("2."), Ok(20));
+        for invalid in ["", ".", "-1", "+1", "1e3", "NaN", "inf", "1.2.3", " 1", "99999999999999999999"] {
+            assert_eq!(converter.price_to_tick(invalid), Err(ParsePriceError::Invalid), "{}", invalid);
+        }
+        assert_eq!(ParsePriceError::SubTick.to_string(), "price falls between two ticks");
+        assert_eq!(converter.format_tick(12, None), "1.2");
+        assert_eq!(converter.format_tick(20, None), "2");
+        assert_eq!(converter.format_tick(12, Some(3)), "1.200");
+        assert_eq!(converter.ticks_per_unit(), Some(10));
+        assert!(!converter.contains(0) && !converter.contains(u64::MAX));
+        for tick_id in 0..200 {
+            assert_eq!(converter.price_to_tick(&converter.format_tick(tick_id, None)), Ok(tick_id));
+        }
+    }
+
+    #[test]
+    fn test_notional_follows_the_price() {
+        let converter = TickConverter::default();
+        assert_eq!(converter.notional(10, 12), 12);
+        // 5 OSMO at 1.3 is worth 6.5 USD, rounded up to a whole unit
+        assert_eq!(converter.notional(5, 13), 7);
+        assert_eq!(converter.affordable_quantity(7, 13), 5);
+        assert_eq!(converter.affordable_quantity(6, 13), 4);
+        assert_eq!(converter.notional(u64::MAX / 2, 30), u64::MAX);
+        assert_eq!(converter.notional(u64::MAX, u64::MAX), u64::MAX);
+
+        // The same price costs the same at any tick size: 0.65 is tick 13 in steps of 0.05
+        let converter = TickConverter::from_tick_size("0.05").unwrap();
+        assert_eq!(converter.notional(100, 13), 65);
+        assert_eq!(converter.affordable_quantity(65, 13), 100);
+        assert_eq!(TickConverter::from_tick_size("25").unwrap().notional(2, 3), 150);
+    }
+
+    #[test]
+    fn test_configured_tick_size_and_range() {
+        let converter = TickConverter::from_tick_size("0.05").unwrap().with_tick_range(2, 400);
+        assert_eq!((*converter.tick_size(), *converter.precision()), (5, 2));
+        assert_eq!(converter.price_to_tick("0.6"), Ok(12));
+        assert_eq!(converter.price_to_tick("0.62"), Err(ParsePriceError::SubTick));
+        assert_eq!(converter.format_tick(12, None), "0.6");
+        assert_eq!(converter.format_tick(13, Some(1)), "0.65");
+        assert!((converter.tick_to_price(13) - 0.65).abs() < 1e-9);
+        assert_eq!(converter.ticks_per_unit(), Some(20));
+        assert!(converter.contains(400) && !converter.contains(401) && !converter.contains(1));
+
+        // Whole-number tick sizes drop the decimals altogether
+        let converter = TickConverter::from_tick_size("25").unwrap();
+        assert_eq!(converter.price_to_tick("75"), Ok(3));
+        assert_eq!(converter.format_tick(3, None), "75");
+        assert_eq!(converter.ticks_per_unit(), None);
+        assert_eq!(TickConverter::from_tick_size("0"), Err(ParsePriceError::Invalid));
+        assert_eq!(TickConverter::from_tick_size("0.0000000001"), Err(ParsePriceError::SubTick));
+    }
+}
@@ -0,0 +1,71 @@
+// Minimum price increment per price band. Tick ids step by the book's tick size (0.1 by default, see
+// TickConverter), so at the default a band with a step of 10 only accepts every tenth tick id (whole prices). Each
+// band runs from its starting tick up to the next band, and prices below the first band accept every tick id.
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TickSizeTable {
+    // (from_tick, step) pairs ordered by from_tick.
+    bands: Vec<(u64, u64)>,
+}
+
+impl TickSizeTable {
+    pub fn new() -> TickSizeTable {
+        TickSizeTable::default()
+    }
+
+    // Adds a band starting at `from_tick`, replacing any band that already starts there. A step of 0 is treated as 1.
+    pub fn with_band(mut self, from_tick: u64, step: u64) -> TickSizeTable {
+        self.bands.retain(|(band_from_tick, _)| *band_from_tick != from_tick);
+        self.bands.push((from_tick, step.max(1)));
+        self.bands.sort_unstable();
+        self
+    }
+
+    pub fn bands(&self) -> &[(u64, u64)] {
+        &self.bands
+    }
+
+    // Price increment, in tick ids, in force at the given tick.
+    pub fn step_at(&self, tick_id: u64) -> u64 {
+        self.bands.iter()
+            .rev()
+            .find(|(from_tick, _)| *from_tick <= tick_id)
+            .map_or(1, |(_, step)| *step)
+    }
+
+    pub fn is_valid(&self, tick_id: u64) -> bool {
+        tick_id.is_multiple_of(self.step_at(tick_id))
+    }
+
+    // Nearest valid ticks at or below and at or above the given tick, for suggesting a price to the user.
+    pub fn nearest_valid(&self, tick_id: u64) -> (u64, u64) {
+        let step = self.step_at(tick_id);
+        let below = tick_id - tick_id % step;
+        let above = if below == tick_id { tick_id } else { below.saturating_add(step) };
+        (below, above)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banded_tick_sizes() {
+        // 0.1 increments below price 100, whole prices from 100, steps of 5 from 1000
+        let table = TickSizeTable::new().with_band(1000, 10).with_band(10000, 50);
+
+        assert_eq!(table.step_at(999), 1);
+        assert_eq!(table.step_at(1000), 10);
+        assert_eq!(table.step_at(20000), 50);
+        assert!(table.is_valid(999));
+        assert!(table.is_valid(1010));
+        assert!(!table.is_valid(1015));
+        assert!(!table.is_valid(10010));
+        assert_eq!(table.nearest_valid(1015), (1010, 1020));
+        assert_eq!(table.nearest_valid(1020), (1020, 1020));
+
+        // Without bands every tick is valid
+        assert!(TickSizeTable::new().is_valid(12345));
+    }
+}
@@ -0,0 +1,129 @@
+use getset::Getters;
+use super::order::OrderDirection;
+use super::trade::Trade;
+
+// Traded volume split by aggressor side, filled up to a fixed total before the next bucket starts.
+#[derive(Getters, Clone, Copy, Debug, Default, PartialEq)]
+pub struct VolumeBucket {
+    #[get = "pub"]
+    buy_volume: u64,
+    #[get = "pub"]
+    sell_volume: u64,
+}
+
+impl VolumeBucket {
+    pub fn volume(&self) -> u64 {
+        self.buy_volume + self.sell_volume
+    }
+
+    pub fn imbalance(&self) -> u64 {
+        self.buy_volume.abs_diff(self.sell_volume)
+    }
+}
+
+// VPIN-style estimate of order flow toxicity. The trade stream is cut into buckets of equal volume, and VPIN is
+// the share of volume in the last `window` buckets that was one-sided: sum(|buys - sells|) / (buckets * volume).
+// Trades carry their aggressor side, so volume is classified exactly rather than from price changes. A trade that
+// overflows a bucket is split across it and the next one, and a trailing partial bucket is left out.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct FlowToxicity {
+    #[get = "pub"]
+    bucket_volume: u64,
+    #[get = "pub"]
+    window: usize,
+    // Completed buckets, oldest first.
+    #[get = "pub"]
+    buckets: Vec<VolumeBucket>,
+}
+
+impl FlowToxicity {
+    // Buckets the trades, which must be in execution order. Panics if bucket_volume or window is zero.
+    pub fn from_trades<'a>(trades: impl IntoIterator<Item = &'a Trade>, bucket_volume: u64, window: usize) -> FlowToxicity {
+        assert!(bucket_volume > 0 && window > 0, "bucket volume and window must be positive");
+
+        let mut buckets = Vec::new();
+        let mut current = VolumeBucket::default();
+        for trade in trades {
+            let mut remaining = *trade.quantity();
+            while remaining > 0 {
+                let taken = remaining.min(bucket_volume - current.volume());
+                match trade.aggressor() {
+                    OrderDirection::Bid => current.buy_volume += taken,
+                    OrderDirection::Ask => current.sell_volume += taken,
+                }
+                remaining -= taken;
+
+                if current.volume() == bucket_volume {
+                    buckets.push(current);
+                    current = VolumeBucket::default();
+                }
+            }
+        }
+
+        FlowToxicity { bucket_volume, window, buckets }
+    }
+
+    // VPIN over the latest `window` buckets, or over all of them while fewer have completed. None before the first.
+    pub fn vpin(&self) -> Option<f64> {
+        let start = self.buckets.len().saturating_sub(self.window);
+        Self::vpin_of(&self.buckets[start..], self.bucket_volume)
+    }
+
+    // VPIN as it stood after each completed bucket, oldest first, for plotting how toxicity evolved.
+    pub fn series(&self) -> Vec<f64> {
+        (1..=self.buckets.len())
+            .filter_map(|end| Self::vpin_of(&self.buckets[end.saturating_sub(self.window)..end], self.bucket_volume))
+            .collect()
+    }
+
+    fn vpin_of(buckets: &[VolumeBucket], bucket_volume: u64) -> Option<f64> {
+        if buckets.is_empty() {
+            return None;
+        }
+        let imbalance: u64 = buckets.iter().map(VolumeBucket::imbalance).sum();
+        Some(imbalance as f64 / (buckets.len() as u64 * bucket_volume) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn trade(aggressor: OrderDirection, quantity: u64) -> Trade {
+        Trade::new(0, 10, quantity, aggressor, 0, 0, 1, 0, Instant::now())
+    }
+
+    #[test]
+    fn test_trades_split_across_buckets() {
+        let trades = vec![
+            trade(OrderDirection::Bid, 70),
+            trade(OrderDirection::Ask, 50),
+            trade(OrderDirection::Ask, 100),
+            trade(OrderDirection::Bid, 30),
+        ];
+        let toxicity = FlowToxicity::from_trades(&trades, 100, 2);
+
+        // The second trade tops up the first bucket and spills 20 into the next; the last 50 don't fill a bucket
+        let buckets: Vec<(u64, u64)> = toxicity.buckets().iter().map(|bucket| (*bucket.buy_volume(), *bucket.sell_volume())).collect();
+        assert_eq!(buckets, vec![(70, 30), (0, 100)]);
+
+        // (40 + 100) / (2 * 100)
+        assert_eq!(toxicity.vpin(), Some(0.7));
+        assert_eq!(toxicity.series(), vec![0.4, 0.7]);
+    }
+
+    #[test]
+    fn test_vpin_uses_the_latest_window() {
+        let mut trades = vec![trade(OrderDirection::Bid, 300)];
+        trades.extend((0..4).map(|i| trade(if i % 2 == 0 { OrderDirection::Bid } else { OrderDirection::Ask }, 50)));
+        let toxicity = FlowToxicity::from_trades(&trades, 100, 2);
+
+        // Three one-sided buckets followed by two balanced ones
+        assert_eq!(toxicity.buckets().len(), 5);
+        assert_eq!(toxicity.vpin(), Some(0.0));
+        assert_eq!(toxicity.series(), vec![1.0, 1.0, 1.0, 0.5, 0.0]);
+
+        assert_eq!(FlowToxicity::from_trades(&trades[..0], 100, 2).vpin(), None);
+    }
+}
@@ -0,0 +1,214 @@
+use getset::Getters;
+use super::order::OrderDirection;
+use std::time::{Duration, Instant};
+
+// A fill between an incoming (taker) order and one resting (maker) order, at the maker's tick.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trade {
+    #[get = "pub"]
+    trade_id: u64,
+    #[get = "pub"]
+    tick_id: u64,
+    #[get = "pub"]
+    quantity: u64,
+    // Side of the taker: Bid when the taker bought OSMO, Ask when it sold.
+    #[get = "pub"]
+    aggressor: OrderDirection,
+    #[get = "pub"]
+    taker_account_id: u64,
+    #[get = "pub"]
+    taker_order_id: u64,
+    #[get = "pub"]
+    maker_account_id: u64,
+    #[get = "pub"]
+    maker_order_id: u64,
+    #[get = "pub"]
+    #[cfg_attr(feature = "serde", serde(with = "super::persist::instant_age"))]
+    executed_at: Instant,
+    // Agreed trade between two named accounts printed off the queue (see Orderbook::cross), rather than a fill.
+    #[get = "pub"]
+    cross: bool,
+}
+
+impl Trade {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        trade_id: u64,
+        tick_id: u64,
+        quantity: u64,
+        aggressor: OrderDirection,
+        taker_account_id: u64,
+        taker_order_id: u64,
+        maker_account_id: u64,
+        maker_order_id: u64,
+        executed_at: Instant,
+    ) -> Trade {
+        Trade {
+            trade_id,
+            tick_id,
+            quantity,
+            aggressor,
+            taker_account_id,
+            taker_order_id,
+            maker_account_id,
+            maker_order_id,
+            executed_at,
+            cross: false,
+        }
+    }
+
+    // The same trade, marked as a cross.
+    pub fn into_cross(mut self) -> Trade {
+        self.cross = true;
+        self
+    }
+
+    // Whether the account was on either side of the trade.
+    pub fn involves(&self, account_id: u64) -> bool {
+        self.taker_account_id == account_id || self.maker_account_id == account_id
+    }
+
+    // Single-line text form for archiving, e.g. "7 12 40 Bid 1 21 2 5 1500000 0". Instants have no absolute value,
+    // so the execution time is stored as microseconds since `epoch` and comes back relative to the same epoch.
+    // The last field marks crosses; records written before it existed have nine fields.
+    pub fn to_record(&self, epoch: Instant) -> String {
+        format!(
+            "{} {} {} {} {} {} {} {} {} {}",
+            self.trade_id,
+            self.tick_id,
+            self.quantity,
+            self.aggressor,
+            self.taker_account_id,
+            self.taker_order_id,
+            self.maker_account_id,
+            self.maker_order_id,
+            self.executed_at.saturating_duration_since(epoch).as_micros(),
+            u8::from(self.cross),
+        )
+    }
+
+    pub fn from_record(record: &str, epoch: Instant) -> Option<Trade> {
+        let fields: Vec<&str> = record.split(' ').collect();
+        if !(9..=10).contains(&fields.len()) {
+            return None;
+        }
+        let cross = match fields.get(9) {
+            None | Some(&"0") => false,
+            Some(&"1") => true,
+            Some(_) => return None,
+        };
+        let number = |index: usize| fields[index].parse::<u64>().ok();
+        let aggressor = match fields[3] {
+            "Bid" => OrderDirection::Bid,
+            "Ask" => OrderDirection::Ask,
+            _ => return None,
+        };
+        let trade = Trade::new(
+            number(0)?,
+            number(1)?,
+            number(2)?,
+            aggressor,
+            number(4)?,
+            number(5)?,
+            number(6)?,
+            number(7)?,
+            epoch + Duration::from_micros(number(8)?),
+        );
+        Some(if cross { trade.into_cross() } else { trade })
+    }
+}
+
+// Criteria for Orderbook::trades_query. Every criterion left unset matches all trades, and results are paged
+// newest first, `limit` trades at a time after skipping `offset` matches.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct TradeFilter {
+    #[get = "pub"]
+    account_id: Option<u64>,
+    // Inclusive (min, max) tick range.
+    #[get = "pub"]
+    ticks: Option<(u64, u64)>,
+    // Trades executed at or after `since`.
+    #[get = "pub"]
+    since: Option<Instant>,
+    // Trades executed before `until`.
+    #[get = "pub"]
+    until: Option<Instant>,
+    #[get = "pub"]
+    aggressor: Option<OrderDirection>,
+    #[get = "pub"]
+    offset: usize,
+    #[get = "pub"]
+    limit: usize,
+}
+
+impl Default for TradeFilter {
+    fn default() -> Self {
+        TradeFilter {
+            account_id: None,
+            ticks: None,
+            since: None,
+            until: None,
+            aggressor: None,
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+impl TradeFilter {
+    pub fn new() -> TradeFilter {
+        TradeFilter::default()
+    }
+
+    // Trades the account took part in, as taker or maker.
+    pub fn with_account(mut self, account_id: u64) -> TradeFilter {
+        self.account_id = Some(account_id);
+        self
+    }
+
+    pub fn with_ticks(mut self, min_tick: u64, max_tick: u64) -> TradeFilter {
+        self.ticks = Some((min_tick, max_tick));
+        self
+    }
+
+    pub fn with_time_range(mut self, since: Option<Instant>, until: Option<Instant>) -> TradeFilter {
+        self.since = since;
+        self.until = until;
+        self
+    }
+
+    pub fn with_aggressor(mut self, aggressor: OrderDirection) -> TradeFilter {
+        self.aggressor = Some(aggressor);
+        self
+    }
+
+    pub fn with_page(mut self, offset: usize, limit: usize) -> TradeFilter {
+        self.offset = offset;
+        self.limit = limit;
+        self
+    }
+
+    pub fn matches(&self, trade: &Trade) -> bool {
+        self.account_id.is_none_or(|account_id| trade.involves(account_id))
+            && self.ticks.is_none_or(|(min_tick, max_tick)| (min_tick..=max_tick).contains(&trade.tick_id))
+            && self.since.is_none_or(|since| trade.executed_at >= since)
+            && self.until.is_none_or(|until| trade.executed_at < until)
+            && self.aggressor.is_none_or(|aggressor| trade.aggressor == aggressor)
+    }
+}
+
+// One page of trades_query results, along with the number of trades matching the filter across all pages.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct TradePage {
+    #[get = "pub"]
+    trades: Vec<Trade>,
+    #[get = "pub"]
+    total_matches: usize,
+}
+
+impl TradePage {
+    pub fn new(trades: Vec<Trade>, total_matches: usize) -> TradePage {
+        TradePage { trades, total_matches }
+    }
+}
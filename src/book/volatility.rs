@@ -0,0 +1,139 @@
+use getset::Getters;
+use super::query::DepthSnapshot;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// Best bid and ask (in ticks) at one moment.
+#[derive(Getters, Clone, Copy, Debug, PartialEq)]
+pub struct QuoteSample {
+    #[get = "pub"]
+    recorded_at: Instant,
+    #[get = "pub"]
+    best_bid: u64,
+    #[get = "pub"]
+    best_ask: u64,
+}
+
+impl QuoteSample {
+    pub fn spread(&self) -> u64 {
+        self.best_ask.saturating_sub(self.best_bid)
+    }
+
+    pub fn mid(&self) -> f64 {
+        (self.best_bid + self.best_ask) as f64 / 2.0
+    }
+}
+
+// Ring buffer of the newest `capacity` quote samples, from which the spread and mid price series and the mid's
+// realized volatility are read. Realized volatility over n samples is sqrt(sum(r^2)) of the n - 1 log returns of
+// the mid between consecutive samples, so it is per n-sample period rather than annualized, and only comparable
+// between histories sampled at the same interval.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct QuoteHistory {
+    #[get = "pub"]
+    capacity: usize,
+    // Oldest first.
+    #[get = "pub"]
+    samples: VecDeque<QuoteSample>,
+}
+
+impl QuoteHistory {
+    // Panics if capacity is zero.
+    pub fn new(capacity: usize) -> QuoteHistory {
+        assert!(capacity > 0, "quote history capacity must be positive");
+        QuoteHistory { capacity, samples: VecDeque::with_capacity(capacity) }
+    }
+
+    // Records the snapshot's best bid and ask, dropping the oldest sample once full. Returns false without recording
+    // while either side is empty, since there is no spread or mid then.
+    pub fn record(&mut self, snapshot: &DepthSnapshot, recorded_at: Instant) -> bool {
+        let (best_bid, best_ask) = match (snapshot.bids().first(), snapshot.asks().first()) {
+            (Some((best_bid, _)), Some((best_ask, _))) => (*best_bid, *best_ask),
+            _ => return false,
+        };
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(QuoteSample { recorded_at, best_bid, best_ask });
+        true
+    }
+
+    pub fn spreads(&self) -> Vec<u64> {
+        self.samples.iter().map(QuoteSample::spread).collect()
+    }
+
+    pub fn mids(&self) -> Vec<f64> {
+        self.samples.iter().map(QuoteSample::mid).collect()
+    }
+
+    // Mean spread in ticks over every sample held, None while empty.
+    pub fn average_spread(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.spreads().iter().sum::<u64>() as f64 / self.samples.len() as f64)
+    }
+
+    // Realized volatility of the mid over the newest `window` samples, or all of them while fewer are held. None
+    // until there are two samples to take a return between.
+    pub fn realized_volatility(&self, window: usize) -> Option<f64> {
+        let mids = self.mids();
+        Self::realized_volatility_of(&mids[mids.len().saturating_sub(window)..])
+    }
+
+    // Realized volatility as it stood after each sample from the second on, oldest first, for plotting its trend.
+    pub fn volatility_series(&self, window: usize) -> Vec<f64> {
+        let mids = self.mids();
+        (2..=mids.len())
+            .filter_map(|end| Self::realized_volatility_of(&mids[end.saturating_sub(window)..end]))
+            .collect()
+    }
+
+    fn realized_volatility_of(mids: &[f64]) -> Option<f64> {
+        if mids.len() < 2 {
+            return None;
+        }
+        let sum_of_squares: f64 = mids.windows(2).map(|pair| (pair[1] / pair[0]).ln().powi(2)).sum();
+        Some(sum_of_squares.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::BookBuilder;
+
+    fn history_of(quotes: &[(u64, u64)], capacity: usize) -> QuoteHistory {
+        let mut history = QuoteHistory::new(capacity);
+        for (bid, ask) in quotes {
+            history.record(&BookBuilder::new().with_bid(*bid, 10).with_ask(*ask, 10).build().snapshot(), Instant::now());
+        }
+        history
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_newest_samples() {
+        let history = history_of(&[(9, 11), (10, 14), (8, 10), (9, 12)], 3);
+        assert_eq!(history.spreads(), vec![4, 2, 3]);
+        assert_eq!(history.mids(), vec![12.0, 9.0, 10.5]);
+        assert_eq!(history.average_spread(), Some(3.0));
+
+        // One-sided books have no touch to record
+        let mut history = QuoteHistory::new(3);
+        assert!(!history.record(&BookBuilder::new().with_bid(9, 10).build().snapshot(), Instant::now()));
+        assert_eq!(history.average_spread(), None);
+    }
+
+    #[test]
+    fn test_realized_volatility_of_mid() {
+        // Mids 10, 11, 10: returns ln(1.1) and ln(10/11)
+        let history = history_of(&[(9, 11), (10, 12), (9, 11)], 10);
+        let expected = (1.1f64.ln().powi(2) + (10.0f64 / 11.0).ln().powi(2)).sqrt();
+        assert!((history.realized_volatility(10).unwrap() - expected).abs() < 1e-12);
+        assert!((history.realized_volatility(2).unwrap() - (10.0f64 / 11.0).ln().abs()).abs() < 1e-12);
+        assert_eq!(history.volatility_series(10).len(), 2);
+
+        assert_eq!(history_of(&[(9, 11), (9, 11)], 10).realized_volatility(10), Some(0.0));
+        assert_eq!(history_of(&[(9, 11)], 10).realized_volatility(10), None);
+    }
+}
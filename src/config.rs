@@ -0,0 +1,328 @@
+use getset::Getters;
+use crate::bank::currency::Currency;
+use crate::book::tick_converter::TickConverter;
+use crate::book::tick_size::TickSizeTable;
+use crate::storage::StorageConfig;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// Account id of the interactive user.
+pub const USER_ACCOUNT_ID: u64 = 0;
+
+// Balances an account starts the session with.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct AccountSeed {
+    #[get = "pub"]
+    account_id: u64,
+    #[get = "pub"]
+    balances: Vec<(Currency, u64)>,
+}
+
+// Command a function key submits as soon as it is pressed. `@bid` and `@ask` in the command stand for the best bid
+// and ask prices at that moment, e.g. `buy osmo limit 100 @bid` joins the best bid.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct Hotkey {
+    // Function key number, 1 to 12.
+    #[get = "pub"]
+    key: u8,
+    #[get = "pub"]
+    command: String,
+}
+
+// Where and how often the session's book and balances are saved, so a crash or kill loses at most the operations
+// since the last save. A save is due once the book has changed `every_operations` times, or once it has changed at
+// all and `every` has passed, whichever comes first.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct AutosaveConfig {
+    #[get = "pub"]
+    path: PathBuf,
+    #[get = "pub"]
+    every_operations: Option<u64>,
+    #[get = "pub"]
+    every: Option<Duration>,
+    // Flush every save to disk before it replaces the previous one, so it also survives a power cut.
+    #[get = "pub"]
+    fsync: bool,
+}
+
+impl AutosaveConfig {
+    pub fn new(path: impl Into<PathBuf>, every_operations: Option<u64>, every: Option<Duration>, fsync: bool) -> AutosaveConfig {
+        AutosaveConfig { path: path.into(), every_operations, every, fsync }
+    }
+}
+
+// Startup configuration, read from a plain text file with one setting per line:
+//
+//   # the user trades from account 0
+//   account 0 OSMO=100000 USD=500000
+//   account 1 OSMO=10000000000 USD=10000000000
+//   bot 1
+//   pricing 0.1 0.1 100000
+//   ticksize 100 1
+//   hotkey F5 buy osmo market 100
+//   storage file ./history
+//   autosave ./session.json every 50 secs 10 fsync
+//
+// `account` declares an account and its starting balances, `bot` picks the account that funds the
+// liquidity bot, `pricing <tick size> [<min price> <max price>]` sets the price of one tick (0.1 by default) and the
+// range orders can be priced in, `ticksize <from price> <increment>` restricts limit prices from that price upwards to
+// multiples of the increment, `hotkey <F1-F12> <command>` binds a command to a function key and
+// `storage <file/sqlite> <path>` archives old trades to a directory of files or a sqlite database, and
+// `autosave <path> [every <operations>] [secs <seconds>] [fsync]` saves the book and balances to a file (after every
+// change if neither limit is given).
+// Blank lines and lines starting with `#` are ignored.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct Config {
+    #[get = "pub"]
+    accounts: Vec<AccountSeed>,
+    #[get = "pub"]
+    bot_account_id: Option<u64>,
+    #[get = "pub"]
+    tick_converter: TickConverter,
+    #[get = "pub"]
+    tick_sizes: TickSizeTable,
+    #[get = "pub"]
+    hotkeys: Vec<Hotkey>,
+    #[get = "pub"]
+    storage: Option<StorageConfig>,
+    #[get = "pub"]
+    autosave: Option<AutosaveConfig>,
+}
+
+impl Default for Config {
+    // The balances the user has always started with when no config file is given.
+    fn default() -> Self {
+        Config {
+            accounts: vec![AccountSeed {
+                account_id: USER_ACCOUNT_ID,
+                balances: vec![(Currency::OSMO, 100000), (Currency::USD, 500000)],
+            }],
+            bot_account_id: None,
+            tick_converter: TickConverter::default(),
+            tick_sizes: TickSizeTable::new(),
+            hotkeys: Vec::new(),
+            storage: None,
+            autosave: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Config::parse(&contents)?)
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let mut accounts: Vec<AccountSeed> = Vec::new();
+        let mut bot_account_id = None;
+        let mut tick_converter = TickConverter::default();
+        // ticksize bands are read once every line is in, as they are priced with the tick size of a `pricing` line
+        let mut tick_size_lines: Vec<(usize, &str, &str)> = Vec::new();
+        let mut hotkeys: Vec<Hotkey> = Vec::new();
+        let mut storage = None;
+        let mut autosave = None;
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let error = |reason: &str| format!("Config line {}: {}", line_number + 1, reason);
+
+            match tokens.first() {
+                None => continue,
+                Some(token) if token.starts_with('#') => continue,
+                Some(&"account") => {
+                    let account_id: u64 = tokens.get(1)
+                        .and_then(|id| id.parse().ok())
+                        .ok_or_else(|| error("expected `account <id> <CURRENCY>=<amount> ...`"))?;
+                    if accounts.iter().any(|seed| seed.account_id == account_id) {
+                        return Err(error(&format!("account {} is declared twice", account_id)));
+                    }
+
+                    let balances = tokens[2..].iter()
+                        .map(|balance| parse_balance(balance).ok_or_else(|| error(&format!("invalid balance `{}`", balance))))
+                        .collect::<Result<Vec<(Currency, u64)>, String>>()?;
+                    accounts.push(AccountSeed { account_id, balances });
+                }
+                Some(&"bot") => {
+                    let account_id: u64 = tokens.get(1)
+                        .and_then(|id| id.parse().ok())
+                        .ok_or_else(|| error("expected `bot <account id>`"))?;
+                    bot_account_id = Some(account_id);
+                }
+                Some(&"pricing") => {
+                    let usage = "expected `pricing <tick size> [<min price> <max price>]`";
+                    tick_converter = tokens.get(1)
+                        .and_then(|tick_size| TickConverter::from_tick_size(tick_size).ok())
+                        .ok_or_else(|| error(usage))?;
+                    match tokens[2..] {
+                        [] => {}
+                        [min, max] => match (tick_converter.price_to_tick(min), tick_converter.price_to_tick(max)) {
+                            (Ok(min_tick), Ok(max_tick)) if min_tick <= max_tick => tick_converter = tick_converter.with_tick_range(min_tick, max_tick),
+                            _ => return Err(error(usage)),
+                        },
+                        _ => return Err(error(usage)),
+                    }
+                }
+                Some(&"ticksize") => match tokens[1..] {
+                    [from, step] => tick_size_lines.push((line_number, from, step)),
+                    _ => return Err(error("expected `ticksize <from price> <increment>`")),
+                },
+                Some(&"hotkey") => {
+                    let key = match tokens.get(1).and_then(|key| parse_function_key(key)) {
+                        Some(key) if tokens.len() > 2 => key,
+                        _ => return Err(error("expected `hotkey <F1-F12> <command>`")),
+                    };
+                    if hotkeys.iter().any(|hotkey| hotkey.key == key) {
+                        return Err(error(&format!("F{} is bound twice", key)));
+                    }
+                    hotkeys.push(Hotkey { key, command: tokens[2..].join(" ") });
+                }
+                Some(&"storage") => {
+                    let path = tokens[2.min(tokens.len())..].join(" ");
+                    storage = match tokens.get(1) {
+                        Some(&"file") if !path.is_empty() => Some(StorageConfig::File(path.into())),
+                        Some(&"sqlite") if !path.is_empty() => Some(StorageConfig::Sqlite(path.into())),
+                        _ => return Err(error("expected `storage <file/sqlite> <path>`")),
+                    };
+                }
+                Some(&"autosave") => {
+                    let usage = "expected `autosave <path> [every <operations>] [secs <seconds>] [fsync]`";
+                    let path = tokens.get(1).ok_or_else(|| error(usage))?;
+                    let (mut every_operations, mut every, mut fsync) = (None, None, false);
+                    let mut options = tokens[2..].iter();
+                    while let Some(option) = options.next() {
+                        let mut value = || options.next().and_then(|value| value.parse::<u64>().ok()).filter(|value| *value > 0).ok_or_else(|| error(usage));
+                        match *option {
+                            "every" => every_operations = Some(value()?),
+                            "secs" => every = Some(Duration::from_secs(value()?)),
+                            "fsync" => fsync = true,
+                            _ => return Err(error(usage)),
+                        }
+                    }
+                    autosave = Some(AutosaveConfig::new(*path, every_operations, every, fsync));
+                }
+                Some(setting) => return Err(error(&format!("unknown setting `{}`", setting))),
+            }
+        }
+
+        if let Some(account_id) = bot_account_id {
+            if !accounts.iter().any(|seed| seed.account_id == account_id) {
+                return Err(format!("Config: bot account {} is not declared", account_id));
+            }
+        }
+
+        let mut tick_sizes = TickSizeTable::new();
+        for (line_number, from, step) in tick_size_lines {
+            tick_sizes = match (tick_converter.price_to_tick(from), tick_converter.price_to_tick(step)) {
+                (Ok(from_tick), Ok(step)) if step > 0 => tick_sizes.with_band(from_tick, step),
+                _ => return Err(format!(
+                    "Config line {}: expected `ticksize <from price> <increment>` in multiples of {}",
+                    line_number + 1,
+                    tick_converter.format_tick(1, None),
+                )),
+            };
+        }
+
+        Ok(Config { accounts, bot_account_id, tick_converter, tick_sizes, hotkeys, storage, autosave })
+    }
+}
+
+// "OSMO=1000" (currency names are case insensitive)
+fn parse_balance(balance: &str) -> Option<(Currency, u64)> {
+    let (currency, amount) = balance.split_once('=')?;
+    Some((Currency::from_str(&currency.to_uppercase()).ok()?, amount.parse().ok()?))
+}
+
+// "F5" (or "f5") to 5, for F1 to F12
+fn parse_function_key(key: &str) -> Option<u8> {
+    let number: u8 = key.strip_prefix(['F', 'f'])?.parse().ok()?;
+    (1..=12).contains(&number).then_some(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accounts_and_bot() {
+        let config = Config::parse("
+            # user and a market maker
+            account 0 OSMO=1000 usd=5000
+            account 7 USD=10
+
+            bot 7
+        ").unwrap();
+
+        assert_eq!(config.accounts().len(), 2);
+        assert_eq!(config.accounts()[0].balances(), &vec![(Currency::OSMO, 1000), (Currency::USD, 5000)]);
+        assert_eq!(*config.accounts()[1].account_id(), 7);
+        assert_eq!(*config.bot_account_id(), Some(7));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Config::parse("account 0 BTC=5").unwrap_err(), "Config line 1: invalid balance `BTC=5`");
+        assert_eq!(Config::parse("account 0\naccount 0").unwrap_err(), "Config line 2: account 0 is declared twice");
+        assert_eq!(Config::parse("bot 3").unwrap_err(), "Config: bot account 3 is not declared");
+        assert!(Config::parse("theme dark").is_err());
+        assert!(Config::parse("ticksize 100 0.05").is_err());
+    }
+
+    #[test]
+    fn test_parse_tick_sizes() {
+        let config = Config::parse("ticksize 100 1
+ticksize 1000 5").unwrap();
+        assert_eq!(config.tick_sizes().bands(), &[(1000, 10), (10000, 50)]);
+        assert!(Config::default().tick_sizes().bands().is_empty());
+    }
+
+    #[test]
+    fn test_parse_pricing() {
+        // Bands are priced with the configured tick size, whichever line comes first
+        let config = Config::parse("ticksize 10 0.5\npricing 0.05 0.05 500").unwrap();
+        assert_eq!(config.tick_converter(), &TickConverter::from_tick_size("0.05").unwrap().with_tick_range(1, 10000));
+        assert_eq!(config.tick_sizes().bands(), &[(200, 10)]);
+        assert_eq!(Config::default().tick_converter(), &TickConverter::default());
+
+        assert_eq!(Config::parse("ticksize 10 0.05").unwrap_err(), "Config line 1: expected `ticksize <from price> <increment>` in multiples of 0.1");
+        assert_eq!(Config::parse("pricing 0").unwrap_err(), "Config line 1: expected `pricing <tick size> [<min price> <max price>]`");
+        assert!(Config::parse("pricing 0.05 10 1").is_err());
+        assert!(Config::parse("pricing 0.05 0.01 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_hotkeys() {
+        let config = Config::parse("hotkey F5 buy osmo market 100\nhotkey f7   buy osmo limit 100 @bid").unwrap();
+        let hotkeys: Vec<(u8, &str)> = config.hotkeys().iter().map(|hotkey| (*hotkey.key(), hotkey.command().as_str())).collect();
+        assert_eq!(hotkeys, vec![(5, "buy osmo market 100"), (7, "buy osmo limit 100 @bid")]);
+
+        assert_eq!(Config::parse("hotkey F13 stats").unwrap_err(), "Config line 1: expected `hotkey <F1-F12> <command>`");
+        assert_eq!(Config::parse("hotkey F2").unwrap_err(), "Config line 1: expected `hotkey <F1-F12> <command>`");
+        assert_eq!(Config::parse("hotkey F2 stats\nhotkey F2 debug").unwrap_err(), "Config line 2: F2 is bound twice");
+    }
+
+    #[test]
+    fn test_parse_storage() {
+        assert_eq!(Config::parse("storage file ./history").unwrap().storage(), &Some(StorageConfig::File("./history".into())));
+        assert_eq!(Config::parse("storage sqlite trades.db").unwrap().storage(), &Some(StorageConfig::Sqlite("trades.db".into())));
+        assert_eq!(Config::default().storage(), &None);
+        assert_eq!(Config::parse("storage s3 bucket").unwrap_err(), "Config line 1: expected `storage <file/sqlite> <path>`");
+        assert!(Config::parse("storage file").is_err());
+    }
+
+    #[test]
+    fn test_parse_autosave() {
+        let config = Config::parse("autosave ./session.json every 50 secs 10 fsync").unwrap();
+        assert_eq!(config.autosave(), &Some(AutosaveConfig::new("./session.json", Some(50), Some(Duration::from_secs(10)), true)));
+        assert_eq!(Config::parse("autosave s.json").unwrap().autosave(), &Some(AutosaveConfig::new("s.json", None, None, false)));
+        assert_eq!(
+            Config::parse("autosave s.json every 0").unwrap_err(),
+            "Config line 1: expected `autosave <path> [every <operations>] [secs <seconds>] [fsync]`",
+        );
+        assert!(Config::parse("autosave").is_err());
+        assert!(Config::parse("autosave s.json hourly").is_err());
+    }
+}
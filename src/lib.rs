@@ -1,3 +1,14 @@
+// The matching engine is always built, the other subsystems are picked with the crate's features (see Cargo.toml).
 pub mod bank;
 pub mod book;
+pub mod prelude;
+pub mod storage;
+
+#[cfg(feature = "sim")]
+pub mod algo;
+#[cfg(feature = "tui")]
+pub mod config;
+#[cfg(any(test, feature = "sim"))]
+pub mod testkit;
+#[cfg(feature = "tui")]
 pub mod ui;
@@ -1,13 +1,15 @@
 use orderbook::ui::{
     app::{App, AppResult},
     event::{Event, EventHandler},
-    handler::handle_key_events,
+    handler::{handle_key_events, handle_paste_event},
+    script::run_script,
+    theme::Theme,
     tui::Tui,
 };
-use orderbook::bank::account::{Account, AccountType};
-use orderbook::bank::currency::Currency;
-use orderbook::book::orderbook::{Orderbook};
-use std::{io, rc::Rc, cell::RefCell};
+use orderbook::config::Config;
+use orderbook::storage;
+use std::fs::File;
+use std::io::{self, BufReader};
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
@@ -15,9 +17,43 @@ fn main() -> AppResult<()> {
     // Create an application.
     let mut app = App::new();
 
-    // Fund user with starting balance
-    app.user_account.borrow_mut().deposit(Currency::OSMO, 100000);
-    app.user_account.borrow_mut().deposit(Currency::USD, 500000);
+    // Pick the color theme from the environment if one is configured
+    if let Some(theme) = std::env::var("ORDERBOOK_THEME").ok().and_then(|name| Theme::by_name(&name)) {
+        app.theme = theme;
+    }
+
+    // Seed accounts from the config file if one is given, otherwise fund the user with the default starting balance
+    let config = match std::env::var("ORDERBOOK_CONFIG") {
+        Ok(path) => Config::load(path)?,
+        Err(_) => Config::default(),
+    };
+    app.apply_config(&config);
+
+    // Archive old trades to the configured storage instead of keeping the whole history in memory
+    if let Some(storage_config) = config.storage() {
+        app.storage = Some(storage::open(storage_config)?);
+    }
+
+    // `--sim <script>` runs a script of commands (`-` reads them from stdin) and prints the trades and balances,
+    // without the terminal UI
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next()) {
+        (None, _) => {}
+        (Some("--sim"), Some(path)) => {
+            let mut out = io::stdout().lock();
+            match path.as_str() {
+                "-" => run_script(&mut app, io::stdin().lock(), &mut out)?,
+                path => run_script(&mut app, BufReader::new(File::open(path)?), &mut out)?,
+            };
+            return Ok(());
+        }
+        _ => return Err("usage: orderbook [--sim <script, or - for stdin>]".into()),
+    }
+
+    // Save the book and balances periodically, offering to recover an earlier session's save first
+    if let Some(autosave_config) = config.autosave() {
+        app.enable_autosave(autosave_config.clone());
+    }
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
@@ -25,21 +61,30 @@ fn main() -> AppResult<()> {
     let events = EventHandler::new(250);
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
+    app.resize(tui.size()?.width);
 
-    // Start the main loop.
+    // Start the main loop. Errors are held until the terminal has been restored.
+    let result = run(&mut app, &mut tui);
+    app.flush_autosave();
+
+    // Exit the user interface.
+    tui.exit()?;
+    result
+}
+
+fn run(app: &mut App, tui: &mut Tui<CrosstermBackend<io::Stderr>>) -> AppResult<()> {
     while app.running {
         // Render the user interface.
-        tui.draw(&mut app)?;
+        tui.draw(app)?;
         // Handle events.
         match tui.events.next()? {
             Event::Tick => app.tick(),
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
+            Event::Key(key_event) => handle_key_events(key_event, app)?,
             Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Paste(text) => handle_paste_event(&text, app)?,
+            Event::Resize(width, _) => app.resize(width),
+            Event::Shutdown => app.quit(),
         }
     }
-
-    // Exit the user interface.
-    tui.exit()?;
     Ok(())
 }
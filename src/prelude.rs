@@ -0,0 +1,52 @@
+//! Single import path for the types needed to drive the book from library code, and to export its trades as
+//! execution reports.
+//!
+//! ```
+//! use std::{cell::RefCell, rc::Rc};
+//! use orderbook::prelude::*;
+//!
+//! let mut book = Orderbook::new(0);
+//! let maker = Rc::new(RefCell::new(Account::new(1, AccountType::Individual)));
+//! maker.borrow_mut().deposit(Currency::OSMO, 100);
+//!
+//! let mut ask = Order::new(0, 12, 0, maker, OrderType::Limit, OrderDirection::Ask, 100);
+//! book.handle_order(&mut ask).unwrap();
+//! assert_eq!(book.snapshot().asks(), &vec![(12, 100)]);
+//!
+//! // A taker lifts 40 of it, and both sides of the trade get an execution report
+//! let taker = Rc::new(RefCell::new(Account::new(2, AccountType::Individual)));
+//! taker.borrow_mut().deposit(Currency::USD, 500);
+//! book.handle_order(&mut Order::new(1, 0, 0, taker, OrderType::Market, OrderDirection::Bid, 40)).unwrap();
+//! let symbol = pair_name((*book.base_asset(), *book.quote_asset()));
+//! let [taker_report, maker_report] = execution_reports(&book.trades()[0], &symbol, book.tick_converter());
+//! assert!(taker_report.contains("55=OSMO/USD") && maker_report.contains("32=40"));
+//! ```
+
+#[cfg(feature = "sim")]
+pub use crate::algo::twap::{AlgoState, SliceReport, TwapOrder};
+pub use crate::bank::account::{Account, AccountType};
+pub use crate::bank::currency::Currency;
+pub use crate::book::auction::{clearing_tick, AuctionResult};
+pub use crate::book::conditional::{BookCondition, BookMetric, Comparison};
+pub use crate::book::error::OrderbookError;
+pub use crate::book::exchange::{pair_name, parse_pair, Exchange, Pair};
+pub use crate::book::execution_report::{anonymized_execution_reports, execution_reports, write_execution_reports, AccountPseudonyms};
+pub use crate::book::handle::{OrderHandle, OrderStatus};
+pub use crate::book::inverted::InvertedView;
+#[cfg(feature = "serde")]
+pub use crate::book::l2_snapshot::L2Snapshot;
+pub use crate::book::matching::MatchingPolicy;
+pub use crate::book::order::{Order, OrderDirection, OrderType, TimeInForce, TriggerType};
+pub use crate::book::orderbook::Orderbook;
+pub use crate::book::query::{DepthSnapshot, MarketImpact, MemoryStats, OpenOrder, QueuedOrder};
+pub use crate::book::rejection::{reject_code, RejectCode};
+pub use crate::book::risk::{DuplicateGuard, DuplicatePolicy, RiskConfig, SlippageGuard, SlippageLimit, SlippagePolicy};
+pub use crate::book::tick::{MakerFill, Tick};
+pub use crate::book::tick_converter::{ParsePriceError, TickConverter};
+pub use crate::book::tick_size::TickSizeTable;
+pub use crate::book::toxicity::{FlowToxicity, VolumeBucket};
+pub use crate::book::trade::{Trade, TradeFilter, TradePage};
+pub use crate::book::volatility::{QuoteHistory, QuoteSample};
+pub use crate::storage::{memory::MemoryStorage, Storage};
+#[cfg(feature = "persistence")]
+pub use crate::storage::{file::FileStorage, StorageConfig};
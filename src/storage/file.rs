@@ -0,0 +1,69 @@
+use super::{validate_records, validate_stream, Storage};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// One append-only `<stream>.log` file per stream in a directory, one record per line.
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    // Uses `dir` for the stream files, creating it if it doesn't exist yet. Streams already in it are kept.
+    pub fn open(dir: impl AsRef<Path>) -> Result<FileStorage, Box<dyn Error>> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(FileStorage { dir: dir.as_ref().to_path_buf() })
+    }
+
+    fn stream_path(&self, stream: &str) -> Result<PathBuf, Box<dyn Error>> {
+        validate_stream(stream)?;
+        Ok(self.dir.join(format!("{}.log", stream)))
+    }
+}
+
+impl Storage for FileStorage {
+    fn append(&mut self, stream: &str, records: &[String]) -> Result<(), Box<dyn Error>> {
+        validate_records(records)?;
+        let file = OpenOptions::new().create(true).append(true).open(self.stream_path(stream)?)?;
+        let mut writer = BufWriter::new(file);
+        for record in records {
+            writeln!(writer, "{}", record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn read(&self, stream: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let file = match File::open(self.stream_path(stream)?) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(BufReader::new(file).lines().collect::<Result<Vec<String>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_survive_reopening() {
+        let dir = std::env::temp_dir().join(format!("orderbook-file-storage-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut storage = FileStorage::open(&dir).unwrap();
+        assert!(storage.read("trades").unwrap().is_empty());
+        storage.append("trades", &["1 2 3".to_string(), "4 5 6".to_string()]).unwrap();
+        storage.append("trades", &["7 8 9".to_string()]).unwrap();
+
+        let reopened = FileStorage::open(&dir).unwrap();
+        assert_eq!(reopened.read("trades").unwrap(), vec!["1 2 3", "4 5 6", "7 8 9"]);
+        assert_eq!(reopened.len("trades").unwrap(), 3);
+        assert!(dir.join("trades.log").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
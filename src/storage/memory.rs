@@ -0,0 +1,56 @@
+use super::{validate_records, validate_stream, Storage};
+use std::collections::HashMap;
+use std::error::Error;
+
+// Keeps every stream in memory. Nothing survives the process, but it behaves like the other backends, which makes
+// it the backend of choice for tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    streams: HashMap<String, Vec<String>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn append(&mut self, stream: &str, records: &[String]) -> Result<(), Box<dyn Error>> {
+        validate_stream(stream)?;
+        validate_records(records)?;
+        self.streams.entry(stream.to_string()).or_default().extend_from_slice(records);
+        Ok(())
+    }
+
+    fn read(&self, stream: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        validate_stream(stream)?;
+        Ok(self.streams.get(stream).cloned().unwrap_or_default())
+    }
+
+    fn len(&self, stream: &str) -> Result<u64, Box<dyn Error>> {
+        validate_stream(stream)?;
+        Ok(self.streams.get(stream).map_or(0, |records| records.len() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_are_separate_and_ordered() {
+        let mut storage = MemoryStorage::new();
+        storage.append("trades", &["a".to_string(), "b".to_string()]).unwrap();
+        storage.append("trades", &["c".to_string()]).unwrap();
+        storage.append("other", &["x".to_string()]).unwrap();
+
+        assert_eq!(storage.read("trades").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(storage.len("other").unwrap(), 1);
+        assert!(storage.is_empty("missing").unwrap());
+
+        assert!(storage.append("trades", &["two\nlines".to_string()]).is_err());
+        assert!(storage.append("../escape", &[]).is_err());
+        assert_eq!(storage.len("trades").unwrap(), 3);
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(feature = "persistence")]
+pub mod file;
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::error::Error;
+use std::fmt::Debug;
+#[cfg(feature = "persistence")]
+use std::path::PathBuf;
+
+// Append-only persistence for history that would otherwise grow without bound in memory. Data is kept as named
+// streams of single-line text records, oldest first, so every kind of history (trade logs today, anything else
+// that needs archiving later) can sit on the same backend and pick its own record format.
+pub trait Storage: Debug {
+    // Adds records to the end of a stream, creating the stream if needed. Records may not contain line breaks.
+    fn append(&mut self, stream: &str, records: &[String]) -> Result<(), Box<dyn Error>>;
+
+    // Every record of a stream, oldest first. Empty for a stream that was never written to.
+    fn read(&self, stream: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
+    // Number of records in a stream.
+    fn len(&self, stream: &str) -> Result<u64, Box<dyn Error>> {
+        Ok(self.read(stream)?.len() as u64)
+    }
+
+    fn is_empty(&self, stream: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.len(stream)? == 0)
+    }
+}
+
+// Backend selected in the config file.
+#[cfg(feature = "persistence")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum StorageConfig {
+    // A directory holding one file per stream.
+    File(PathBuf),
+    // A sqlite database file. Only available when built with the `sqlite` feature.
+    Sqlite(PathBuf),
+}
+
+#[cfg(feature = "persistence")]
+pub fn open(config: &StorageConfig) -> Result<Box<dyn Storage>, Box<dyn Error>> {
+    match config {
+        StorageConfig::File(dir) => Ok(Box::new(file::FileStorage::open(dir)?)),
+        #[cfg(feature = "sqlite")]
+        StorageConfig::Sqlite(path) => Ok(Box::new(sqlite::SqliteStorage::open(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        StorageConfig::Sqlite(_) => Err("sqlite storage needs a build with the `sqlite` feature".into()),
+    }
+}
+
+// Stream names double as file names, so they are kept to lowercase letters, digits, '_' and '-'.
+fn validate_stream(stream: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !stream.is_empty()
+        && stream.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_' || byte == b'-');
+    if !valid {
+        return Err(format!("invalid stream name `{}`", stream).into());
+    }
+    Ok(())
+}
+
+fn validate_records(records: &[String]) -> Result<(), Box<dyn Error>> {
+    if records.iter().any(|record| record.contains(['\n', '\r'])) {
+        return Err("storage records must be a single line".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use super::{validate_records, validate_stream, Storage};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::path::Path;
+
+// All streams in one sqlite table, ordered by insertion. Suits long simulations better than flat files since a
+// stream can be counted without reading it back.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    // Opens (or creates) the database at `path`. Streams already in it are kept.
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteStorage, Box<dyn Error>> {
+        SqliteStorage::with_connection(Connection::open(path)?)
+    }
+
+    // A database that only lives as long as the storage.
+    pub fn in_memory() -> Result<SqliteStorage, Box<dyn Error>> {
+        SqliteStorage::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<SqliteStorage, Box<dyn Error>> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS records (id INTEGER PRIMARY KEY AUTOINCREMENT, stream TEXT NOT NULL, body TEXT NOT NULL)",
+            [],
+        )?;
+        connection.execute("CREATE INDEX IF NOT EXISTS records_by_stream ON records (stream, id)", [])?;
+        Ok(SqliteStorage { connection })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn append(&mut self, stream: &str, records: &[String]) -> Result<(), Box<dyn Error>> {
+        validate_stream(stream)?;
+        validate_records(records)?;
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached("INSERT INTO records (stream, body) VALUES (?1, ?2)")?;
+            for record in records {
+                insert.execute(params![stream, record])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn read(&self, stream: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        validate_stream(stream)?;
+        let mut select = self.connection.prepare_cached("SELECT body FROM records WHERE stream = ?1 ORDER BY id")?;
+        let records = select.query_map(params![stream], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+        Ok(records)
+    }
+
+    fn len(&self, stream: &str) -> Result<u64, Box<dyn Error>> {
+        validate_stream(stream)?;
+        let count: i64 = self.connection.query_row("SELECT COUNT(*) FROM records WHERE stream = ?1", params![stream], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_round_trip() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        storage.append("trades", &["a".to_string(), "b".to_string()]).unwrap();
+        storage.append("other", &["x".to_string()]).unwrap();
+        storage.append("trades", &["c".to_string()]).unwrap();
+
+        assert_eq!(storage.read("trades").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(storage.len("trades").unwrap(), 3);
+        assert!(storage.is_empty("missing").unwrap());
+        assert!(storage.append("trades", &["two\nlines".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::{cell::RefCell, rc::Rc};
+use crate::bank::account::{Account, AccountType};
+use crate::bank::currency::Currency;
+use crate::book::matching::MatchingPolicy;
+use crate::book::order::{Order, OrderDirection, OrderType};
+use crate::book::orderbook::Orderbook;
+
+// Balance of each currency given to the default liquidity account behind BookBuilder orders.
+const MAKER_FUNDING: u64 = 1000000000000;
+
+// Fluent builder for accounts used in tests and examples.
+pub struct AccountBuilder {
+    account_id: u64,
+    account_type: AccountType,
+    balances: Vec<(Currency, u64)>,
+}
+
+impl AccountBuilder {
+    pub fn new(account_id: u64) -> AccountBuilder {
+        AccountBuilder {
+            account_id,
+            account_type: AccountType::Individual,
+            balances: Vec::new(),
+        }
+    }
+
+    // Shorthand for an individual account holding the given balances.
+    pub fn funded(account_id: u64, balances: &[(Currency, u64)]) -> AccountBuilder {
+        balances.iter().fold(AccountBuilder::new(account_id), |builder, (currency, amount)| {
+            builder.with_balance(*currency, *amount)
+        })
+    }
+
+    pub fn with_type(mut self, account_type: AccountType) -> AccountBuilder {
+        self.account_type = account_type;
+        self
+    }
+
+    pub fn with_balance(mut self, currency: Currency, amount: u64) -> AccountBuilder {
+        self.balances.push((currency, amount));
+        self
+    }
+
+    pub fn build(self) -> Rc<RefCell<Account>> {
+        let mut account = Account::new(self.account_id, self.account_type);
+        for (currency, amount) in self.balances {
+            account.deposit(currency, amount);
+        }
+        Rc::new(RefCell::new(account))
+    }
+}
+
+// Fluent builder for orderbooks pre-populated with resting limit orders.
+// Orders are placed through `Orderbook::handle_order` in the order they were added, so escrow and
+// best bid/ask tracking behave exactly like they would for real orders.
+#[derive(Default)]
+pub struct BookBuilder {
+    book_id: u64,
+    maker: Option<Rc<RefCell<Account>>>,
+    matching_policy: MatchingPolicy,
+    orders: Vec<(OrderDirection, u64, u64)>,
+}
+
+impl BookBuilder {
+    pub fn new() -> BookBuilder {
+        BookBuilder::default()
+    }
+
+    pub fn with_book_id(mut self, book_id: u64) -> BookBuilder {
+        self.book_id = book_id;
+        self
+    }
+
+    pub fn with_matching_policy(mut self, matching_policy: MatchingPolicy) -> BookBuilder {
+        self.matching_policy = matching_policy;
+        self
+    }
+
+    // Account that owns every resting order. Defaults to a well funded liquidity account with id u64::MAX.
+    pub fn with_maker(mut self, maker: Rc<RefCell<Account>>) -> BookBuilder {
+        self.maker = Some(maker);
+        self
+    }
+
+    // Adds a single resting ask. Adding several at the same tick queues them in FIFO order.
+    pub fn with_ask(mut self, tick_id: u64, quantity: u64) -> BookBuilder {
+        self.orders.push((OrderDirection::Ask, tick_id, quantity));
+        self
+    }
+
+    // Adds one resting ask per (tick_id, quantity) pair.
+    pub fn with_asks(self, levels: &[(u64, u64)]) -> BookBuilder {
+        levels.iter().fold(self, |builder, (tick_id, quantity)| builder.with_ask(*tick_id, *quantity))
+    }
+
+    // Adds a single resting bid. Adding several at the same tick queues them in FIFO order.
+    pub fn with_bid(mut self, tick_id: u64, quantity: u64) -> BookBuilder {
+        self.orders.push((OrderDirection::Bid, tick_id, quantity));
+        self
+    }
+
+    // Adds one resting bid per (tick_id, quantity) pair.
+    pub fn with_bids(self, levels: &[(u64, u64)]) -> BookBuilder {
+        levels.iter().fold(self, |builder, (tick_id, quantity)| builder.with_bid(*tick_id, *quantity))
+    }
+
+    // Builds the book, panicking if any of the orders is rejected since that means the fixture itself is broken.
+    pub fn build(self) -> Orderbook {
+        let maker = self.maker.unwrap_or_else(|| {
+            AccountBuilder::funded(u64::MAX, &[(Currency::USD, MAKER_FUNDING), (Currency::OSMO, MAKER_FUNDING)]).build()
+        });
+
+        let mut book = Orderbook::new(self.book_id);
+        book.set_matching_policy(self.matching_policy);
+        for (order_id, (direction, tick_id, quantity)) in self.orders.into_iter().enumerate() {
+            let mut order = Order::new(order_id as u64, tick_id, self.book_id, Rc::clone(&maker), OrderType::Limit, direction, quantity);
+            if let Err(e) = book.handle_order(&mut order) {
+                panic!("BookBuilder failed to place {} of {} at tick {}: {}", direction, quantity, tick_id, e);
+            }
+        }
+        book
+    }
+}
+
+// Limit order owned by `owner`, ready to be passed to `Orderbook::handle_order`.
+pub fn limit_order(owner: &Rc<RefCell<Account>>, direction: OrderDirection, tick_id: u64, quantity: u64) -> Order {
+    Order::new(0, tick_id, 0, Rc::clone(owner), OrderType::Limit, direction, quantity)
+}
+
+// Market order owned by `owner`, ready to be passed to `Orderbook::handle_order`.
+pub fn market_order(owner: &Rc<RefCell<Account>>, direction: OrderDirection, quantity: u64) -> Order {
+    Order::new(0, 0, 0, Rc::clone(owner), OrderType::Market, direction, quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_builder_funded() {
+        let account = AccountBuilder::funded(3, &[(Currency::USD, 500), (Currency::OSMO, 20)])
+            .with_balance(Currency::USD, 100)
+            .build();
+
+        assert_eq!(*account.borrow().account_id(), 3);
+        assert_eq!(account.borrow().balance(Currency::USD), 600);
+        assert_eq!(account.borrow().balance(Currency::OSMO), 20);
+    }
+
+    #[test]
+    fn test_book_builder_places_levels() {
+        let book = BookBuilder::new()
+            .with_asks(&[(10, 300), (13, 300)])
+            .with_bid(8, 100)
+            .with_bid(8, 50)
+            .build();
+
+        // Both orders on tick 8 are queued on the same level
+        assert_eq!(book.ticks().get(&8).unwrap().orders().len(), 2);
+        assert_eq!(*book.ticks().get(&8).unwrap().total_liquidity(), 150);
+        assert_eq!(*book.ticks().get(&13).unwrap().total_liquidity(), 300);
+        assert_eq!(*book.next_ask_tick(), 10);
+        assert_eq!(*book.next_bid_tick(), 8);
+    }
+}
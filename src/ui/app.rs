@@ -1,8 +1,37 @@
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::error;
+use std::ops::Range;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::algo::twap::TwapOrder;
+use crate::ui::autosave::Autosave;
+use crate::bank::currency::Currency;
+use crate::book::exchange::{pair_name, Exchange};
 use crate::book::orderbook::Orderbook;
+use crate::book::inverted::InvertedView;
+use crate::book::order::OrderDirection;
+use crate::book::query::DepthSnapshot;
+use crate::book::volatility::QuoteHistory;
+use crate::ui::challenge::{equity, Challenge};
+use crate::ui::chart::{changed_levels, visible_bars, visible_ticks, ChartScale, HIGHLIGHT_FRAMES};
+use crate::ui::format::{NumberStyle, PriceStyle, QuantityUnit};
+use crate::ui::theme::Theme;
 use crate::bank::account::{Account, AccountType};
+use crate::config::{AutosaveConfig, Config, Hotkey, USER_ACCOUNT_ID};
+use crate::storage::Storage;
+
+/// How long depth snapshots are kept around for the `diff` command.
+const DEPTH_HISTORY_RETENTION: Duration = Duration::from_secs(600);
+
+/// The best bid and ask are sampled once a second into a history covering the same 10 minutes as the depth history.
+const QUOTE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const QUOTE_HISTORY_SAMPLES: usize = 600;
+
+/// Trades kept in memory before the oldest are moved to storage, when a storage backend is configured. Archiving
+/// goes down to half of this so it runs in batches rather than on every tick.
+pub const TRADES_IN_MEMORY: usize = 10000;
 
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
@@ -13,26 +42,129 @@ pub struct App {
     pub running: bool,
     pub counter: u8,
     pub updates: Vec<String>,
-    pub positions: Vec<u64>,
+    // resting quantity of every populated tick, keyed by tick id so far apart levels cost no more than close ones
+    pub positions: BTreeMap<u64, u64>,
+
+    // width of the terminal as of the last resize, and the ticks the depth chart shows at that width
+    pub terminal_width: u16,
+    pub chart_ticks: Range<u64>,
+
+    // book version the depth chart (positions) was last built from, so it is only rebuilt once the book moves on
+    pub positions_version: Option<u64>,
+
+    // levels of the live book whose depth changed recently, with the frames left before their highlight fades out
+    pub level_highlights: BTreeMap<u64, u8>,
     pub command_line: String,
 
-    // session orderbook
+    // validation hint for the partially typed command, refreshed on every keystroke
+    pub command_hint: Option<String>,
+
+    // book of the market on screen, checked out of the exchange while it is active
     pub session_book: Orderbook,
 
+    // every listed market, and the id allocator for orders placed from the app
+    pub exchange: Exchange,
+
     // user account
     pub user_account: Rc<RefCell<Account>>,
+
+    // every account in the session by id, including the user's
+    pub accounts: HashMap<u64, Rc<RefCell<Account>>>,
+
+    // account funding the liquidity bot, if one was assigned in the config
+    pub bot_account: Option<Rc<RefCell<Account>>>,
+
+    // depth snapshots recorded whenever the book changed, oldest first
+    pub depth_history: VecDeque<(Instant, Arc<DepthSnapshot>)>,
+
+    // id of the first trade not yet checked for the user's executions
+    pub reported_trades: u64,
+
+    // best bid and ask sampled every QUOTE_SAMPLE_INTERVAL, for spread and volatility stats
+    pub quote_history: QuoteHistory,
+
+    // frame of the depth history the chart shows in review mode, None while it follows the live book
+    pub review_frame: Option<usize>,
+
+    // color palette used when rendering
+    pub theme: Theme,
+
+    // how amounts are written out in balances and messages
+    pub number_style: NumberStyle,
+
+    /// how prices are written out in updates
+    pub price_style: PriceStyle,
+
+    /// whether level quantities and order sizes are shown as quantities or as their notional
+    pub quantity_unit: QuantityUnit,
+
+    // execution algos started by the user, driven forward on every tick
+    pub algos: Vec<TwapOrder>,
+    pub next_algo_id: u64,
+
+    // how the depth chart scales its bars, and how many decimals its price labels show
+    pub chart_scale: ChartScale,
+    pub chart_price_decimals: usize,
+
+    // whether the debug panel with the book's memory usage is shown next to the balances
+    pub show_debug: bool,
+
+    // paper-trading challenge in progress, if any
+    pub challenge: Option<Challenge>,
+
+    // function keys bound to commands in the config, and whether the help overlay listing them is open
+    pub hotkeys: Vec<Hotkey>,
+    pub show_help: bool,
+
+    // where trades beyond TRADES_IN_MEMORY are archived, if a storage backend is configured
+    pub storage: Option<Box<dyn Storage>>,
+
+    // periodic saves of the book and balances, if autosave is configured
+    pub autosave: Option<Autosave>,
 }
 
 impl Default for App {
     fn default() -> Self {
+        let user_account = Rc::new(RefCell::new(Account::new(USER_ACCOUNT_ID, AccountType::Individual)));
+        let mut exchange = Exchange::new();
+        let session_book = exchange.create_market(Currency::USD, Currency::OSMO)
+            .ok()
+            .and_then(|book_id| exchange.checkout(book_id))
+            .expect("an empty exchange lists OSMO/USD");
         Self {
             running: true,
             counter: 0,
             updates: vec![String::new()],
-            positions: vec![0],
+            positions: BTreeMap::new(),
+            positions_version: None,
+            level_highlights: BTreeMap::new(),
+            terminal_width: 0,
+            chart_ticks: 0..0,
             command_line: String::new(),
-            session_book: Orderbook::new(0),
-            user_account: Rc::new(RefCell::new(Account::new(0, AccountType::Individual))),
+            command_hint: None,
+            session_book,
+            exchange,
+            accounts: HashMap::from([(USER_ACCOUNT_ID, user_account.clone())]),
+            bot_account: None,
+            user_account,
+            depth_history: VecDeque::new(),
+            reported_trades: 0,
+            quote_history: QuoteHistory::new(QUOTE_HISTORY_SAMPLES),
+            review_frame: None,
+            theme: Theme::default(),
+            number_style: NumberStyle::default(),
+            price_style: PriceStyle::default(),
+            quantity_unit: QuantityUnit::default(),
+            algos: Vec::new(),
+            next_algo_id: 0,
+            chart_scale: ChartScale::default(),
+            chart_price_decimals: 2,
+            show_debug: false,
+            challenge: None,
+            hotkeys: Vec::new(),
+            show_help: false,
+            storage: None,
+            autosave: None,
         }
     }
 }
@@ -43,8 +175,369 @@ impl App {
         Self::default()
     }
 
+    /// Creates the accounts declared in the config with their starting balances, assigns the bot's account, applies the pricing and tick size bands and binds the hotkeys.
+    pub fn apply_config(&mut self, config: &Config) {
+        for seed in config.accounts() {
+            let account = self.accounts
+                .entry(*seed.account_id())
+                .or_insert_with(|| Rc::new(RefCell::new(Account::new(*seed.account_id(), AccountType::Individual))));
+            for (currency, amount) in seed.balances() {
+                account.borrow_mut().deposit(*currency, *amount);
+            }
+        }
+
+        self.bot_account = config.bot_account_id().and_then(|account_id| self.accounts.get(&account_id).cloned());
+        self.session_book.set_tick_converter(*config.tick_converter());
+        self.session_book.set_tick_sizes(config.tick_sizes().clone());
+        self.hotkeys = config.hotkeys().clone();
+    }
+
+    /// Pair of the market on screen, e.g. "OSMO/USD".
+    pub fn market_name(&self) -> String {
+        pair_name((*self.session_book.base_asset(), *self.session_book.quote_asset()))
+    }
+
+    /// Puts the market with the given book id on screen, checking the current book back into the exchange. The depth
+    /// history and chart were recorded from the old book, so they start over.
+    pub fn switch_market(&mut self, book_id: u64) -> Result<(), Box<dyn error::Error>> {
+        let book = self.exchange.checkout(book_id).ok_or_else(|| format!("No market with book id {} to switch to", book_id))?;
+        let previous = std::mem::replace(&mut self.session_book, book);
+        self.exchange.checkin(previous)?;
+        self.depth_history.clear();
+        self.review_frame = None;
+        self.quote_history = QuoteHistory::new(QUOTE_HISTORY_SAMPLES);
+        self.reported_trades = self.session_book.next_trade_id();
+        self.positions_version = None;
+        self.level_highlights.clear();
+        Ok(())
+    }
+
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        self.report_executions();
+        self.run_algos(now);
+        self.expire_orders(now);
+        self.update_challenge(now);
+        self.fade_highlights();
+        self.sync_depth();
+        self.record_depth_snapshot(now);
+        self.sample_quotes(now);
+        self.archive_trades();
+        self.autosave(now);
+    }
+
+    /// Cancels the good-till-date orders that have expired on every market, including ones not on screen, and tells
+    /// the user about their own.
+    pub fn expire_orders(&mut self, now: Instant) {
+        let mut expired = self.session_book.expire_orders(now);
+        expired.extend(self.exchange.expire_orders(now));
+
+        let account_id = *self.user_account.borrow().account_id();
+        for order in expired.iter().filter(|order| *order.owner().borrow().account_id() == account_id) {
+            self.updates.push(format!(
+                "Order {} expired: the unfilled {} {} of your {} was cancelled and refunded.",
+                order.order_id(),
+                self.number_style.format(*order.quantity()),
+                order.quote_asset(),
+                order.order_direction(),
+            ));
+        }
+    }
+
+    /// Moves the oldest trades to storage once the in-memory log outgrows [`TRADES_IN_MEMORY`]. If the storage
+    /// fails it is reported and dropped, and trades stay in memory from then on.
+    pub fn archive_trades(&mut self) {
+        let storage = match self.storage.as_mut() {
+            Some(storage) => storage,
+            None => return,
+        };
+        if self.session_book.trades().len() <= TRADES_IN_MEMORY {
+            return;
+        }
+        if let Err(e) = self.session_book.archive_trades(storage.as_mut(), TRADES_IN_MEMORY / 2) {
+            self.updates.push(format!("Failed to archive trades, keeping them in memory: {}", e));
+            self.storage = None;
+        }
+    }
+
+    /// Starts saving the book and balances as configured. If a save from an earlier session is still there, saving
+    /// holds off until the user recovers or discards it with the `recover` command.
+    pub fn enable_autosave(&mut self, config: AutosaveConfig) {
+        let autosave = Autosave::new(config, Instant::now());
+        if autosave.pending_recovery {
+            self.updates.push(format!(
+                "Found a saved session in {}. Type `recover` to restore it or `recover discard` to start over.",
+                autosave.config().path().display(),
+            ));
+        }
+        self.autosave = Some(autosave);
+    }
+
+    /// Saves the OSMO/USD book with every account in the session once a save is due. Other markets are not saved.
+    /// If saving fails it is reported and autosave stops.
+    pub fn autosave(&mut self, now: Instant) {
+        let autosave = match self.autosave.as_mut() {
+            Some(autosave) => autosave,
+            None => return,
+        };
+        if *self.session_book.book_id() != 0 || !autosave.is_due(*self.session_book.version(), now) {
+            return;
+        }
+        self.save_session(now);
+    }
+
+    /// Saves whatever changed since the last autosave, whether or not a save is due yet. Called on the way out so a
+    /// clean exit loses nothing.
+    pub fn flush_autosave(&mut self) {
+        let unsaved = self.autosave.as_ref()
+            .is_some_and(|autosave| autosave.has_unsaved_changes(*self.session_book.version()));
+        if *self.session_book.book_id() == 0 && unsaved {
+            self.save_session(Instant::now());
+        }
+    }
+
+    fn save_session(&mut self, now: Instant) {
+        let autosave = match self.autosave.as_mut() {
+            Some(autosave) => autosave,
+            None => return,
+        };
+        let accounts: Vec<Rc<RefCell<Account>>> = self.accounts.values().cloned().collect();
+        if let Err(e) = autosave.save(&self.session_book, &accounts, now) {
+            self.updates.push(format!("Failed to autosave to {}, autosave is off: {}", autosave.config().path().display(), e));
+            self.autosave = None;
+        }
+    }
+
+    /// Replaces the book and accounts of the session with a restored save. The depth history and chart were
+    /// recorded from the replaced book, so they start over.
+    pub fn recover_session(&mut self, book: Orderbook, accounts: HashMap<u64, Rc<RefCell<Account>>>) {
+        for (account_id, account) in accounts {
+            self.accounts.insert(account_id, account);
+        }
+        if let Some(user_account) = self.accounts.get(&USER_ACCOUNT_ID) {
+            self.user_account = Rc::clone(user_account);
+        }
+        if let Some(bot_account) = self.bot_account.as_ref() {
+            let account_id = *bot_account.borrow().account_id();
+            self.bot_account = self.accounts.get(&account_id).cloned();
+        }
+        self.session_book = book;
+        self.depth_history.clear();
+        self.review_frame = None;
+        self.quote_history = QuoteHistory::new(QUOTE_HISTORY_SAMPLES);
+        self.reported_trades = self.session_book.next_trade_id();
+        self.positions_version = None;
+        self.level_highlights.clear();
+    }
+
+    /// Samples the user's equity for the running challenge, and reports the result once its timer runs out.
+    pub fn update_challenge(&mut self, now: Instant) {
+        let challenge = match self.challenge.as_mut() {
+            Some(challenge) => challenge,
+            None => return,
+        };
+
+        if challenge.is_over(now) {
+            let summary = self.challenge_summary("Challenge over");
+            self.updates.push(summary);
+            self.challenge = None;
+        } else if let Some(equity) = equity(&self.session_book, &self.user_account.borrow()) {
+            challenge.record(equity, now);
+        }
+    }
+
+    /// e.g. "Challenge over: PnL +1,200 USD, max drawdown 300 USD, score 900."
+    pub fn challenge_summary(&self, title: &str) -> String {
+        match &self.challenge {
+            Some(challenge) => format!(
+                "{}: PnL {} {}, max drawdown {} {}, score {}.",
+                title,
+                self.format_signed(challenge.pnl()),
+                self.session_book.base_asset(),
+                self.number_style.format(challenge.max_drawdown()),
+                self.session_book.base_asset(),
+                self.format_signed(challenge.score()),
+            ),
+            None => format!("{}: no challenge running.", title),
+        }
+    }
+
+    /// Formats a signed amount in the current number style, with an explicit sign.
+    pub fn format_signed(&self, amount: i64) -> String {
+        let sign = if amount < 0 { "-" } else { "+" };
+        format!("{}{}", sign, self.number_style.format(amount.unsigned_abs()))
+    }
+
+    /// Releases the child orders of every algo that has come due and reports them in the updates panel.
+    pub fn run_algos(&mut self, now: Instant) {
+        let mut updates = Vec::new();
+        for algo in self.algos.iter_mut() {
+            for report in algo.poll(&mut self.session_book, now) {
+                updates.push(match report.result {
+                    Ok(filled_quantity) => format!(
+                        "TWAP #{} slice {}/{}: filled {} of {} {}, {} of {} done.",
+                        algo.parent_id(),
+                        report.slice + 1,
+                        algo.slices(),
+                        self.number_style.format(filled_quantity),
+                        self.number_style.format(report.quantity),
+                        self.session_book.quote_asset(),
+                        self.number_style.format(*algo.filled_quantity()),
+                        self.number_style.format(*algo.total_quantity()),
+                    ),
+                    Err(e) => format!("TWAP #{} slice {}/{} failed: {}", algo.parent_id(), report.slice + 1, algo.slices(), e),
+                });
+            }
+        }
+
+        self.updates.extend(updates);
+    }
+
+    /// Rebuilds the depth chart data from the book's current depth, highlighting the levels that changed since the
+    /// last build. Nothing is highlighted for the first build after switching markets, or for reviewed frames.
+    pub fn refresh_positions(&mut self) {
+        let snapshot = self.displayed_snapshot();
+        let positions: BTreeMap<u64, u64> = snapshot.bids().iter().chain(snapshot.asks().iter()).copied().collect();
+        if self.positions_version.is_some() && self.review_frame.is_none() {
+            for tick_id in changed_levels(&self.positions, &positions) {
+                self.level_highlights.insert(tick_id, HIGHLIGHT_FRAMES);
+            }
+        }
+        self.positions = positions;
+        self.positions_version = Some(*snapshot.version());
+        self.chart_ticks = visible_ticks(&snapshot, visible_bars(self.terminal_width));
+    }
+
+    /// Moves every level highlight one frame closer to fading out.
+    pub fn fade_highlights(&mut self) {
+        self.level_highlights.retain(|_, frames_left| {
+            *frames_left -= 1;
+            *frames_left > 0
+        });
+    }
+
+    /// Handles the terminal being resized: recomputes which ticks fit in the depth chart at the new width.
+    pub fn resize(&mut self, width: u16) {
+        self.terminal_width = width;
+        self.chart_ticks = visible_ticks(&self.displayed_snapshot(), visible_bars(width));
+    }
+
+    /// Rebuilds the depth chart if the book changed since it was last built. Called once per event rather than after
+    /// every order, so a burst of orders (e.g. from the liquidity bot or stops firing) costs a single rebuild.
+    pub fn sync_depth(&mut self) {
+        if self.positions_version != Some(*self.displayed_snapshot().version()) {
+            self.refresh_positions();
+        }
+    }
+
+    /// Records the current depth if the book changed since the last recorded snapshot.
+    pub fn record_depth_snapshot(&mut self, now: Instant) {
+        let snapshot = self.session_book.snapshot();
+        if self.depth_history.back().is_none_or(|(_, last)| last.version() != snapshot.version()) {
+            self.depth_history.push_back((now, snapshot));
+        }
+
+        // Drop snapshots past the retention window, keeping the newest one older than it as a baseline.
+        while self.depth_history.len() > 1 && now.duration_since(self.depth_history[1].0) > DEPTH_HISTORY_RETENTION {
+            self.depth_history.pop_front();
+            self.review_frame = self.review_frame.map(|frame| frame.saturating_sub(1));
+        }
+    }
+
+    /// Top of the book on screen for the strip above the depth chart, e.g. "Bid 1.1  Ask 1.2  Spread 1 tick  Mid 1.15".
+    /// A side without orders shows as "-", and so do the spread and mid then.
+    pub fn quote_header(&self) -> String {
+        let book = &self.session_book;
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let spread = book.spread().map(|ticks| format!("{} tick{}", ticks, if ticks == 1 { "" } else { "s" }));
+        // A mid between two ticks needs one decimal more than the book quotes prices with
+        let converter = book.tick_converter();
+        let mid = book.mid_price().map(|mid| {
+            if mid.fract() == 0.0 { self.format_price(mid as u64) } else { format!("{:.*}", *converter.precision() as usize + 1, mid * converter.price_per_tick()) }
+        });
+        format!(
+            "Bid {}  Ask {}  Spread {}  Mid {}",
+            or_dash(book.best_bid().map(|tick_id| self.format_price(tick_id))),
+            or_dash(book.best_ask().map(|tick_id| self.format_price(tick_id))),
+            or_dash(spread),
+            or_dash(mid),
+        )
+    }
+
+    /// Writes out the price of a tick on the book on screen, in the chosen style and following the tick size there.
+    pub fn format_price(&self, tick_id: u64) -> String {
+        self.price_style.format(self.session_book.tick_converter(), tick_id, self.session_book.tick_sizes().step_at(tick_id))
+    }
+
+    /// Writes out `quantity` resting at a tick on the book on screen with its asset, in the chosen unit, e.g. `10 OSMO`
+    /// or its notional `120 USD`.
+    pub fn format_quantity(&self, quantity: u64, tick_id: u64) -> String {
+        let amount = self.quantity_unit.amount(self.session_book.tick_converter(), quantity, tick_id);
+        let asset = match self.quantity_unit {
+            QuantityUnit::Quantity => self.session_book.quote_asset(),
+            QuantityUnit::Notional => self.session_book.base_asset(),
+        };
+        format!("{} {}", self.number_style.format(amount), asset)
+    }
+
+    /// Lists every fill the user took part in since the last report, one update each, e.g.
+    /// "Executed: bought 25 OSMO at 1.2 from account 9 (order 3)." Fills against the user's own orders are listed once.
+    pub fn report_executions(&mut self) {
+        let account_id = *self.user_account.borrow().account_id();
+        let mut updates = Vec::new();
+        for trade in self.session_book.trades_since(self.reported_trades) {
+            let (direction, counterparty, order_id) = if *trade.taker_account_id() == account_id {
+                (*trade.aggressor(), trade.maker_account_id(), trade.taker_order_id())
+            } else if *trade.maker_account_id() == account_id {
+                (InvertedView::invert_direction(*trade.aggressor()), trade.taker_account_id(), trade.maker_order_id())
+            } else {
+                continue;
+            };
+            let (verb, preposition) = match direction {
+                OrderDirection::Bid => ("bought", "from"),
+                OrderDirection::Ask => ("sold", "to"),
+            };
+            updates.push(format!(
+                "Executed: {} {} {} at {} {} account {} (order {}).",
+                verb,
+                self.number_style.format(*trade.quantity()),
+                self.session_book.quote_asset(),
+                self.format_price(*trade.tick_id()),
+                preposition,
+                counterparty,
+                order_id,
+            ));
+        }
+        self.reported_trades = self.session_book.next_trade_id();
+        self.updates.extend(updates);
+    }
+
+    /// Samples the best bid and ask if a sample interval has passed since the last one.
+    pub fn sample_quotes(&mut self, now: Instant) {
+        let due = self.quote_history.samples().back()
+            .is_none_or(|last| now.duration_since(*last.recorded_at()) >= QUOTE_SAMPLE_INTERVAL);
+        if due {
+            self.quote_history.record(&self.session_book.snapshot(), now);
+        }
+    }
+
+    /// Depth the chart shows: the reviewed frame of the depth history in review mode, the live book otherwise.
+    pub fn displayed_snapshot(&self) -> Arc<DepthSnapshot> {
+        match self.review_frame.and_then(|frame| self.depth_history.get(frame)) {
+            Some((_, snapshot)) => Arc::clone(snapshot),
+            None => self.session_book.snapshot(),
+        }
+    }
+
+    /// Returns the depth as it was at the given time, or the oldest recorded depth if history doesn't reach back that far.
+    pub fn depth_snapshot_at(&self, at: Instant) -> Option<Arc<DepthSnapshot>> {
+        self.depth_history.iter()
+            .rev()
+            .find(|(recorded_at, _)| *recorded_at <= at)
+            .or(self.depth_history.front())
+            .map(|(_, snapshot)| Arc::clone(snapshot))
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
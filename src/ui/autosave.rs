@@ -0,0 +1,143 @@
+use crate::bank::account::Account;
+use crate::book::orderbook::Orderbook;
+use crate::config::AutosaveConfig;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Periodic saves of the session's book and balances to the file named in the config. Each save is written to a
+/// temporary file next to it and renamed over the previous one, so a kill in the middle of a save leaves the last
+/// complete one in place.
+#[derive(Clone, Debug)]
+pub struct Autosave {
+    config: AutosaveConfig,
+    /// book version as of the last save, None until the first one
+    saved_version: Option<u64>,
+    saved_at: Instant,
+    /// set while a save from an earlier session is waiting to be recovered or discarded, so it isn't overwritten
+    pub pending_recovery: bool,
+}
+
+impl Autosave {
+    pub fn new(config: AutosaveConfig, now: Instant) -> Self {
+        let pending_recovery = config.path().exists();
+        Autosave { config, saved_version: None, saved_at: now, pending_recovery }
+    }
+
+    pub fn config(&self) -> &AutosaveConfig {
+        &self.config
+    }
+
+    /// Whether the book at `version` should be saved now. The first save is always due; after that the book has to
+    /// have moved on by `every_operations` versions, or at all once `every` has passed. With neither limit set every
+    /// change is saved.
+    pub fn is_due(&self, version: u64, now: Instant) -> bool {
+        let saved_version = match self.saved_version {
+            Some(saved_version) => saved_version,
+            None => return !self.pending_recovery,
+        };
+        let changes = version.saturating_sub(saved_version);
+        if self.pending_recovery || changes == 0 {
+            return false;
+        }
+        match (self.config.every_operations(), self.config.every()) {
+            (None, None) => true,
+            (every_operations, every) => {
+                every_operations.is_some_and(|operations| changes >= operations)
+                    || every.is_some_and(|every| now.saturating_duration_since(self.saved_at) >= every)
+            }
+        }
+    }
+
+    /// Whether the book at `version` differs from the last save, or was never saved.
+    pub fn has_unsaved_changes(&self, version: u64) -> bool {
+        !self.pending_recovery && self.saved_version != Some(version)
+    }
+
+    /// Saves the book and `accounts` and marks the book's version as saved.
+    pub fn save(&mut self, book: &Orderbook, accounts: &[Rc<RefCell<Account>>], now: Instant) -> Result<(), Box<dyn Error>> {
+        let path = self.config.path();
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        book.snapshot_to_writer(accounts, &mut writer)?;
+        writer.flush()?;
+        if *self.config.fsync() {
+            writer.get_ref().sync_all()?;
+        }
+        drop(writer);
+        fs::rename(&temporary, path)?;
+
+        self.saved_version = Some(*book.version());
+        self.saved_at = now;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::account::AccountType;
+    use crate::bank::currency::Currency;
+    use crate::book::order::{Order, OrderDirection, OrderType};
+    use std::time::Duration;
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("orderbook-autosave-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_saves_are_due_after_enough_operations_or_time() {
+        let now = Instant::now();
+        let path = temporary_path("due");
+        let mut autosave = Autosave::new(AutosaveConfig::new(&path, Some(3), Some(Duration::from_secs(10)), false), now);
+        let book = Orderbook::new(0);
+        assert!(autosave.is_due(0, now));
+        autosave.save(&book, &[], now).unwrap();
+
+        assert!(!autosave.is_due(0, now + Duration::from_secs(60)));
+        assert!(!autosave.is_due(2, now + Duration::from_secs(9)));
+        assert!(autosave.is_due(3, now));
+        assert!(autosave.is_due(1, now + Duration::from_secs(10)));
+        assert!(!autosave.has_unsaved_changes(0));
+        assert!(autosave.has_unsaved_changes(1));
+
+        // Every change is saved when no limit is set
+        let every_change = Autosave::new(AutosaveConfig::new(&path, None, None, false), now);
+        assert!(every_change.pending_recovery);
+        assert!(!every_change.is_due(0, now));
+        let mut every_change = Autosave { pending_recovery: false, ..every_change };
+        every_change.save(&book, &[], now).unwrap();
+        assert!(every_change.is_due(1, now));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_replaces_the_previous_one() {
+        let now = Instant::now();
+        let path = temporary_path("replace");
+        let mut autosave = Autosave::new(AutosaveConfig::new(&path, None, None, true), now);
+        let user = Rc::new(RefCell::new(Account::new(0, AccountType::Individual)));
+        user.borrow_mut().deposit(Currency::USD, 1000);
+        let mut book = Orderbook::new(0);
+        autosave.save(&book, &[Rc::clone(&user)], now).unwrap();
+
+        book.handle_order(&mut Order::new(1, 10, 0, Rc::clone(&user), OrderType::Limit, OrderDirection::Bid, 20)).unwrap();
+        autosave.save(&book, &[Rc::clone(&user)], now).unwrap();
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        assert!(!PathBuf::from(temporary).exists());
+
+        let (restored, accounts) = Orderbook::restore_from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(restored.snapshot().bids(), book.snapshot().bids());
+        assert_eq!(accounts[&0].borrow().balance(Currency::USD), 1000 - 20);
+        fs::remove_file(&path).unwrap();
+    }
+}
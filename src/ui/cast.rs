@@ -0,0 +1,115 @@
+use crate::book::query::DepthSnapshot;
+use crate::book::tick_converter::TickConverter;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Terminal size declared in exported casts.
+pub const CAST_WIDTH: usize = 80;
+pub const CAST_HEIGHT: usize = 24;
+
+/// Longest bar in a cast frame, leaving room for the price, side and quantity columns.
+const BAR_COLUMNS: usize = 50;
+
+/// Text rendering of a depth snapshot of the `pair` market (e.g. "OSMO/USD") for a cast frame: one line per level,
+/// asks above bids and the best prices in the middle, with bars scaled to the deepest level shown. Prices come from
+/// the book's `converter`, all with its decimals so the column lines up. Keeps to the levels nearest the touch that
+/// fit.
+pub fn depth_frame(snapshot: &DepthSnapshot, pair: &str, converter: &TickConverter) -> Vec<String> {
+    let per_side = (CAST_HEIGHT - 2) / 2;
+    let asks: Vec<(u64, u64)> = snapshot.asks().iter().take(per_side).rev().copied().collect();
+    let bids: Vec<(u64, u64)> = snapshot.bids().iter().take(per_side).copied().collect();
+    let deepest = asks.iter().chain(bids.iter()).map(|(_, quantity)| *quantity).max().unwrap_or(0).max(1);
+
+    let line = |side: &str, (tick_id, quantity): (u64, u64)| {
+        let bar = (quantity as u128 * BAR_COLUMNS as u128).div_ceil(deepest as u128) as usize;
+        let price = converter.format_tick(tick_id, Some(*converter.precision() as usize));
+        format!("{:>8} {} {:<width$} {}", price, side, "#".repeat(bar), quantity, width = BAR_COLUMNS)
+    };
+
+    let mut lines = vec![format!("Orderbook: {}, version {}", pair, snapshot.version())];
+    lines.extend(asks.into_iter().map(|level| line("ask", level)));
+    lines.push("-".repeat(CAST_WIDTH));
+    lines.extend(bids.into_iter().map(|level| line("bid", level)));
+    lines
+}
+
+/// Writes the snapshots as an asciinema v2 cast: a JSON header line, then one output event per snapshot that clears
+/// the screen and draws its frame, timed relative to the first snapshot.
+pub fn write_cast(history: &[(Instant, Arc<DepthSnapshot>)], pair: &str, converter: &TickConverter, writer: &mut impl Write) -> io::Result<()> {
+    writeln!(writer, "{{\"version\": 2, \"width\": {}, \"height\": {}}}", CAST_WIDTH, CAST_HEIGHT)?;
+
+    let start = match history.first() {
+        Some((recorded_at, _)) => *recorded_at,
+        None => return Ok(()),
+    };
+    for (recorded_at, snapshot) in history {
+        let frame = format!("\x1b[2J\x1b[H{}", depth_frame(snapshot, pair, converter).join("\r\n"));
+        writeln!(writer, "[{:.3}, \"o\", \"{}\"]", recorded_at.duration_since(start).as_secs_f64(), json_escape(&frame))?;
+    }
+    Ok(())
+}
+
+/// Escapes a string for use inside a JSON string literal.
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::BookBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_depth_frame_puts_asks_above_bids() {
+        let book = BookBuilder::new().with_bids(&[(9, 10), (8, 5)]).with_asks(&[(11, 20), (12, 1)]).build();
+        let frame = depth_frame(&book.snapshot(), "USD/OSMO", book.tick_converter());
+
+        assert_eq!(frame.len(), 6);
+        assert!(frame[0].starts_with("Orderbook: USD/OSMO, version "));
+        assert!(frame[1].starts_with("     1.2 ask ### "));
+        assert!(frame[2].starts_with(&format!("     1.1 ask {} 20", "#".repeat(50))));
+        assert!(frame[4].starts_with(&format!("     0.9 bid {} ", "#".repeat(25))));
+        assert!(frame[5].trim_end().ends_with(" 5"));
+    }
+
+    #[test]
+    fn test_depth_frame_prices_with_the_book_tick_size() {
+        let mut book = BookBuilder::new().with_bid(13, 10).with_ask(20, 10).build();
+        book.set_tick_converter(TickConverter::from_tick_size("0.05").unwrap());
+        let frame = depth_frame(&book.snapshot(), "OSMO/USD", book.tick_converter());
+
+        assert!(frame[1].starts_with("    1.00 ask "));
+        assert!(frame[3].starts_with("    0.65 bid "));
+    }
+
+    #[test]
+    fn test_cast_events_are_timed_from_the_first_snapshot() {
+        let start = Instant::now();
+        let book = BookBuilder::new().with_bid(9, 10).build();
+        let history = vec![(start, book.snapshot()), (start + Duration::from_millis(1500), book.snapshot())];
+
+        let mut cast = Vec::new();
+        write_cast(&history, "OSMO/USD", book.tick_converter(), &mut cast).unwrap();
+        let cast = String::from_utf8(cast).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+
+        assert_eq!(lines[0], "{\"version\": 2, \"width\": 80, \"height\": 24}");
+        assert!(lines[1].starts_with("[0.000, \"o\", \"\\u001b[2J\\u001b[HOrderbook: OSMO/USD"));
+        assert!(lines[2].starts_with("[1.500, \"o\", "));
+        assert!(lines[2].contains("\\r\\n"));
+        assert_eq!(json_escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::bank::account::Account;
+use crate::book::order::OrderDirection;
+use crate::book::orderbook::Orderbook;
+use std::time::{Duration, Instant};
+
+/// Capital the user starts a challenge with, in the book's base asset (USD on OSMO/USD). Any of the traded asset
+/// has to be bought with it.
+pub const CHALLENGE_CAPITAL_USD: u64 = 500000;
+
+/// Value of an account in the book's base asset, marking the traded asset at the book's mid (or the last trade if a
+/// side is empty) and counting the funds escrowed in its resting orders. None while the book has no price to mark at.
+pub fn equity(book: &Orderbook, account: &Account) -> Option<u64> {
+    let mark = book.snapshot().mid_tick().or(*book.last_trade_tick())?;
+
+    let mut base = account.balance(*book.base_asset());
+    let mut quote = account.balance(*book.quote_asset());
+    for tick in book.ticks().values() {
+        for order in tick.orders().iter().filter(|order| order.owner().borrow().account_id() == account.account_id()) {
+            match order.order_direction() {
+                OrderDirection::Bid => base = base.saturating_add(order.worth(*order.quantity())),
+                OrderDirection::Ask => quote = quote.saturating_add(*order.quantity()),
+            }
+        }
+    }
+    Some(base.saturating_add(book.tick_converter().notional(quote, mark)))
+}
+
+/// A timed paper-trading round: the user's equity is sampled on every tick until the timer runs out, tracking the
+/// PnL and the worst drop from a previous high.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    started_at: Instant,
+    duration: Duration,
+    starting_equity: u64,
+    peak_equity: u64,
+    current_equity: u64,
+    max_drawdown: u64,
+}
+
+impl Challenge {
+    pub fn new(now: Instant, duration: Duration, starting_equity: u64) -> Self {
+        Self {
+            started_at: now,
+            duration,
+            starting_equity,
+            peak_equity: starting_equity,
+            current_equity: starting_equity,
+            max_drawdown: 0,
+        }
+    }
+
+    /// Takes a new equity sample. Samples after the timer ran out are ignored so the result stays final.
+    pub fn record(&mut self, equity: u64, now: Instant) {
+        if self.is_over(now) {
+            return;
+        }
+        self.current_equity = equity;
+        self.peak_equity = self.peak_equity.max(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak_equity - equity);
+    }
+
+    pub fn pnl(&self) -> i64 {
+        self.current_equity as i64 - self.starting_equity as i64
+    }
+
+    pub fn max_drawdown(&self) -> u64 {
+        self.max_drawdown
+    }
+
+    /// PnL penalized by the largest drawdown, so steady gains score higher than the same gain after a deep hole.
+    pub fn score(&self) -> i64 {
+        self.pnl() - self.max_drawdown as i64
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.duration.saturating_sub(now.duration_since(self.started_at))
+    }
+
+    pub fn is_over(&self, now: Instant) -> bool {
+        self.remaining(now).is_zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::currency::Currency;
+    use crate::testkit::{limit_order, AccountBuilder, BookBuilder};
+
+    #[test]
+    fn test_pnl_drawdown_and_score() {
+        let start = Instant::now();
+        let mut challenge = Challenge::new(start, Duration::from_secs(60), 1000);
+
+        challenge.record(1200, start + Duration::from_secs(10));
+        challenge.record(900, start + Duration::from_secs(20));
+        challenge.record(1100, start + Duration::from_secs(30));
+        assert_eq!(challenge.pnl(), 100);
+        assert_eq!(challenge.max_drawdown(), 300);
+        assert_eq!(challenge.score(), -200);
+        assert_eq!(challenge.remaining(start + Duration::from_secs(30)), Duration::from_secs(30));
+
+        // Once the timer is up the result no longer moves
+        challenge.record(5000, start + Duration::from_secs(60));
+        assert_eq!(challenge.pnl(), 100);
+        assert!(challenge.is_over(start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_equity_counts_escrow_and_marks_at_mid() {
+        let mut book = BookBuilder::new().with_bid(8, 10).with_ask(12, 10).build();
+        let trader = AccountBuilder::funded(1, &[(Currency::USD, 1000), (Currency::OSMO, 50)]).build();
+        // 50 OSMO marked at a mid of 1.0
+        assert_eq!(equity(&book, &trader.borrow()), Some(1000 + 50));
+
+        // Resting orders move funds into escrow without changing what the account is worth
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Bid, 9, 20)).unwrap();
+        book.handle_order(&mut limit_order(&trader, OrderDirection::Ask, 11, 30)).unwrap();
+        assert_eq!(equity(&book, &trader.borrow()), Some(1000 + 50));
+
+        assert_eq!(equity(&BookBuilder::new().build(), &trader.borrow()), None);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::book::query::DepthSnapshot;
+use crate::book::tick_converter::TickConverter;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::Widget;
+
+/// Columns taken by each bar of the depth chart, and the gap left after it.
+pub const BAR_WIDTH: u16 = 3;
+pub const BAR_GAP: u16 = 1;
+
+/// Frames (app ticks) a level whose depth changed stays highlighted in the depth chart before fading back.
+pub const HIGHLIGHT_FRAMES: u8 = 8;
+
+/// How resting quantity maps to bar height in the depth chart.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChartScale {
+    /// Bar height proportional to quantity.
+    #[default]
+    Linear,
+    /// Bar height proportional to the order of magnitude of quantity, so thin levels stay visible next to deep ones.
+    Log,
+}
+
+impl ChartScale {
+    /// All scales, in the order they are listed to the user.
+    pub const ALL: [ChartScale; 2] = [ChartScale::Linear, ChartScale::Log];
+
+    /// Name used to select the scale from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChartScale::Linear => "linear",
+            ChartScale::Log => "log",
+        }
+    }
+
+    /// Looks up a scale by name (case insensitive).
+    pub fn by_name(name: &str) -> Option<ChartScale> {
+        ChartScale::ALL.into_iter().find(|scale| scale.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Bar value drawn for a level holding `quantity`. On the log scale this is 100 * log10(1 + quantity),
+    /// so an empty level stays at zero and every order of magnitude adds 100.
+    pub fn bar_value(&self, quantity: u64) -> u64 {
+        match self {
+            ChartScale::Linear => quantity,
+            ChartScale::Log => ((quantity as f64).ln_1p() / std::f64::consts::LN_10 * 100.0).round() as u64,
+        }
+    }
+
+    /// Top of the chart's y-axis: the deepest level in the snapshot, so it always fills the chart.
+    /// Never below 1 so an empty book still renders.
+    pub fn axis_max(&self, snapshot: &DepthSnapshot) -> u64 {
+        self.axis_max_by(snapshot, |_, quantity| quantity)
+    }
+
+    /// Top of the y-axis for bars drawn from `amount(tick, quantity)` of each level rather than its quantity, e.g.
+    /// its notional.
+    pub fn axis_max_by(&self, snapshot: &DepthSnapshot, amount: impl Fn(u64, u64) -> u64) -> u64 {
+        let deepest_level = snapshot.bids().iter()
+            .chain(snapshot.asks().iter())
+            .map(|(tick_id, quantity)| amount(*tick_id, *quantity))
+            .max()
+            .unwrap_or(0);
+        self.bar_value(deepest_level).max(1)
+    }
+}
+
+/// How many ticks fit side by side in a depth chart `width` columns wide, borders included. Always at least one.
+pub fn visible_bars(width: u16) -> u64 {
+    (width.saturating_sub(2) / (BAR_WIDTH + BAR_GAP)).max(1) as u64
+}
+
+/// Ticks the depth chart shows when it has room for `bars` of them. Starts at tick 0 while every level fits,
+/// otherwise centers on the mid so the touch stays in view however narrow the terminal gets.
+pub fn visible_ticks(snapshot: &DepthSnapshot, bars: u64) -> Range<u64> {
+    let highest_tick = snapshot.asks().last().or(snapshot.bids().first()).map_or(0, |(tick_id, _)| *tick_id);
+    let start = match snapshot.mid_tick() {
+        Some(mid_tick) if highest_tick >= bars => mid_tick.saturating_sub(bars / 2),
+        _ => 0,
+    };
+    start..start + bars
+}
+
+/// Price axis label for the bar at `index`, where each bar is one tick of the book's converter wide.
+pub fn price_label(converter: &TickConverter, index: u64, decimals: usize) -> String {
+    format!("{:.*}", decimals, converter.tick_to_price(index))
+}
+
+/// Ticks whose resting quantity differs between two builds of the depth chart, including levels that were added
+/// or emptied.
+pub fn changed_levels(before: &BTreeMap<u64, u64>, after: &BTreeMap<u64, u64>) -> Vec<u64> {
+    let mut changed: Vec<u64> = before.keys()
+        .chain(after.keys())
+        .filter(|tick_id| before.get(tick_id) != after.get(tick_id))
+        .copied()
+        .collect();
+    changed.sort_unstable();
+    changed.dedup();
+    changed
+}
+
+/// Style of a highlighted bar with `frames_left` of [`HIGHLIGHT_FRAMES`] to go. RGB colors blend from `highlight`
+/// back to `bar` as the frames run out; named colors can't be blended, so the bar stays `highlight` throughout.
+/// The first half of the highlight is also bold, which keeps it visible in themes without colors.
+pub fn highlight_style(bar: Color, highlight: Color, frames_left: u8) -> Style {
+    let weight = f64::from(frames_left.min(HIGHLIGHT_FRAMES)) / f64::from(HIGHLIGHT_FRAMES);
+    let color = match (bar, highlight) {
+        (Color::Rgb(r0, g0, b0), Color::Rgb(r1, g1, b1)) => {
+            let blend = |from: u8, to: u8| (f64::from(from) + (f64::from(to) - f64::from(from)) * weight).round() as u8;
+            Color::Rgb(blend(r0, r1), blend(g0, g1), blend(b0, b1))
+        }
+        _ => highlight,
+    };
+    let style = Style::default().fg(color);
+    match frames_left > HIGHLIGHT_FRAMES / 2 {
+        true => style.add_modifier(Modifier::BOLD),
+        false => style,
+    }
+}
+
+/// Restyles the bars of a depth chart that was already rendered to the same area, given as (bar index, style)
+/// pairs. The chart widget only takes one style for all of its bars, so highlights are drawn over it.
+pub struct BarHighlights<'a> {
+    pub bars: &'a [(usize, Style)],
+}
+
+impl Widget for BarHighlights<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Inside the chart's borders and above its row of price labels
+        let bars_area = Rect::new(area.x + 1, area.y + 1, area.width.saturating_sub(2), area.height.saturating_sub(3));
+        for (index, style) in self.bars {
+            let column = Rect::new(bars_area.x + *index as u16 * (BAR_WIDTH + BAR_GAP), bars_area.y, BAR_WIDTH, bars_area.height);
+            buf.set_style(column.intersection(bars_area), *style);
+        }
+    }
+}
+
+/// One block character per value, scaled so `max` reaches the full block. Values are expected in 0..=max.
+pub fn sparkline(values: &[f64], max: f64) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values.iter()
+        .map(|value| {
+            let level = if max > 0.0 { (value / max * 7.0).round() as usize } else { 0 };
+            BLOCKS[level.min(7)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::BookBuilder;
+
+    #[test]
+    fn test_axis_follows_deepest_level() {
+        let book = BookBuilder::new().with_bids(&[(8, 40), (9, 250)]).with_ask(12, 7).build();
+        let snapshot = book.snapshot();
+
+        assert_eq!(ChartScale::Linear.axis_max(&snapshot), 250);
+        assert_eq!(ChartScale::Log.axis_max(&snapshot), 240);
+        assert_eq!(ChartScale::Linear.axis_max(&BookBuilder::new().build().snapshot()), 1);
+
+        // Drawn by notional, 30 at tick 12 outweighs 40 at tick 8
+        let notional = |tick_id: u64, quantity: u64| tick_id * quantity;
+        assert_eq!(ChartScale::Linear.axis_max_by(&snapshot, notional), 9 * 250);
+        let snapshot = BookBuilder::new().with_bid(8, 40).with_ask(12, 30).build().snapshot();
+        assert_eq!(ChartScale::Linear.axis_max_by(&snapshot, notional), 360);
+    }
+
+    #[test]
+    fn test_log_scale_and_labels() {
+        assert_eq!(ChartScale::Log.bar_value(0), 0);
+        assert_eq!(ChartScale::Log.bar_value(9), 100);
+        assert_eq!(ChartScale::Log.bar_value(999), 300);
+        assert_eq!(ChartScale::by_name("LOG"), Some(ChartScale::Log));
+
+        assert_eq!(price_label(&TickConverter::default(), 12, 2), "1.20");
+        assert_eq!(price_label(&TickConverter::default(), 12, 0), "1");
+        assert_eq!(price_label(&TickConverter::from_tick_size("0.05").unwrap(), 13, 2), "0.65");
+    }
+
+    #[test]
+    fn test_visible_ticks_follow_the_mid_when_the_book_does_not_fit() {
+        assert_eq!(visible_bars(82), 20);
+        assert_eq!(visible_bars(0), 1);
+
+        let book = BookBuilder::new().with_bids(&[(5, 10), (30, 10)]).with_asks(&[(40, 10), (90, 10)]).build();
+        let snapshot = book.snapshot();
+        assert_eq!(visible_ticks(&snapshot, 100), 0..100);
+
+        // Shrinking below the highest level centers the window on the mid at 35
+        assert_eq!(visible_ticks(&snapshot, 20), 25..45);
+        assert_eq!(visible_ticks(&BookBuilder::new().build().snapshot(), 20), 0..20);
+    }
+
+    #[test]
+    fn test_changed_levels_and_highlight_fade() {
+        let before = BTreeMap::from([(8, 40), (9, 250), (12, 7)]);
+        let after = BTreeMap::from([(8, 40), (9, 200), (13, 5)]);
+        assert_eq!(changed_levels(&before, &after), vec![9, 12, 13]);
+        assert!(changed_levels(&after, &after).is_empty());
+
+        let (bar, highlight) = (Color::Rgb(0, 100, 200), Color::Rgb(200, 100, 0));
+        assert_eq!(highlight_style(bar, highlight, HIGHLIGHT_FRAMES), Style::default().fg(highlight).add_modifier(Modifier::BOLD));
+        assert_eq!(highlight_style(bar, highlight, HIGHLIGHT_FRAMES / 2), Style::default().fg(Color::Rgb(100, 100, 100)));
+        assert_eq!(highlight_style(Color::Reset, Color::White, 1), Style::default().fg(Color::White));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0.0, 0.5, 1.0], 1.0), "▁▅█");
+        assert_eq!(sparkline(&[2.0], 1.0), "█");
+        assert_eq!(sparkline(&[], 1.0), "");
+    }
+}
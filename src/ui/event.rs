@@ -5,7 +5,7 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -15,6 +15,10 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Text pasted into the terminal in one go (bracketed paste), possibly spanning several lines.
+    Paste(String),
+    /// The process was asked to stop (SIGINT, SIGTERM or SIGHUP).
+    Shutdown,
 }
 
 /// Terminal event handler.
@@ -48,6 +52,7 @@ impl EventHandler {
                             CrosstermEvent::Key(e) => sender.send(Event::Key(e)),
                             CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
                             CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
+                            CrosstermEvent::Paste(text) => sender.send(Event::Paste(text)),
                             _ => unimplemented!(),
                         }
                         .expect("failed to send terminal event")
@@ -60,6 +65,9 @@ impl EventHandler {
                 }
             })
         };
+        #[cfg(unix)]
+        Self::forward_shutdown_signals(sender.clone());
+
         Self {
             sender,
             receiver,
@@ -67,6 +75,25 @@ impl EventHandler {
         }
     }
 
+    /// Turns termination signals into [`Event::Shutdown`] so the main loop can restore the terminal before exiting.
+    ///
+    /// In raw mode Ctrl-C arrives as a key event rather than SIGINT, but signals sent from outside the terminal
+    /// (e.g. `kill` or a closing terminal emulator) would otherwise end the process with the terminal left in raw mode.
+    #[cfg(unix)]
+    fn forward_shutdown_signals(sender: mpsc::Sender<Event>) {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP]).expect("failed to register signal handlers");
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                if sender.send(Event::Shutdown).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -0,0 +1,225 @@
+use crate::book::tick_converter::TickConverter;
+
+/// How amounts (balances, quantities, depth) are written out in the UI.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberStyle {
+    /// Raw digits, e.g. `10000000000`.
+    Plain,
+    /// Digits grouped by thousands, e.g. `10,000,000,000`.
+    #[default]
+    Separated,
+    /// Short form with a magnitude suffix, e.g. `10B`.
+    Compact,
+}
+
+impl NumberStyle {
+    /// All styles, in the order they are listed to the user.
+    pub const ALL: [NumberStyle; 3] = [NumberStyle::Plain, NumberStyle::Separated, NumberStyle::Compact];
+
+    /// Name used to select the style from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumberStyle::Plain => "plain",
+            NumberStyle::Separated => "separated",
+            NumberStyle::Compact => "compact",
+        }
+    }
+
+    /// Looks up a style by name (case insensitive).
+    pub fn by_name(name: &str) -> Option<NumberStyle> {
+        NumberStyle::ALL.into_iter().find(|style| style.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Formats an amount according to this style.
+    pub fn format(&self, amount: u64) -> String {
+        match self {
+            NumberStyle::Plain => amount.to_string(),
+            NumberStyle::Separated => with_separators(amount),
+            NumberStyle::Compact => compact(amount),
+        }
+    }
+}
+
+/// How tick prices are written out in updates. A whole price in a band whose tick size only allows whole prices
+/// always drops its decimals, since they could never be anything but zero there.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PriceStyle {
+    /// As few decimals as the price needs, e.g. `1.2` and `2`.
+    #[default]
+    Shortest,
+    /// A fixed number of decimals, e.g. `1.20` and `2.00` with two.
+    Fixed(usize),
+}
+
+impl PriceStyle {
+    /// Most decimals a fixed style can show.
+    pub const MAX_DECIMALS: usize = 3;
+
+    /// Name used to select the style from the command line: `auto` or the number of decimals.
+    pub fn name(&self) -> String {
+        match self {
+            PriceStyle::Shortest => "auto".to_string(),
+            PriceStyle::Fixed(decimals) => decimals.to_string(),
+        }
+    }
+
+    /// Looks up a style by name. Fixed styles need at least one decimal, as a tick is a tenth.
+    pub fn by_name(name: &str) -> Option<PriceStyle> {
+        match name.parse() {
+            Ok(decimals) if (1..=PriceStyle::MAX_DECIMALS).contains(&decimals) => Some(PriceStyle::Fixed(decimals)),
+            Ok(_) => None,
+            Err(_) => name.eq_ignore_ascii_case("auto").then_some(PriceStyle::Shortest),
+        }
+    }
+
+    /// Formats the price of a tick on a book with the given converter, where `step` is the tick size in force at that
+    /// tick (see `TickSizeTable::step_at`).
+    pub fn format(&self, converter: &TickConverter, tick_id: u64, step: u64) -> String {
+        let whole_band = converter.ticks_per_unit().is_some_and(|ticks| step.is_multiple_of(ticks) && tick_id.is_multiple_of(ticks));
+        match self {
+            _ if whole_band => converter.format_tick(tick_id, None),
+            PriceStyle::Shortest => converter.format_tick(tick_id, None),
+            PriceStyle::Fixed(decimals) => converter.format_tick(tick_id, Some(*decimals)),
+        }
+    }
+}
+
+/// What level quantities and order sizes are shown as: the amount of the traded asset, or what it is worth in the
+/// asset it is priced in at the level's price. Traders think in notional as often as in units.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum QuantityUnit {
+    /// Units of the traded asset, e.g. `10 OSMO`.
+    #[default]
+    Quantity,
+    /// Worth at the level's price, counted like balances of the pricing asset, e.g. `12 USD` for 10 OSMO at 1.2.
+    Notional,
+}
+
+impl QuantityUnit {
+    /// All units, in the order they are listed to the user.
+    pub const ALL: [QuantityUnit; 2] = [QuantityUnit::Quantity, QuantityUnit::Notional];
+
+    /// Name used to select the unit from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuantityUnit::Quantity => "quantity",
+            QuantityUnit::Notional => "notional",
+        }
+    }
+
+    /// Looks up a unit by name (case insensitive).
+    pub fn by_name(name: &str) -> Option<QuantityUnit> {
+        QuantityUnit::ALL.into_iter().find(|unit| unit.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Amount shown for `quantity` resting at a tick of a book priced by `converter`.
+    pub fn amount(&self, converter: &TickConverter, quantity: u64, tick_id: u64) -> u64 {
+        match self {
+            QuantityUnit::Quantity => quantity,
+            QuantityUnit::Notional => converter.notional(quantity, tick_id),
+        }
+    }
+}
+
+/// Formats a price averaged over several ticks, e.g. the fill of an order across levels, to three decimals.
+pub fn average_price(price: f64) -> String {
+    format!("{:.3}", price)
+}
+
+/// Groups the digits of an amount by thousands, e.g. `1234567` becomes `1,234,567`.
+pub fn with_separators(amount: u64) -> String {
+    let digits = amount.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Shortens an amount to at most one decimal with a K/M/B/T suffix, e.g. `1540000` becomes `1.5M`.
+/// The decimal is truncated rather than rounded so a value is never shown larger than it is.
+pub fn compact(amount: u64) -> String {
+    const UNITS: [(u64, &str); 4] = [
+        (1_000_000_000_000, "T"),
+        (1_000_000_000, "B"),
+        (1_000_000, "M"),
+        (1_000, "K"),
+    ];
+
+    match UNITS.iter().find(|(unit, _)| amount >= *unit) {
+        None => amount.to_string(),
+        Some((unit, suffix)) => {
+            let whole = amount / unit;
+            let tenths = (amount % unit) * 10 / unit;
+            if tenths == 0 {
+                format!("{}{}", whole, suffix)
+            } else {
+                format!("{}.{}{}", whole, tenths, suffix)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_styles() {
+        let converter = TickConverter::default();
+        assert_eq!(PriceStyle::Shortest.format(&converter, 12, 1), "1.2");
+        assert_eq!(PriceStyle::Shortest.format(&converter, 20, 1), "2");
+        assert_eq!(PriceStyle::Fixed(2).format(&converter, 12, 1), "1.20");
+        assert_eq!(PriceStyle::Fixed(3).format(&converter, 20, 1), "2.000");
+        // Whole-price bands never show decimals
+        assert_eq!(PriceStyle::Fixed(2).format(&converter, 1050, 10), "105");
+        assert_eq!(PriceStyle::by_name("auto"), Some(PriceStyle::Shortest));
+        assert_eq!(PriceStyle::by_name("2"), Some(PriceStyle::Fixed(2)));
+        assert_eq!(PriceStyle::by_name("0"), None);
+        assert_eq!(average_price(1.25), "1.250");
+
+        // Books quoted in 0.05 steps show the second decimal where a price needs it
+        let converter = TickConverter::from_tick_size("0.05").unwrap();
+        assert_eq!(PriceStyle::Shortest.format(&converter, 13, 1), "0.65");
+        assert_eq!(PriceStyle::Fixed(1).format(&converter, 12, 1), "0.6");
+        assert_eq!(PriceStyle::Fixed(2).format(&converter, 40, 20), "2");
+    }
+
+    #[test]
+    fn test_quantity_units() {
+        let converter = TickConverter::default();
+        assert_eq!(QuantityUnit::Quantity.amount(&converter, 10, 12), 10);
+        assert_eq!(QuantityUnit::Notional.amount(&converter, 10, 12), 12);
+        // The notional follows the price, not the tick: tick 13 is 0.65 in steps of 0.05
+        assert_eq!(QuantityUnit::Notional.amount(&TickConverter::from_tick_size("0.05").unwrap(), 100, 13), 65);
+        assert_eq!(QuantityUnit::by_name("Notional"), Some(QuantityUnit::Notional));
+        assert_eq!(QuantityUnit::by_name("usd"), None);
+    }
+
+    #[test]
+    fn test_with_separators() {
+        assert_eq!(with_separators(0), "0");
+        assert_eq!(with_separators(999), "999");
+        assert_eq!(with_separators(1000), "1,000");
+        assert_eq!(with_separators(10000000000), "10,000,000,000");
+    }
+
+    #[test]
+    fn test_compact() {
+        assert_eq!(compact(999), "999");
+        assert_eq!(compact(1000), "1K");
+        assert_eq!(compact(1540000), "1.5M");
+        assert_eq!(compact(999999), "999.9K");
+        assert_eq!(compact(10000000000), "10B");
+    }
+
+    #[test]
+    fn test_style_by_name() {
+        assert_eq!(NumberStyle::by_name("Compact"), Some(NumberStyle::Compact));
+        assert_eq!(NumberStyle::by_name("scientific"), None);
+        assert_eq!(NumberStyle::Plain.format(12345), "12345");
+    }
+}
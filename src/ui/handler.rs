@@ -1,18 +1,48 @@
 use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
 use std::rc::Rc;
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use rand::prelude::*;
 use rand_distr::{Distribution, Normal};
 
+use crate::algo::twap::{AlgoState, TwapOrder};
 use crate::bank::account::{Account, AccountType};
 use crate::bank::currency::Currency;
 use crate::ui::app::{App, AppResult};
+use crate::ui::cast::write_cast;
+use crate::ui::challenge::{Challenge, CHALLENGE_CAPITAL_USD};
+use crate::ui::chart::{sparkline, ChartScale};
+use crate::ui::format::{self, NumberStyle, PriceStyle, QuantityUnit};
+use crate::ui::theme::Theme;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-// import book and set up Orderbook object
-use crate::book::orderbook;
 // import order
-use crate::book::order::{self, OrderDirection, OrderType};
+use crate::book::conditional::{BookCondition, BookMetric, Comparison};
+use crate::book::error::OrderbookError;
+use crate::book::exchange::{pair_name, parse_pair};
+use crate::book::order::{self, OrderDirection, OrderType, TimeInForce, TriggerType};
+use crate::book::orderbook::Orderbook;
+use crate::book::tick_converter::TickConverter;
+use crate::book::query::{DepthSnapshot, OpenOrder};
+use crate::book::execution_report::{write_execution_reports, AccountPseudonyms};
+use crate::book::inverted::InvertedView;
+use crate::book::l2_snapshot::L2Snapshot;
+use crate::book::rejection::RejectCode;
+use crate::book::trade::TradeFilter;
+use crate::book::risk::{DuplicateGuard, DuplicatePolicy, SlippageGuard, SlippageLimit, SlippagePolicy};
+
+/// Distance from the mid, in ticks, that the `stats` command counts as near-mid depth.
+const STATS_NEAR_MID_TICKS: u64 = 5;
+// VPIN in stats: OSMO traded per volume bucket, buckets per estimate, and how many past estimates the trend shows
+const STATS_VPIN_BUCKET_VOLUME: u64 = 50;
+const STATS_VPIN_WINDOW: usize = 10;
+const STATS_VPIN_TREND_POINTS: usize = 30;
+// Spread and volatility in stats: quote samples (one a second) per realized volatility estimate, and trend length
+const STATS_VOLATILITY_WINDOW: usize = 60;
+const STATS_QUOTE_TREND_POINTS: usize = 30;
+
+/// Number of trades the `trades` command lists per page.
+const TRADES_PAGE_SIZE: usize = 10;
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
@@ -22,16 +52,33 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             app.quit();
         }
 
+        // Raw mode swallows SIGINT, so Ctrl-C is delivered as a key press instead
+        KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.quit();
+        }
+
         KeyCode::Tab => {
             generate_normal_distribution_orders(app, 1, 40)?;
         }
 
+        // Function keys submit the command bound to them in the config, leaving the command line as it is
+        KeyCode::F(key) => {
+            run_hotkey(app, key)?;
+        }
+
+        // In review mode the arrow keys step through the recorded depth history
+        KeyCode::Left | KeyCode::Right if app.review_frame.is_some() => {
+            step_review(app, key_event.code == KeyCode::Right);
+        }
+
         // Note: c is a generic char that interprets all alphanumeric characters
         KeyCode::Char(c) => {
             app.command_line.push(c); // Collect the character
+            app.command_hint = command_hint(app);
         }
         KeyCode::Backspace => {
             app.command_line.pop(); // Remove the last character if there are any left
+            app.command_hint = command_hint(app);
         }
         KeyCode::Enter => {
             // Process the input
@@ -39,14 +86,61 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             // wait 50 ms
             std::thread::sleep(std::time::Duration::from_millis(50));
             app.command_line.clear();
+            app.command_hint = None;
         }
 
         // Other handlers you could add here.
         _ => {}
     }
+
+    // The depth chart is rebuilt once per event, however many orders the event placed
+    app.sync_depth();
+    Ok(())
+}
+
+/// Handles text pasted into the terminal. A single line is added to the command line as if it was typed, while
+/// several lines are run one after another as a batch of commands (continuing whatever was already typed), followed
+/// by a summary of how many were rejected. Blank lines and lines starting with `#` are skipped.
+pub fn handle_paste_event(text: &str, app: &mut App) -> AppResult<()> {
+    // Terminals send pasted line breaks as \r, \n or both
+    let pasted = format!("{}{}", app.command_line, text);
+    let mut lines: Vec<&str> = pasted.split(['\r', '\n']).map(str::trim).collect();
+    if lines.len() == 1 {
+        app.command_line = pasted;
+        app.command_hint = command_hint(app);
+        return Ok(());
+    }
+
+    lines.retain(|line| !line.is_empty() && !line.starts_with('#'));
+    app.command_line.clear();
+    app.command_hint = None;
+
+    let mut rejected = 0;
+    for (index, line) in lines.iter().enumerate() {
+        app.updates.push(format!("Paste {}/{}: {}", index + 1, lines.len(), line));
+        let updates_before = app.updates.len();
+        run_command(app, line)?;
+        if app.updates[updates_before..].iter().any(|update| reports_rejection(update)) {
+            rejected += 1;
+        }
+    }
+    app.updates.push(format!(
+        "Pasted {} command{}: {} ran, {} rejected.",
+        lines.len(),
+        if lines.len() == 1 { "" } else { "s" },
+        lines.len() - rejected,
+        rejected,
+    ));
+
+    app.sync_depth();
     Ok(())
 }
 
+// Whether an update reports a command, order, cancel, amend or cross that was refused
+pub(crate) fn reports_rejection(update: &str) -> bool {
+    update.starts_with("Error placing order [") || update.contains(" rejected [")
+}
+
 // handle command function (takes in orderbook, account, app, and command string)
 // "buy OSMO": bid order direction
 // "sell OSMO": ask order direction
@@ -57,78 +151,1491 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
 // the fourth argument is the quantity of the order
 // only require the fifth argument if the order type is limit, and use the price times 10 as the tick_id
 // Use this information to build an Order object and pass it to place_and_process_order
-fn handle_command(app: &mut App) -> AppResult<()> {
-    let tokens: Vec<&str> = app.command_line.split_whitespace().collect();
+/// Runs the command typed into the command line, as if Enter was pressed, without clearing the line.
+pub fn handle_command(app: &mut App) -> AppResult<()> {
+    let command_line = app.command_line.clone();
+    run_command(app, &command_line)
+}
+
+// A command that doesn't place an order, run with the tokens after its keyword.
+type CommandHandler = fn(&mut App, &[&str]) -> AppResult<()>;
+
+// Every command routed by its leading keyword. run_command dispatches through this table and command_hint leaves
+// these keywords alone, so a new command only needs adding here.
+const COMMANDS: &[(&str, CommandHandler)] = &[
+    ("preview", handle_preview_command),
+    ("risk", handle_risk_command),
+    ("diff", handle_diff_command),
+    ("review", handle_review_command),
+    ("theme", handle_theme_command),
+    ("numbers", handle_numbers_command),
+    ("stats", |app, _| handle_stats_command(app)),
+    ("twap", handle_twap_command),
+    ("algo", handle_algo_command),
+    ("tick", handle_tick_command),
+    ("orders", handle_orders_command),
+    ("cancel", handle_cancel_command),
+    ("amend", handle_amend_command),
+    ("cross", handle_cross_command),
+    ("market", handle_market_command),
+    ("trades", handle_trades_command),
+    ("debug", |app, _| handle_debug_command(app)),
+    ("chart", handle_chart_command),
+    ("compact", |app, _| handle_compact_command(app)),
+    ("export", handle_export_command),
+    ("help", |app, _| handle_help_command(app)),
+    ("cast", handle_cast_command),
+    ("challenge", handle_challenge_command),
+    ("recover", handle_recover_command),
+    ("seed", handle_seed_command),
+    ("auction", handle_auction_command),
+];
+
+// The handler of the command starting with this keyword (case insensitive), None for order commands.
+fn command_handler(keyword: &str) -> Option<CommandHandler> {
+    COMMANDS.iter().find(|(name, _)| name.eq_ignore_ascii_case(keyword)).map(|(_, handler)| *handler)
+}
+
+pub(crate) fn run_command(app: &mut App, command_line: &str) -> AppResult<()> {
+    let tokens: Vec<&str> = command_line.split_whitespace().collect();
+
+    // Commands that don't place orders are routed by their leading keyword
+    if let Some(handler) = tokens.first().and_then(|keyword| command_handler(keyword)) {
+        return handler(app, &tokens[1..]);
+    }
 
     if tokens.len() < 4 {
         // Invalid command format
-        app.command_line = "Invalid command format: ".to_string() + &(tokens.join(" "));
+        return reject_command(app, &format!("Invalid command format: {}", tokens.join(" ")));
+    }
+
+    let mut order = match parse_order_command(app, &tokens) {
+        Ok(order) => order,
+        Err(e) => return reject_command(app, &e),
+    };
+
+    place_and_process_order(&mut order, app)?;
+    
+    Ok(())
+}
+
+// Builds the user's order from "[buy/sell] [osmo/usd] [limit/market] [quantity] [price] [flags]",
+// reporting the first argument that is missing or can't be parsed. "stop [quantity] [stop price]" and
+// "stoplimit [quantity] [price] [stop price]" are shorthands for a market or limit order with --stop.
+fn parse_order_command(app: &App, tokens: &[&str]) -> Result<order::Order, String> {
+    let order_direction = match (tokens.first(), tokens.get(1)) {
+        (Some(action), Some(asset)) => parse_order_direction(action, asset, *app.session_book.quote_asset()).ok_or("Unsupported command format")?,
+        _ => return Err("Missing asset, expected [buy/sell] [osmo/usd]".into()),
+    };
+
+    let (order_type, is_stop) = match tokens.get(2).map(|order_type| order_type.to_lowercase()).as_deref() {
+        Some("limit") => (OrderType::Limit, false),
+        Some("market") => (OrderType::Market, false),
+        Some("stop") => (OrderType::Market, true),
+        Some("stoplimit") => (OrderType::Limit, true),
+        Some(_) => return Err("Unsupported order type".into()),
+        None => return Err("Missing order type, expected [limit/market/stop/stoplimit]".into()),
+    };
+
+    let quantity: u64 = tokens.get(3)
+        .ok_or("Missing quantity")?
+        .parse()
+        .map_err(|_| "Failed to parse quantity")?;
+
+    let converter = app.session_book.tick_converter();
+    let (tick_id, mut flags) = if let OrderType::Limit = order_type {
+        let tick_id = converter.price_to_tick(tokens.get(4).ok_or("Missing price argument for limit order")?)
+            .map_err(|_| format!("Failed to parse price, expected a multiple of {}", converter.format_tick(1, None)))?;
+
+        (tick_id, &tokens[5..])
+    } else {
+        (0, &tokens[4..])  // Default value if not a limit order
+    };
+
+    let trigger_tick = if is_stop {
+        let trigger_tick = converter.price_to_tick(flags.first().ok_or("Missing stop price")?)
+            .map_err(|_| "Failed to parse stop price")?;
+        flags = &flags[1..];
+        Some(trigger_tick)
+    } else {
+        None
+    };
+
+    // Here I am assuming order_id, book_id are default set as 0. Adjust as necessary.
+    let mut order = order::Order::new(
+        0,
+        tick_id,
+        0,
+        app.user_account.clone(),
+        order_type,
+        order_direction,
+        quantity,
+    );
+    order.set_trigger_tick(trigger_tick);
+
+    // Optional flags follow the positional arguments, e.g. "--max-slippage 5" (ticks) or "--max-slippage 50bps"
+    apply_order_flags(&mut order, converter, flags)?;
+
+    Ok(order)
+}
+
+/// Validates the partially typed command line, returning a hint about what is missing or wrong with it.
+///
+/// Only order commands are checked. Once an order parses, its cost is checked against the user's
+/// balance: limit orders escrow their full size, market orders are estimated against current depth.
+pub fn command_hint(app: &App) -> Option<String> {
+    let tokens: Vec<&str> = app.command_line.split_whitespace().collect();
+
+    if tokens.first().is_none_or(|keyword| command_handler(keyword).is_some()) {
+        return None;
+    }
+
+    let order = match parse_order_command(app, &tokens) {
+        Ok(order) => order,
+        Err(e) => return Some(e),
+    };
+
+    // Stops and conditional orders are only paid for when they activate, against whatever the book looks like then
+    if order.trigger_tick().is_some() || order.condition().is_some() {
+        return None;
+    }
+
+    let quantity = *order.quantity();
+    let (base_asset, quote_asset) = (*app.session_book.base_asset(), *app.session_book.quote_asset());
+    let (currency, required) = match (order.order_type(), order.order_direction()) {
+        (OrderType::Limit, OrderDirection::Bid) => (base_asset, app.session_book.tick_converter().notional(quantity, *order.tick_id())),
+        (OrderType::Limit, OrderDirection::Ask) => (quote_asset, quantity),
+        (OrderType::Market, direction) => {
+            let impact = app.session_book.impact(quantity, *direction);
+            if *impact.filled_quantity() == 0 {
+                return Some("No liquidity available for this market order".to_string());
+            }
+            match direction {
+                OrderDirection::Bid => (base_asset, *impact.total_cost()),
+                OrderDirection::Ask => (quote_asset, *impact.filled_quantity()),
+            }
+        }
+    };
+
+    let available = app.user_account.borrow().balance(currency);
+    if required > available {
+        return Some(format!(
+            "Insufficient {} for this size: needs {}, have {}",
+            currency,
+            app.number_style.format(required),
+            app.number_style.format(available),
+        ));
+    }
+
+    None
+}
+
+// Suggests the largest size of a rejected order its owner can pay for at the same price
+fn affordable_hint(app: &App, order: &order::Order) -> String {
+    let quantity = app.session_book.max_affordable_quantity(order);
+    if quantity == 0 {
+        return format!("You can't afford any {} at this price.", app.session_book.quote_asset());
+    }
+    match order.order_type() {
+        OrderType::Limit => format!(
+            "You can afford at most {} {} at price {}.",
+            app.number_style.format(quantity),
+            app.session_book.quote_asset(),
+            app.format_price(*order.tick_id()),
+        ),
+        OrderType::Market => format!(
+            "You can afford at most {} {} at market right now.",
+            app.number_style.format(quantity),
+            app.session_book.quote_asset(),
+        ),
+    }
+}
+
+// Reports a command that was rejected before reaching the book in the Updates panel
+fn reject_command(app: &mut App, reason: &str) -> AppResult<()> {
+    app.updates.push(format!("Command rejected [{}]: {}", RejectCode::InvalidCommand, reason));
+    Ok(())
+}
+
+// Maps the "[buy/sell] [osmo/usd]" pair of a command to the side of the book the order lands on. Buying the book's
+// traded asset is a bid, buying the asset it is priced in is an ask.
+fn parse_order_direction(action: &str, asset: &str, quote_asset: Currency) -> Option<OrderDirection> {
+    let direction = match action.to_lowercase().as_str() {
+        "buy" => OrderDirection::Bid,
+        "sell" => OrderDirection::Ask,
+        _ => return None,
+    };
+    match asset.to_uppercase().parse::<Currency>().ok()? {
+        asset if asset == quote_asset => Some(direction),
+        _ => Some(InvertedView::invert_direction(direction)),
+    }
+}
+
+// "--max-slippage <limit>" sets the order's slippage limit and "--truncate" fills up to the limit instead of rejecting.
+// "--aggressive" lets a limit order through the book's price protection, "--ioc" and "--fok" make it immediate-or-cancel
+// or fill-or-kill, and "--post-only" rejects it if it would take liquidity. "--strict" rejects a market order the book
+// can't fill in full.
+// "--stop <price>" and "--touch <price>" hold the order until trades move against it or in its favor to the price.
+fn apply_order_flags(order: &mut order::Order, converter: &TickConverter, flags: &[&str]) -> Result<(), &'static str> {
+    let mut limit = None;
+    let mut policy = SlippagePolicy::Reject;
+
+    let mut flag_iter = flags.iter();
+    while let Some(flag) = flag_iter.next() {
+        match flag.to_lowercase().as_str() {
+            "--max-slippage" => {
+                limit = Some(flag_iter.next().and_then(|value| parse_slippage_limit(value)).ok_or("Failed to parse max slippage")?);
+            }
+            "--worst-price" => {
+                let tick_id = flag_iter.next().and_then(|value| converter.price_to_tick(value).ok()).ok_or("Failed to parse worst price")?;
+                limit = Some(SlippageLimit::WorstTick(tick_id));
+            }
+            // Sells bound what they get back, buys what they spend
+            flag @ ("--min-output" | "--max-cost") => {
+                let notional = flag_iter.next().and_then(|value| value.parse().ok()).ok_or("Failed to parse notional amount")?;
+                match (flag, order.order_direction()) {
+                    ("--min-output", OrderDirection::Ask) | ("--max-cost", OrderDirection::Bid) => limit = Some(SlippageLimit::Notional(notional)),
+                    ("--min-output", _) => return Err("Use --max-cost to bound what a buy spends"),
+                    _ => return Err("Use --min-output to bound what a sell gets back"),
+                }
+            }
+            "--truncate" => policy = SlippagePolicy::Truncate,
+            "--aggressive" => order.set_skip_price_protection(true),
+            "--ioc" => order.set_time_in_force(TimeInForce::IOC),
+            "--fok" => order.set_time_in_force(TimeInForce::FOK),
+            "--post-only" => order.set_post_only(true),
+            "--strict" => order.set_strict(true),
+            "--gtd" => {
+                let seconds: u64 = flag_iter.next().and_then(|value| value.parse().ok()).ok_or("Failed to parse good-till-date seconds")?;
+                order.set_expires_at(Some(*order.placed_at() + Duration::from_secs(seconds)));
+            }
+            "--display" => {
+                let display_quantity = flag_iter.next().and_then(|value| value.parse().ok()).ok_or("Failed to parse display quantity")?;
+                order.set_display_quantity(Some(display_quantity));
+            }
+            "--when" => {
+                let mut condition_args = flag_iter.by_ref().take(3);
+                let condition = match (condition_args.next(), condition_args.next(), condition_args.next()) {
+                    (Some(metric), Some(comparison), Some(value)) => parse_book_condition(converter, metric, comparison, value),
+                    _ => None,
+                };
+                order.set_condition(Some(condition.ok_or("Expected --when [bid/ask/spread/biddepth/askdepth] [above/below] [value]")?));
+            }
+            "--stop" => {
+                let trigger_tick = flag_iter.next().and_then(|value| converter.price_to_tick(value).ok()).ok_or("Failed to parse stop price")?;
+                order.set_trigger_tick(Some(trigger_tick));
+            }
+            "--touch" => {
+                let trigger_tick = flag_iter.next().and_then(|value| converter.price_to_tick(value).ok()).ok_or("Failed to parse touch price")?;
+                order.set_trigger_tick(Some(trigger_tick));
+                order.set_trigger_type(TriggerType::IfTouched);
+            }
+            _ => return Err("Unsupported order flag"),
+        }
+    }
+
+    order.set_max_slippage(limit.map(|limit| SlippageGuard::new(limit, policy)));
+    Ok(())
+}
+
+// Conditions compare prices for bid and ask, ticks for the spread and quantities for depth, e.g. "spread above 3"
+fn parse_book_condition(converter: &TickConverter, metric: &str, comparison: &str, value: &str) -> Option<BookCondition> {
+    let metric: BookMetric = metric.to_lowercase().parse().ok()?;
+    let comparison: Comparison = comparison.to_lowercase().parse().ok()?;
+    let threshold = match metric {
+        BookMetric::Bid | BookMetric::Ask => converter.price_to_tick(value).ok()?,
+        BookMetric::Spread | BookMetric::BidDepth | BookMetric::AskDepth => value.parse().ok()?,
+    };
+    Some(BookCondition::new(metric, comparison, threshold))
+}
+
+// Slippage limits are plain tick counts ("5") or basis points from the mid price ("50bps")
+fn parse_slippage_limit(value: &str) -> Option<SlippageLimit> {
+    match value.to_lowercase().strip_suffix("bps") {
+        Some(bps) => bps.parse().ok().map(SlippageLimit::Bps),
+        None => value.parse().ok().map(SlippageLimit::Ticks),
+    }
+}
+
+// "risk [slippage/protection] ..." configures the risk checks applied to the user's orders
+fn handle_risk_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    match args.first().map(|setting| setting.to_lowercase()).as_deref() {
+        Some("slippage") if args.len() >= 2 => handle_slippage_risk_command(app, &args[1..]),
+        Some("protection") if args.len() >= 2 => handle_price_protection_command(app, &args[1..]),
+        Some("duplicates") if args.len() >= 2 => handle_duplicate_risk_command(app, &args[1..]),
+        Some("strict") if args.len() >= 2 => handle_strict_risk_command(app, &args[1..]),
+        _ => reject_command(
+            app,
+            "Usage: risk slippage [limit/off] [reject/truncate] | risk protection [ticks/off] | risk duplicates [ms/off] [flag/reject] | risk strict [on/off]",
+        ),
+    }
+}
+
+// "risk strict [on/off]" rejects every market order the book can't fill in full instead of filling it partially
+fn handle_strict_risk_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let strict = match args[0].to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return reject_command(app, "Expected risk strict [on/off]"),
+    };
+    app.session_book.set_strict_market_orders(strict);
+
+    app.updates.push(match strict {
+        true => "Market orders that can't be filled in full will be rejected.".to_string(),
+        false => "Market orders will fill as much as the book allows, unless sent with --strict.".to_string(),
+    });
+
+    Ok(())
+}
+
+// "risk protection [ticks/off]" rejects limit orders priced more than N ticks through the opposite best
+fn handle_price_protection_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let price_protection_ticks = if args[0].eq_ignore_ascii_case("off") {
+        None
+    } else {
+        match args[0].parse() {
+            Ok(ticks) => Some(ticks),
+            Err(_) => return reject_command(app, "Failed to parse price protection ticks"),
+        }
+    };
+    app.session_book.set_price_protection_ticks(price_protection_ticks);
+
+    app.updates.push(match price_protection_ticks {
+        None => "Price protection disabled for limit orders.".to_string(),
+        Some(ticks) => format!("Limit orders priced more than {} ticks through the opposite best will be rejected unless sent with --aggressive.", ticks),
+    });
+
+    Ok(())
+}
+
+// "risk duplicates [ms/off] [flag/reject]" catches identical orders the user sends within the given number of milliseconds
+fn handle_duplicate_risk_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let duplicate_guard = if args[0].eq_ignore_ascii_case("off") {
+        None
+    } else {
+        let window = match args[0].parse() {
+            Ok(milliseconds) => Duration::from_millis(milliseconds),
+            Err(_) => return reject_command(app, "Failed to parse duplicate window"),
+        };
+
+        let policy = match args.get(1).map(|policy| policy.to_lowercase()).as_deref() {
+            None | Some("reject") => DuplicatePolicy::Reject,
+            Some("flag") => DuplicatePolicy::Flag,
+            _ => return reject_command(app, "Unsupported duplicate policy"),
+        };
+
+        Some(DuplicateGuard::new(window, policy))
+    };
+
+    let account_id = *app.user_account.borrow().account_id();
+    let mut config = app.session_book.risk_config(account_id).cloned().unwrap_or_default();
+    config.set_duplicate_guard(duplicate_guard);
+    app.session_book.set_risk_config(account_id, config);
+
+    app.updates.push(match duplicate_guard {
+        None => "Duplicate order detection disabled.".to_string(),
+        Some(guard) => format!(
+            "Identical orders sent within {}ms of each other will be {}.",
+            guard.window().as_millis(),
+            match guard.policy() {
+                DuplicatePolicy::Flag => "flagged",
+                DuplicatePolicy::Reject => "rejected",
+            },
+        ),
+    });
+
+    Ok(())
+}
+
+// "risk slippage [limit/off] [reject/truncate]" sets the default slippage guard for all of the user's market orders
+fn handle_slippage_risk_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let max_slippage = if args[0].eq_ignore_ascii_case("off") {
+        None
+    } else {
+        let limit = match parse_slippage_limit(args[0]) {
+            Some(limit) => limit,
+            None => return reject_command(app, "Failed to parse max slippage"),
+        };
+
+        let policy = match args.get(1).map(|policy| policy.to_lowercase()).as_deref() {
+            None | Some("reject") => SlippagePolicy::Reject,
+            Some("truncate") => SlippagePolicy::Truncate,
+            _ => return reject_command(app, "Unsupported slippage policy"),
+        };
+
+        Some(SlippageGuard::new(limit, policy))
+    };
+
+    let account_id = *app.user_account.borrow().account_id();
+    let mut config = app.session_book.risk_config(account_id).cloned().unwrap_or_default();
+    config.set_max_slippage(max_slippage);
+    app.session_book.set_risk_config(account_id, config);
+
+    app.updates.push(match max_slippage {
+        None => "Max slippage disabled for market orders.".to_string(),
+        Some(guard) => {
+            let limit = match guard.limit() {
+                SlippageLimit::Ticks(ticks) => format!("{} ticks", ticks),
+                SlippageLimit::Bps(bps) => format!("{} bps", bps),
+                SlippageLimit::WorstTick(tick_id) => format!("a worst price of {}", app.format_price(*tick_id)),
+                SlippageLimit::Notional(notional) => format!("{} {} notional", notional, app.session_book.base_asset()),
+            };
+            format!("Max slippage for market orders set to {} ({:?} when exceeded).", limit, guard.policy())
+        }
+    });
+
+    Ok(())
+}
+
+// "review" freezes the depth chart on the newest recorded frame of the depth history, so Left/Right can step back
+// and forth through the session like a recording. "review" again returns to the live book.
+fn handle_review_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    if !args.is_empty() {
+        return reject_command(app, "Usage: review");
+    }
+
+    if app.review_frame.take().is_some() {
+        app.updates.push("Back to the live book.".to_string());
+    } else if app.depth_history.is_empty() {
+        app.updates.push("No depth history recorded yet.".to_string());
+    } else {
+        app.review_frame = Some(app.depth_history.len() - 1);
+        app.updates.push(format!(
+            "Reviewing {} recorded frame(s), Left/Right to step, `review` to return to the live book.",
+            app.depth_history.len(),
+        ));
+    }
+    Ok(())
+}
+
+// Moves review mode one frame back or forward and replays the tape in between: the trades that printed between
+// the previous frame and the one now shown.
+fn step_review(app: &mut App, forward: bool) {
+    let frame = match app.review_frame {
+        Some(frame) if forward => (frame + 1).min(app.depth_history.len().saturating_sub(1)),
+        Some(frame) => frame.saturating_sub(1),
+        None => return,
+    };
+    app.review_frame = Some(frame);
+
+    let recorded_at = app.depth_history[frame].0;
+    let previous_at = frame.checked_sub(1).map(|previous| app.depth_history[previous].0);
+    let tape: Vec<_> = app.session_book.trades().iter()
+        .filter(|trade| *trade.executed_at() <= recorded_at && previous_at.is_none_or(|previous_at| *trade.executed_at() > previous_at))
+        .collect();
+    let tape_summary = match tape.last() {
+        Some(last) if previous_at.is_some() => format!(
+            "{} trade(s) since the previous frame, the last {} {} at {}",
+            tape.len(),
+            app.number_style.format(*last.quantity()),
+            app.session_book.quote_asset(),
+            app.format_price(*last.tick_id()),
+        ),
+        _ if previous_at.is_some() => "no trades since the previous frame".to_string(),
+        _ => "the oldest recorded frame".to_string(),
+    };
+    app.updates.push(format!(
+        "Frame {}/{}, {}s ago: {}.",
+        frame + 1,
+        app.depth_history.len(),
+        recorded_at.elapsed().as_secs(),
+        tape_summary,
+    ));
+}
+
+// "diff [seconds]" reports net liquidity added/removed per level compared to the book N seconds ago
+fn handle_diff_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let seconds: u64 = match args.first().map(|seconds| seconds.parse()) {
+        Some(Ok(seconds)) => seconds,
+        _ => return reject_command(app, "Usage: diff [seconds]"),
+    };
+
+    let current = app.session_book.snapshot();
+    let earlier = Instant::now()
+        .checked_sub(Duration::from_secs(seconds))
+        .and_then(|at| app.depth_snapshot_at(at))
+        .or_else(|| app.depth_snapshot_at(Instant::now()));
+
+    let update = match earlier {
+        None => "No depth history recorded yet.".to_string(),
+        Some(earlier) => {
+            let changes = current.diff(&earlier);
+            if changes.is_empty() {
+                format!("No depth changes in the last {}s.", seconds)
+            } else {
+                let numbers = app.number_style;
+                let added: i64 = changes.iter().map(|(_, change)| change).filter(|change| **change > 0).sum();
+                let removed: i64 = changes.iter().map(|(_, change)| change).filter(|change| **change < 0).sum();
+                let levels: Vec<String> = changes.iter()
+                    .map(|(tick_id, change)| {
+                        let sign = if *change > 0 { "+" } else { "-" };
+                        format!("{}: {}{}", app.format_price(*tick_id), sign, numbers.format(change.unsigned_abs()))
+                    })
+                    .collect();
+                format!(
+                    "Depth change over the last {}s (+{} / -{}): {}",
+                    seconds,
+                    numbers.format(added.unsigned_abs()),
+                    numbers.format(removed.unsigned_abs()),
+                    levels.join(", "),
+                )
+            }
+        }
+    };
+    app.updates.push(update);
+
+    Ok(())
+}
+
+// "stats" reports how concentrated resting liquidity is now and at the start of the recorded depth history,
+// along with the number of stop and conditional orders waiting to activate
+fn handle_stats_command(app: &mut App) -> AppResult<()> {
+    let describe = |snapshot: &DepthSnapshot| match (snapshot.herfindahl_index(), snapshot.depth_share_near_mid(STATS_NEAR_MID_TICKS)) {
+        (Some(herfindahl_index), Some(near_mid_share)) => format!(
+            "HHI {:.3} over {} levels, {:.1}% of depth within {} ticks of mid",
+            herfindahl_index,
+            snapshot.bids().len() + snapshot.asks().len(),
+            near_mid_share * 100.0,
+            STATS_NEAR_MID_TICKS,
+        ),
+        _ => "empty book".to_string(),
+    };
+
+    let current = app.session_book.snapshot();
+    let mut update = format!("Liquidity concentration: {}", describe(&current));
+    if let Some((recorded_at, earliest)) = app.depth_history.front() {
+        if earliest.version() != current.version() {
+            update.push_str(&format!(" (was {} {}s ago)", describe(earliest), recorded_at.elapsed().as_secs()));
+        }
+    }
+    app.updates.push(update);
+
+    let (stop_bids, stop_asks) = app.session_book.pending_stops();
+    let (touch_bids, touch_asks) = app.session_book.pending_touches();
+    app.updates.push(format!(
+        "Pending stops: {} buy, {} sell. Pending if-touched: {} buy, {} sell. Pending conditional orders: {}.",
+        stop_bids,
+        stop_asks,
+        touch_bids,
+        touch_asks,
+        app.session_book.pending_conditionals(),
+    ));
+
+    let toxicity = app.session_book.flow_toxicity(STATS_VPIN_BUCKET_VOLUME, STATS_VPIN_WINDOW);
+    app.updates.push(match toxicity.vpin() {
+        Some(vpin) => {
+            let series = toxicity.series();
+            let trend = &series[series.len().saturating_sub(STATS_VPIN_TREND_POINTS)..];
+            format!(
+                "Order flow toxicity: VPIN {:.2} over the last {} buckets of {} {}, trend {}",
+                vpin,
+                toxicity.buckets().len().min(STATS_VPIN_WINDOW),
+                STATS_VPIN_BUCKET_VOLUME,
+                app.session_book.quote_asset(),
+                sparkline(trend, 1.0),
+            )
+        }
+        None => format!(
+            "Order flow toxicity: not enough volume yet ({} {} per bucket).",
+            STATS_VPIN_BUCKET_VOLUME,
+            app.session_book.quote_asset(),
+        ),
+    });
+
+    let history = &app.quote_history;
+    let update = match (history.samples().back(), history.average_spread(), history.realized_volatility(STATS_VOLATILITY_WINDOW)) {
+        (Some(last), Some(average_spread), Some(volatility)) => {
+            let spreads: Vec<f64> = history.spreads().iter().map(|spread| *spread as f64).collect();
+            let spread_trend = &spreads[spreads.len().saturating_sub(STATS_QUOTE_TREND_POINTS)..];
+            let volatilities = history.volatility_series(STATS_VOLATILITY_WINDOW);
+            let volatility_trend = &volatilities[volatilities.len().saturating_sub(STATS_QUOTE_TREND_POINTS)..];
+            format!(
+                "Spread: {} ticks, {:.1} on average over {} samples, trend {}. Realized volatility of mid: {:.2}% over the last {} samples, trend {}",
+                last.spread(),
+                average_spread,
+                history.samples().len(),
+                sparkline(spread_trend, spread_trend.iter().cloned().fold(0.0, f64::max)),
+                volatility * 100.0,
+                history.samples().len().min(STATS_VOLATILITY_WINDOW),
+                sparkline(volatility_trend, volatility_trend.iter().cloned().fold(0.0, f64::max)),
+            )
+        }
+        _ => "Spread and volatility: not enough quotes sampled yet (one a second while both sides are quoted).".to_string(),
+    };
+    app.updates.push(update);
+
+    Ok(())
+}
+
+// "cancel [order id]" takes one of the user's resting orders off the book and refunds its escrow,
+// "cancel [bids/asks] [from price] [to price]" all of the user's orders on that side within the band
+fn handle_cancel_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let usage = "Usage: cancel [order id] | cancel [bids/asks] [from price] [to price]";
+    if let [side, from, to] = args {
+        let direction = match side.to_lowercase().as_str() {
+            "bids" => OrderDirection::Bid,
+            "asks" => OrderDirection::Ask,
+            _ => return reject_command(app, usage),
+        };
+        let converter = *app.session_book.tick_converter();
+        let (from, to) = match (converter.price_to_tick(from), converter.price_to_tick(to)) {
+            (Ok(from), Ok(to)) => (from.min(to), from.max(to)),
+            _ => return reject_command(app, usage),
+        };
+
+        let account_id = *app.user_account.borrow().account_id();
+        let cancelled = app.session_book.cancel_range(account_id, direction, from, to);
+        let (refund, asset) = match direction {
+            OrderDirection::Bid => (cancelled.iter().map(|order| order.worth(*order.quantity())).sum(), app.session_book.base_asset()),
+            OrderDirection::Ask => (cancelled.iter().map(|order| *order.quantity()).sum::<u64>(), app.session_book.quote_asset()),
+        };
+        app.updates.push(match cancelled.len() {
+            0 => format!(
+                "No {} of yours rest between {} and {}.",
+                side.to_lowercase(),
+                app.format_price(from),
+                app.format_price(to),
+            ),
+            count => format!(
+                "Cancelled {} {} between {} and {}, refunded {} {}.",
+                count,
+                side.to_lowercase(),
+                app.format_price(from),
+                app.format_price(to),
+                app.number_style.format(refund),
+                asset,
+            ),
+        });
         return Ok(());
     }
 
-    let order_direction = match tokens[0].to_lowercase().as_str() {
-        "buy" if tokens[1].eq_ignore_ascii_case("OSMO") => OrderDirection::Bid,
-        "sell" if tokens[1].eq_ignore_ascii_case("OSMO") => OrderDirection::Ask,
-        "buy" if tokens[1].eq_ignore_ascii_case("USD") => OrderDirection::Ask,  // Note the inversion
-        "sell" if tokens[1].eq_ignore_ascii_case("USD") => OrderDirection::Bid,  // Note the inversion
-        _ => {
-            app.command_line = "Unsupported command format".to_string();
-            return Ok(());
+    let order_id: u64 = match args {
+        [order_id] => match order_id.trim_start_matches('#').parse() {
+            Ok(order_id) => order_id,
+            Err(_) => return reject_command(app, usage),
+        },
+        _ => return reject_command(app, usage),
+    };
+
+    let account_id = *app.user_account.borrow().account_id();
+    match app.session_book.cancel_order(order_id, account_id) {
+        Ok(order) => {
+            let refund = match order.order_direction() {
+                OrderDirection::Bid => format!("{} {}", app.number_style.format(order.worth(*order.quantity())), app.session_book.base_asset()),
+                OrderDirection::Ask => format!("{} {}", app.number_style.format(*order.quantity()), app.session_book.quote_asset()),
+            };
+            app.updates.push(format!(
+                "Cancelled order {}: {} {} {} at {}, refunded {}.",
+                order_id,
+                order.order_direction(),
+                app.number_style.format(*order.quantity()),
+                app.session_book.quote_asset(),
+                app.format_price(*order.tick_id()),
+                refund,
+            ));
         }
+        Err(e) => app.updates.push(format!("Cancel rejected [{}]: {}", e.code(), describe_error(app, &e))),
+    }
+
+    Ok(())
+}
+
+// "amend [order id] [quantity] [price]" changes the size and price of one of the user's resting orders. The price
+// defaults to where the order rests, so "amend 3 20" only resizes it.
+fn handle_amend_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let usage = "Usage: amend [order id] [quantity] [price]";
+    let (order_id, quantity, price) = match args {
+        [order_id, quantity, rest @ ..] if rest.len() <= 1 => {
+            match (order_id.trim_start_matches('#').parse::<u64>(), quantity.parse::<u64>(), rest.first().map(|price| app.session_book.tick_converter().price_to_tick(price)).transpose()) {
+                (Ok(order_id), Ok(quantity), Ok(price)) => (order_id, quantity, price),
+                _ => return reject_command(app, usage),
+            }
+        }
+        _ => return reject_command(app, usage),
     };
 
-    let order_type = match tokens[2].to_lowercase().as_str() {
-        "limit" => OrderType::Limit,
-        "market" => OrderType::Market,
-        _ => {
-            app.command_line = "Unsupported order type".to_string();
+    let account_id = *app.user_account.borrow().account_id();
+    let resting_tick = app.session_book.resting_tick(order_id);
+    let new_tick = match (price, resting_tick) {
+        (Some(tick_id), _) => tick_id,
+        (None, Some(tick_id)) => tick_id,
+        (None, None) => {
+            let error = OrderbookError::UnknownOrder(order_id);
+            app.updates.push(format!("Amend rejected [{}]: {}", error.code(), describe_error(app, &error)));
             return Ok(());
         }
     };
 
-    let quantity: u64 = match tokens[3].parse() {
-        Ok(q) => q,
-        Err(_) => {
-            app.command_line = "Failed to parse quantity".to_string();
-            return Ok(());
+    match app.session_book.amend_order(order_id, account_id, quantity, new_tick) {
+        Ok(previous) => app.updates.push(format!(
+            "Amended order {}: {} {} {} at {} is now {} at {}{}.",
+            order_id,
+            previous.order_direction(),
+            app.number_style.format(*previous.quantity()),
+            app.session_book.quote_asset(),
+            app.format_price(*previous.tick_id()),
+            app.number_style.format(quantity),
+            app.format_price(new_tick),
+            if new_tick == *previous.tick_id() && quantity <= *previous.quantity() { ", keeping its place in the queue" } else { "" },
+        )),
+        Err(e) => app.updates.push(format!("Amend rejected [{}]: {}", e.code(), describe_error(app, &e))),
+    }
+
+    Ok(())
+}
+
+// "cross [buyer id] [seller id] [quantity] [price]" prints a prearranged trade between two accounts without
+// touching the resting orders. The accounts may be the same, which books a transfer to itself.
+fn handle_cross_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let usage = "Usage: cross [buyer id] [seller id] [quantity] [price]";
+    let converter = app.session_book.tick_converter();
+    let (buyer_id, seller_id, quantity, tick_id): (u64, u64, u64, u64) = match args {
+        [buyer_id, seller_id, quantity, price] => match (buyer_id.parse(), seller_id.parse(), quantity.parse(), converter.price_to_tick(price)) {
+            (Ok(buyer_id), Ok(seller_id), Ok(quantity), Ok(tick_id)) => (buyer_id, seller_id, quantity, tick_id),
+            _ => return reject_command(app, usage),
+        },
+        _ => return reject_command(app, usage),
+    };
+
+    let (buyer, seller) = match (app.accounts.get(&buyer_id).cloned(), app.accounts.get(&seller_id).cloned()) {
+        (Some(buyer), Some(seller)) => (buyer, seller),
+        (None, _) => return reject_command(app, &format!("No account {}", buyer_id)),
+        (_, None) => return reject_command(app, &format!("No account {}", seller_id)),
+    };
+
+    match app.session_book.cross(&buyer, &seller, tick_id, quantity) {
+        Ok(trade) => app.updates.push(format!(
+            "Crossed {} {} at {} from account {} to account {} off the book.",
+            app.number_style.format(*trade.quantity()),
+            app.session_book.quote_asset(),
+            app.format_price(tick_id),
+            seller_id,
+            buyer_id,
+        )),
+        Err(e) => app.updates.push(format!("Cross rejected [{}]: {}", e.code(), describe_error(app, &e))),
+    }
+
+    Ok(())
+}
+
+// "market" lists the markets, "market add [pair]" lists a new one and "market [pair]" puts it on screen, e.g.
+// "market add USD/OSMO" then "market USD/OSMO". Pairs are written traded asset first.
+fn handle_market_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let usage = "Usage: market [add] [pair, e.g. OSMO/USD]";
+    let active = *app.session_book.book_id();
+
+    match args {
+        [] => {
+            let markets: Vec<String> = app.exchange.markets().iter()
+                .map(|(book_id, pair)| match *book_id == active {
+                    true => format!("{} (book {}, on screen)", pair_name(*pair), book_id),
+                    false => format!("{} (book {})", pair_name(*pair), book_id),
+                })
+                .collect();
+            app.updates.push(format!("Markets: {}.", markets.join(", ")));
+        }
+        [add, pair] if add.eq_ignore_ascii_case("add") => {
+            let (base_asset, quote_asset) = match parse_pair(pair) {
+                Some(pair) => pair,
+                None => return reject_command(app, usage),
+            };
+            app.updates.push(match app.exchange.create_market(base_asset, quote_asset) {
+                Ok(book_id) => format!("Listed {} as book {}.", pair_name((base_asset, quote_asset)), book_id),
+                Err(e) => format!("Market rejected: {}", e),
+            });
         }
+        [pair] => {
+            let book_id = match parse_pair(pair).map(|pair| (pair, app.exchange.find_market(pair))) {
+                Some((_, Some(book_id))) => book_id,
+                Some((pair, None)) => {
+                    let name = pair_name(pair);
+                    return reject_command(app, &format!("{} isn't listed, add it with `market add {}`", name, name));
+                }
+                None => return reject_command(app, usage),
+            };
+
+            // Algos and the challenge follow the book on screen, so they would jump markets with it
+            let update = if book_id == active {
+                format!("{} is already on screen.", app.market_name())
+            } else if app.algos.iter().any(|algo| algo.is_active()) {
+                "Switch rejected: cancel running algos before changing markets.".to_string()
+            } else if app.challenge.is_some() {
+                "Switch rejected: stop the challenge before changing markets.".to_string()
+            } else {
+                match app.switch_market(book_id) {
+                    Ok(()) => format!("Switched to {} (book {}).", app.market_name(), book_id),
+                    Err(e) => format!("Switch rejected: {}", e),
+                }
+            };
+            app.updates.push(update);
+        }
+        _ => return reject_command(app, usage),
+    }
+
+    Ok(())
+}
+
+// "tick [price]" lists the orders resting at a price level in the order they will be filled
+fn handle_tick_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let tick_id = match args.first().map(|price| app.session_book.tick_converter().price_to_tick(price)) {
+        Some(Ok(tick_id)) => tick_id,
+        _ => return reject_command(app, "Usage: tick [price]"),
     };
 
-    let tick_id = if let OrderType::Limit = order_type {
-        if tokens.len() < 5 {
-            app.command_line = "Missing price argument for limit order".to_string();  // Missing price argument for limit order
+    let queue = app.session_book.tick_detail(tick_id);
+    if queue.is_empty() {
+        app.updates.push(format!("No orders resting at {}.", app.format_price(tick_id)));
+        return Ok(());
+    }
+
+    // Updates are shown newest first, so the queue is pushed back to front to read top down
+    for queued in queue.iter().rev() {
+        app.updates.push(format!(
+            "  #{} order {} from account {}: {} {}, resting {}s",
+            queued.position(),
+            queued.order_id(),
+            queued.owner_id(),
+            queued.order_direction(),
+            app.format_quantity(*queued.quantity(), tick_id),
+            queued.age().as_secs(),
+        ));
+    }
+    let total_quantity: u64 = queue.iter().map(|queued| *queued.quantity()).sum();
+    app.updates.push(format!(
+        "Queue at {}: {} orders, {} in priority order:",
+        app.format_price(tick_id),
+        queue.len(),
+        app.format_quantity(total_quantity, tick_id),
+    ));
+
+    Ok(())
+}
+
+// "orders [order id]" lists the user's orders resting on the book, or shows how far one of them has filled
+fn handle_orders_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let account_id = *app.user_account.borrow().account_id();
+    let describe = |app: &App, order: &OpenOrder| format!(
+        "{} at {}, {} filled, {} left",
+        order.order_direction(),
+        app.format_price(*order.tick_id()),
+        app.format_quantity(*order.filled_quantity(), *order.tick_id()),
+        app.format_quantity(*order.remaining_quantity(), *order.tick_id()),
+    );
+
+    match args {
+        [] => {}
+        [order_id] => {
+            let order_id = match order_id.trim_start_matches('#').parse::<u64>() {
+                Ok(order_id) => order_id,
+                Err(_) => return reject_command(app, "Usage: orders [order id]"),
+            };
+            let update = match app.session_book.order_status(order_id).filter(|order| *order.owner_id() == account_id) {
+                Some(order) => format!("Order {}: {}.", order_id, describe(app, &order)),
+                None => format!("Order {} isn't resting on the book: it filled, was cancelled or is waiting on a trigger.", order_id),
+            };
+            app.updates.push(update);
             return Ok(());
         }
-        
-        let price: f64 = match tokens[4].parse() {
-            Ok(p) => p,
-            Err(_) => {
-                app.command_line = "Failed to parse price".to_string();
-                return Ok(());
+        _ => return reject_command(app, "Usage: orders [order id]"),
+    }
+
+    let open_orders = app.session_book.open_orders(account_id);
+    if open_orders.is_empty() {
+        app.updates.push("You have no orders resting on the book.".to_string());
+        return Ok(());
+    }
+    // Updates are shown newest first, so the orders are pushed back to front to read top down
+    for order in open_orders.iter().rev() {
+        app.updates.push(format!("  order {}: {}", order.order_id(), describe(app, order)));
+    }
+    app.updates.push(format!("Your {} open order(s), lowest id first:", open_orders.len()));
+    Ok(())
+}
+
+// "trades [mine] [buys/sells] [last <seconds>] [page <n>]" lists executed trades, newest first
+fn handle_trades_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let usage = "Usage: trades [mine] [buys/sells] [last <seconds>] [page <n>]";
+    let mut filter = TradeFilter::new();
+    let mut page = 1;
+
+    let mut arg_iter = args.iter();
+    while let Some(arg) = arg_iter.next() {
+        match arg.to_lowercase().as_str() {
+            "mine" => filter = filter.with_account(*app.user_account.borrow().account_id()),
+            "buys" => filter = filter.with_aggressor(OrderDirection::Bid),
+            "sells" => filter = filter.with_aggressor(OrderDirection::Ask),
+            "last" => match arg_iter.next().and_then(|seconds| seconds.parse().ok()) {
+                Some(seconds) => {
+                    let since = Instant::now().checked_sub(Duration::from_secs(seconds));
+                    filter = filter.with_time_range(since, None);
+                }
+                None => return reject_command(app, usage),
+            },
+            "page" => match arg_iter.next().and_then(|page| page.parse().ok()) {
+                Some(number) if number > 0 => page = number,
+                _ => return reject_command(app, usage),
+            },
+            _ => return reject_command(app, usage),
+        }
+    }
+
+    let result = app.session_book.trades_query(&filter.with_page((page - 1) * TRADES_PAGE_SIZE, TRADES_PAGE_SIZE));
+    if result.trades().is_empty() {
+        app.updates.push(format!("No trades to show ({} matching).", result.total_matches()));
+        return Ok(());
+    }
+
+    // Updates are shown newest first, so the page is pushed back to front to read top down
+    for trade in result.trades().iter().rev() {
+        if *trade.cross() {
+            app.updates.push(format!(
+                "  #{} cross {} {} at {}, buyer account {} / seller account {}, {}s ago",
+                trade.trade_id(),
+                app.number_style.format(*trade.quantity()),
+                app.session_book.quote_asset(),
+                app.format_price(*trade.tick_id()),
+                trade.taker_account_id(),
+                trade.maker_account_id(),
+                trade.executed_at().elapsed().as_secs(),
+            ));
+            continue;
+        }
+        app.updates.push(format!(
+            "  #{} {} {} {} at {}, taker account {} / maker account {}, {}s ago",
+            trade.trade_id(),
+            trade.aggressor(),
+            app.number_style.format(*trade.quantity()),
+            app.session_book.quote_asset(),
+            app.format_price(*trade.tick_id()),
+            trade.taker_account_id(),
+            trade.maker_account_id(),
+            trade.executed_at().elapsed().as_secs(),
+        ));
+    }
+    app.updates.push(format!(
+        "Trades page {} of {} ({} matching), newest first:",
+        page,
+        result.total_matches().div_ceil(TRADES_PAGE_SIZE),
+        result.total_matches(),
+    ));
+
+    Ok(())
+}
+
+// "chart [linear/log]" picks how bar heights scale with depth, "chart decimals [0-3]" how price labels are rounded
+fn handle_chart_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let names: Vec<&str> = ChartScale::ALL.iter().map(|scale| scale.name()).collect();
+    let usage = format!("Usage: chart [{}] | chart decimals [0-3]", names.join("/"));
+
+    match args.first() {
+        Some(setting) if setting.eq_ignore_ascii_case("decimals") => match args.get(1).and_then(|decimals| decimals.parse().ok()) {
+            Some(decimals) if decimals <= 3 => {
+                app.chart_price_decimals = decimals;
+                app.updates.push(format!("Chart prices are now shown with {} decimals.", decimals));
+                Ok(())
+            }
+            _ => reject_command(app, &usage),
+        },
+        Some(name) => match ChartScale::by_name(name) {
+            Some(scale) => {
+                app.chart_scale = scale;
+                app.updates.push(format!("The depth chart now uses a {} scale.", scale.name()));
+                Ok(())
+            }
+            None => reject_command(app, &usage),
+        },
+        None => reject_command(app, &usage),
+    }
+}
+
+// "debug" shows or hides the panel with the book's memory usage
+fn handle_debug_command(app: &mut App) -> AppResult<()> {
+    app.show_debug = !app.show_debug;
+    app.updates.push(format!("Debug panel {}.", if app.show_debug { "shown" } else { "hidden" }));
+    Ok(())
+}
+
+// "compact" releases spare capacity the book has held on to, e.g. after large sweeps
+fn handle_compact_command(app: &mut App) -> AppResult<()> {
+    let before = *app.session_book.memory_stats().approx_bytes();
+    app.session_book.compact();
+    let after = *app.session_book.memory_stats().approx_bytes();
+    app.updates.push(format!(
+        "Compacted the book from about {} to {} bytes.",
+        app.number_style.format(before as u64),
+        app.number_style.format(after as u64),
+    ));
+    Ok(())
+}
+
+// "challenge [minutes]" starts a paper-trading round from fixed capital, "challenge stop" ends it early
+fn handle_challenge_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let minutes: u64 = match args {
+        [stop] if stop.eq_ignore_ascii_case("stop") => {
+            let summary = app.challenge_summary("Challenge stopped");
+            app.updates.push(summary);
+            app.challenge = None;
+            return Ok(());
+        }
+        [minutes] => match minutes.parse() {
+            Ok(minutes) if minutes > 0 => minutes,
+            _ => return reject_command(app, "Usage: challenge [minutes] or challenge stop"),
+        },
+        _ => return reject_command(app, "Usage: challenge [minutes] or challenge stop"),
+    };
+
+    // Escrowed funds would come back on top of the fresh capital, so the user has to start without resting orders
+    let user_id = *app.user_account.borrow().account_id();
+    let has_resting_orders = app.session_book.ticks().values()
+        .flat_map(|tick| tick.orders().iter())
+        .any(|order| *order.owner().borrow().account_id() == user_id);
+    if has_resting_orders {
+        return reject_command(app, "Cancel or fill your resting orders before starting a challenge");
+    }
+
+    let base_asset = *app.session_book.base_asset();
+    {
+        let mut account = app.user_account.borrow_mut();
+        for currency in [base_asset, *app.session_book.quote_asset()] {
+            let balance = account.balance(currency);
+            account.withdraw(currency, balance)?;
+        }
+        account.deposit(base_asset, CHALLENGE_CAPITAL_USD);
+    }
+    app.challenge = Some(Challenge::new(Instant::now(), Duration::from_secs(minutes * 60), CHALLENGE_CAPITAL_USD));
+    app.updates.push(format!(
+        "Challenge started: {} minute(s) to grow {} {}. Your balances were reset for it.",
+        minutes,
+        app.number_style.format(CHALLENGE_CAPITAL_USD),
+        base_asset,
+    ));
+    Ok(())
+}
+
+// "cast [path]" writes the depth recorded over the last 10 minutes to an asciinema cast, one frame per change
+fn handle_cast_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let path = match args {
+        [path] => *path,
+        _ => return reject_command(app, "Usage: cast [path]"),
+    };
+
+    // Include the current depth even if the next tick hasn't recorded it yet
+    app.record_depth_snapshot(Instant::now());
+    let history: Vec<_> = app.depth_history.iter().cloned().collect();
+    let pair = app.market_name();
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write_cast(&history, &pair, app.session_book.tick_converter(), &mut writer)?;
+        writer.flush()
+    });
+    app.updates.push(match result {
+        Ok(()) => format!("Wrote {} depth frame(s) to {}, play it with `asciinema play {}`.", history.len(), path, path),
+        Err(e) => format!("Error writing cast to {}: {}", path, e),
+    });
+    Ok(())
+}
+
+// "help" opens or closes the overlay listing the commands and the configured hotkeys
+fn handle_help_command(app: &mut App) -> AppResult<()> {
+    app.show_help = !app.show_help;
+    Ok(())
+}
+
+// Runs the command bound to a function key, with @bid and @ask replaced by the current best prices
+fn run_hotkey(app: &mut App, key: u8) -> AppResult<()> {
+    let command = match app.hotkeys.iter().find(|hotkey| *hotkey.key() == key) {
+        Some(hotkey) => hotkey.command().clone(),
+        None => return Ok(()),
+    };
+
+    let snapshot = app.session_book.snapshot();
+    let mut tokens = Vec::new();
+    for token in command.split_whitespace() {
+        let (side, best) = match token.to_lowercase().as_str() {
+            "@bid" => ("bid", snapshot.bids().first()),
+            "@ask" => ("ask", snapshot.asks().first()),
+            _ => {
+                tokens.push(token.to_string());
+                continue;
             }
         };
-        
-        (price * 10.0).trunc() as u64
-    } else {
-        0  // Default value if not a limit order
+        match best {
+            Some((tick_id, _)) => tokens.push(app.session_book.tick_converter().format_tick(*tick_id, None)),
+            None => return reject_command(app, &format!("F{}: there is no best {} to price `{}` at", key, side, command)),
+        }
+    }
+
+    let command_line = tokens.join(" ");
+    app.updates.push(format!("F{}: {}", key, command_line));
+    run_command(app, &command_line)
+}
+
+// "export [path] [anonymous]" writes every trade so far to a file as FIX-style execution reports, one per side.
+// "anonymous" replaces account ids with pseudonyms numbered in order of appearance, so the file can be shared.
+fn handle_export_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let (path, anonymous) = match args {
+        [path] => (*path, false),
+        [path, "anonymous"] => (*path, true),
+        _ => return reject_command(app, "Usage: export [path] [anonymous]"),
     };
 
-    // Here I am assuming order_id, book_id are default set as 0. Adjust as necessary.
-    let mut order = order::Order::new(
-        0,
-        tick_id,
-        0,
+    let symbol = format!("{}/{}", app.session_book.quote_asset(), app.session_book.base_asset());
+    let mut pseudonyms = anonymous.then(AccountPseudonyms::new);
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write_execution_reports(app.session_book.trades(), &symbol, app.session_book.tick_converter(), pseudonyms.as_mut(), &mut writer)?;
+        writer.flush()
+    });
+    let exported = format!("Exported {} trade(s) as execution reports to {}", app.session_book.trades().len(), path);
+    app.updates.push(match (result, pseudonyms) {
+        (Err(e), _) => format!("Error exporting trades to {}: {}", path, e),
+        (Ok(()), None) => format!("{}.", exported),
+        (Ok(()), Some(pseudonyms)) => {
+            let user = match pseudonyms.get(*app.user_account.borrow().account_id()) {
+                Some(pseudonym) => format!("you are {}", pseudonym),
+                None => "you didn't trade".to_string(),
+            };
+            format!("{}, with {} account(s) replaced by pseudonyms ({}).", exported, pseudonyms.len(), user)
+        }
+    });
+    Ok(())
+}
+
+// "recover [discard]" restores the book and balances autosaved by an earlier session, or discards them so autosave can
+// start over. Only offered before anything has been traded, since the restored accounts replace the session's.
+fn handle_recover_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let discard = match args {
+        [] => false,
+        ["discard"] => true,
+        _ => return reject_command(app, "Usage: recover [discard]"),
+    };
+    let path = match app.autosave.as_ref().filter(|autosave| autosave.pending_recovery) {
+        Some(autosave) => autosave.config().path().clone(),
+        None => return reject_command(app, "No saved session to recover"),
+    };
+    if discard {
+        set_recovery_done(app);
+        app.updates.push(format!("Discarded the saved session in {}, autosave will overwrite it.", path.display()));
+        return Ok(());
+    }
+    if *app.session_book.book_id() != 0 || *app.session_book.version() != 0 {
+        return reject_command(app, "Recover before trading or switching markets, or use `recover discard`");
+    }
+
+    let restored = File::open(&path)
+        .map_err(|e| e.into())
+        .and_then(Orderbook::restore_from_reader)
+        .and_then(|(book, accounts)| {
+            let pair = (*book.base_asset(), *book.quote_asset());
+            if *book.book_id() != 0 || pair != (*app.session_book.base_asset(), *app.session_book.quote_asset()) {
+                return Err(format!("it holds {} book {}, not {}", pair_name(pair), book.book_id(), app.market_name()).into());
+            }
+            Ok((book, accounts))
+        });
+    match restored {
+        Ok((book, accounts)) => {
+            let orders = book.orders().count();
+            app.recover_session(book, accounts);
+            set_recovery_done(app);
+            app.updates.push(format!("Recovered the session saved in {} with {} resting order(s).", path.display(), orders));
+            app.sync_depth();
+        }
+        Err(e) => app.updates.push(format!("Error recovering from {}: {}", path.display(), e)),
+    }
+    Ok(())
+}
+
+// "seed <path>" rests the levels of an exchange's L2 snapshot JSON on the book, owned by the liquidity bot's account
+fn handle_seed_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let path = match args {
+        [path] => *path,
+        _ => return reject_command(app, "Usage: seed <path>"),
+    };
+
+    let account = liquidity_account(app);
+    let seeded = File::open(path)
+        .map_err(|e| e.into())
+        .and_then(|file| L2Snapshot::from_reader(BufReader::new(file), app.session_book.tick_converter()))
+        .and_then(|snapshot| {
+            let exchange = &mut app.exchange;
+            let placed = app.session_book.seed_from_l2(&snapshot, &account, || exchange.allocate_order_id())?;
+            Ok((snapshot, placed))
+        });
+    match seeded {
+        Ok((snapshot, placed)) => {
+            let mut update = format!(
+                "Seeded {} order(s) from {} for account {}: {} bid and {} ask level(s)",
+                placed,
+                path,
+                account.borrow().account_id(),
+                snapshot.bids().len(),
+                snapshot.asks().len(),
+            );
+            if *snapshot.skipped() > 0 {
+                update.push_str(&format!(", skipped {} level(s) under 1 {}", snapshot.skipped(), app.session_book.quote_asset()));
+            }
+            app.updates.push(format!("{}.", update));
+            app.sync_depth();
+        }
+        Err(e) => app.updates.push(format!("Error seeding the book from {}: {}", path, e)),
+    }
+    Ok(())
+}
+
+// "auction start" opens a call auction's collection window on the book on screen, "auction run" crosses what it
+// collected at one clearing price, and "auction" on its own shows how many orders are waiting
+fn handle_auction_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    match args.first().map(|action| action.to_lowercase()).as_deref() {
+        None => {
+            let update = match *app.session_book.collecting_auction() {
+                true => format!(
+                    "Auction collecting orders: {} order(s) wait for `auction run`.",
+                    app.session_book.auction_orders().len(),
+                ),
+                false => "No auction is collecting orders, open one with `auction start`.".to_string(),
+            };
+            app.updates.push(update);
+        }
+        Some("start") if args.len() == 1 => {
+            if *app.session_book.collecting_auction() {
+                return reject_command(app, "An auction is already collecting orders, cross them with `auction run`.");
+            }
+            app.session_book.start_auction();
+            app.updates.push("Auction started: limit orders are collected instead of matched until `auction run`.".to_string());
+        }
+        Some("run") if args.len() == 1 => {
+            if !*app.session_book.collecting_auction() {
+                return reject_command(app, "No auction is collecting orders, open one with `auction start`.");
+            }
+            let result = app.session_book.run_auction();
+            // Fills are listed first so the auction's outcome stays the newest update
+            app.report_executions();
+            let update = match result.clearing_tick() {
+                Some(tick_id) => format!(
+                    "Auction crossed {} {} at {} in {} trade(s), {} collected order(s) left resting on the book.",
+                    app.number_style.format(*result.matched_quantity()),
+                    app.session_book.quote_asset(),
+                    app.format_price(*tick_id),
+                    result.trades().len(),
+                    result.rested(),
+                ),
+                None => format!(
+                    "Auction closed without a cross, {} collected order(s) now rest on the book.",
+                    result.rested(),
+                ),
+            };
+            app.updates.push(update);
+            app.sync_depth();
+        }
+        _ => return reject_command(app, "Usage: auction [start/run]"),
+    }
+    Ok(())
+}
+
+fn set_recovery_done(app: &mut App) {
+    if let Some(autosave) = app.autosave.as_mut() {
+        autosave.pending_recovery = false;
+    }
+}
+
+// "theme [name]" switches the color palette, listing the available themes if the name is unknown
+fn handle_theme_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let names: Vec<&str> = Theme::BUILT_IN.iter().map(|theme| theme.name).collect();
+    match args.first().and_then(|name| Theme::by_name(name)) {
+        Some(theme) => {
+            app.theme = theme;
+            app.updates.push(format!("Switched to the {} theme.", theme.name));
+            Ok(())
+        }
+        None => reject_command(app, &format!("Usage: theme [{}]", names.join("/"))),
+    }
+}
+
+// "numbers [plain/separated/compact]" switches how balances, quantities and depth are written out, "numbers prices
+// [auto/1-3]" how many decimals prices show
+fn handle_numbers_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let names: Vec<&str> = NumberStyle::ALL.iter().map(|style| style.name()).collect();
+    let units: Vec<&str> = QuantityUnit::ALL.iter().map(|unit| unit.name()).collect();
+    let usage = format!(
+        "Usage: numbers [{}] | numbers prices [auto/1-{}] | numbers units [{}]",
+        names.join("/"),
+        PriceStyle::MAX_DECIMALS,
+        units.join("/"),
+    );
+    if let [setting, ..] = args {
+        if setting.eq_ignore_ascii_case("units") {
+            return match args.get(1).and_then(|name| QuantityUnit::by_name(name)) {
+                Some(unit) => {
+                    app.quantity_unit = unit;
+                    app.updates.push(format!(
+                        "Level quantities and order sizes are now shown as {}, e.g. 10 {} at {} as {}.",
+                        unit.name(),
+                        app.session_book.quote_asset(),
+                        app.format_price(12),
+                        app.format_quantity(10, 12),
+                    ));
+                    Ok(())
+                }
+                None => reject_command(app, &usage),
+            };
+        }
+        if setting.eq_ignore_ascii_case("prices") {
+            return match args.get(1).and_then(|name| PriceStyle::by_name(name)) {
+                Some(style) => {
+                    app.price_style = style;
+                    app.updates.push(format!("Prices are now shown in {} style, e.g. {}.", style.name(), app.format_price(12)));
+                    Ok(())
+                }
+                None => reject_command(app, &usage),
+            };
+        }
+    }
+
+    match args.first().and_then(|name| NumberStyle::by_name(name)) {
+        Some(style) => {
+            app.number_style = style;
+            app.updates.push(format!("Numbers are now shown in {} style, e.g. {}.", style.name(), style.format(1234567)));
+            Ok(())
+        }
+        None => reject_command(app, &usage),
+    }
+}
+
+// "twap [buy/sell] [osmo/usd] [quantity] [slices] [interval seconds]" works a large order into the book as
+// equally sized market orders, one every interval, starting right away
+fn handle_twap_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    if args.len() < 5 {
+        return reject_command(app, "Usage: twap [buy/sell] [osmo/usd] [quantity] [slices] [interval seconds]");
+    }
+
+    let order_direction = match parse_order_direction(args[0], args[1], *app.session_book.quote_asset()) {
+        Some(direction) => direction,
+        None => return reject_command(app, "Unsupported command format"),
+    };
+
+    let (quantity, slices, interval): (u64, u64, u64) = match (args[2].parse(), args[3].parse(), args[4].parse()) {
+        (Ok(quantity), Ok(slices), Ok(interval)) => (quantity, slices, interval),
+        _ => return reject_command(app, "Failed to parse quantity, slices or interval"),
+    };
+
+    let parent_id = app.next_algo_id;
+    let twap = match TwapOrder::new(
+        parent_id,
         app.user_account.clone(),
-        order_type,
         order_direction,
         quantity,
-    );
+        slices,
+        Duration::from_secs(interval),
+        Instant::now(),
+    ) {
+        Ok(twap) => twap,
+        Err(e) => return reject_command(app, e),
+    };
+
+    app.next_algo_id += 1;
+    app.updates.push(format!(
+        "TWAP #{} started: {} {} in {} slices, one every {}s.",
+        parent_id,
+        order_direction,
+        app.number_style.format(quantity),
+        slices,
+        interval,
+    ));
+    app.algos.push(twap);
+
+    // Release the first slice straight away instead of waiting for the next tick
+    app.run_algos(Instant::now());
 
-    place_and_process_order(&mut order, app)?;
-    
     Ok(())
 }
 
+// "algo status" lists every algo with its progress, "algo [pause/resume/cancel] [id]" controls one of them
+fn handle_algo_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    let action = args.first().map(|action| action.to_lowercase());
+    if action.as_deref() == Some("status") {
+        if app.algos.is_empty() {
+            app.updates.push("No algos have been started.".to_string());
+        }
+        for algo in app.algos.iter() {
+            app.updates.push(format!(
+                "TWAP #{} ({:?}): {} slices of {} sent, {} of {} {} filled.",
+                algo.parent_id(),
+                algo.state(),
+                algo.slices_sent(),
+                algo.slices(),
+                app.number_style.format(*algo.filled_quantity()),
+                app.number_style.format(*algo.total_quantity()),
+                app.session_book.quote_asset(),
+            ));
+        }
+        return Ok(());
+    }
+
+    let usage = "Usage: algo [status/pause/resume/cancel] [id]";
+    let parent_id: u64 = match args.get(1).map(|id| id.trim_start_matches('#').parse()) {
+        Some(Ok(parent_id)) => parent_id,
+        _ => return reject_command(app, usage),
+    };
+    let algo = match app.algos.iter_mut().find(|algo| *algo.parent_id() == parent_id) {
+        Some(algo) => algo,
+        None => return reject_command(app, &format!("No algo with id {}", parent_id)),
+    };
+
+    let result = match action.as_deref() {
+        Some("pause") => algo.pause(),
+        Some("resume") => algo.resume(Instant::now()),
+        Some("cancel") => algo.cancel(),
+        _ => return reject_command(app, usage),
+    };
+    let state = *algo.state();
+
+    match result {
+        Ok(()) if state == AlgoState::Cancelled => {
+            app.updates.push(format!("TWAP #{} cancelled, unsent slices dropped.", parent_id));
+            Ok(())
+        }
+        Ok(()) => {
+            app.updates.push(format!("TWAP #{} is now {:?}.", parent_id, state));
+            Ok(())
+        }
+        Err(e) => reject_command(app, e),
+    }
+}
+
+// "preview [buy/sell] [osmo/usd] [amount]" estimates a market order against current depth without placing it
+fn handle_preview_command(app: &mut App, args: &[&str]) -> AppResult<()> {
+    if args.len() < 3 {
+        return reject_command(app, "Usage: preview [buy/sell] [osmo/usd] [amount]");
+    }
+
+    let order_direction = match parse_order_direction(args[0], args[1], *app.session_book.quote_asset()) {
+        Some(direction) => direction,
+        None => return reject_command(app, "Unsupported command format"),
+    };
+
+    let quantity: u64 = match args[2].parse() {
+        Ok(q) => q,
+        Err(_) => return reject_command(app, "Failed to parse quantity"),
+    };
+
+    let numbers = app.number_style;
+    let impact = app.session_book.impact(quantity, order_direction);
+    let update = match (impact.average_price(), impact.final_tick()) {
+        (None, _) => format!("Preview: no liquidity available for a market {} of {}.", order_direction, numbers.format(quantity)),
+        (Some(average_price), final_tick) => format!(
+            "Preview: market {} would fill {} of {} at an average price of {} {}, leaving the book at {}.",
+            order_direction,
+            numbers.format(*impact.filled_quantity()),
+            numbers.format(quantity),
+            format::average_price(average_price),
+            app.session_book.base_asset(),
+            final_tick.map_or("an empty side".to_string(), |tick| app.format_price(tick)),
+        ),
+    };
+    app.updates.push(update);
+
+    Ok(())
+}
 
 // Place order and wire up result to UI
 fn place_and_process_order(
@@ -137,51 +1644,131 @@ fn place_and_process_order(
 ) -> AppResult<()> {
     // In the Ok case, add "Order placed successfully" to the front of the app updates vector
     // In the err case, add "Error placing order: <error>" to the front of the app updates vector
+    order.set_order_id(app.exchange.allocate_order_id());
+
+    let flagged_duplicates = *app.session_book.flagged_duplicates();
+    let activations = activation_counts(&app.session_book);
+    let (quantity, trades_before) = (*order.quantity(), app.session_book.trades().len());
     match app.session_book.handle_order(order) {
         Ok(_) => {
+            // Fills are listed first so the outcome of the order itself stays the newest update
+            app.report_executions();
+            if *app.session_book.flagged_duplicates() > flagged_duplicates {
+                app.updates.push("Warning: this order is identical to one sent moments ago.".to_string());
+            }
+
+            if let Some(condition) = order.condition() {
+                app.updates.push(format!(
+                    "Conditional {} {} for {} {} waits for {}.",
+                    order.order_type(),
+                    order.order_direction(),
+                    app.number_style.format(*order.quantity()),
+                    app.session_book.quote_asset(),
+                    describe_condition(app, condition),
+                ));
+                report_activations(app, activations);
+                return Ok(());
+            }
+
+            if let Some(trigger_tick) = order.trigger_tick() {
+                app.updates.push(format!(
+                    "{} {} {} for {} {} armed, triggers when {} trades at {}.",
+                    order.trigger_type(),
+                    order.order_type(),
+                    order.order_direction(),
+                    app.number_style.format(*order.quantity()),
+                    app.session_book.quote_asset(),
+                    app.session_book.quote_asset(),
+                    app.format_price(*trigger_tick),
+                ));
+                report_activations(app, activations);
+                return Ok(());
+            }
+
+            // Any other order that went through while an auction collects was held for it
+            if *app.session_book.collecting_auction() {
+                app.updates.push(format!(
+                    "Limit {} for {} {} at {} collected for the auction, {} order(s) wait for `auction run`.",
+                    order.order_direction(),
+                    app.number_style.format(*order.quantity()),
+                    app.session_book.quote_asset(),
+                    app.format_price(*order.tick_id()),
+                    app.session_book.auction_orders().len(),
+                ));
+                return Ok(());
+            }
+
+            // IOC and FOK limits never rest, so report what they filled rather than a placement
+            if *order.order_type() == OrderType::Limit && *order.time_in_force() != TimeInForce::GTC {
+                let filled: u64 = app.session_book.trades()[trades_before..].iter()
+                    .filter(|trade| trade.taker_order_id() == order.order_id())
+                    .map(|trade| *trade.quantity())
+                    .sum();
+                app.updates.push(match filled == quantity {
+                    true => format!(
+                        "{} limit {} filled in full: {} {}.",
+                        order.time_in_force(),
+                        order.order_direction(),
+                        app.number_style.format(quantity),
+                        app.session_book.quote_asset(),
+                    ),
+                    false => format!(
+                        "{} limit {} filled {} of {} {}, the other {} cancelled.",
+                        order.time_in_force(),
+                        order.order_direction(),
+                        app.number_style.format(filled),
+                        app.number_style.format(quantity),
+                        app.session_book.quote_asset(),
+                        app.number_style.format(quantity - filled),
+                    ),
+                });
+                report_activations(app, activations);
+                return Ok(());
+            }
+
             // now also add what kind of order and for how much e.g. "Limit for 1000 OSMO (quote asset) placed successfully at price (tick_id)"
             // A general framing for this is "{OrderType} for {Order.quantity()} {orderbook.quote_asset()} placed successfully"
             
             // if order was a bid, then the order was for OSMO (quote asset) and the price was the tick_id USD
-            // if order was an ask, then the order was for USD (base asset) and the price was 1/tick_id per OSMO
+            // if order was an ask, then the order was for USD (base asset) and is priced in OSMO per USD on the inverted pair
             let order_quote_asset;
             let order_base_asset;
-            let order_price: f64;
+            let order_price: String;
 
             match order.order_direction() {
                 OrderDirection::Bid => {
-                    order_quote_asset = app.session_book.quote_asset();
-                    order_base_asset = app.session_book.base_asset();
-                    order_price = *order.tick_id() as f64;
+                    order_quote_asset = *app.session_book.quote_asset();
+                    order_base_asset = *app.session_book.base_asset();
+                    order_price = app.format_price(*order.tick_id());
                 }
                 OrderDirection::Ask => {
-                    order_quote_asset = app.session_book.base_asset();
-                    order_base_asset = app.session_book.quote_asset();
-                    let tick_id = *order.tick_id() as f64;
-                    order_price = 1.0 / tick_id;
+                    let inverted = app.session_book.inverted_view();
+                    order_quote_asset = inverted.quote_asset();
+                    order_base_asset = inverted.base_asset();
+                    order_price = inverted.price(*order.tick_id()).unwrap_or_default().to_string();
                 }
             }
             
             match order.order_type() {
                 // If limit order, we need to specify the price
                 OrderType::Limit => {
+                    let mut details = order.display_quantity()
+                        .map(|display| format!(", showing {} at a time", app.number_style.format(display)))
+                        .unwrap_or_default();
+                    if let Some(expires_at) = order.expires_at() {
+                        details.push_str(&format!(", good till {}s from now", expires_at.saturating_duration_since(Instant::now()).as_secs_f64().round()));
+                    }
                     app.updates.push(
                         format!(
-                            "{} order successfully placed for {} {} at price {} {}.",
-                            order.order_type().to_string(),
-                            order.quantity(),
-                            order_quote_asset.to_string(),
-                            order_price / 10.0,
-                            order_base_asset.to_string(),
+                            "{} order successfully placed for {} {} at price {} {}{}.",
+                            order.order_type(),
+                            app.number_style.format(*order.quantity()),
+                            order_quote_asset,
+                            order_price,
+                            order_base_asset,
+                            details,
                         ),
                     );
-
-                    // iterate through all active ticks in orderbook tick tree and update
-                    let mut new_positions = Vec::new();
-                    for (key, value) in app.session_book.ticks().range(..) {
-                        insert_or_assign(&mut new_positions, *key as usize, *value.total_orders());
-                    }
-                    app.positions = new_positions;
                 }
 
                 // If market order, we don't need to specify a price
@@ -189,34 +1776,99 @@ fn place_and_process_order(
                     app.updates.push(
                         format!(
                             "{} order for {} {} successfully placed. Order will be filled for however much {} is available at the best price.",
-                            order.order_type().to_string(),
-                            order.quantity(),
-                            order_quote_asset.to_string(),
-                            order_quote_asset.to_string(),
+                            order.order_type(),
+                            app.number_style.format(*order.quantity()),
+                            order_quote_asset,
+                            order_quote_asset,
                         ),
                     );
-
-                    let mut new_positions = Vec::new();
-                    for (key, value) in app.session_book.ticks().range(..) {
-                        insert_or_assign(&mut new_positions, *key as usize, *value.total_orders());
-                    }
-                    app.positions = new_positions;
                 }
             }
+
+            report_activations(app, activations);
         }
         Err(e) => {
-            app.updates
-                .insert(0, format!("Error placing order: {}", e));
+            // Tell the user what they could place instead so they can resubmit straight away. Pushed first so it
+            // shows under the error.
+            if let OrderbookError::InsufficientFunds { .. } = e {
+                app.updates.push(affordable_hint(app, order));
+            }
+
+            app.updates.push(format!("Error placing order [{}]: {}", e.code(), describe_error(app, &e)));
         }
     }
     Ok(())
 }
 
-fn insert_or_assign(vec: &mut Vec<u64>, index: usize, value: u64) {
-    if vec.len() <= index {
-        vec.resize_with(index + 1, Default::default); // This will fill in gaps with 0
+// Reason shown to the user when the book refuses an order, cancel or cross, with prices and amounts written out the
+// way the rest of the updates write them
+fn describe_error(app: &App, error: &OrderbookError) -> String {
+    let numbers = app.number_style;
+    match error {
+        OrderbookError::InsufficientFunds { currency, required, available } => format!(
+            "Insufficient funds, {} {} needed but {} available",
+            numbers.format(*required),
+            currency,
+            numbers.format(*available),
+        ),
+        OrderbookError::InvalidTick { tick_id, nearest_valid: Some((below, above)) } => format!(
+            "{} is off the tick size grid, the nearest valid prices are {} and {}",
+            app.format_price(*tick_id),
+            app.format_price(*below),
+            app.format_price(*above),
+        ),
+        OrderbookError::InvalidTick { nearest_valid: None, .. } => "Price is outside the book's range".to_string(),
+        OrderbookError::InsufficientLiquidity { filled, requested } => format!(
+            "All-or-none order would only fill {} of {} {}",
+            numbers.format(*filled),
+            numbers.format(*requested),
+            app.session_book.quote_asset(),
+        ),
+        OrderbookError::UnknownOrder(order_id) => format!("You have no resting order {}", order_id),
+        _ => error.to_string(),
     }
-    vec[index] = value;
+}
+
+// Stops triggered, stops rejected when they ran, conditional orders activated and conditional orders rejected when they ran
+fn activation_counts(book: &Orderbook) -> [(u64, u64); 3] {
+    [
+        (*book.stop_activations(), *book.failed_stop_activations()),
+        (*book.touch_activations(), *book.failed_touch_activations()),
+        (*book.conditional_activations(), *book.failed_conditional_activations()),
+    ]
+}
+
+// Reports the trigger and conditional orders that ran since the counts were taken, i.e. those activated by the order
+// just placed (or the order itself, if its trigger or condition was already met)
+fn report_activations(app: &mut App, before: [(u64, u64); 3]) {
+    let after = activation_counts(&app.session_book);
+    let kinds = ["stop order(s) triggered", "if-touched order(s) triggered", "conditional order(s) activated"];
+    for ((kind, (ran, failed)), (ran_before, failed_before)) in kinds.iter().zip(after).zip(before) {
+        if ran > ran_before {
+            app.updates.push(format!("{} {}, {} rejected when they ran.", ran - ran_before, kind, failed - failed_before));
+        }
+    }
+}
+
+// e.g. "spread above 3 ticks" or "bid below 1.2"
+fn describe_condition(app: &App, condition: &BookCondition) -> String {
+    let threshold = match condition.metric() {
+        BookMetric::Bid | BookMetric::Ask => app.format_price(*condition.threshold()),
+        BookMetric::Spread => format!("{} ticks", condition.threshold()),
+        BookMetric::BidDepth | BookMetric::AskDepth => format!("{} {}", condition.threshold(), app.session_book.quote_asset()),
+    };
+    format!("{} {} {}", condition.metric(), condition.comparison(), threshold)
+}
+
+// Bot account from the config, or a freshly funded one (kept separate from the user's account id 0 so user risk
+// settings don't apply to it)
+fn liquidity_account(app: &App) -> Rc<RefCell<Account>> {
+    app.bot_account.clone().unwrap_or_else(|| {
+        let acc = Rc::new(RefCell::new(Account::new(1, AccountType::Individual)));
+        acc.borrow_mut().deposit(*app.session_book.quote_asset(), 10000000000);
+        acc.borrow_mut().deposit(*app.session_book.base_asset(), 10000000000);
+        acc
+    })
 }
 
 // Generates a normal distribution of orders
@@ -226,10 +1878,7 @@ fn generate_normal_distribution_orders(app: &mut App, min_tick: u64, max_tick: u
     let standard_deviation = (max_tick - min_tick) as f64 / 6.0; // Roughly 99.7% of data will be within min_tick and max_tick
     let normal = Normal::new(mid_point, standard_deviation).unwrap();
 
-    // bot account
-    let acc = Rc::new(RefCell::new(Account::new(0, AccountType::Individual)));
-    acc.borrow_mut().deposit(Currency::OSMO, 10000000000);
-    acc.borrow_mut().deposit(Currency::USD, 10000000000);
+    let acc = liquidity_account(app);
 
     // generate the orders
     for _ in 0..20000 { // replace number_of_orders with your desired number
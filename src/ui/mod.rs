@@ -1,5 +1,13 @@
 pub mod app;
+pub mod autosave;
+pub mod cast;
+pub mod challenge;
+pub mod chart;
 pub mod event;
+pub mod format;
 pub mod handler;
+pub mod script;
+pub mod theme;
 pub mod tui;
+#[allow(clippy::module_inception)]
 pub mod ui;
\ No newline at end of file
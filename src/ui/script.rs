@@ -0,0 +1,87 @@
+use crate::bank::currency::Currency;
+use crate::ui::app::{App, AppResult};
+use crate::ui::handler::{reports_rejection, run_command};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+/// What a script run did, for the closing summary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptSummary {
+    pub commands: usize,
+    pub rejected: usize,
+    pub trades: usize,
+    pub elapsed: Duration,
+}
+
+/// Runs a script of commands against the app without a terminal, one command per line in the same grammar as the
+/// command line. Blank lines and lines starting with `#` are skipped. Each command is echoed to `out` followed by the
+/// updates it produced and the trades it printed, and the run ends with a summary and every account's balances.
+///
+/// Nothing ticks in between commands, so time-driven features (TWAP algos, challenges, depth history) don't move.
+pub fn run_script(app: &mut App, script: impl BufRead, out: &mut impl Write) -> AppResult<ScriptSummary> {
+    let started = Instant::now();
+    let (mut commands, mut rejected, mut trades) = (0, 0, 0);
+
+    for line in script.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        commands += 1;
+        writeln!(out, "> {}", line)?;
+
+        let updates_before = app.updates.len();
+        let (book_before, trades_before) = (*app.session_book.book_id(), app.session_book.next_trade_id());
+        run_command(app, line)?;
+        for update in &app.updates[updates_before..] {
+            writeln!(out, "  {}", update)?;
+        }
+        if app.updates[updates_before..].iter().any(|update| reports_rejection(update)) {
+            rejected += 1;
+        }
+        // A `market` command puts another book on screen, which trades nothing itself
+        let printed = if *app.session_book.book_id() == book_before { app.session_book.trades_since(trades_before) } else { &[] };
+        trades += printed.len();
+        for trade in printed {
+            writeln!(
+                out,
+                "  trade #{} {} {} {} at {}, taker account {} / maker account {}",
+                trade.trade_id(),
+                if *trade.cross() { "cross".to_string() } else { trade.aggressor().to_string() },
+                app.number_style.format(*trade.quantity()),
+                app.session_book.quote_asset(),
+                app.format_price(*trade.tick_id()),
+                trade.taker_account_id(),
+                trade.maker_account_id(),
+            )?;
+        }
+    }
+    app.sync_depth();
+
+    let summary = ScriptSummary {
+        commands,
+        rejected,
+        trades,
+        elapsed: started.elapsed(),
+    };
+    writeln!(
+        out,
+        "Ran {} command(s) in {:.2?}: {} rejected, {} trade(s).",
+        summary.commands, summary.elapsed, summary.rejected, summary.trades,
+    )?;
+
+    let mut account_ids: Vec<u64> = app.accounts.keys().copied().collect();
+    account_ids.sort_unstable();
+    for account_id in account_ids {
+        let account = app.accounts[&account_id].borrow();
+        writeln!(
+            out,
+            "Account {}: {} OSMO, {} USD",
+            account_id,
+            app.number_style.format(account.balance(Currency::OSMO)),
+            app.number_style.format(account.balance(Currency::USD)),
+        )?;
+    }
+    Ok(summary)
+}
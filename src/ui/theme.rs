@@ -0,0 +1,96 @@
+use tui::style::Color;
+
+/// Color palette consumed by [`crate::ui::ui::render`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    /// Name used to select the theme from the command line.
+    pub name: &'static str,
+    /// Depth chart bars.
+    pub depth_bar: Color,
+    /// Depth chart bars of levels that just changed, fading back to `depth_bar`.
+    pub depth_highlight: Color,
+    /// Price labels under the depth chart.
+    pub depth_label: Color,
+    /// USD balance line.
+    pub usd_balance: Color,
+    /// OSMO balance line.
+    pub osmo_balance: Color,
+    /// Messages in the updates panel.
+    pub update_text: Color,
+    /// Text typed into the command line.
+    pub command_text: Color,
+    /// Validation hint shown next to the command line while typing.
+    pub command_hint: Color,
+    /// Panel borders and titles.
+    pub border: Color,
+}
+
+impl Theme {
+    /// Built-in themes, in the order they are listed to the user.
+    pub const BUILT_IN: [Theme; 3] = [Theme::DEFAULT, Theme::MONOCHROME, Theme::HIGH_CONTRAST];
+
+    /// The original purple/green palette.
+    pub const DEFAULT: Theme = Theme {
+        name: "default",
+        depth_bar: Color::Rgb(79, 74, 162),
+        depth_highlight: Color::Rgb(196, 192, 255),
+        depth_label: Color::White,
+        usd_balance: Color::LightGreen,
+        osmo_balance: Color::LightMagenta,
+        update_text: Color::Green,
+        command_text: Color::Yellow,
+        command_hint: Color::LightRed,
+        border: Color::Reset,
+    };
+
+    /// Uses only the terminal's own foreground color, for terminals or users that can't rely on color.
+    pub const MONOCHROME: Theme = Theme {
+        name: "monochrome",
+        depth_bar: Color::Reset,
+        depth_highlight: Color::Reset,
+        depth_label: Color::Reset,
+        usd_balance: Color::Reset,
+        osmo_balance: Color::Reset,
+        update_text: Color::Reset,
+        command_text: Color::Reset,
+        command_hint: Color::Reset,
+        border: Color::Reset,
+    };
+
+    /// Bright, strongly separated colors for low-vision users.
+    pub const HIGH_CONTRAST: Theme = Theme {
+        name: "high-contrast",
+        depth_bar: Color::LightYellow,
+        depth_highlight: Color::White,
+        depth_label: Color::White,
+        usd_balance: Color::LightCyan,
+        osmo_balance: Color::LightYellow,
+        update_text: Color::White,
+        command_text: Color::LightCyan,
+        command_hint: Color::LightRed,
+        border: Color::White,
+    };
+
+    /// Looks up a built-in theme by name (case insensitive).
+    pub fn by_name(name: &str) -> Option<Theme> {
+        Theme::BUILT_IN.into_iter().find(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_by_name() {
+        assert_eq!(Theme::by_name("High-Contrast"), Some(Theme::HIGH_CONTRAST));
+        assert_eq!(Theme::by_name("monochrome"), Some(Theme::MONOCHROME));
+        assert_eq!(Theme::by_name("neon"), None);
+    }
+}
@@ -1,11 +1,12 @@
 use crate::ui::app::{App, AppResult};
 use crate::ui::event::EventHandler;
 use crate::ui::ui;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use std::io;
 use std::panic;
 use tui::backend::Backend;
+use tui::layout::Rect;
 use tui::Terminal;
 
 /// Representation of a terminal user interface.
@@ -28,10 +29,11 @@ impl<B: Backend> Tui<B> {
 
     /// Initializes the terminal interface.
     ///
-    /// It enables the raw mode and sets terminal properties.
+    /// It enables the raw mode and sets terminal properties, including bracketed paste so a pasted list of
+    /// commands arrives as one event instead of being typed (and submitted) line by line.
     pub fn init(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
 
         // Define a custom panic hook to reset the terminal properties.
         // This way, you won't have your terminal messed up if an unexpected error happens.
@@ -55,13 +57,18 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 
+    /// Current size of the terminal.
+    pub fn size(&self) -> AppResult<Rect> {
+        Ok(self.terminal.size()?)
+    }
+
     /// Resets the terminal interface.
     ///
     /// This function is also used for the panic hook to revert
     /// the terminal properties if unexpected errors occur.
     fn reset() -> AppResult<()> {
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste)?;
         Ok(())
     }
 
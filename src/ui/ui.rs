@@ -1,17 +1,28 @@
 use crate::ui::app::App;
+use crate::ui::format::QuantityUnit;
+use crate::ui::chart::{highlight_style, price_label, BarHighlights, ChartScale, BAR_GAP, BAR_WIDTH};
 use tui::{
-    layout::{Constraint, Direction, Layout, Alignment},
-    widgets::{BarChart, Block, Borders, Paragraph, Sparkline, canvas::Canvas},
-    style::{Style, Color, Modifier},
-    text::{Spans, Span},
+    layout::{Constraint, Direction, Layout, Rect},
+    widgets::{BarChart, Block, Borders, Clear, Paragraph},
+    style::{Style, Modifier},
+    text::{Line, Span},
     backend::Backend,
     Frame,
 };
-use rand::{Rng, thread_rng};
-use crate::bank::currency::Currency;
+use std::time::Instant;
+
+/// Commands listed in the help overlay, one line each.
+const HELP_COMMANDS: &[&str] = &[
+    "[buy/sell] [osmo/usd] [limit/market/stop/stoplimit] [quantity] [price] [stop price] [--touch price] [--when ...] [--ioc/--fok/--post-only/--strict] [--display qty] [--gtd secs] [--max-slippage/--worst-price/--min-output/--max-cost ... --truncate]",
+    "preview, risk, twap, algo [status/pause/resume/cancel], review (Left/Right to step)",
+    "cancel [order id / bids/asks from to], amend [order id] [qty] [price], cross [buyer] [seller] [qty] [price], market [add] [pair], stats, diff [secs], tick [price], orders [id], trades [mine] [buys/sells] [last secs] [page n]",
+    "theme, numbers [style/prices n/units quantity|notional], chart [linear/log/decimals n], debug, compact, export [path] [anonymous], cast [path], challenge [minutes/stop], recover [discard], seed <path>, auction [start/run], help",
+    "Tab: liquidity bot, Esc or Ctrl-C: quit",
+];
 
 pub fn render<B: Backend>(app: &mut App, frame: &mut Frame<'_, B>) {
     let size = frame.size();
+    let theme = app.theme;
 
     let chunks = Layout::default()
     .direction(Direction::Vertical)
@@ -26,17 +37,16 @@ pub fn render<B: Backend>(app: &mut App, frame: &mut Frame<'_, B>) {
     )
     .split(size);
 
-    // Produce just the bar data values first
-    let bar_values: Vec<u64> = app.positions.iter()
-        .chain(std::iter::repeat(&0u64))  // Chain an infinite iterator of zeros to the end
-        .take(size.width as usize)        // Only take as many values as size.width
-        .cloned()                         // Clone each value from the iterator to get ownership
-        .collect();                       // Collect values into a new Vec<u64>
-
-    // Now, produce the labels
-    let step = 0.1;
-    let labels: Vec<String> = (0..size.width)
-        .map(|i| format!("{:.2}", step * i as f32))
+    // Produce just the bar data values first, read from the depth snapshot for the ticks that fit at this width
+    let snapshot = app.displayed_snapshot();
+    let amount = |tick_id: u64, quantity: u64| app.quantity_unit.amount(app.session_book.tick_converter(), quantity, tick_id);
+    let bar_values: Vec<u64> = app.chart_ticks.clone()
+        .map(|tick_id| app.chart_scale.bar_value(amount(tick_id, snapshot.quantity_at(tick_id))))
+        .collect();
+
+    // Now, produce the labels, one tick apart
+    let labels: Vec<String> = app.chart_ticks.clone()
+        .map(|tick_id| price_label(app.session_book.tick_converter(), tick_id, app.chart_price_decimals))
         .collect();
 
     // Combine the two to produce the sample data
@@ -45,61 +55,169 @@ pub fn render<B: Backend>(app: &mut App, frame: &mut Frame<'_, B>) {
     .zip(bar_values.iter().cloned())
     .collect();
 
+    // The y-axis tops out at the deepest level so bars neither vanish in thin books nor clip in deep ones
+    let axis_max = app.chart_scale.axis_max_by(&snapshot, amount);
+    let mut title = match app.chart_scale {
+        ChartScale::Linear => format!("Orderbook: {}", app.market_name()),
+        scale => format!("Orderbook: {} ({} scale)", app.market_name(), scale.name()),
+    };
+    if app.quantity_unit == QuantityUnit::Notional {
+        title.push_str(&format!(" - depth in {} notional", app.session_book.base_asset()));
+    }
+    if let Some((frame, (recorded_at, _))) = app.review_frame.and_then(|frame| Some((frame, app.depth_history.get(frame)?))) {
+        title.push_str(&format!(
+            " - review {}/{}, {}s ago (Left/Right to step, `review` for live)",
+            frame + 1,
+            app.depth_history.len(),
+            recorded_at.elapsed().as_secs(),
+        ));
+    }
+
     let barchart = BarChart::default()
-    .block(Block::default().title("Orderbook: OSMO/USD").borders(Borders::ALL))
-    .bar_width(3)
-    .bar_gap(1)
-    .bar_style(Style::default().fg(Color::Rgb(79,74,162)))
+    .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+    .bar_width(BAR_WIDTH)
+    .bar_gap(BAR_GAP)
+    .bar_style(Style::default().fg(theme.depth_bar))
     .value_style(Style::default().add_modifier(Modifier::DIM))
-    .label_style(Style::default().fg(Color::White))
+    .label_style(Style::default().fg(theme.depth_label))
     .data(&sample_data)
-    .max(10000);
+    .max(axis_max);
+
+    // The best bid, ask, spread and mid sit in a one line strip along the top of the orderbook panel
+    let orderbook_panel = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(chunks[0]);
+    let header = Paragraph::new(Line::from(Span::styled(app.quote_header(), Style::default().fg(theme.depth_label))));
+    frame.render_widget(header, orderbook_panel[0]);
 
     // Now, render your updated widget on top.
-    frame.render_widget(barchart, chunks[0]);
+    frame.render_widget(barchart, orderbook_panel[1]);
+
+    // Levels that just changed are drawn brighter over the chart, fading back over a few frames
+    if app.review_frame.is_none() {
+        let highlighted_bars: Vec<(usize, Style)> = app.level_highlights.range(app.chart_ticks.clone())
+            .map(|(tick_id, frames_left)| {
+                ((tick_id - app.chart_ticks.start) as usize, highlight_style(theme.depth_bar, theme.depth_highlight, *frames_left))
+            })
+            .collect();
+        frame.render_widget(BarHighlights { bars: &highlighted_bars }, orderbook_panel[1]);
+    }
 
-    // 2. Render user balances
-    let usd_style = Style::default().fg(Color::LightGreen).add_modifier(Modifier::BOLD);
-    let osmo_style = Style::default().fg(Color::LightMagenta).add_modifier(Modifier::BOLD);
+    // 2. Render user balances, in the assets of the market on screen
+    let (base_asset, quote_asset) = (*app.session_book.base_asset(), *app.session_book.quote_asset());
+    let base_style = Style::default().fg(theme.usd_balance).add_modifier(Modifier::BOLD);
+    let quote_style = Style::default().fg(theme.osmo_balance).add_modifier(Modifier::BOLD);
 
-    let osmo_balance_span = Span::styled(
-        format!("OSMO Balance: {}", app.user_account.borrow().balance(Currency::OSMO)),
-        osmo_style
+    let quote_balance_span = Span::styled(
+        format!("{} Balance: {}", quote_asset, app.number_style.format(app.user_account.borrow().balance(quote_asset))),
+        quote_style
     );
     
-    let usd_balance_span = Span::styled(
-        format!("USD Balance: {}", app.user_account.borrow().balance(Currency::USD)),
-        usd_style
+    let base_balance_span = Span::styled(
+        format!("{} Balance: {}", base_asset, app.number_style.format(app.user_account.borrow().balance(base_asset))),
+        base_style
     );
 
-    let balances_text = vec![Spans::from(usd_balance_span), Spans::from(osmo_balance_span)];
-    let block = Block::default().borders(Borders::ALL).title("User Balances");
+    let mut balances_text = vec![Line::from(base_balance_span), Line::from(quote_balance_span)];
+    if let Some(challenge) = &app.challenge {
+        let remaining = challenge.remaining(Instant::now()).as_secs();
+        balances_text.push(Line::from(format!(
+            "Challenge: {}m {:02}s left, PnL {} {}, max drawdown {} {}",
+            remaining / 60,
+            remaining % 60,
+            app.format_signed(challenge.pnl()),
+            base_asset,
+            app.number_style.format(challenge.max_drawdown()),
+            base_asset,
+        )));
+    }
+    let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border)).title("User Balances");
     let para = Paragraph::new(balances_text).block(block);
-    frame.render_widget(para, chunks[1]);
+
+    // The debug panel takes the right half of the balances row when it is switched on
+    if app.show_debug {
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[1]);
+        frame.render_widget(para, halves[0]);
+
+        let stats = app.session_book.memory_stats();
+        let numbers = app.number_style;
+        let debug_text = vec![
+            Line::from(format!(
+                "Levels: {}  Resting orders: {}  Pending: {}",
+                numbers.format(*stats.levels() as u64),
+                numbers.format(*stats.resting_orders() as u64),
+                numbers.format(*stats.pending_orders() as u64),
+            )),
+            Line::from(format!(
+                "Trades: {}  Memory: ~{} bytes",
+                numbers.format(*stats.trades() as u64),
+                numbers.format(*stats.approx_bytes() as u64),
+            )),
+        ];
+        let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border)).title("Debug");
+        frame.render_widget(Paragraph::new(debug_text).block(block), halves[1]);
+    } else {
+        frame.render_widget(para, chunks[1]);
+    }
 
     // 3. Render dynamic updates
     let update_text = app.updates.iter()
         .rev()
         .filter(|&message| !message.is_empty())
         .map(|message| {
-            Spans::from(Span::styled(
-                format!("{}", message),
-                Style::default().fg(Color::Green)
+            Line::from(Span::styled(
+                message.clone(),
+                Style::default().fg(theme.update_text)
             ))
         })
-        .collect::<Vec<Spans>>();
+        .collect::<Vec<Line>>();
 
-    let block = Block::default().borders(Borders::ALL).title("Updates");
+    let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border)).title("Updates");
     let para = Paragraph::new(update_text).block(block);
     frame.render_widget(para, chunks[2]);
 
 
-    // 4. Render command line
-    let input_text = Spans::from(Span::styled(
-        format!("{}", app.command_line),
-        Style::default().fg(Color::Yellow)
-    ));
-    let block = Block::default().borders(Borders::ALL).title("Command Line");
+    // 4. Render command line, followed by the validation hint for what has been typed so far
+    let mut input_spans = vec![Span::styled(
+        app.command_line.clone(),
+        Style::default().fg(theme.command_text)
+    )];
+    if let Some(hint) = &app.command_hint {
+        input_spans.push(Span::styled(
+            format!("  ({})", hint),
+            Style::default().fg(theme.command_hint).add_modifier(Modifier::ITALIC)
+        ));
+    }
+    let input_text = Line::from(input_spans);
+    let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border)).title("Command Line");
     let para = Paragraph::new(input_text).block(block);
     frame.render_widget(para, chunks[3]);
+
+    // 5. Help overlay on top of the depth chart
+    if app.show_help {
+        let mut help_text: Vec<Line> = HELP_COMMANDS.iter().map(|command| Line::from(*command)).collect();
+        help_text.push(Line::from(""));
+        if app.hotkeys.is_empty() {
+            help_text.push(Line::from("No hotkeys configured, bind one with `hotkey F5 <command>` in the config file."));
+        }
+        for hotkey in &app.hotkeys {
+            help_text.push(Line::from(format!("F{}: {}", hotkey.key(), hotkey.command())));
+        }
+
+        let area = centered(chunks[0], help_text.len() as u16 + 2);
+        let block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border)).title("Help (type `help` to close)");
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(help_text).block(block).style(Style::default().fg(theme.update_text)), area);
+    }
+}
+
+/// Area of the given height, and most of the width, centered in `area`.
+fn centered(area: Rect, height: u16) -> Rect {
+    let height = height.min(area.height);
+    let width = area.width.saturating_sub(area.width / 5);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
 }
@@ -0,0 +1,79 @@
+use orderbook::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// A fixed scenario run straight against the book, whose outcome is pinned to golden values. Anything that makes
+// matching depend on the platform (hash iteration order, float rounding in prices, pointer values) shows up as a
+// checksum mismatch. Only integer sampling is used so the random stream itself is the same everywhere.
+// If a change to matching is intentional, rerun with `-- --nocapture` and update the golden values.
+const SEED: u64 = 2248;
+const ORDERS: u64 = 3000;
+const ACCOUNTS: u64 = 5;
+
+const GOLDEN_CHECKSUM: u64 = 0xfaee30b9ed580eee;
+const GOLDEN_TRADES: usize = 2821;
+// (USD, OSMO) per account, by account id
+const GOLDEN_BALANCES: [(u64, u64); ACCOUNTS as usize] = [
+    (10008004, 97152),
+    (9963837, 101148),
+    (9994187, 96820),
+    (9938658, 103729),
+    (9990980, 99094),
+];
+
+fn run_scenario() -> (Orderbook, Vec<Rc<RefCell<Account>>>) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut book = Orderbook::new(0);
+    let accounts: Vec<_> = (0..ACCOUNTS)
+        .map(|account_id| {
+            let mut account = Account::new(account_id, AccountType::Individual);
+            account.deposit(Currency::USD, 10_000_000);
+            account.deposit(Currency::OSMO, 100_000);
+            Rc::new(RefCell::new(account))
+        })
+        .collect();
+
+    for order_id in 0..ORDERS {
+        let owner = accounts[rng.gen_range(0..ACCOUNTS) as usize].clone();
+        let direction = if rng.gen_bool(0.5) { OrderDirection::Bid } else { OrderDirection::Ask };
+        let order_type = if rng.gen_ratio(4, 5) { OrderType::Limit } else { OrderType::Market };
+        let mut order = Order::new(order_id, rng.gen_range(80..=120), 0, owner, order_type, direction, rng.gen_range(1..=200));
+
+        // Some orders wait for a trigger so activation order is covered too
+        match rng.gen_range(0..20) {
+            0 => order.set_trigger_tick(Some(rng.gen_range(80..=120))),
+            1 => {
+                order.set_trigger_tick(Some(rng.gen_range(80..=120)));
+                order.set_trigger_type(TriggerType::IfTouched);
+            }
+            _ => {}
+        }
+
+        // Rejections are part of the scenario as well
+        let _ = book.handle_order(&mut order);
+    }
+
+    (book, accounts)
+}
+
+#[test]
+fn determinism_scenario_matches_golden_values() {
+    let (book, accounts) = run_scenario();
+    let balances: Vec<(u64, u64)> = accounts.iter()
+        .map(|account| (account.borrow().balance(Currency::USD), account.borrow().balance(Currency::OSMO)))
+        .collect();
+    println!("checksum {:#x}, trades {}, balances {:?}", book.checksum(), book.trades().len(), balances);
+
+    assert_eq!(book.checksum(), GOLDEN_CHECKSUM);
+    assert_eq!(book.trades().len(), GOLDEN_TRADES);
+    assert_eq!(balances, GOLDEN_BALANCES);
+}
+
+#[test]
+fn determinism_scenario_is_repeatable_in_process() {
+    let (first, _) = run_scenario();
+    let (second, _) = run_scenario();
+    assert_eq!(first.checksum(), second.checksum());
+}
@@ -0,0 +1,182 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use orderbook::bank::currency::Currency;
+use orderbook::book::order::OrderDirection;
+use orderbook::book::orderbook::Orderbook;
+use orderbook::ui::app::App;
+use orderbook::ui::handler::{handle_command, handle_key_events};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+// Fixed seeds keep failures reproducible; bump the count locally to fuzz harder
+const SEEDS: u64 = 8;
+const KEY_EVENTS_PER_SEED: usize = 2000;
+const COMMANDS_PER_SEED: usize = 1000;
+
+// Tokens the command parser knows about, mixed with values that are malformed, extreme or out of place
+const TOKENS: &[&str] = &[
+    "buy", "sell", "BUY", "osmo", "usd", "OSMO", "limit", "market", "stop", "stoplimit", "preview", "risk", "slippage", "protection",
+    "duplicates", "diff", "review", "theme", "numbers", "prices", "auto", "stats", "twap", "algo", "status", "pause", "resume", "cancel", "amend", "bids", "asks", "cross", "market", "add", "OSMO/USD", "USD/OSMO", "tick", "trades", "debug", "compact", "chart", "decimals", "log", "linear", "mine", "buys", "sells", "last", "page",
+    "reject", "truncate", "flag", "off", "compact", "plain", "high-contrast", "--max-slippage", "--truncate",
+    "--aggressive", "--ioc", "--fok", "--post-only", "--strict", "--display", "--stop", "--touch", "--when", "spread", "bid", "askdepth", "above", "below", "0", "1", "5", "10", "0.4", "1.2", "3.9", "-1", "50bps", "#0", "1e30", "NaN", "inf",
+    "18446744073709551615", "99999999999", "", "🦀",
+];
+
+fn funded_app() -> App {
+    let app = App::new();
+    app.user_account.borrow_mut().deposit(Currency::OSMO, 100000);
+    app.user_account.borrow_mut().deposit(Currency::USD, 500000);
+    app
+}
+
+const QUANTITIES: &[&str] = &["0", "1", "7", "100", "2500", "99999999999", "18446744073709551615", "-3", "x"];
+const PRICES: &[&str] = &["0", "0.1", "0.4", "1", "1.2", "2.5", "3.9", "1e30", "-1", "NaN"];
+
+// Half the commands are well-formed orders with random values so they get past the parser and reach the book,
+// the other half are random token soup
+fn random_command(rng: &mut StdRng) -> String {
+    if rng.gen_bool(0.5) {
+        let mut tokens = vec![
+            *["buy", "sell"].choose(rng).unwrap(),
+            *["osmo", "usd"].choose(rng).unwrap(),
+            *["limit", "market"].choose(rng).unwrap(),
+            *QUANTITIES.choose(rng).unwrap(),
+            *PRICES.choose(rng).unwrap(),
+        ];
+        if rng.gen_bool(0.2) {
+            let flag = *["--aggressive", "--ioc", "--fok", "--post-only", "--strict", "--truncate", "--max-slippage", "--stop", "--touch", "--display", "--when"].choose(rng).unwrap();
+            tokens.push(flag);
+            if flag == "--when" {
+                tokens.push(*["bid", "ask", "spread", "biddepth", "askdepth"].choose(rng).unwrap());
+                tokens.push(*["above", "below"].choose(rng).unwrap());
+            }
+            if !["--aggressive", "--ioc", "--fok", "--post-only", "--strict", "--truncate"].contains(&flag) {
+                tokens.push(*PRICES.choose(rng).unwrap());
+            }
+        }
+        return tokens.join(" ");
+    }
+
+    let length = rng.gen_range(0..8);
+    (0..length).map(|_| *TOKENS.choose(rng).unwrap()).collect::<Vec<_>>().join(" ")
+}
+
+fn random_key(rng: &mut StdRng) -> KeyEvent {
+    let code = match rng.gen_range(0..20) {
+        0 => KeyCode::Backspace,
+        1 => KeyCode::Left,
+        2 => KeyCode::Delete,
+        3 => KeyCode::Char(' '),
+        _ => KeyCode::Char(*b"abcdeiklmnoprstuy0123456789.-#".choose(rng).unwrap() as char),
+    };
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+// OSMO and USD held by the user plus what the user's resting orders have escrowed in every market's book
+fn user_holdings(app: &App) -> (u64, u64) {
+    let account = app.user_account.borrow();
+    let mut osmo = account.balance(Currency::OSMO);
+    let mut usd = account.balance(Currency::USD);
+    let parked = app.exchange.markets().into_iter().filter_map(|(book_id, _)| app.exchange.book(book_id));
+    for book in std::iter::once(&app.session_book).chain(parked) {
+        for tick in book.ticks().values() {
+            for order in tick.orders() {
+                // Bids escrow the asset the book is priced in, asks the asset it trades
+                let (asset, amount) = match order.order_direction() {
+                    OrderDirection::Bid => (*book.base_asset(), order.worth(*order.quantity())),
+                    OrderDirection::Ask => (*book.quote_asset(), *order.quantity()),
+                };
+                match asset {
+                    Currency::OSMO => osmo += amount,
+                    Currency::USD => usd += amount,
+                }
+            }
+        }
+    }
+    (osmo, usd)
+}
+
+fn assert_book_invariants(book: &Orderbook, context: &str) {
+    let mut best_bid = None;
+    let mut best_ask = None;
+
+    for (tick_id, tick) in book.ticks() {
+        let resting: u64 = tick.orders().iter().map(|order| *order.quantity()).sum();
+        assert_eq!(*tick.total_liquidity(), resting, "tick {} total drifted after {:?}", tick_id, context);
+        assert!(tick.orders().iter().all(|order| order.tick_id() == tick_id), "misplaced order after {:?}", context);
+        assert!(tick.orders().iter().all(|order| *order.quantity() > 0), "empty order left resting after {:?}", context);
+        assert!(
+            tick.orders().iter().all(|order| (1..=*order.quantity()).contains(&order.visible_quantity())),
+            "resting order shows nothing or more than it holds after {:?}", context,
+        );
+
+        let direction = tick.orders().front().map(|order| *order.order_direction());
+        assert!(
+            tick.orders().iter().all(|order| Some(*order.order_direction()) == direction),
+            "mixed sides on tick {} after {:?}", tick_id, context,
+        );
+        match direction {
+            Some(OrderDirection::Bid) => best_bid = best_bid.max(Some(*tick_id)),
+            Some(OrderDirection::Ask) => best_ask = best_ask.min(Some(*tick_id)).or(Some(*tick_id)),
+            None => {}
+        }
+    }
+
+    assert_eq!((book.best_bid(), book.best_ask()), (best_bid, best_ask), "best prices drifted after {:?}", context);
+    if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+        assert!(bid < ask, "book crossed or locked ({} over {}) after {:?}", bid, ask, context);
+    }
+    if let Err(e) = book.verify_escrow() {
+        panic!("{} after {:?}", e, context);
+    }
+}
+
+#[test]
+fn fuzz_random_commands() {
+    for seed in 0..SEEDS {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut app = funded_app();
+        let holdings = user_holdings(&app);
+        let mut placed_orders = 0;
+
+        for _ in 0..COMMANDS_PER_SEED {
+            let command = random_command(&mut rng);
+            app.command_line = command.clone();
+            handle_command(&mut app).unwrap();
+            app.tick();
+
+            // The user only ever trades with themselves, so nothing is created or destroyed
+            assert_book_invariants(&app.session_book, &command);
+            assert_eq!(user_holdings(&app), holdings, "funds leaked after {:?} (seed {})", command, seed);
+            if app.updates.last().is_some_and(|update| update.contains("successfully placed")) {
+                placed_orders += 1;
+            }
+        }
+
+        // Guards against the generator drifting to the point where nothing reaches the book
+        assert!(placed_orders > 0, "no orders were placed with seed {}", seed);
+    }
+}
+
+#[test]
+fn fuzz_random_key_events() {
+    for seed in 0..SEEDS {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut app = funded_app();
+
+        for _ in 0..KEY_EVENTS_PER_SEED {
+            let key = random_key(&mut rng);
+            handle_key_events(key, &mut app).unwrap();
+
+            // The hint is recomputed on every keystroke, so a blank line never keeps a stale one
+            if app.command_line.trim().is_empty() {
+                assert_eq!(app.command_hint, None);
+            }
+        }
+
+        // Submitting whatever was typed must be handled like any other command
+        let context = app.command_line.clone();
+        handle_key_events(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE), &mut app).unwrap();
+        assert!(app.command_line.is_empty());
+        assert_book_invariants(&app.session_book, &context);
+    }
+}
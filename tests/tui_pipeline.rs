@@ -0,0 +1,1115 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use orderbook::bank::currency::Currency;
+use orderbook::book::order::OrderDirection;
+use orderbook::config::{AutosaveConfig, Config};
+use orderbook::storage::memory::MemoryStorage;
+use orderbook::testkit::{limit_order, market_order, AccountBuilder};
+use orderbook::ui::app::{App, TRADES_IN_MEMORY};
+use orderbook::ui::chart::HIGHLIGHT_FRAMES;
+use orderbook::ui::handler::{handle_key_events, handle_paste_event};
+use orderbook::ui::script::run_script;
+use std::time::{Duration, Instant};
+
+const USER_OSMO: u64 = 1000;
+const USER_USD: u64 = 5000;
+
+// App with the user funded the same way main.rs funds it, scaled down
+fn funded_app() -> App {
+    let app = App::new();
+    app.user_account.borrow_mut().deposit(Currency::OSMO, USER_OSMO);
+    app.user_account.borrow_mut().deposit(Currency::USD, USER_USD);
+    app
+}
+
+fn press(app: &mut App, code: KeyCode) {
+    handle_key_events(KeyEvent::new(code, KeyModifiers::NONE), app).unwrap();
+}
+
+// Types the command one key at a time and submits it with Enter, exactly like a user would
+fn submit(app: &mut App, command: &str) {
+    for c in command.chars() {
+        press(app, KeyCode::Char(c));
+    }
+    press(app, KeyCode::Enter);
+}
+
+fn last_update(app: &App) -> &str {
+    app.updates.last().unwrap()
+}
+
+#[test]
+fn test_limit_buy_osmo_rests_bid() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+
+    // Price 0.4 maps to tick 4 and buying OSMO is a bid escrowing 10 * 0.4 USD
+    let tick = app.session_book.ticks().get(&4).unwrap();
+    assert_eq!(*tick.orders().front().unwrap().order_direction(), OrderDirection::Bid);
+    assert_eq!(*tick.total_liquidity(), 10);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 4);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO);
+
+    // The update is reported and the depth chart picks up the new level
+    assert!(last_update(&app).starts_with("Limit order successfully placed for 10 OSMO"));
+    assert_eq!(app.positions[&4], 10);
+    assert!(app.command_line.is_empty());
+}
+
+#[test]
+fn test_decimal_prices_are_parsed_exactly() {
+    let mut app = funded_app();
+
+    // 3.3 * 10.0 is 32.999... in f64, which used to truncate to the 3.2 tick
+    submit(&mut app, "buy osmo limit 10 3.3");
+    assert!(app.session_book.ticks().contains_key(&33));
+    assert!(last_update(&app).ends_with("at price 3.3 USD."));
+
+    // Prices between ticks are rejected instead of being rounded onto one
+    submit(&mut app, "buy osmo limit 10 0.95");
+    assert!(last_update(&app).contains("Failed to parse price"));
+    assert_eq!(app.session_book.ticks().len(), 1);
+}
+
+#[test]
+fn test_buy_usd_is_inverted_to_ask() {
+    let mut app = funded_app();
+    submit(&mut app, "buy usd limit 10 0.5");
+
+    // Buying USD means selling OSMO, so the order rests as an ask escrowing OSMO
+    let tick = app.session_book.ticks().get(&5).unwrap();
+    assert_eq!(*tick.orders().front().unwrap().order_direction(), OrderDirection::Ask);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 10);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD);
+
+    // The confirmation quotes the price on the inverted pair: 1 / 0.5 OSMO per USD
+    assert!(last_update(&app).ends_with("at price 2 OSMO."));
+}
+
+#[test]
+fn test_sell_usd_is_inverted_to_bid() {
+    let mut app = funded_app();
+    submit(&mut app, "sell usd limit 10 0.5");
+
+    let tick = app.session_book.ticks().get(&5).unwrap();
+    assert_eq!(*tick.orders().front().unwrap().order_direction(), OrderDirection::Bid);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 5);
+}
+
+#[test]
+fn test_market_buy_fills_resting_asks() {
+    let mut app = funded_app();
+
+    // Another trader offers 100 OSMO at 0.6 USD and 100 OSMO at 0.8 USD
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 6, 100)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 8, 100)).unwrap();
+
+    submit(&mut app, "buy osmo market 150");
+
+    // All of tick 6 and half of tick 8 are bought
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO + 150);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - (60 + 40));
+    assert!(!app.session_book.ticks().contains_key(&6));
+    assert_eq!(*app.session_book.ticks().get(&8).unwrap().total_liquidity(), 50);
+    assert!(last_update(&app).starts_with("Market order for 150 OSMO successfully placed"));
+}
+
+#[test]
+fn test_invalid_commands_are_rejected_without_touching_the_book() {
+    let mut app = funded_app();
+
+    submit(&mut app, "buy osmo limit 10");
+    assert!(last_update(&app).contains("Missing price argument for limit order"));
+
+    submit(&mut app, "buy btc market 10");
+    assert!(last_update(&app).contains("Unsupported command format"));
+
+    submit(&mut app, "buy osmo market ten");
+    assert!(last_update(&app).contains("Failed to parse quantity"));
+
+    assert!(app.session_book.ticks().is_empty());
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO);
+}
+
+#[test]
+fn test_backspace_edits_the_command_line() {
+    let mut app = funded_app();
+
+    // Typo in the quantity is corrected before submitting
+    for c in "sell osmo limit 19".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(app.command_line, "sell osmo limit 1");
+    submit(&mut app, "0 0.9");
+
+    assert_eq!(*app.session_book.ticks().get(&9).unwrap().total_liquidity(), 10);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 10);
+}
+
+#[test]
+fn test_command_hint_updates_while_typing() {
+    let mut app = funded_app();
+    let type_keys = |app: &mut App, keys: &str| {
+        for c in keys.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    };
+
+    type_keys(&mut app, "buy osmo limit 10");
+    assert_eq!(app.command_hint.as_deref(), Some("Missing price argument for limit order"));
+
+    // A complete, affordable order has nothing to report
+    type_keys(&mut app, " 0.4");
+    assert_eq!(app.command_hint, None);
+
+    // 10000 OSMO at 4.0 needs 40000 USD, far more than the user holds
+    for _ in 0..4 {
+        press(&mut app, KeyCode::Backspace);
+    }
+    type_keys(&mut app, "000 4");
+    assert_eq!(app.command_hint.as_deref(), Some("Insufficient USD for this size: needs 40,000, have 5,000"));
+
+    // Submitting clears the hint along with the command line
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.command_hint, None);
+}
+
+#[test]
+fn test_command_hint_checks_the_assets_of_the_market_on_screen() {
+    let mut app = funded_app();
+    submit(&mut app, "market add usd/osmo");
+    submit(&mut app, "market USD/OSMO");
+
+    // Buying 3000 USD at 0.5 OSMO each escrows 1500 OSMO on this book, not USD
+    for c in "buy usd limit 3000 0.5".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    assert_eq!(app.command_hint.as_deref(), Some("Insufficient OSMO for this size: needs 1,500, have 1,000"));
+}
+
+#[test]
+fn test_command_hint_leaves_other_commands_alone() {
+    let mut app = funded_app();
+
+    // Keywords the command router handles aren't parsed as orders, whatever their case
+    for c in "Auction start".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    assert_eq!(app.command_hint, None);
+    submit(&mut app, "auction run");
+    assert!(!last_update(&app).starts_with("Invalid command format"));
+}
+
+#[test]
+fn test_twap_releases_first_slice_and_can_be_cancelled() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 6, 1000)).unwrap();
+
+    // The first of four 25 OSMO slices is bought as soon as the algo starts
+    submit(&mut app, "twap buy osmo 100 4 60");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO + 25);
+    assert!(last_update(&app).starts_with("TWAP #0 slice 1/4: filled 25 of 25 OSMO"));
+    assert_eq!(app.positions[&6], 975);
+
+    // Cancelling drops the remaining slices, so later ticks trade nothing
+    submit(&mut app, "algo cancel 0");
+    assert_eq!(last_update(&app), "TWAP #0 cancelled, unsent slices dropped.");
+    app.tick();
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO + 25);
+}
+
+#[test]
+fn test_config_seeds_accounts_and_bot() {
+    let mut app = App::new();
+    let config = Config::parse("account 0 OSMO=20 USD=300\naccount 4 OSMO=1000000000 USD=1000000000\nbot 4").unwrap();
+    app.apply_config(&config);
+
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), 20);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), 300);
+
+    // The liquidity bot trades from the configured account instead of minting its own funds
+    let bot_account = app.bot_account.clone().unwrap();
+    assert_eq!(*bot_account.borrow().account_id(), 4);
+    press(&mut app, KeyCode::Tab);
+    assert!(bot_account.borrow().balance(Currency::USD) < 1000000000);
+    assert!(!app.session_book.ticks().is_empty());
+}
+
+#[test]
+fn test_ctrl_c_quits() {
+    let mut app = funded_app();
+    handle_key_events(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL), &mut app).unwrap();
+
+    // The key is not typed into the command line
+    assert!(!app.running);
+    assert!(app.command_line.is_empty());
+}
+
+#[test]
+fn test_tick_command_lists_the_queue() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+    submit(&mut app, "buy osmo limit 5 0.4");
+
+    // The header ends up on top of the panel, followed by the queue from the head down
+    submit(&mut app, "tick 0.4");
+    let len = app.updates.len();
+    assert_eq!(app.updates[len - 1], "Queue at 0.4: 2 orders, 15 OSMO in priority order:");
+    assert!(app.updates[len - 2].starts_with("  #1 order 1 from account 0: Bid 10 OSMO"));
+    assert!(app.updates[len - 3].starts_with("  #2 order 2 from account 0: Bid 5 OSMO"));
+
+    submit(&mut app, "tick 0.5");
+    assert_eq!(last_update(&app), "No orders resting at 0.5.");
+}
+
+#[test]
+fn test_orders_command_lists_open_orders_and_fills() {
+    let mut app = funded_app();
+    submit(&mut app, "orders");
+    assert_eq!(last_update(&app), "You have no orders resting on the book.");
+
+    submit(&mut app, "buy osmo limit 10 0.4");
+    submit(&mut app, "sell osmo limit 20 1.5");
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 100)]).build();
+    app.session_book.handle_order(&mut market_order(&maker, OrderDirection::Ask, 4)).unwrap();
+
+    submit(&mut app, "orders");
+    let len = app.updates.len();
+    assert_eq!(app.updates[len - 1], "Your 2 open order(s), lowest id first:");
+    assert_eq!(app.updates[len - 2], "  order 1: Bid at 0.4, 4 OSMO filled, 6 OSMO left");
+    assert_eq!(app.updates[len - 3], "  order 2: Ask at 1.5, 0 OSMO filled, 20 OSMO left");
+
+    submit(&mut app, "orders 1");
+    assert_eq!(last_update(&app), "Order 1: Bid at 0.4, 4 OSMO filled, 6 OSMO left.");
+    submit(&mut app, "cancel 1");
+    submit(&mut app, "orders 1");
+    assert_eq!(last_update(&app), "Order 1 isn't resting on the book: it filled, was cancelled or is waiting on a trigger.");
+    submit(&mut app, "orders one");
+    assert!(last_update(&app).contains("Usage: orders [order id]"));
+}
+
+#[test]
+fn test_auction_collects_orders_and_crosses_them_at_one_price() {
+    let mut app = funded_app();
+    submit(&mut app, "auction run");
+    assert!(last_update(&app).contains("No auction is collecting orders"));
+
+    submit(&mut app, "auction start");
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 100)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 30)).unwrap();
+    submit(&mut app, "buy osmo limit 20 1.5");
+    assert_eq!(last_update(&app), "Limit Bid for 20 OSMO at 1.5 collected for the auction, 2 order(s) wait for `auction run`.");
+    submit(&mut app, "buy osmo market 5");
+    assert!(last_update(&app).starts_with("Error placing order"));
+    submit(&mut app, "auction");
+    assert_eq!(last_update(&app), "Auction collecting orders: 2 order(s) wait for `auction run`.");
+
+    // 20 matches anywhere from 1.2 to 1.5, so the bid pays the lowest of those rather than its limit
+    submit(&mut app, "auction run");
+    let len = app.updates.len();
+    assert!(app.updates[len - 2].starts_with("Executed: bought 20 OSMO at 1.2 from account 9"));
+    assert_eq!(app.updates[len - 1], "Auction crossed 20 OSMO at 1.2 in 1 trade(s), 1 collected order(s) left resting on the book.");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO + 20);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 24);
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(12, 10)]);
+}
+
+#[test]
+fn test_stop_order_triggers_on_trade() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 50)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 15, 50)).unwrap();
+
+    submit(&mut app, "buy osmo market 30 --stop 1.5");
+    assert_eq!(last_update(&app), "Stop Market Bid for 30 OSMO armed, triggers when OSMO trades at 1.5.");
+    assert_eq!(app.session_book.pending_stops(), (1, 0));
+
+    // Clearing the level at 1.0 trades into 1.5, which fires the stop
+    submit(&mut app, "buy osmo market 60");
+    assert_eq!(last_update(&app), "1 stop order(s) triggered, 0 rejected when they ran.");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO + 90);
+    assert_eq!(app.positions[&15], 10);
+}
+
+#[test]
+fn test_stop_and_stop_limit_order_types() {
+    let mut app = funded_app();
+
+    // The shorthands arm the same stops as the --stop flag
+    submit(&mut app, "sell osmo stop 30 0.8");
+    assert_eq!(last_update(&app), "Stop Market Ask for 30 OSMO armed, triggers when OSMO trades at 0.8.");
+    submit(&mut app, "buy osmo stoplimit 20 1.6 1.5");
+    assert_eq!(last_update(&app), "Stop Limit Bid for 20 OSMO armed, triggers when OSMO trades at 1.5.");
+    assert_eq!(app.session_book.pending_stops(), (1, 1));
+
+    submit(&mut app, "buy osmo stoplimit 20 1.6");
+    assert!(last_update(&app).ends_with("Missing stop price"));
+    assert_eq!(app.session_book.pending_stops(), (1, 1));
+}
+
+#[test]
+fn test_conditional_order_waits_for_spread() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 50)).unwrap();
+    submit(&mut app, "buy osmo limit 20 0.8");
+
+    submit(&mut app, "buy osmo limit 30 0.9 --when spread above 3");
+    assert_eq!(last_update(&app), "Conditional Limit Bid for 30 OSMO waits for spread above 3 ticks.");
+    assert_eq!(app.session_book.pending_conditionals(), 1);
+
+    // Lifting the 1.0 ask leaves no spread at all, a new ask at 1.4 opens it to 6 ticks
+    submit(&mut app, "buy osmo market 50");
+    assert_eq!(app.session_book.pending_conditionals(), 1);
+    submit(&mut app, "sell osmo limit 10 1.4");
+    assert_eq!(last_update(&app), "1 conditional order(s) activated, 0 rejected when they ran.");
+    assert_eq!(app.positions[&9], 30);
+}
+
+#[test]
+fn test_trades_command_lists_fills() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 50)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 50)).unwrap();
+
+    submit(&mut app, "buy osmo market 70");
+    submit(&mut app, "trades mine buys");
+    let len = app.updates.len();
+    assert_eq!(app.updates[len - 1], "Trades page 1 of 1 (2 matching), newest first:");
+    assert!(app.updates[len - 2].starts_with("  #1 Bid 20 OSMO at 1.2, taker account 0 / maker account 9"));
+    assert!(app.updates[len - 3].starts_with("  #0 Bid 50 OSMO at 1, taker account 0 / maker account 9"));
+
+    submit(&mut app, "trades sells");
+    assert_eq!(last_update(&app), "No trades to show (0 matching).");
+}
+
+#[test]
+fn test_insufficient_funds_reports_affordable_size() {
+    let mut app = funded_app();
+
+    // A bid at 2.5 (tick 25) escrows 2.5 USD per OSMO, so 5,000 USD covers at most 2,000
+    submit(&mut app, "buy osmo limit 3000 2.5");
+    let len = app.updates.len();
+    assert_eq!(app.updates[len - 1], "Error placing order [300 insufficient_funds]: Insufficient funds, 7,500 USD needed but 5,000 available");
+    assert_eq!(app.updates[len - 2], "You can afford at most 2,000 OSMO at price 2.5.");
+    assert!(app.session_book.ticks().get(&25).is_none());
+
+    submit(&mut app, "buy osmo limit 2000 2.5");
+    assert_eq!(*app.session_book.ticks().get(&25).unwrap().total_liquidity(), 2000);
+}
+
+#[test]
+fn test_depth_chart_follows_the_book_on_tick() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+
+    // Orders placed outside the key handler show up on the chart at the next tick
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 40)).unwrap();
+    assert!(app.positions.is_empty());
+    app.tick();
+    assert_eq!(app.positions[&12], 40);
+    assert_eq!(app.positions_version, Some(*app.session_book.version()));
+
+    // A whole bot burst is picked up once the key event is done
+    press(&mut app, KeyCode::Tab);
+    assert_eq!(app.positions_version, Some(*app.session_book.version()));
+    assert!(app.positions.values().sum::<u64>() > 40);
+}
+
+#[test]
+fn test_quote_header_shows_top_of_book() {
+    let mut app = funded_app();
+    assert_eq!(app.quote_header(), "Bid -  Ask -  Spread -  Mid -");
+
+    submit(&mut app, "buy osmo limit 10 1.1");
+    assert_eq!(app.quote_header(), "Bid 1.1  Ask -  Spread -  Mid -");
+    submit(&mut app, "sell osmo limit 10 1.2");
+    assert_eq!(app.quote_header(), "Bid 1.1  Ask 1.2  Spread 1 tick  Mid 1.15");
+    submit(&mut app, "sell osmo limit 10 1.3");
+    submit(&mut app, "buy osmo market 10");
+    assert_eq!(app.quote_header(), "Bid 1.1  Ask 1.3  Spread 2 ticks  Mid 1.2");
+}
+
+#[test]
+fn test_resize_keeps_the_touch_in_view() {
+    let mut app = funded_app();
+    app.resize(402);
+    submit(&mut app, "buy osmo limit 10 2");
+    submit(&mut app, "sell osmo limit 10 2.2");
+    assert_eq!(app.chart_ticks, 0..100);
+
+    // At 20 bars the ask at tick 22 would be cut off, so the window centers on the mid at tick 21
+    app.resize(82);
+    assert_eq!(app.chart_ticks, 11..31);
+}
+
+#[test]
+fn test_depth_chart_windows_sparse_ticks_near_a_billion() {
+    let mut app = funded_app();
+    app.resize(82);
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000), (Currency::USD, u64::MAX / 2)]).build();
+    for (direction, tick_id) in [(OrderDirection::Bid, 3), (OrderDirection::Bid, 999_999_990), (OrderDirection::Ask, 999_999_994), (OrderDirection::Ask, 1_000_000_000)] {
+        app.session_book.handle_order(&mut limit_order(&maker, direction, tick_id, 10)).unwrap();
+    }
+
+    // Only the populated levels are held for the chart, and its window centers on the mid far up the tick range
+    app.tick();
+    assert_eq!(app.positions.len(), 4);
+    assert_eq!(app.positions[&1_000_000_000], 10);
+    assert_eq!(app.chart_ticks, 999_999_982..1_000_000_002);
+    submit(&mut app, "sell osmo limit 5 99999999.2");
+    assert_eq!(app.positions[&999_999_992], 5);
+}
+
+#[test]
+fn test_if_touched_order_triggers_on_dip() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000), (Currency::USD, 100000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 10, 50)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 50)).unwrap();
+
+    submit(&mut app, "buy osmo limit 20 1.2 --touch 1");
+    assert_eq!(last_update(&app), "If-touched Limit Bid for 20 OSMO armed, triggers when OSMO trades at 1.");
+    assert_eq!(app.session_book.pending_touches(), (1, 0));
+
+    // Selling into the bid at 1.0 touches the trigger, and the buy lifts the ask at 1.2
+    submit(&mut app, "sell osmo market 5");
+    assert_eq!(last_update(&app), "1 if-touched order(s) triggered, 0 rejected when they ran.");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 5 + 20);
+    assert_eq!(app.positions[&12], 30);
+}
+
+#[test]
+fn test_export_writes_execution_reports() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 50)).unwrap();
+    submit(&mut app, "buy osmo market 20");
+
+    let path = std::env::temp_dir().join(format!("orderbook-blotter-{}.fix", std::process::id()));
+    submit(&mut app, &format!("export {}", path.display()));
+    assert_eq!(last_update(&app), format!("Exported 1 trade(s) as execution reports to {}.", path.display()));
+
+    let blotter = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let reports: Vec<&str> = blotter.lines().collect();
+    assert_eq!(reports.len(), 2);
+    assert!(reports[0].contains("|17=0-T|37=1|1=0|55=OSMO/USD|54=1|150=F|32=20|31=1|851=2"));
+    assert!(reports[1].contains("|1=9|55=OSMO/USD|54=2|"));
+}
+
+#[test]
+fn test_anonymous_export_replaces_account_ids() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 50)).unwrap();
+    submit(&mut app, "buy osmo market 20");
+    submit(&mut app, "buy osmo market 5");
+
+    let path = std::env::temp_dir().join(format!("orderbook-anonymous-{}.fix", std::process::id()));
+    submit(&mut app, &format!("export {} anonymous", path.display()));
+    assert_eq!(
+        last_update(&app),
+        format!("Exported 2 trade(s) as execution reports to {}, with 2 account(s) replaced by pseudonyms (you are P1).", path.display()),
+    );
+
+    let blotter = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let accounts: Vec<&str> = blotter.lines()
+        .map(|report| report.split('|').find_map(|field| field.strip_prefix("1=")).unwrap())
+        .collect();
+    assert_eq!(accounts, ["P1", "P2", "P1", "P2"]);
+
+    submit(&mut app, &format!("export {} secret", path.display()));
+    assert!(last_update(&app).contains("Usage: export [path] [anonymous]"));
+}
+
+#[test]
+fn test_good_till_date_orders_expire_on_tick() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4 --gtd 0");
+    assert_eq!(last_update(&app), "Limit order successfully placed for 10 OSMO at price 0.4 USD, good till 0s from now.");
+    submit(&mut app, "buy osmo limit 10 0.3 --gtd 3600");
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 4 - 3);
+
+    app.tick();
+    assert_eq!(last_update(&app), "Order 1 expired: the unfilled 10 OSMO of your Bid was cancelled and refunded.");
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(3, 10)]);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 3);
+
+    submit(&mut app, "buy osmo limit 5 0.3 --gtd soon");
+    assert!(last_update(&app).contains("Failed to parse good-till-date seconds"));
+}
+
+#[test]
+fn test_seed_rests_snapshot_levels_for_the_liquidity_account() {
+    let mut app = funded_app();
+    app.apply_config(&Config::parse("pricing 0.05").unwrap());
+    let path = std::env::temp_dir().join(format!("orderbook-l2-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"bids": [["1.20", "30"], ["1.15", "12.5"]], "asks": [["1.25", "0.2"], ["1.30", "40"]]}"#).unwrap();
+
+    submit(&mut app, &format!("seed {}", path.display()));
+    assert_eq!(
+        last_update(&app),
+        format!("Seeded 3 order(s) from {} for account 1: 2 bid and 1 ask level(s), skipped 1 level(s) under 1 OSMO.", path.display()),
+    );
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(24, 30), (23, 12)]);
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(26, 40)]);
+
+    // A snapshot crossing what already rests on the book is refused as a whole
+    std::fs::write(&path, r#"{"asks": [["1.20", "5"]]}"#).unwrap();
+    submit(&mut app, &format!("seed {}", path.display()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(last_update(&app), format!("Error seeding the book from {}: a bid at 1.2 would cross an ask at 1.2", path.display()));
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(26, 40)]);
+}
+
+#[test]
+fn test_configured_tick_size_prices_commands_and_labels() {
+    let mut app = funded_app();
+    app.apply_config(&Config::parse("pricing 0.05 0.05 10").unwrap());
+
+    submit(&mut app, "buy osmo limit 10 0.62");
+    assert_eq!(last_update(&app), "Command rejected [101 invalid_command]: Failed to parse price, expected a multiple of 0.05");
+    submit(&mut app, "buy osmo limit 20 0.65");
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(13, 20)]);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 13);
+    submit(&mut app, "buy osmo limit 10 10.05");
+    assert!(last_update(&app).contains("outside the book's range"), "{}", last_update(&app));
+
+    submit(&mut app, "sell osmo limit 5 0.7");
+    assert_eq!(app.quote_header(), "Bid 0.65  Ask 0.7  Spread 1 tick  Mid 0.675");
+    submit(&mut app, "tick 0.65");
+    assert!(app.updates.iter().any(|update| update.contains("0.65")));
+}
+
+#[test]
+fn test_hotkeys_submit_configured_orders() {
+    let mut app = funded_app();
+    app.apply_config(&Config::parse("account 0\nhotkey F5 buy osmo market 10\nhotkey F7 buy osmo limit 20 @bid").unwrap());
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000), (Currency::USD, 100000)]).build();
+
+    // Joining the bid needs a bid to join
+    press(&mut app, KeyCode::F(7));
+    assert!(last_update(&app).ends_with("F7: there is no best bid to price `buy osmo limit 20 @bid` at"));
+
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 8, 50)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 50)).unwrap();
+    for c in "sell".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::F(7));
+    press(&mut app, KeyCode::F(5));
+    assert_eq!(*app.session_book.ticks().get(&8).unwrap().total_liquidity(), 70);
+    assert_eq!(*app.session_book.ticks().get(&12).unwrap().total_liquidity(), 40);
+    assert!(app.updates.iter().any(|update| update == "F7: buy osmo limit 20 0.8"));
+
+    // The half typed command is left alone and unbound keys do nothing
+    press(&mut app, KeyCode::F(9));
+    assert_eq!(app.command_line, "sell");
+}
+
+#[test]
+fn test_challenge_tracks_pnl_from_fixed_capital() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 1000), (Currency::USD, 100000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 10, 100)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 12, 100)).unwrap();
+
+    submit(&mut app, "challenge 5");
+    assert!(last_update(&app).starts_with("Challenge started: 5 minute(s) to grow 500,000 USD."));
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), 500000);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), 0);
+
+    // Buying 100 at 1.2 and marking them at the new mid of 1.0 loses 20
+    submit(&mut app, "buy osmo market 100");
+    app.tick();
+    submit(&mut app, "challenge stop");
+    assert_eq!(last_update(&app), "Challenge stopped: PnL -20 USD, max drawdown 20 USD, score -40.");
+    assert!(app.challenge.is_none());
+
+    // A new round can't start with escrow on the book
+    submit(&mut app, "buy osmo limit 10 0.9");
+    submit(&mut app, "challenge 5");
+    assert!(last_update(&app).ends_with("Cancel or fill your resting orders before starting a challenge"));
+}
+
+#[test]
+fn test_cast_writes_depth_frames() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+    app.tick();
+    submit(&mut app, "buy osmo limit 10 0.5");
+
+    let path = std::env::temp_dir().join(format!("orderbook-depth-{}.cast", std::process::id()));
+    submit(&mut app, &format!("cast {}", path.display()));
+    assert!(last_update(&app).starts_with("Wrote 2 depth frame(s)"));
+
+    let cast = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(cast.lines().count(), 3);
+    assert!(cast.lines().last().unwrap().contains("0.5 bid"));
+}
+
+#[test]
+fn test_old_trades_are_archived_to_storage() {
+    let mut app = funded_app();
+    app.storage = Some(Box::new(MemoryStorage::new()));
+    let maker = AccountBuilder::funded(9, &[(Currency::OSMO, 100000)]).build();
+    let taker = AccountBuilder::funded(8, &[(Currency::USD, 1000000)]).build();
+
+    // One fill per resting order, so sweeping them leaves one more trade than fits in memory
+    let fills = TRADES_IN_MEMORY as u64 + 1;
+    for _ in 0..fills {
+        app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Ask, 10, 1)).unwrap();
+    }
+    app.session_book.handle_order(&mut market_order(&taker, OrderDirection::Bid, fills)).unwrap();
+
+    app.tick();
+    assert_eq!(app.session_book.trades().len(), TRADES_IN_MEMORY / 2);
+    assert_eq!(*app.session_book.archived_trades(), fills - TRADES_IN_MEMORY as u64 / 2);
+
+    let archived = app.session_book.archived_trade_history(app.storage.as_deref().unwrap()).unwrap();
+    assert_eq!(archived.len() as u64, *app.session_book.archived_trades());
+    assert_eq!(*archived.last().unwrap().trade_id() + 1, *app.session_book.trades()[0].trade_id());
+}
+
+#[test]
+fn test_cancel_refunds_resting_order() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+    let order_id = *app.session_book.ticks().get(&4).unwrap().orders().front().unwrap().order_id();
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 4);
+
+    submit(&mut app, &format!("cancel {}", order_id));
+    assert_eq!(last_update(&app), format!("Cancelled order {}: Bid 10 OSMO at 0.4, refunded 4 USD.", order_id));
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD);
+    assert!(app.session_book.ticks().is_empty());
+    assert_eq!(app.positions.values().sum::<u64>(), 0);
+
+    submit(&mut app, &format!("cancel {}", order_id));
+    assert!(last_update(&app).starts_with("Cancel rejected [102 unknown_order]"));
+    submit(&mut app, "cancel");
+    assert!(last_update(&app).starts_with("Command rejected"));
+}
+
+#[test]
+fn test_amend_resizes_and_reprices_resting_order() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+    submit(&mut app, "sell osmo limit 10 1.2");
+    let order_id = *app.session_book.ticks().get(&4).unwrap().orders().front().unwrap().order_id();
+
+    submit(&mut app, &format!("amend {} 5", order_id));
+    assert_eq!(last_update(&app), format!("Amended order {}: Bid 10 OSMO at 0.4 is now 5 at 0.4, keeping its place in the queue.", order_id));
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 2);
+
+    submit(&mut app, &format!("amend #{} 20 0.5", order_id));
+    assert_eq!(last_update(&app), format!("Amended order {}: Bid 5 OSMO at 0.4 is now 20 at 0.5.", order_id));
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(5, 20)]);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD - 10);
+
+    submit(&mut app, &format!("amend {} 20 1.2", order_id));
+    assert_eq!(last_update(&app), "Amend rejected [100 invalid_order]: Amended price would cross the spread");
+    submit(&mut app, "amend 999 20");
+    assert!(last_update(&app).starts_with("Amend rejected [102 unknown_order]"));
+    submit(&mut app, "amend 1");
+    assert!(last_update(&app).starts_with("Command rejected"));
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(5, 20)]);
+}
+
+#[test]
+fn test_iceberg_shows_one_slice_at_a_time() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 100 1.2 --display 25");
+    assert!(last_update(&app).ends_with(", showing 25 at a time."));
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(12, 25)]);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 100);
+
+    // A buyer takes a slice and a half, and the chart only ever shows what is left of the current slice
+    let buyer = AccountBuilder::funded(9, &[(Currency::USD, 10000)]).build();
+    app.session_book.handle_order(&mut market_order(&buyer, OrderDirection::Bid, 40)).unwrap();
+    app.tick();
+    assert_eq!(app.positions[&12], 10);
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD + 48);
+
+    submit(&mut app, "buy osmo market 10 --display 5");
+    assert!(last_update(&app).starts_with("Error placing order [100 invalid_order]"));
+    submit(&mut app, "sell osmo limit 10 1.3 --display");
+    assert!(last_update(&app).contains("Failed to parse display quantity"));
+}
+
+#[test]
+fn test_ioc_and_fok_limits_never_rest() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 30 1.2");
+
+    submit(&mut app, "buy osmo limit 50 1.2 --fok");
+    assert!(last_update(&app).starts_with("Error placing order [104 insufficient_liquidity]"));
+    submit(&mut app, "buy osmo limit 50 1.2 --ioc");
+    assert_eq!(last_update(&app), "IOC limit Bid filled 30 of 50 OSMO, the other 20 cancelled.");
+    assert!(app.session_book.ticks().is_empty());
+
+    submit(&mut app, "sell osmo limit 10 1.1");
+    submit(&mut app, "buy osmo limit 10 1.1 --fok");
+    assert_eq!(last_update(&app), "FOK limit Bid filled in full: 10 OSMO.");
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD);
+}
+
+#[test]
+fn test_market_orders_respect_worst_price_and_notional_bounds() {
+    let mut app = funded_app();
+    let maker = AccountBuilder::funded(9, &[(Currency::USD, 10_000)]).build();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 12, 50)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&maker, OrderDirection::Bid, 10, 50)).unwrap();
+
+    // Selling 80 in full would get back 50 * 1.2 + 30 * 1.0 = 90
+    submit(&mut app, "sell osmo market 80 --min-output 92");
+    assert_eq!(last_update(&app), "Error placing order [200 risk_limit_exceeded]: Market order exceeds maximum slippage");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO);
+
+    submit(&mut app, "sell osmo market 80 --worst-price 1.2 --truncate");
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 50);
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(10, 50)]);
+
+    submit(&mut app, "sell osmo market 10 --max-cost 100");
+    assert!(last_update(&app).contains("Use --min-output to bound what a sell gets back"));
+    submit(&mut app, "sell osmo market 10 --min-output 10");
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), USER_USD + 60 + 10);
+}
+
+#[test]
+fn test_strict_market_orders_are_all_or_none() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 30 1.2");
+
+    submit(&mut app, "buy osmo market 50 --strict");
+    assert_eq!(last_update(&app), "Error placing order [104 insufficient_liquidity]: All-or-none order would only fill 30 of 50 OSMO");
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(12, 30)]);
+
+    submit(&mut app, "risk strict on");
+    submit(&mut app, "buy osmo market 40");
+    assert!(last_update(&app).starts_with("Error placing order [104 insufficient_liquidity]"));
+    submit(&mut app, "risk strict off");
+    submit(&mut app, "buy osmo market 40");
+    assert!(app.session_book.ticks().is_empty());
+
+    submit(&mut app, "risk strict maybe");
+    assert!(last_update(&app).contains("Expected risk strict [on/off]"));
+}
+
+#[test]
+fn test_pasted_commands_run_as_a_batch() {
+    let mut app = funded_app();
+
+    // A single line waits on the command line, after whatever was already typed
+    press(&mut app, KeyCode::Char('s'));
+    handle_paste_event("ell osmo limit 10 1.2", &mut app).unwrap();
+    assert_eq!(app.command_line, "sell osmo limit 10 1.2");
+    assert!(app.session_book.ticks().is_empty());
+
+    // Several lines run in order, with terminals' \r line breaks, blank lines and comments handled
+    handle_paste_event("\r# ladder\r\nsell osmo limit 10 1.3\n\nbuy osmo limit 5 bad\rbuy osmo limit 10 0.9\n", &mut app).unwrap();
+    assert_eq!(last_update(&app), "Pasted 4 commands: 3 ran, 1 rejected.");
+    assert!(app.updates.contains(&"Paste 3/4: buy osmo limit 5 bad".to_string()));
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(12, 10), (13, 10)]);
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(9, 10)]);
+    assert!(app.command_line.is_empty());
+}
+
+#[test]
+fn test_autosaved_session_is_recovered_on_the_next_start() {
+    let path = std::env::temp_dir().join(format!("orderbook-autosave-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut app = funded_app();
+    app.enable_autosave(AutosaveConfig::new(&path, Some(2), None, true));
+    submit(&mut app, "recover");
+    assert_eq!(last_update(&app), "Command rejected [101 invalid_command]: No saved session to recover");
+
+    submit(&mut app, "sell osmo limit 10 1.2");
+    submit(&mut app, "buy osmo market 4");
+    app.tick();
+    // One more change is below the limit of 2, so it is only saved on the way out
+    submit(&mut app, "buy osmo limit 10 0.9");
+    app.tick();
+    app.flush_autosave();
+
+    // The next session finds the save and waits for the user before overwriting it
+    let mut next = funded_app();
+    next.enable_autosave(AutosaveConfig::new(&path, Some(2), None, true));
+    assert!(last_update(&next).starts_with("Found a saved session in"));
+    next.tick();
+    submit(&mut next, "recover");
+    assert!(last_update(&next).starts_with("Recovered the session saved in"), "{}", last_update(&next));
+    assert_eq!(next.session_book.snapshot().asks(), &vec![(12, 6)]);
+    assert_eq!(next.session_book.snapshot().bids(), &vec![(9, 10)]);
+    assert_eq!(next.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 10 + 4);
+    // The 4 bought came from the user's own ask, so only the bid at 0.9 is still out of pocket
+    assert_eq!(next.user_account.borrow().balance(Currency::USD), USER_USD - 9);
+    assert_eq!(next.positions[&12], 6);
+
+    // The recovered orders still trade and settle with the recovered accounts
+    submit(&mut next, "buy osmo market 6");
+    assert!(next.session_book.snapshot().asks().is_empty());
+    next.session_book.verify_escrow().unwrap();
+    submit(&mut next, "recover");
+    assert_eq!(last_update(&next), "Command rejected [101 invalid_command]: No saved session to recover");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_script_runs_headless_and_prints_trades_and_balances() {
+    let mut app = funded_app();
+    let script = "# ladder\nsell osmo limit 10 1.2\n\nbuy osmo limit 5 bad\nbuy osmo market 4\n";
+    let mut out = Vec::new();
+    let summary = run_script(&mut app, script.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert_eq!((summary.commands, summary.rejected, summary.trades), (3, 1, 1));
+    assert!(out.starts_with("> sell osmo limit 10 1.2\n  Limit order successfully placed"), "{}", out);
+    assert!(out.contains("> buy osmo limit 5 bad\n  Command rejected [101 invalid_command]"));
+    assert!(out.contains("  trade #0 Bid 4 OSMO at 1.2, taker account 0 / maker account 0\n"));
+    assert!(out.contains(": 1 rejected, 1 trade(s).\n"));
+    assert!(out.ends_with("Account 0: 994 OSMO, 5,000 USD\n"), "{}", out);
+    assert_eq!(app.positions[&12], 6);
+}
+
+#[test]
+fn test_review_steps_through_depth_history() {
+    let mut app = funded_app();
+    submit(&mut app, "review");
+    assert_eq!(last_update(&app), "No depth history recorded yet.");
+
+    submit(&mut app, "sell osmo limit 10 1.2");
+    app.tick();
+    submit(&mut app, "buy osmo market 4");
+    app.tick();
+    assert_eq!(app.depth_history.len(), 2);
+
+    submit(&mut app, "review");
+    assert_eq!(app.review_frame, Some(1));
+    press(&mut app, KeyCode::Left);
+    assert_eq!(last_update(&app), "Frame 1/2, 0s ago: the oldest recorded frame.");
+    assert_eq!(app.positions[&12], 10);
+
+    // Orders placed while reviewing change the book but not the chart being reviewed
+    submit(&mut app, "sell osmo limit 5 1.3");
+    assert_eq!(app.positions[&12], 10);
+    press(&mut app, KeyCode::Right);
+    assert_eq!(last_update(&app), "Frame 2/2, 0s ago: 1 trade(s) since the previous frame, the last 4 OSMO at 1.2.");
+    assert_eq!(app.positions[&12], 6);
+
+    submit(&mut app, "review");
+    assert_eq!(last_update(&app), "Back to the live book.");
+    assert_eq!(app.review_frame, None);
+    assert_eq!(app.positions[&13], 5);
+}
+
+#[test]
+fn test_changed_levels_are_highlighted_until_they_fade() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 10 1.2");
+    submit(&mut app, "sell osmo limit 10 1.3");
+    assert_eq!(app.level_highlights.keys().copied().collect::<Vec<_>>(), vec![12, 13]);
+
+    // Each frame fades every highlight, and a level that changes again starts over
+    for _ in 0..HIGHLIGHT_FRAMES - 1 {
+        app.tick();
+    }
+    submit(&mut app, "buy osmo market 4");
+    assert_eq!(app.level_highlights[&12], HIGHLIGHT_FRAMES);
+    assert_eq!(app.level_highlights[&13], 1);
+    app.tick();
+    assert_eq!(app.level_highlights.keys().copied().collect::<Vec<_>>(), vec![12]);
+
+    // Switching markets starts the new chart without highlights
+    submit(&mut app, "market add usd/osmo");
+    submit(&mut app, "market USD/OSMO");
+    assert!(app.level_highlights.is_empty());
+}
+
+#[test]
+fn test_post_only_rejected_when_crossing() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 30 1.2");
+    submit(&mut app, "buy osmo limit 10 1.2 --post-only");
+    assert!(last_update(&app).starts_with("Error placing order [203 post_only]"));
+    submit(&mut app, "buy osmo limit 10 1.1 --post-only");
+    assert!(last_update(&app).contains("successfully placed"));
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(11, 10)]);
+}
+
+#[test]
+fn test_market_switches_active_book() {
+    let mut app = funded_app();
+    submit(&mut app, "sell osmo limit 10 1.2");
+    submit(&mut app, "market USD/OSMO");
+    assert!(last_update(&app).contains("USD/OSMO isn't listed, add it with `market add USD/OSMO`"));
+
+    submit(&mut app, "market add usd/osmo");
+    assert_eq!(last_update(&app), "Listed USD/OSMO as book 1.");
+    submit(&mut app, "market add OSMO/USD");
+    assert!(last_update(&app).starts_with("Market rejected: OSMO/USD is already listed as book 0"));
+    submit(&mut app, "market USD/OSMO");
+    assert_eq!(last_update(&app), "Switched to USD/OSMO (book 1).");
+    submit(&mut app, "market");
+    assert_eq!(last_update(&app), "Markets: OSMO/USD (book 0), USD/OSMO (book 1, on screen).");
+
+    // Buying USD on the USD/OSMO book is a bid that escrows OSMO at 0.5 OSMO per USD
+    submit(&mut app, "buy usd limit 10 0.5");
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(5, 10)]);
+    assert_eq!(app.user_account.borrow().balance(Currency::OSMO), USER_OSMO - 10 - 5);
+    let bid_id = *app.session_book.ticks()[&5].orders().front().unwrap().order_id();
+
+    // Switching back leaves the order resting on the parked book, and order ids never repeat across books
+    submit(&mut app, "market osmo/usd");
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(12, 10)]);
+    assert_eq!(app.exchange.book(1).unwrap().snapshot().bids(), &vec![(5, 10)]);
+    let ask_id = *app.session_book.ticks()[&12].orders().front().unwrap().order_id();
+    assert_ne!(ask_id, bid_id);
+    submit(&mut app, "market OSMO/USD");
+    assert_eq!(last_update(&app), "OSMO/USD is already on screen.");
+}
+
+#[test]
+fn test_cancel_bids_in_price_band() {
+    let mut app = funded_app();
+    for command in ["buy osmo limit 10 0.8", "buy osmo limit 10 0.9", "buy osmo limit 10 1", "sell osmo limit 10 1.1"] {
+        submit(&mut app, command);
+    }
+
+    submit(&mut app, "cancel bids 1 0.9");
+    assert_eq!(last_update(&app), "Cancelled 2 bids between 0.9 and 1, refunded 19 USD.");
+    assert_eq!(app.session_book.snapshot().bids(), &vec![(8, 10)]);
+    assert_eq!(app.session_book.snapshot().asks(), &vec![(11, 10)]);
+    submit(&mut app, "cancel asks 0.9 1");
+    assert_eq!(last_update(&app), "No asks of yours rest between 0.9 and 1.");
+    submit(&mut app, "cancel both 1 2");
+    assert!(last_update(&app).starts_with("Command rejected"));
+}
+
+#[test]
+fn test_stats_report_spread_and_volatility() {
+    let mut app = funded_app();
+    submit(&mut app, "stats");
+    assert!(last_update(&app).starts_with("Spread and volatility: not enough quotes sampled yet"));
+
+    // Samples are at most one a second, however often the app ticks
+    let start = Instant::now();
+    submit(&mut app, "buy osmo limit 10 0.9");
+    submit(&mut app, "sell osmo limit 10 1.1");
+    app.sample_quotes(start);
+    app.sample_quotes(start + Duration::from_millis(500));
+    submit(&mut app, "sell osmo limit 10 1");
+    app.sample_quotes(start + Duration::from_secs(1));
+    assert_eq!(app.quote_history.spreads(), vec![2, 1]);
+
+    submit(&mut app, "stats");
+    assert!(last_update(&app).starts_with("Spread: 1 ticks, 1.5 on average over 2 samples, trend"));
+    // Mid from 10 to 9.5
+    assert!(last_update(&app).contains(&format!("Realized volatility of mid: {:.2}% over the last 2 samples", (10.0f64 / 9.5).ln() * 100.0)));
+}
+
+#[test]
+fn test_cross_prints_off_the_book() {
+    let mut app = funded_app();
+    app.apply_config(&Config::parse("account 7 OSMO=500").unwrap());
+    submit(&mut app, "sell osmo limit 10 1.2");
+    let user_usd = app.user_account.borrow().balance(Currency::USD);
+
+    submit(&mut app, "cross 0 7 500 1");
+    assert_eq!(last_update(&app), "Crossed 500 OSMO at 1 from account 7 to account 0 off the book.");
+    assert_eq!(app.user_account.borrow().balance(Currency::USD), user_usd - 500);
+    assert_eq!(app.accounts[&7].borrow().balance(Currency::USD), 500);
+    assert_eq!(app.session_book.ticks().get(&12).map(|tick| *tick.total_liquidity()), Some(10));
+
+    submit(&mut app, "trades");
+    assert!(app.updates.iter().any(|update| update.starts_with("  #0 cross 500 OSMO at 1, buyer account 0 / seller account 7")));
+
+    submit(&mut app, "cross 0 7 1 1.3");
+    assert!(last_update(&app).starts_with("Cross rejected [") && last_update(&app).contains("outside the best bid and ask"));
+    submit(&mut app, "cross 0 9 1 1");
+    assert_eq!(last_update(&app), "Command rejected [101 invalid_command]: No account 9");
+    submit(&mut app, "cross 0 7 1");
+    assert!(last_update(&app).starts_with("Command rejected"));
+}
+
+
+#[test]
+fn test_fills_are_reported_as_executions() {
+    let mut app = funded_app();
+    let other = AccountBuilder::funded(9, &[(Currency::OSMO, 1000), (Currency::USD, 1000)]).build();
+    app.session_book.handle_order(&mut limit_order(&other, OrderDirection::Ask, 6, 100)).unwrap();
+    app.session_book.handle_order(&mut limit_order(&other, OrderDirection::Ask, 8, 100)).unwrap();
+
+    submit(&mut app, "buy osmo market 150");
+    let executions: Vec<&String> = app.updates.iter().filter(|update| update.starts_with("Executed")).collect();
+    assert_eq!(executions.len(), 2);
+    assert!(executions[0].starts_with("Executed: bought 100 OSMO at 0.6 from account 9"));
+    assert!(executions[1].starts_with("Executed: bought 50 OSMO at 0.8 from account 9"));
+    assert!(last_update(&app).starts_with("Market order for 150 OSMO successfully placed"));
+
+    // A resting bid filled by someone else is reported on the next tick, from the maker's side
+    submit(&mut app, "buy osmo limit 20 0.5");
+    app.session_book.handle_order(&mut market_order(&other, OrderDirection::Ask, 20)).unwrap();
+    app.tick();
+    assert!(last_update(&app).starts_with("Executed: bought 20 OSMO at 0.5 from account 9"));
+
+    // Trades between other accounts aren't the user's executions
+    let updates = app.updates.len();
+    app.session_book.handle_order(&mut limit_order(&other, OrderDirection::Bid, 4, 10)).unwrap();
+    let third = AccountBuilder::funded(3, &[(Currency::OSMO, 100)]).build();
+    app.session_book.handle_order(&mut market_order(&third, OrderDirection::Ask, 10)).unwrap();
+    app.tick();
+    assert!(app.updates.iter().skip(updates).all(|update| !update.starts_with("Executed")));
+}
+
+#[test]
+fn test_notional_units_show_level_and_order_sizes_in_usd() {
+    let mut app = funded_app();
+    submit(&mut app, "buy osmo limit 10 0.4");
+    submit(&mut app, "buy osmo limit 5 0.4");
+
+    submit(&mut app, "numbers units notional");
+    assert_eq!(last_update(&app), "Level quantities and order sizes are now shown as notional, e.g. 10 OSMO at 1.2 as 12 USD.");
+    submit(&mut app, "tick 0.4");
+    let len = app.updates.len();
+    assert_eq!(app.updates[len - 1], "Queue at 0.4: 2 orders, 6 USD in priority order:");
+    assert!(app.updates[len - 2].starts_with("  #1 order 1 from account 0: Bid 4 USD"));
+    submit(&mut app, "orders 2");
+    assert_eq!(last_update(&app), "Order 2: Bid at 0.4, 0 USD filled, 2 USD left.");
+
+    submit(&mut app, "numbers units quantity");
+    submit(&mut app, "orders 2");
+    assert_eq!(last_update(&app), "Order 2: Bid at 0.4, 0 OSMO filled, 5 OSMO left.");
+    submit(&mut app, "numbers units usd");
+    assert!(last_update(&app).contains("numbers units [quantity/notional]"));
+}
+
+#[test]
+fn test_price_decimals_follow_style_and_tick_size() {
+    let mut app = funded_app();
+    app.apply_config(&Config::parse("ticksize 100 1").unwrap());
+
+    submit(&mut app, "numbers prices 2");
+    assert_eq!(last_update(&app), "Prices are now shown in 2 style, e.g. 1.20.");
+    submit(&mut app, "buy osmo limit 10 0.4");
+    assert_eq!(last_update(&app), "Limit order successfully placed for 10 OSMO at price 0.40 USD.");
+    submit(&mut app, "tick 0.4");
+    assert!(last_update(&app).starts_with("Queue at 0.40:"));
+
+    // Prices from $100 up can only be whole, so they never show decimals
+    submit(&mut app, "tick 150");
+    assert_eq!(last_update(&app), "No orders resting at 150.");
+
+    submit(&mut app, "numbers prices auto");
+    submit(&mut app, "tick 0.4");
+    assert!(last_update(&app).starts_with("Queue at 0.4:"));
+    submit(&mut app, "numbers prices 0");
+    assert!(last_update(&app).starts_with("Command rejected"));
+}